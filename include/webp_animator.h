@@ -0,0 +1,202 @@
+#ifndef WEBP_ANIMATOR_H
+#define WEBP_ANIMATOR_H
+
+/* Generated by cbindgen from src/capi.rs.  Do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * An animation being assembled.
+ */
+typedef struct WebPAnimator WebPAnimator;
+
+/**
+ * The result of a call.
+ */
+typedef enum WebPAnimatorStatus {
+  WebPAnimatorStatus_Ok = 0,
+  /**
+   * A pointer was null, or a path wasn't UTF-8.
+   */
+  WebPAnimatorStatus_InvalidArgument,
+  WebPAnimatorStatus_InvalidDimensions,
+  WebPAnimatorStatus_InvalidDuration,
+  WebPAnimatorStatus_NoFrames,
+  /**
+   * Frame data isn't a WebP image.
+   */
+  WebPAnimatorStatus_UnrecognizedImage,
+  WebPAnimatorStatus_InvalidMetadata,
+  /**
+   * The file would exceed the 4 GiB size limit of the RIFF format.
+   */
+  WebPAnimatorStatus_TooLarge,
+  /**
+   * Reading or writing a file failed.
+   */
+  WebPAnimatorStatus_Io,
+  /**
+   * Any other error.
+   */
+  WebPAnimatorStatus_Failed,
+} WebPAnimatorStatus;
+
+/**
+ * The canvas settings, as in [`Params`](crate::Params).
+ */
+typedef struct WebPAnimatorParams {
+  uint32_t width;
+  uint32_t height;
+  uint8_t background_bgra[4];
+  /**
+   * The number of times to play the animation, where 0 means forever.
+   */
+  uint16_t loop_count;
+  /**
+   * Whether the frames have alpha.  If false, it is detected from the
+   * frames.
+   */
+  bool has_alpha;
+} WebPAnimatorParams;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an animator and stores it in `*out`.  Free it with
+ * [`webp_animator_free`].
+ *
+ * # Safety
+ *
+ * `out` must be null or valid for writes.
+ */
+enum WebPAnimatorStatus webp_animator_new(struct WebPAnimatorParams params, WebPAnimator **out);
+
+/**
+ * Frees an animator.  Does nothing if `animator` is null.
+ *
+ * # Safety
+ *
+ * `animator` must be null or come from [`webp_animator_new`], and must not
+ * be used afterwards.
+ */
+void webp_animator_free(WebPAnimator *animator);
+
+/**
+ * Adds an encoded WebP image of `len` bytes as a frame shown for
+ * `duration` milliseconds.  See [`WebPAnimator::add_frame`].
+ *
+ * # Safety
+ *
+ * `animator` must come from [`webp_animator_new`], and `data` must point
+ * to `len` readable bytes.
+ */
+enum WebPAnimatorStatus webp_animator_add_frame(WebPAnimator *animator,
+                                                const uint8_t *data,
+                                                size_t len,
+                                                uint32_t duration);
+
+/**
+ * Encodes `width * height` RGBA pixels, row by row with no padding,
+ * losslessly and adds them as a frame shown for `duration` milliseconds.
+ * See [`WebPAnimator::add_rgba_frame`].
+ *
+ * # Safety
+ *
+ * `animator` must come from [`webp_animator_new`], and `pixels` must point
+ * to `width * height * 4` readable bytes.
+ */
+enum WebPAnimatorStatus webp_animator_add_rgba_frame(WebPAnimator *animator,
+                                                     const uint8_t *pixels,
+                                                     uint32_t width,
+                                                     uint32_t height,
+                                                     uint32_t duration);
+
+/**
+ * Sets the ICC profile.  See [`WebPAnimator::set_icc_profile`].
+ *
+ * # Safety
+ *
+ * `animator` must come from [`webp_animator_new`], and `data` must point
+ * to `len` readable bytes.
+ */
+enum WebPAnimatorStatus webp_animator_set_icc_profile(WebPAnimator *animator,
+                                                      const uint8_t *data,
+                                                      size_t len);
+
+/**
+ * Sets the EXIF metadata.  See [`WebPAnimator::set_exif_metadata`].
+ *
+ * # Safety
+ *
+ * As for [`webp_animator_set_icc_profile`].
+ */
+enum WebPAnimatorStatus webp_animator_set_exif_metadata(WebPAnimator *animator,
+                                                        const uint8_t *data,
+                                                        size_t len);
+
+/**
+ * Sets the XMP metadata.  See [`WebPAnimator::set_xmp_metadata`].
+ *
+ * # Safety
+ *
+ * As for [`webp_animator_set_icc_profile`].
+ */
+enum WebPAnimatorStatus webp_animator_set_xmp_metadata(WebPAnimator *animator,
+                                                       const uint8_t *data,
+                                                       size_t len);
+
+/**
+ * Writes the animation to a new buffer, and stores it and its length in
+ * `*out_data` and `*out_len`.  Free it with [`webp_animator_free_buffer`].
+ * The animator can still be used afterwards.
+ *
+ * # Safety
+ *
+ * `animator` must come from [`webp_animator_new`], and `out_data` and
+ * `out_len` must be valid for writes.
+ */
+enum WebPAnimatorStatus webp_animator_write_to_buffer(WebPAnimator *animator,
+                                                      uint8_t **out_data,
+                                                      size_t *out_len);
+
+/**
+ * Frees a buffer from [`webp_animator_write_to_buffer`].  Does nothing if
+ * `data` is null.
+ *
+ * # Safety
+ *
+ * `data` and `len` must come from the same call to
+ * [`webp_animator_write_to_buffer`], and `data` must not be used
+ * afterwards.
+ */
+void webp_animator_free_buffer(uint8_t *data, size_t len);
+
+/**
+ * Writes the animation to the file at `path`, a UTF-8 string, replacing
+ * it if it exists.  See [`WebPAnimator::write_to_file`].
+ *
+ * # Safety
+ *
+ * `animator` must come from [`webp_animator_new`], and `path` must be a
+ * null-terminated string.
+ */
+enum WebPAnimatorStatus webp_animator_write_to_file(WebPAnimator *animator, const char *path);
+
+/**
+ * Returns a static, null-terminated description of `status`, which is a
+ * [`WebPAnimatorStatus`].  It is taken as an `int` so that other values are
+ * safe to pass; they are described as an unknown status.
+ */
+const char *webp_animator_status_message(int status);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* WEBP_ANIMATOR_H */
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use image::{Rgba, RgbaImage};
+
+use crate::{Decoder, EncodingError};
+
+/// Picks a background color for [`Params::background_bgra`](crate::Params)
+/// from the most common color on the borders of `images`, in BGRA order.
+/// Fully transparent pixels all count as transparent black, so frames with
+/// transparent edges get a transparent background.  Returns transparent
+/// black if there are no pixels.
+pub fn border_color<'a, I: IntoIterator<Item = &'a RgbaImage>>(images: I) -> [u8; 4] {
+    let mut counts: HashMap<[u8; 4], usize> = HashMap::new();
+    for image in images {
+        let (width, height) = image.dimensions();
+        let mut count = |x, y| {
+            let Rgba([r, g, b, a]) = *image.get_pixel(x, y);
+            let bgra = if a == 0 { [0; 4] } else { [b, g, r, a] };
+            *counts.entry(bgra).or_default() += 1;
+        };
+        for x in 0..width {
+            count(x, 0);
+            if height > 1 {
+                count(x, height - 1);
+            }
+        }
+        for y in 1..height.saturating_sub(1) {
+            count(0, y);
+            if width > 1 {
+                count(width - 1, y);
+            }
+        }
+    }
+    counts
+        .into_iter()
+        .max_by_key(|&(bgra, n)| (n, bgra))
+        .map_or([0; 4], |(bgra, _)| bgra)
+}
+
+impl Decoder<'_> {
+    /// Suggests a background color by rendering every frame and applying
+    /// [`border_color`] to the results.
+    pub fn suggest_background(&self) -> Result<[u8; 4], EncodingError> {
+        let frames = self.canvas_frames().collect::<Result<Vec<_>, _>>()?;
+        Ok(border_color(frames.iter().map(|f| &f.image)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgba, RgbaImage};
+
+    use crate::border_color;
+
+    #[test]
+    fn test_border_color() {
+        let mut image = RgbaImage::from_pixel(8, 8, Rgba([255, 255, 255, 255]));
+        for x in 0..8 {
+            image.put_pixel(x, 0, Rgba([10, 20, 30, 255]));
+            image.put_pixel(x, 7, Rgba([10, 20, 30, 255]));
+        }
+        assert_eq!(border_color([&image]), [30, 20, 10, 255]);
+        let sticker = RgbaImage::from_fn(8, 8, |x, _| Rgba([x as u8, 0, 0, 0]));
+        assert_eq!(border_color([&image, &sticker, &sticker]), [0; 4]);
+        assert_eq!(border_color([]), [0; 4]);
+    }
+}
@@ -0,0 +1,126 @@
+use std::{error::Error, fs, io::Cursor, path::PathBuf};
+
+use image::ImageReader;
+use webp_animator::{FrameOptions, LoopCount, WebPAnimator};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// The frames, as image files or directories of image files.  The files
+    /// in a directory are sorted by name.  WebP files are used without
+    /// re-encoding; other formats are encoded losslessly.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+    /// The output file.
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Frames per second.
+    #[arg(long, conflicts_with = "duration")]
+    fps: Option<f64>,
+    /// The duration of each frame in milliseconds.
+    #[arg(long, default_value_t = 100)]
+    duration: u32,
+    /// The number of times to play the animation, or 0 to loop forever.
+    #[arg(long, default_value_t = 0)]
+    loop_count: u16,
+    /// The background color as RRGGBB or RRGGBBAA hexadecimal.
+    #[arg(long, default_value = "00000000", value_parser = parse_color)]
+    background: [u8; 4],
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let paths = frame_paths(&args.inputs)?;
+    let Some(first) = paths.first() else {
+        return Err("no input frames".into());
+    };
+    let (width, height) = ImageReader::open(first)?
+        .with_guessed_format()?
+        .into_dimensions()?;
+    let [r, g, b, a] = args.background;
+    let mut animator = WebPAnimator::builder()
+        .width(width)
+        .height(height)
+        .background([b, g, r, a])
+        .loop_count(LoopCount::from(args.loop_count))
+        .detect_alpha(true)
+        .build()?;
+    for (i, path) in paths.iter().enumerate() {
+        let duration = match args.fps {
+            Some(fps) => fps_duration(fps, i)?,
+            None => args.duration,
+        };
+        let options = FrameOptions::with_duration(duration);
+        let data = fs::read(path)?;
+        let result = if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+            animator.add_frame(&data, options)
+        } else {
+            let image = ImageReader::new(Cursor::new(&data))
+                .with_guessed_format()?
+                .decode()?;
+            animator.add_image(&image, options)
+        };
+        result.map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+    animator.write_to_file(&args.output)?;
+    Ok(())
+}
+
+/// Expands directories into their files, sorted by name.
+fn frame_paths(inputs: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut paths = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            let mut entries = Vec::new();
+            for entry in fs::read_dir(input)? {
+                let path = entry?.path();
+                if path.is_file() {
+                    entries.push(path);
+                }
+            }
+            entries.sort();
+            paths.extend(entries);
+        } else {
+            paths.push(input.clone());
+        }
+    }
+    Ok(paths)
+}
+
+/// The duration in milliseconds of frame `index` at the given frame rate.
+/// Frame start times are rounded individually so that the rounding errors
+/// don't accumulate.
+fn fps_duration(fps: f64, index: usize) -> Result<u32, Box<dyn Error>> {
+    if !(fps > 0.0 && fps.is_finite()) {
+        return Err("fps must be positive".into());
+    }
+    let start = |i: usize| (i as f64 * 1000.0 / fps).round();
+    Ok((start(index + 1) - start(index)) as u32)
+}
+
+fn parse_color(s: &str) -> Result<[u8; 4], String> {
+    let s = s.strip_prefix('#').unwrap_or(s);
+    let hex = |i: usize| {
+        s.get(i..i + 2)
+            .and_then(|h| u8::from_str_radix(h, 16).ok())
+            .ok_or_else(|| format!("invalid color: {s}"))
+    };
+    match s.len() {
+        6 => Ok([hex(0)?, hex(2)?, hex(4)?, 255]),
+        8 => Ok([hex(0)?, hex(2)?, hex(4)?, hex(6)?]),
+        _ => Err(format!("invalid color: {s}")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fps_duration, parse_color};
+
+    #[test]
+    fn test_args() {
+        let durations: Vec<_> = (0..3).map(|i| fps_duration(30.0, i).unwrap()).collect();
+        assert_eq!(durations, [33, 34, 33]);
+        assert!(fps_duration(0.0, 0).is_err());
+        assert_eq!(parse_color("#ff8000").unwrap(), [255, 128, 0, 255]);
+        assert_eq!(parse_color("01020304").unwrap(), [1, 2, 3, 4]);
+        assert!(parse_color("12345").is_err());
+    }
+}
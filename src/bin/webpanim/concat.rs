@@ -0,0 +1,44 @@
+use std::{error::Error, fs, path::PathBuf};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// The animated WebP files to join, in order.
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+    /// The output file.
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Scale animations whose canvas differs from the first to fit, instead
+    /// of failing.
+    #[arg(long)]
+    fit: bool,
+    /// Insert this many frames that dissolve from each animation into the
+    /// next.
+    #[arg(long, conflicts_with = "fit")]
+    crossfade: Option<u32>,
+    /// The duration of each dissolve frame in milliseconds.
+    #[arg(long, default_value_t = 40, requires = "crossfade")]
+    crossfade_duration: u32,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let data = args
+        .inputs
+        .iter()
+        .map(fs::read)
+        .collect::<Result<Vec<_>, _>>()?;
+    let inputs: Vec<&[u8]> = data.iter().map(Vec::as_slice).collect();
+    let out = if let Some(frames) = args.crossfade {
+        let crossfade = webp_animator::Crossfade {
+            frames,
+            duration: args.crossfade_duration,
+        };
+        webp_animator::concat_crossfade(&inputs, crossfade)?
+    } else if args.fit {
+        webp_animator::concat_fit(&inputs)?
+    } else {
+        webp_animator::concat(&inputs)?
+    };
+    fs::write(&args.output, out)?;
+    Ok(())
+}
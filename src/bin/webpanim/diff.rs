@@ -0,0 +1,26 @@
+use std::{error::Error, fs, path::PathBuf};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// The first animated WebP file.
+    a: PathBuf,
+    /// The second animated WebP file.
+    b: PathBuf,
+    /// How much a channel of a pixel may differ before the pixel counts as
+    /// different.
+    #[arg(short, long, default_value_t = 0)]
+    tolerance: u8,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let (a, b) = (fs::read(&args.a)?, fs::read(&args.b)?);
+    let report = webp_animator::diff(&a, &b, args.tolerance)?;
+    for difference in &report.differences {
+        println!("{difference}");
+    }
+    if report.is_empty() {
+        Ok(())
+    } else {
+        Err("the animations differ".into())
+    }
+}
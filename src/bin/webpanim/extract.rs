@@ -0,0 +1,72 @@
+use std::{error::Error, fs, path::PathBuf};
+
+use image::codecs::webp::WebPEncoder;
+use webp_animator::{Compositor, Decoder};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// An animated WebP file.
+    input: PathBuf,
+    /// The pattern for the output files.  `%d` is replaced by the frame
+    /// number, and `%04d` by the frame number padded with zeros to 4 digits.
+    #[arg(short, long, default_value = "%04d.webp")]
+    output: String,
+    /// Render each frame onto the full canvas, as a viewer would display it,
+    /// instead of extracting the frame data without re-encoding.
+    #[arg(long)]
+    composite: bool,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let data = fs::read(&args.input)?;
+    let decoder = Decoder::new(&data)?;
+    let mut compositor = Compositor::new(decoder.width(), decoder.height());
+    for (i, frame) in decoder.frames().iter().enumerate() {
+        let path = format_pattern(&args.output, i)?;
+        if let Some(parent) = PathBuf::from(&path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let image = if args.composite {
+            let mut buf = Vec::new();
+            compositor
+                .add_frame(frame)?
+                .write_with_encoder(WebPEncoder::new_lossless(&mut buf))?;
+            buf
+        } else {
+            frame.to_webp()
+        };
+        fs::write(&path, image)?;
+    }
+    Ok(())
+}
+
+/// Replaces the first `%d` or `%0Nd` in `pattern` by `n`.
+fn format_pattern(pattern: &str, n: usize) -> Result<String, Box<dyn Error>> {
+    let invalid = || format!("output pattern must contain %d: {pattern}");
+    let start = pattern.find('%').ok_or_else(invalid)?;
+    let rest = &pattern[start + 1..];
+    let end = rest.find('d').ok_or_else(invalid)?;
+    let spec = &rest[..end];
+    if !spec.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid().into());
+    }
+    let width = spec.parse().unwrap_or(0);
+    Ok(format!(
+        "{}{n:0width$}{}",
+        &pattern[..start],
+        &rest[end + 1..]
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::format_pattern;
+
+    #[test]
+    fn test_format_pattern() {
+        assert_eq!(format_pattern("out/%04d.webp", 7).unwrap(), "out/0007.webp");
+        assert_eq!(format_pattern("f%d.webp", 12).unwrap(), "f12.webp");
+        assert!(format_pattern("frame.webp", 0).is_err());
+        assert!(format_pattern("%x.webp", 0).is_err());
+    }
+}
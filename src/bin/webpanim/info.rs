@@ -0,0 +1,73 @@
+use std::{error::Error, fs, path::PathBuf};
+
+use webp_animator::{Decoder, LoopCount};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// An animated WebP file.
+    input: PathBuf,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let data = fs::read(&args.input)?;
+    let decoder = Decoder::new(&data)?;
+    println!("canvas: {}x{}", decoder.width(), decoder.height());
+    let [b, g, r, a] = decoder.background_bgra();
+    println!("background: #{r:02x}{g:02x}{b:02x}{a:02x}");
+    match decoder.loop_count() {
+        LoopCount::Infinite => println!("loop count: infinite"),
+        LoopCount::Finite(n) => println!("loop count: {n}"),
+    }
+    println!("alpha: {}", decoder.has_alpha());
+    for (name, metadata) in [
+        ("ICC profile", decoder.icc_profile()),
+        ("EXIF", decoder.exif_metadata()),
+        ("XMP", decoder.xmp_metadata()),
+    ] {
+        match metadata {
+            Some(m) => println!("{name}: {} bytes", m.len()),
+            None => println!("{name}: none"),
+        }
+    }
+    let total: u64 = decoder.frames().iter().map(|f| f.duration as u64).sum();
+    println!("frames: {} ({total} ms)", decoder.frames().len());
+    for (i, frame) in decoder.frames().iter().enumerate() {
+        let rect = frame.rect;
+        println!(
+            "  {i}: {}x{} at ({}, {}), {} ms, {}, dispose {:?}, blend {:?}, {} bytes",
+            rect.width,
+            rect.height,
+            rect.x,
+            rect.y,
+            frame.duration,
+            image_format(frame.data),
+            frame.dispose,
+            frame.blend,
+            frame.data.len()
+        );
+    }
+    Ok(())
+}
+
+/// Describes the chunks in the frame data, e.g. `ALPH+VP8`.
+fn image_format(data: &[u8]) -> String {
+    let mut names = Vec::new();
+    let mut rest = data;
+    while let Some((header, tail)) = rest.split_first_chunk::<8>() {
+        names.push(String::from_utf8_lossy(&header[..4]).trim_end().to_string());
+        let len = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+        rest = tail.get(len + (len & 1)..).unwrap_or_default();
+    }
+    names.join("+")
+}
+
+#[cfg(test)]
+mod test {
+    use super::image_format;
+
+    #[test]
+    fn test_image_format() {
+        assert_eq!(image_format(b"ALPH\x01\0\0\0\0\0VP8 \0\0\0\0"), "ALPH+VP8");
+        assert_eq!(image_format(b"VP8L\x10\0\0\0"), "VP8L");
+    }
+}
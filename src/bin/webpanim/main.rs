@@ -0,0 +1,59 @@
+//! Command line tool for working with animated WebP images.
+
+use std::{error::Error, process::ExitCode};
+
+use clap::{Parser, Subcommand};
+
+mod assemble;
+mod concat;
+mod diff;
+mod extract;
+mod info;
+mod optimize;
+mod trim;
+
+/// Tools for working with animated WebP images.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Assemble an animation from a sequence of still images.
+    Assemble(assemble::Args),
+    /// Join animations end to end.
+    Concat(concat::Args),
+    /// Compare two animations, and fail if they differ.
+    Diff(diff::Args),
+    /// Split an animation into still WebP images.
+    Extract(extract::Args),
+    /// Print information about an animated WebP file.
+    Info(info::Args),
+    /// Re-encode an animation to make it smaller.
+    Optimize(optimize::Args),
+    /// Keep only part of an animation.
+    Trim(trim::Args),
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result: Result<(), Box<dyn Error>> = match cli.command {
+        Command::Assemble(args) => assemble::run(args),
+        Command::Concat(args) => concat::run(args),
+        Command::Diff(args) => diff::run(args),
+        Command::Extract(args) => extract::run(args),
+        Command::Info(args) => info::run(args),
+        Command::Optimize(args) => optimize::run(args),
+        Command::Trim(args) => trim::run(args),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("webpanim: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::{
+    error::Error,
+    fs,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "lossy")]
+use webp_animator::DropPolicy;
+use webp_animator::{Compositor, Decoder, Optimizer, WebPAnimator};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// An animated WebP file.
+    input: PathBuf,
+    /// The output file.
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Re-encode lossily with this quality, between 0 and 100, instead of
+    /// losslessly.
+    #[cfg(feature = "lossy")]
+    #[arg(long)]
+    quality: Option<f32>,
+    /// Merge frames whose mean absolute difference from the previous frame,
+    /// between 0 and 255, is at most this.
+    #[arg(long, default_value_t = 0.0)]
+    tolerance: f32,
+    /// Encode a full-canvas frame at least this often.
+    #[arg(long)]
+    keyframe_interval: Option<NonZeroUsize>,
+    /// Re-encode lossily with the highest quality that fits in this many
+    /// bytes.
+    #[cfg(feature = "lossy")]
+    #[arg(long, conflicts_with = "quality")]
+    max_size: Option<usize>,
+    /// With --max-size, drop frames if the lowest quality doesn't fit.
+    #[cfg(feature = "lossy")]
+    #[arg(long, requires = "max_size")]
+    drop_frames: bool,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let data = fs::read(&args.input)?;
+    let decoder = Decoder::new(&data)?;
+    #[cfg(feature = "lossy")]
+    if let Some(max_size) = args.max_size {
+        let out = decoder
+            .fit_to_size(max_size, args.drop_frames.then_some(DropPolicy::Uniform))?
+            .into_bytes()?;
+        return write_output(&args.output, &data, &out);
+    }
+    let mut animator = WebPAnimator::builder()
+        .width(decoder.width())
+        .height(decoder.height())
+        .background(decoder.background_bgra())
+        .loop_count(decoder.loop_count())
+        .detect_alpha(true)
+        .icc_profile(decoder.icc_profile().unwrap_or_default().to_vec())
+        .exif_metadata(decoder.exif_metadata().unwrap_or_default().to_vec())
+        .xmp_metadata(decoder.xmp_metadata().unwrap_or_default().to_vec())
+        .build()?;
+    animator.set_merge_duplicate_frames(true);
+    let mut optimizer = Optimizer::new(animator);
+    optimizer.set_merge_tolerance(args.tolerance);
+    optimizer.set_keyframe_interval(args.keyframe_interval);
+    #[cfg(feature = "lossy")]
+    optimizer.set_lossy_quality(args.quality);
+    let mut compositor = Compositor::new(decoder.width(), decoder.height());
+    for frame in decoder.frames() {
+        optimizer.add_frame(compositor.add_frame(frame)?, frame.duration)?;
+    }
+    let out = optimizer.into_inner().into_bytes()?;
+    write_output(&args.output, &data, &out)
+}
+
+fn write_output(path: &Path, data: &[u8], out: &[u8]) -> Result<(), Box<dyn Error>> {
+    fs::write(path, out)?;
+    let percent = 100.0 * out.len() as f64 / data.len() as f64;
+    println!("{} -> {} bytes ({percent:.1}%)", data.len(), out.len());
+    Ok(())
+}
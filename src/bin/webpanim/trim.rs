@@ -0,0 +1,28 @@
+use std::{error::Error, fs, path::PathBuf};
+
+use webp_animator::Decoder;
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// An animated WebP file.
+    input: PathBuf,
+    /// The output file.
+    #[arg(short, long)]
+    output: PathBuf,
+    /// The start of the part to keep, in milliseconds.
+    #[arg(long, default_value_t = 0)]
+    start: u64,
+    /// The end of the part to keep, in milliseconds.  Defaults to the end of
+    /// the animation.
+    #[arg(long)]
+    end: Option<u64>,
+}
+
+pub fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let data = fs::read(&args.input)?;
+    let decoder = Decoder::new(&data)?;
+    let total: u64 = decoder.frames().iter().map(|f| f.duration as u64).sum();
+    let animator = decoder.trim(args.start, args.end.unwrap_or(total))?;
+    fs::write(&args.output, animator.into_bytes()?)?;
+    Ok(())
+}
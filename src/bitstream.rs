@@ -0,0 +1,89 @@
+//! Parsing of the headers of `VP8 ` and `VP8L` bitstreams.
+
+use crate::riff::{Chunk, Chunks};
+
+/// Returns the width and height of the image in a `VP8 ` or `VP8L` chunk,
+/// or `None` if the header is malformed.
+pub(crate) fn dimensions(chunk: &Chunk) -> Option<(u32, u32)> {
+    let d = chunk.data;
+    match &chunk.fourcc {
+        b"VP8 " => {
+            // A key frame tag followed by the start code.
+            if d.len() < 10 || d[0] & 1 != 0 || d[3..6] != [0x9d, 0x01, 0x2a] {
+                return None;
+            }
+            let width = u16::from_le_bytes([d[6], d[7]]) & 0x3fff;
+            let height = u16::from_le_bytes([d[8], d[9]]) & 0x3fff;
+            Some((width as u32, height as u32))
+        }
+        b"VP8L" => {
+            if d.len() < 5 || d[0] != 0x2f {
+                return None;
+            }
+            let bits = u32::from_le_bytes(d[1..5].try_into().unwrap());
+            if bits >> 29 != 0 {
+                return None;
+            }
+            Some(((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1))
+        }
+        _ => None,
+    }
+}
+
+/// Whether frame data, consisting of a `VP8 ` or `VP8L` chunk possibly
+/// preceded by an `ALPH` chunk, has transparency.  For `VP8L`, this is the
+/// `alpha_is_used` hint in the header.
+pub(crate) fn has_alpha(data: &[u8]) -> bool {
+    Chunks::new(data)
+        .flatten()
+        .any(|chunk| match &chunk.fourcc {
+            b"ALPH" => true,
+            b"VP8L" => chunk.data.len() >= 5 && chunk.data[4] & 0x10 != 0,
+            _ => false,
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgba, RgbaImage};
+
+    use crate::{
+        riff::{Chunks, still_image_chunks},
+        test_util::lossless_webp,
+    };
+
+    use super::{dimensions, has_alpha};
+
+    fn image_chunk_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+        let chunk = Chunks::new(data)
+            .map(Result::unwrap)
+            .find(|c| &c.fourcc != b"ALPH")
+            .unwrap();
+        dimensions(&chunk)
+    }
+
+    #[test]
+    fn test_dimensions() {
+        let buf = lossless_webp(&RgbaImage::from_pixel(37, 12, Rgba([0, 255, 0, 128])));
+        assert_eq!(image_chunk_dimensions(&buf[12..]), Some((37, 12)));
+        assert!(has_alpha(&buf[12..]));
+        let config = webp_animation::EncoderOptions {
+            encoding_config: Some(webp_animation::EncodingConfig::new_lossy(75.0)),
+            ..Default::default()
+        };
+        let mut encoder = webp_animation::Encoder::new_with_options((21, 40), config).unwrap();
+        encoder
+            .add_frame(&[9, 8, 7, 255].repeat(21 * 40), 0)
+            .unwrap();
+        let data = encoder.finalize(100).unwrap();
+        // A single frame animation is written as a still image.
+        let frame = still_image_chunks(&data).unwrap();
+        assert_eq!(&frame[..4], b"VP8 ");
+        assert_eq!(image_chunk_dimensions(&frame), Some((21, 40)));
+        assert!(!has_alpha(&frame));
+        assert_eq!(
+            image_chunk_dimensions(b"VP8L\x05\0\0\0\x2e\0\0\0\0\0"),
+            None
+        );
+    }
+}
@@ -0,0 +1,263 @@
+use crate::{
+    CancellationToken, Decoder, EncodingError, LoopCount, MetadataPlacement, OddOffsets, Params,
+    Validation, WebPAnimator, ZeroDurationPolicy,
+};
+
+/// Builder for [`WebPAnimator`].  The width and height must be set unless
+/// [`auto_canvas`](Self::auto_canvas) is enabled; all other settings are
+/// optional.
+#[derive(Clone, Debug)]
+pub struct WebPAnimatorBuilder {
+    params: Params,
+    icc_profile: Vec<u8>,
+    exif_metadata: Vec<u8>,
+    xmp_metadata: Vec<u8>,
+    merge_duplicates: bool,
+    detect_alpha: bool,
+    auto_canvas: bool,
+    odd_offsets: OddOffsets,
+    zero_duration: ZeroDurationPolicy,
+    metadata_placement: MetadataPlacement,
+    #[cfg(feature = "image")]
+    trim_transparent: bool,
+    #[cfg(feature = "color")]
+    working_space: Option<crate::WorkingSpace>,
+    validation: Validation,
+    cancel: Option<CancellationToken>,
+}
+
+impl Default for WebPAnimatorBuilder {
+    fn default() -> Self {
+        Self {
+            params: Params {
+                width: 0,
+                height: 0,
+                background_bgra: [0; 4],
+                loop_count: LoopCount::Infinite,
+                has_alpha: false,
+            },
+            icc_profile: Vec::new(),
+            exif_metadata: Vec::new(),
+            xmp_metadata: Vec::new(),
+            merge_duplicates: false,
+            detect_alpha: false,
+            auto_canvas: false,
+            odd_offsets: OddOffsets::Reject,
+            zero_duration: ZeroDurationPolicy::Allow,
+            metadata_placement: MetadataPlacement::AfterFrames,
+            #[cfg(feature = "image")]
+            trim_transparent: false,
+            #[cfg(feature = "color")]
+            working_space: None,
+            validation: Validation::Lenient,
+            cancel: None,
+        }
+    }
+}
+
+impl WebPAnimatorBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The canvas width.
+    pub fn width(mut self, width: u32) -> Self {
+        self.params.width = width;
+        self
+    }
+
+    /// The canvas height.
+    pub fn height(mut self, height: u32) -> Self {
+        self.params.height = height;
+        self
+    }
+
+    /// The background color, in BGRA order.  Defaults to transparent black.
+    pub fn background(mut self, bgra: [u8; 4]) -> Self {
+        self.params.background_bgra = bgra;
+        self
+    }
+
+    /// The number of times to loop the animation.  For compatibility with
+    /// the file format, an integer value of 0 means forever.
+    pub fn loop_count(mut self, loop_count: impl Into<LoopCount>) -> Self {
+        self.params.loop_count = loop_count.into();
+        self
+    }
+
+    /// Loop the animation forever.  This is the default.
+    pub fn loop_forever(self) -> Self {
+        self.loop_count(LoopCount::Infinite)
+    }
+
+    /// Whether the image has transparency.  Defaults to `false`.
+    pub fn has_alpha(mut self, has_alpha: bool) -> Self {
+        self.params.has_alpha = has_alpha;
+        self
+    }
+
+    /// See [`WebPAnimator::set_detect_alpha`].
+    pub fn detect_alpha(mut self, detect: bool) -> Self {
+        self.detect_alpha = detect;
+        self
+    }
+
+    /// See [`WebPAnimator::set_auto_canvas`].  A width or height that
+    /// isn't set starts at 1.
+    pub fn auto_canvas(mut self, auto: bool) -> Self {
+        self.auto_canvas = auto;
+        self
+    }
+
+    /// See [`WebPAnimator::set_odd_offsets`].
+    pub fn odd_offsets(mut self, odd_offsets: OddOffsets) -> Self {
+        self.odd_offsets = odd_offsets;
+        self
+    }
+
+    /// See [`WebPAnimator::set_zero_duration_policy`].
+    pub fn zero_duration_policy(mut self, policy: ZeroDurationPolicy) -> Self {
+        self.zero_duration = policy;
+        self
+    }
+
+    /// See [`WebPAnimator::set_metadata_placement`].
+    pub fn metadata_placement(mut self, placement: MetadataPlacement) -> Self {
+        self.metadata_placement = placement;
+        self
+    }
+
+    /// See [`WebPAnimator::set_cancellation_token`].
+    pub fn cancellation_token(mut self, token: CancellationToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// See [`WebPAnimator::set_trim_transparent`].
+    #[cfg(feature = "image")]
+    pub fn trim_transparent(mut self, trim: bool) -> Self {
+        self.trim_transparent = trim;
+        self
+    }
+
+    /// See [`WebPAnimator::set_working_space`].
+    #[cfg(feature = "color")]
+    pub fn working_space(mut self, space: crate::WorkingSpace) -> Self {
+        self.working_space = Some(space);
+        self
+    }
+
+    /// See [`WebPAnimator::set_icc_profile`].
+    pub fn icc_profile(mut self, icc_profile: Vec<u8>) -> Self {
+        self.icc_profile = icc_profile;
+        self
+    }
+
+    /// See [`WebPAnimator::set_exif_metadata`].
+    pub fn exif_metadata(mut self, exif_metadata: Vec<u8>) -> Self {
+        self.exif_metadata = exif_metadata;
+        self
+    }
+
+    /// See [`WebPAnimator::set_xmp_metadata`].
+    pub fn xmp_metadata(mut self, xmp_metadata: Vec<u8>) -> Self {
+        self.xmp_metadata = xmp_metadata;
+        self
+    }
+
+    /// See [`WebPAnimator::set_merge_duplicate_frames`].
+    pub fn merge_duplicate_frames(mut self, merge: bool) -> Self {
+        self.merge_duplicates = merge;
+        self
+    }
+
+    /// See [`WebPAnimator::set_validation`].
+    pub fn validation(mut self, validation: Validation) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    pub fn build(mut self) -> Result<WebPAnimator, EncodingError> {
+        if self.auto_canvas {
+            self.params.width = self.params.width.max(1);
+            self.params.height = self.params.height.max(1);
+        }
+        let mut animator = WebPAnimator::new(self.params)?;
+        animator.set_icc_profile(self.icc_profile)?;
+        animator.set_exif_metadata(self.exif_metadata)?;
+        animator.set_xmp_metadata(self.xmp_metadata)?;
+        animator.set_merge_duplicate_frames(self.merge_duplicates);
+        animator.set_detect_alpha(self.detect_alpha);
+        animator.set_auto_canvas(self.auto_canvas);
+        animator.set_odd_offsets(self.odd_offsets);
+        animator.set_zero_duration_policy(self.zero_duration);
+        animator.set_metadata_placement(self.metadata_placement);
+        #[cfg(feature = "image")]
+        animator.set_trim_transparent(self.trim_transparent);
+        #[cfg(feature = "color")]
+        animator.set_working_space(self.working_space);
+        animator.set_validation(self.validation);
+        animator.set_cancellation_token(self.cancel);
+        Ok(animator)
+    }
+}
+
+impl WebPAnimator {
+    pub fn builder() -> WebPAnimatorBuilder {
+        WebPAnimatorBuilder::new()
+    }
+}
+
+impl Decoder<'_> {
+    /// A builder with the same header, metadata and cancellation token as
+    /// this animation.
+    pub(crate) fn animator_builder(&self) -> WebPAnimatorBuilder {
+        WebPAnimatorBuilder {
+            cancel: self.cancellation_token().cloned(),
+            ..WebPAnimatorBuilder::new()
+        }
+        .width(self.width())
+        .height(self.height())
+        .background(self.background_bgra())
+        .loop_count(self.loop_count())
+        .has_alpha(self.has_alpha())
+        .icc_profile(self.icc_profile().unwrap_or_default().to_vec())
+        .exif_metadata(self.exif_metadata().unwrap_or_default().to_vec())
+        .xmp_metadata(self.xmp_metadata().unwrap_or_default().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{EncodingError, LoopCount, Params, Warning, WebPAnimator};
+
+    #[test]
+    fn test_builder() {
+        let mut built = WebPAnimator::builder()
+            .width(64)
+            .height(32)
+            .loop_count(5)
+            .background([1, 2, 3, 4])
+            .has_alpha(true)
+            .build()
+            .unwrap();
+        let params = Params {
+            width: 64,
+            height: 32,
+            background_bgra: [1, 2, 3, 4],
+            loop_count: LoopCount::from(5),
+            has_alpha: true,
+        };
+        let mut expected = Vec::new();
+        let mut animator = WebPAnimator::new(params).unwrap();
+        animator.write(&mut expected).unwrap();
+        let mut actual = Vec::new();
+        built.write(&mut actual).unwrap();
+        assert_eq!(actual, expected);
+        assert_eq!(built.warnings(), [Warning::EmptyAnimation]);
+        assert!(matches!(
+            WebPAnimator::builder().width(64).build(),
+            Err(EncodingError::InvalidDimensions)
+        ));
+    }
+}
@@ -0,0 +1,97 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+/// Checked between frames by long-running operations, which stop with
+/// [`EncodingError::Cancelled`](crate::EncodingError::Cancelled) once it has
+/// been cancelled.  Clones share the same state.
+#[derive(Clone)]
+pub struct CancellationToken(Arc<dyn Fn() -> bool + Send + Sync>);
+
+impl CancellationToken {
+    /// A token that is cancelled once `flag` is set.
+    pub fn from_flag(flag: Arc<AtomicBool>) -> Self {
+        Self::from_fn(move || flag.load(Ordering::Relaxed))
+    }
+
+    /// A token that is cancelled whenever `cancelled` returns `true`, for
+    /// example once a deadline has passed.
+    pub fn from_fn<F: Fn() -> bool + Send + Sync + 'static>(cancelled: F) -> Self {
+        Self(Arc::new(cancelled))
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        (self.0)()
+    }
+}
+
+impl From<Arc<AtomicBool>> for CancellationToken {
+    fn from(flag: Arc<AtomicBool>) -> Self {
+        Self::from_flag(flag)
+    }
+}
+
+impl std::fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// Fails with [`EncodingError::Cancelled`](crate::EncodingError::Cancelled)
+/// if `token` has been cancelled.
+pub(crate) fn check(token: &Option<CancellationToken>) -> Result<(), crate::EncodingError> {
+    match token {
+        Some(token) if token.is_cancelled() => Err(crate::EncodingError::Cancelled),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::Cursor,
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+    };
+
+    use image::{Rgb, RgbImage};
+
+    use crate::{
+        CancellationToken, EncodingError, WebPAnimator, WebPStreamWriter,
+        test_util::{lossless_webp, params},
+    };
+
+    #[test]
+    fn test_cancel() {
+        let params = params(8, 8);
+        let buf = lossless_webp(&RgbImage::from_pixel(8, 8, Rgb([255, 0, 0])));
+        let flag = Arc::new(AtomicBool::new(false));
+        let mut animator = WebPAnimator::builder()
+            .width(8)
+            .height(8)
+            .cancellation_token(flag.clone().into())
+            .build()
+            .unwrap();
+        animator.add_webp_image(&buf, None, 100).unwrap();
+        flag.store(true, Ordering::Relaxed);
+        assert!(matches!(
+            animator.add_webp_image(&buf, None, 100),
+            Err(EncodingError::Cancelled)
+        ));
+        assert_eq!(animator.frame_count(), 1);
+        // Frames that were added can still be written.
+        animator.into_bytes().unwrap();
+
+        let mut stream = WebPStreamWriter::new(Cursor::new(Vec::new()), params).unwrap();
+        stream.set_cancellation_token(Some(CancellationToken::from_fn(|| true)));
+        assert!(matches!(
+            stream.add_webp_image(&buf, None, 100),
+            Err(EncodingError::Cancelled)
+        ));
+    }
+}
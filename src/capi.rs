@@ -0,0 +1,462 @@
+//! A C API, for using the muxer from C and C++ without libwebp's mux
+//! module.  Build with `cargo build --release --features capi`, which makes
+//! a shared and a static library, and include `include/webp_animator.h`.
+//! The header is generated with
+//! `cbindgen --config cbindgen.toml --output include/webp_animator.h src/capi.rs`.
+//!
+//! ```c
+//! WebPAnimatorParams params = {
+//!     .width = 64, .height = 64, .background_bgra = {0, 0, 0, 0}, .loop_count = 0,
+//! };
+//! WebPAnimator *animator;
+//! if (webp_animator_new(params, &animator) != WebPAnimatorStatus_Ok) { ... }
+//! webp_animator_add_frame(animator, frame_data, frame_len, 100);
+//! uint8_t *data;
+//! size_t len;
+//! WebPAnimatorStatus status = webp_animator_write_to_buffer(animator, &data, &len);
+//! webp_animator_free(animator);
+//! ...
+//! webp_animator_free_buffer(data, len);
+//! ```
+//!
+//! Functions return [`WebPAnimatorStatus::Ok`] on success, and leave their
+//! outputs alone on failure.
+
+use std::ffi::{CStr, c_char, c_int};
+
+use crate::{EncodingError, FrameOptions, WebPAnimator};
+
+/// The result of a call.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WebPAnimatorStatus {
+    Ok = 0,
+    /// A pointer was null, or a path wasn't UTF-8.
+    InvalidArgument,
+    InvalidDimensions,
+    InvalidDuration,
+    NoFrames,
+    /// Frame data isn't a WebP image.
+    UnrecognizedImage,
+    InvalidMetadata,
+    /// The file would exceed the 4 GiB size limit of the RIFF format.
+    TooLarge,
+    /// Reading or writing a file failed.
+    Io,
+    /// Any other error.
+    Failed,
+}
+
+impl From<&EncodingError> for WebPAnimatorStatus {
+    fn from(error: &EncodingError) -> Self {
+        match error {
+            EncodingError::InvalidDimensions | EncodingError::FrameSizeMismatch => {
+                Self::InvalidDimensions
+            }
+            EncodingError::InvalidDuration => Self::InvalidDuration,
+            EncodingError::NoFrames => Self::NoFrames,
+            EncodingError::UnrecognizedImage | EncodingError::Decoding(_) => {
+                Self::UnrecognizedImage
+            }
+            EncodingError::InvalidMetadata => Self::InvalidMetadata,
+            EncodingError::TooLarge => Self::TooLarge,
+            EncodingError::Io(_) => Self::Io,
+            EncodingError::InFile(_, e) => Self::from(&**e),
+            _ => Self::Failed,
+        }
+    }
+}
+
+fn status(result: Result<(), EncodingError>) -> WebPAnimatorStatus {
+    match result {
+        Ok(()) => WebPAnimatorStatus::Ok,
+        Err(e) => {
+            trace_event!(debug, "C API call failed: {e}");
+            WebPAnimatorStatus::from(&e)
+        }
+    }
+}
+
+/// The canvas settings, as in [`Params`](crate::Params).
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct WebPAnimatorParams {
+    pub width: u32,
+    pub height: u32,
+    pub background_bgra: [u8; 4],
+    /// The number of times to play the animation, where 0 means forever.
+    pub loop_count: u16,
+    /// Whether the frames have alpha.  If false, it is detected from the
+    /// frames.
+    pub has_alpha: bool,
+}
+
+/// Returns the bytes at `data`, or `None` if `data` is null and `len` isn't
+/// 0.
+///
+/// # Safety
+///
+/// `data` must be null or point to `len` readable bytes that outlive `'a`.
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    match (data.is_null(), len) {
+        (_, 0) => Some(&[]),
+        (true, _) => None,
+        // SAFETY: guaranteed by the caller.
+        (false, _) => Some(unsafe { std::slice::from_raw_parts(data, len) }),
+    }
+}
+
+/// Creates an animator and stores it in `*out`.  Free it with
+/// [`webp_animator_free`].
+///
+/// # Safety
+///
+/// `out` must be null or valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn webp_animator_new(
+    params: WebPAnimatorParams,
+    out: *mut *mut WebPAnimator,
+) -> WebPAnimatorStatus {
+    if out.is_null() {
+        return WebPAnimatorStatus::InvalidArgument;
+    }
+    let animator = WebPAnimator::builder()
+        .width(params.width)
+        .height(params.height)
+        .background(params.background_bgra)
+        .loop_count(params.loop_count)
+        .has_alpha(params.has_alpha)
+        .detect_alpha(!params.has_alpha)
+        .build();
+    match animator {
+        Ok(animator) => {
+            // SAFETY: guaranteed by the caller.
+            unsafe { *out = Box::into_raw(Box::new(animator)) };
+            WebPAnimatorStatus::Ok
+        }
+        Err(e) => status(Err(e)),
+    }
+}
+
+/// Frees an animator.  Does nothing if `animator` is null.
+///
+/// # Safety
+///
+/// `animator` must be null or come from [`webp_animator_new`], and must not
+/// be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn webp_animator_free(animator: *mut WebPAnimator) {
+    if !animator.is_null() {
+        // SAFETY: guaranteed by the caller.
+        drop(unsafe { Box::from_raw(animator) });
+    }
+}
+
+/// Runs `f` on the animator, or fails if it is null.
+///
+/// # Safety
+///
+/// `animator` must be null or come from [`webp_animator_new`].
+unsafe fn with_animator(
+    animator: *mut WebPAnimator,
+    f: impl FnOnce(&mut WebPAnimator) -> Result<(), EncodingError>,
+) -> WebPAnimatorStatus {
+    // SAFETY: guaranteed by the caller.
+    match unsafe { animator.as_mut() } {
+        Some(animator) => status(f(animator)),
+        None => WebPAnimatorStatus::InvalidArgument,
+    }
+}
+
+/// Adds an encoded WebP image of `len` bytes as a frame shown for
+/// `duration` milliseconds.  See [`WebPAnimator::add_frame`].
+///
+/// # Safety
+///
+/// `animator` must come from [`webp_animator_new`], and `data` must point
+/// to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn webp_animator_add_frame(
+    animator: *mut WebPAnimator,
+    data: *const u8,
+    len: usize,
+    duration: u32,
+) -> WebPAnimatorStatus {
+    // SAFETY: guaranteed by the caller.
+    let Some(data) = (unsafe { bytes(data, len) }) else {
+        return WebPAnimatorStatus::InvalidArgument;
+    };
+    // SAFETY: guaranteed by the caller.
+    unsafe {
+        with_animator(animator, |animator| {
+            animator.add_frame(data, FrameOptions::with_duration(duration))
+        })
+    }
+}
+
+/// Encodes `width * height` RGBA pixels, row by row with no padding,
+/// losslessly and adds them as a frame shown for `duration` milliseconds.
+/// See [`WebPAnimator::add_rgba_frame`].
+///
+/// # Safety
+///
+/// `animator` must come from [`webp_animator_new`], and `pixels` must point
+/// to `width * height * 4` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn webp_animator_add_rgba_frame(
+    animator: *mut WebPAnimator,
+    pixels: *const u8,
+    width: u32,
+    height: u32,
+    duration: u32,
+) -> WebPAnimatorStatus {
+    let len = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|n| n.checked_mul(4));
+    let Some(len) = len else {
+        return WebPAnimatorStatus::InvalidDimensions;
+    };
+    // SAFETY: guaranteed by the caller.
+    let Some(pixels) = (unsafe { bytes(pixels, len) }) else {
+        return WebPAnimatorStatus::InvalidArgument;
+    };
+    // SAFETY: guaranteed by the caller.
+    unsafe {
+        with_animator(animator, |animator| {
+            let options = FrameOptions::with_duration(duration);
+            animator.add_rgba_frame(pixels, width, height, options)
+        })
+    }
+}
+
+/// Sets metadata with `setter`.
+///
+/// # Safety
+///
+/// As for [`webp_animator_set_icc_profile`].
+unsafe fn set_metadata(
+    animator: *mut WebPAnimator,
+    data: *const u8,
+    len: usize,
+    setter: fn(&mut WebPAnimator, Vec<u8>) -> Result<(), EncodingError>,
+) -> WebPAnimatorStatus {
+    // SAFETY: guaranteed by the caller.
+    let Some(data) = (unsafe { bytes(data, len) }) else {
+        return WebPAnimatorStatus::InvalidArgument;
+    };
+    // SAFETY: guaranteed by the caller.
+    unsafe { with_animator(animator, |animator| setter(animator, data.to_vec())) }
+}
+
+/// Sets the ICC profile.  See [`WebPAnimator::set_icc_profile`].
+///
+/// # Safety
+///
+/// `animator` must come from [`webp_animator_new`], and `data` must point
+/// to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn webp_animator_set_icc_profile(
+    animator: *mut WebPAnimator,
+    data: *const u8,
+    len: usize,
+) -> WebPAnimatorStatus {
+    // SAFETY: guaranteed by the caller.
+    unsafe { set_metadata(animator, data, len, WebPAnimator::set_icc_profile) }
+}
+
+/// Sets the EXIF metadata.  See [`WebPAnimator::set_exif_metadata`].
+///
+/// # Safety
+///
+/// As for [`webp_animator_set_icc_profile`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn webp_animator_set_exif_metadata(
+    animator: *mut WebPAnimator,
+    data: *const u8,
+    len: usize,
+) -> WebPAnimatorStatus {
+    // SAFETY: guaranteed by the caller.
+    unsafe { set_metadata(animator, data, len, WebPAnimator::set_exif_metadata) }
+}
+
+/// Sets the XMP metadata.  See [`WebPAnimator::set_xmp_metadata`].
+///
+/// # Safety
+///
+/// As for [`webp_animator_set_icc_profile`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn webp_animator_set_xmp_metadata(
+    animator: *mut WebPAnimator,
+    data: *const u8,
+    len: usize,
+) -> WebPAnimatorStatus {
+    // SAFETY: guaranteed by the caller.
+    unsafe { set_metadata(animator, data, len, WebPAnimator::set_xmp_metadata) }
+}
+
+/// Writes the animation to a new buffer, and stores it and its length in
+/// `*out_data` and `*out_len`.  Free it with [`webp_animator_free_buffer`].
+/// The animator can still be used afterwards.
+///
+/// # Safety
+///
+/// `animator` must come from [`webp_animator_new`], and `out_data` and
+/// `out_len` must be valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn webp_animator_write_to_buffer(
+    animator: *mut WebPAnimator,
+    out_data: *mut *mut u8,
+    out_len: *mut usize,
+) -> WebPAnimatorStatus {
+    if out_data.is_null() || out_len.is_null() {
+        return WebPAnimatorStatus::InvalidArgument;
+    }
+    // SAFETY: guaranteed by the caller.
+    unsafe {
+        with_animator(animator, |animator| {
+            let mut buf = Vec::new();
+            animator.write(&mut buf)?;
+            let buf = buf.into_boxed_slice();
+            *out_len = buf.len();
+            *out_data = Box::into_raw(buf).cast();
+            Ok(())
+        })
+    }
+}
+
+/// Frees a buffer from [`webp_animator_write_to_buffer`].  Does nothing if
+/// `data` is null.
+///
+/// # Safety
+///
+/// `data` and `len` must come from the same call to
+/// [`webp_animator_write_to_buffer`], and `data` must not be used
+/// afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn webp_animator_free_buffer(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        // SAFETY: guaranteed by the caller.
+        drop(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(data, len)) });
+    }
+}
+
+/// Writes the animation to the file at `path`, a UTF-8 string, replacing
+/// it if it exists.  See [`WebPAnimator::write_to_file`].
+///
+/// # Safety
+///
+/// `animator` must come from [`webp_animator_new`], and `path` must be a
+/// null-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn webp_animator_write_to_file(
+    animator: *mut WebPAnimator,
+    path: *const c_char,
+) -> WebPAnimatorStatus {
+    if path.is_null() {
+        return WebPAnimatorStatus::InvalidArgument;
+    }
+    // SAFETY: guaranteed by the caller.
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else {
+        return WebPAnimatorStatus::InvalidArgument;
+    };
+    // SAFETY: guaranteed by the caller.
+    unsafe { with_animator(animator, |animator| animator.write_to_file(path)) }
+}
+
+/// Returns a static, null-terminated description of `status`, which is a
+/// [`WebPAnimatorStatus`].  It is taken as an `int` so that other values are
+/// safe to pass; they are described as an unknown status.
+#[unsafe(no_mangle)]
+pub extern "C" fn webp_animator_status_message(status: c_int) -> *const c_char {
+    use WebPAnimatorStatus as S;
+
+    let message: &CStr = match [
+        S::Ok,
+        S::InvalidArgument,
+        S::InvalidDimensions,
+        S::InvalidDuration,
+        S::NoFrames,
+        S::UnrecognizedImage,
+        S::InvalidMetadata,
+        S::TooLarge,
+        S::Io,
+        S::Failed,
+    ]
+    .into_iter()
+    .find(|&s| s as c_int == status)
+    {
+        Some(S::Ok) => c"ok",
+        Some(S::InvalidArgument) => c"invalid argument",
+        Some(S::InvalidDimensions) => c"invalid dimensions",
+        Some(S::InvalidDuration) => c"invalid duration",
+        Some(S::NoFrames) => c"no frames",
+        Some(S::UnrecognizedImage) => c"unrecognized image",
+        Some(S::InvalidMetadata) => c"invalid metadata",
+        Some(S::TooLarge) => c"file too large",
+        Some(S::Io) => c"I/O error",
+        Some(S::Failed) => c"failed",
+        None => c"unknown status",
+    };
+    message.as_ptr()
+}
+
+#[cfg(test)]
+mod test {
+    use std::ptr;
+
+    use super::*;
+    use crate::LoopCount;
+
+    #[test]
+    fn test_capi() {
+        let params = WebPAnimatorParams {
+            width: 2,
+            height: 2,
+            background_bgra: [0; 4],
+            loop_count: 2,
+            has_alpha: false,
+        };
+        let mut animator = ptr::null_mut();
+        unsafe {
+            assert_eq!(
+                webp_animator_new(params, &mut animator),
+                WebPAnimatorStatus::Ok
+            );
+            let red = [255, 0, 0, 255].repeat(4);
+            assert_eq!(
+                webp_animator_add_rgba_frame(animator, red.as_ptr(), 2, 2, 100),
+                WebPAnimatorStatus::Ok
+            );
+            assert_eq!(
+                webp_animator_add_rgba_frame(animator, ptr::null(), 2, 2, 100),
+                WebPAnimatorStatus::InvalidArgument
+            );
+            assert_eq!(
+                webp_animator_add_frame(animator, red.as_ptr(), red.len(), 100),
+                WebPAnimatorStatus::UnrecognizedImage
+            );
+            let xmp = b"<x:xmpmeta/>";
+            assert_eq!(
+                webp_animator_set_xmp_metadata(animator, xmp.as_ptr(), xmp.len()),
+                WebPAnimatorStatus::Ok
+            );
+            let (mut data, mut len) = (ptr::null_mut(), 0);
+            assert_eq!(
+                webp_animator_write_to_buffer(animator, &mut data, &mut len),
+                WebPAnimatorStatus::Ok
+            );
+            webp_animator_free(animator);
+            let decoder = crate::Decoder::new(std::slice::from_raw_parts(data, len)).unwrap();
+            assert_eq!(decoder.frames().len(), 1);
+            assert_eq!(decoder.loop_count(), LoopCount::from(2));
+            assert_eq!(decoder.xmp_metadata(), Some(&xmp[..]));
+            webp_animator_free_buffer(data, len);
+            let message = |status| CStr::from_ptr(webp_animator_status_message(status));
+            assert_eq!(
+                message(WebPAnimatorStatus::UnrecognizedImage as c_int),
+                c"unrecognized image"
+            );
+            assert_eq!(message(-1), c"unknown status");
+        }
+    }
+}
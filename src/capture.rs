@@ -0,0 +1,189 @@
+use std::{
+    collections::VecDeque,
+    io::{Seek, Write},
+    thread::{self, JoinHandle},
+};
+
+use image::{DynamicImage, RgbaImage};
+
+use crate::{EncodingError, FrameOptions, MAX_DURATION_MS, WebPStreamWriter};
+
+/// A frame that is being encoded, and its timestamp.
+struct Pending {
+    pts_ms: u64,
+    encoding: JoinHandle<Result<Vec<u8>, EncodingError>>,
+}
+
+/// Records frames as they are captured, such as from a screen or a webcam,
+/// to a [`WebPStreamWriter`].
+///
+/// Frames are pushed with the time they were captured and encoded on
+/// background threads.  Each one is written to the stream as soon as it has
+/// been encoded and the next frame, which sets its duration, has arrived.
+/// At most `max_in_flight` frames are kept in memory: pushing another waits
+/// for the oldest one to be written, so memory stays bounded however long
+/// the recording, and a frame is written at most `max_in_flight` frames
+/// after it is captured.
+pub struct CaptureWriter<W: Write + Seek> {
+    writer: WebPStreamWriter<W>,
+    max_in_flight: usize,
+    #[cfg(feature = "lossy")]
+    quality: Option<f32>,
+    pending: VecDeque<Pending>,
+    /// The timestamp of the last frame pushed.
+    last_pts_ms: Option<u64>,
+}
+
+impl<W: Write + Seek> CaptureWriter<W> {
+    /// Creates a recorder that writes to `writer`, keeping at most
+    /// `max_in_flight` frames in memory.  A `max_in_flight` of 0 is treated
+    /// as 1.  Frames are encoded losslessly.
+    pub fn new(writer: WebPStreamWriter<W>, max_in_flight: usize) -> Self {
+        Self {
+            writer,
+            max_in_flight: max_in_flight.max(1),
+            #[cfg(feature = "lossy")]
+            quality: None,
+            pending: VecDeque::new(),
+            last_pts_ms: None,
+        }
+    }
+
+    /// Encode frames lossily at the given quality, between 0 and 100, or
+    /// losslessly if `None`.  Defaults to `None`.  Only affects frames pushed
+    /// afterwards.
+    #[cfg(feature = "lossy")]
+    pub fn set_quality(&mut self, quality: Option<f32>) -> Result<(), EncodingError> {
+        if quality.is_some_and(|quality| !(0.0..=100.0).contains(&quality)) {
+            return Err(EncodingError::InvalidQuality);
+        }
+        self.quality = quality;
+        Ok(())
+    }
+
+    /// The number of frames pushed but not yet written.
+    pub fn in_flight(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Adds a frame that was captured `pts_ms` milliseconds into the
+    /// recording.  The first timestamp is the start of the animation.  It
+    /// must be the size of the canvas.
+    ///
+    /// Fails with [`EncodingError::InvalidDimensions`] if the image isn't the
+    /// size of the canvas, with [`EncodingError::InvalidDuration`] if
+    /// `pts_ms` is earlier than the previous timestamp or the gap is too long
+    /// for a frame, and with any error from encoding or writing an earlier
+    /// frame.  The frame is dropped if it is rejected.
+    pub fn push(&mut self, pts_ms: u64, image: RgbaImage) -> Result<(), EncodingError> {
+        let header = &self.writer.header;
+        if image.dimensions() != (header.width, header.height) {
+            return Err(EncodingError::InvalidDimensions);
+        }
+        self.check_gap(pts_ms)?;
+        self.last_pts_ms = Some(pts_ms);
+        #[cfg(feature = "lossy")]
+        let quality = self.quality;
+        let encoding = thread::spawn(move || {
+            let image = DynamicImage::from(image);
+            #[cfg(feature = "lossy")]
+            if let Some(quality) = quality {
+                return crate::lossy::encode_lossy(&image, quality, crate::lossy::DEFAULT_METHOD);
+            }
+            crate::encode::encode_lossless(&image)
+        });
+        self.pending.push_back(Pending { pts_ms, encoding });
+        while self.pending.len() > self.max_in_flight
+            || (self.pending.len() > 1 && self.pending[0].encoding.is_finished())
+        {
+            self.write_next(None)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that a frame starting at the last timestamp can last until
+    /// `pts_ms`.
+    fn check_gap(&self, pts_ms: u64) -> Result<(), EncodingError> {
+        match self.last_pts_ms {
+            Some(last) if pts_ms < last || pts_ms - last > MAX_DURATION_MS as u64 => {
+                Err(EncodingError::InvalidDuration)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Writes the oldest pending frame.  Its duration lasts until the next
+    /// frame, or until `end_ms` if it is the last one.
+    fn write_next(&mut self, end_ms: Option<u64>) -> Result<(), EncodingError> {
+        let frame = self.pending.pop_front().unwrap();
+        let end = self
+            .pending
+            .front()
+            .map(|next| next.pts_ms)
+            .or(end_ms)
+            .unwrap();
+        // Durations that are too long were rejected by `push`, and the end
+        // time is checked by `finish`.
+        let duration = (end - frame.pts_ms) as u32;
+        let data = frame
+            .encoding
+            .join()
+            .unwrap_or_else(|e| std::panic::resume_unwind(e))?;
+        self.writer
+            .add_frame(&data, FrameOptions::with_duration(duration))
+    }
+
+    /// Writes the remaining frames and finishes the stream.  The recording
+    /// ends `end_ms` milliseconds in, which sets the duration of the last
+    /// frame.
+    ///
+    /// Fails with [`EncodingError::InvalidDuration`] if `end_ms` is earlier
+    /// than the last timestamp or too long after it.
+    pub fn finish(mut self, end_ms: u64) -> Result<W, EncodingError> {
+        self.check_gap(end_ms)?;
+        while !self.pending.is_empty() {
+            self.write_next(Some(end_ms))?;
+        }
+        self.writer.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use image::{Rgba, RgbaImage};
+
+    use crate::{CaptureWriter, Decoder, EncodingError, WebPStreamWriter, test_util::params};
+
+    #[test]
+    fn test_capture_writer() {
+        let params = params(8, 8);
+        let stream = WebPStreamWriter::new(Cursor::new(Vec::new()), params).unwrap();
+        let mut capture = CaptureWriter::new(stream, 2);
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        for (i, (pts_ms, color)) in [0, 40, 73].into_iter().zip(colors).enumerate() {
+            capture
+                .push(pts_ms, RgbaImage::from_pixel(8, 8, Rgba(color)))
+                .unwrap();
+            assert!(capture.in_flight() <= 2.min(i + 1));
+        }
+        assert!(matches!(
+            capture.push(50, RgbaImage::new(8, 8)),
+            Err(EncodingError::InvalidDuration)
+        ));
+        // Rejected before the timestamp is taken, so 80 is still allowed.
+        assert!(matches!(
+            capture.push(90, RgbaImage::new(4, 8)),
+            Err(EncodingError::InvalidDimensions)
+        ));
+        capture.push(80, RgbaImage::new(8, 8)).unwrap();
+        let data = capture.finish(100).unwrap().into_inner();
+        let decoder = Decoder::new(&data).unwrap();
+        let durations: Vec<_> = decoder.frames().iter().map(|f| f.duration).collect();
+        assert_eq!(durations, [40, 33, 7, 20]);
+        for (frame, color) in decoder.canvas_frames().zip(colors) {
+            assert_eq!(frame.unwrap().image.get_pixel(3, 3).0, color);
+        }
+    }
+}
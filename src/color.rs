@@ -0,0 +1,253 @@
+use image::{DynamicImage, ImageFormat, RgbaImage};
+use moxcms::{ColorProfile, Layout, TransformOptions};
+
+use crate::{
+    EncodingError, FrameOptions, WebPAnimator, metadata,
+    riff::{Chunks, webp_payload},
+};
+
+/// The color space that frames are converted to before they are encoded,
+/// described by an ICC profile.  See [`WebPAnimator::set_working_space`].
+#[derive(Clone, Debug)]
+pub struct WorkingSpace {
+    profile: ColorProfile,
+    icc_profile: Vec<u8>,
+}
+
+impl WorkingSpace {
+    /// With the profile [`SRGB_ICC_PROFILE`](crate::metadata::SRGB_ICC_PROFILE).
+    pub fn srgb() -> Self {
+        Self::from_bundled(ColorProfile::new_srgb(), metadata::SRGB_ICC_PROFILE)
+    }
+
+    /// With the profile
+    /// [`DISPLAY_P3_ICC_PROFILE`](crate::metadata::DISPLAY_P3_ICC_PROFILE).
+    pub fn display_p3() -> Self {
+        Self::from_bundled(
+            ColorProfile::new_display_p3(),
+            metadata::DISPLAY_P3_ICC_PROFILE,
+        )
+    }
+
+    pub fn adobe_rgb() -> Self {
+        Self::from_profile(ColorProfile::new_adobe_rgb())
+    }
+
+    fn from_profile(profile: ColorProfile) -> Self {
+        // Encoding the built-in profiles doesn't fail.
+        let icc_profile = profile.encode().unwrap_or_default();
+        Self {
+            profile,
+            icc_profile,
+        }
+    }
+
+    fn from_bundled(profile: ColorProfile, icc_profile: &[u8]) -> Self {
+        Self {
+            profile,
+            icc_profile: icc_profile.to_vec(),
+        }
+    }
+
+    /// The color space described by an ICC profile.
+    pub fn from_icc(icc_profile: &[u8]) -> Result<Self, EncodingError> {
+        Ok(Self {
+            profile: ColorProfile::new_from_slice(icc_profile)?,
+            icc_profile: icc_profile.to_vec(),
+        })
+    }
+
+    /// The ICC profile that is attached to the animation.
+    pub fn icc_profile(&self) -> &[u8] {
+        &self.icc_profile
+    }
+
+    /// Converts `image` in place from the color space described by
+    /// `icc_profile`, or from sRGB if `None`.  Alpha is unchanged.
+    pub fn convert(
+        &self,
+        image: &mut RgbaImage,
+        icc_profile: Option<&[u8]>,
+    ) -> Result<(), EncodingError> {
+        let source = match icc_profile {
+            Some(icc) if icc == self.icc_profile => return Ok(()),
+            Some(icc) => ColorProfile::new_from_slice(icc)?,
+            None => ColorProfile::new_srgb(),
+        };
+        let transform = source.create_transform_8bit(
+            Layout::Rgba,
+            &self.profile,
+            Layout::Rgba,
+            TransformOptions::default(),
+        )?;
+        let src = image.as_raw().clone();
+        transform.transform(&src, image)?;
+        Ok(())
+    }
+}
+
+impl Default for WorkingSpace {
+    /// sRGB.
+    fn default() -> Self {
+        Self::srgb()
+    }
+}
+
+impl WebPAnimator {
+    /// The color space that [`add_tagged_image`](Self::add_tagged_image) and
+    /// [`add_tagged_frame`](Self::add_tagged_frame) convert frames to.
+    /// Setting it replaces the ICC profile of the animation with that of the
+    /// working space.  `None`, the default, means sRGB, whose profile is
+    /// attached once a tagged frame is added.
+    pub fn set_working_space(&mut self, space: Option<WorkingSpace>) {
+        if let Some(space) = &space {
+            self.metadata.icc_profile = space.icc_profile.clone();
+        }
+        self.working_space = space;
+    }
+
+    pub fn working_space(&self) -> Option<&WorkingSpace> {
+        self.working_space.as_ref()
+    }
+
+    /// Like [`add_image`](Self::add_image), but first converts `image` from
+    /// the color space described by `icc_profile`, or from sRGB if `None`,
+    /// to the [working space](Self::set_working_space), so that frames from
+    /// different sources have matching colors.
+    pub fn add_tagged_image(
+        &mut self,
+        image: &DynamicImage,
+        icc_profile: Option<&[u8]>,
+        options: FrameOptions,
+    ) -> Result<(), EncodingError> {
+        let space = self.tagged_space();
+        if icc_profile == Some(space.icc_profile()) {
+            return self.add_image(image, options);
+        }
+        let mut rgba = image.to_rgba8();
+        space.convert(&mut rgba, icc_profile)?;
+        self.add_image(&rgba.into(), options)
+    }
+
+    /// Like [`add_frame`](Self::add_frame), but converts the image to the
+    /// [working space](Self::set_working_space) from the color space given by
+    /// its `ICCP` chunk, or from sRGB if it has none.  Images that are
+    /// already in the working space are added without re-encoding, and
+    /// others are re-encoded losslessly.
+    pub fn add_tagged_frame(
+        &mut self,
+        data: &[u8],
+        options: FrameOptions,
+    ) -> Result<(), EncodingError> {
+        let icc_profile = still_icc_profile(data)?;
+        if icc_profile == Some(self.tagged_space().icc_profile()) {
+            return self.add_frame(data, options);
+        }
+        let image = image::load_from_memory_with_format(data, ImageFormat::WebP)?;
+        self.add_tagged_image(&image, icc_profile, options)
+    }
+
+    /// The working space, which is set to sRGB if there is none.
+    fn tagged_space(&mut self) -> WorkingSpace {
+        if self.working_space.is_none() {
+            self.set_working_space(Some(WorkingSpace::srgb()));
+        }
+        self.working_space.clone().unwrap()
+    }
+}
+
+/// Returns the `ICCP` chunk of a still WebP image, if there is one.
+fn still_icc_profile(data: &[u8]) -> Result<Option<&[u8]>, EncodingError> {
+    let mut chunks = Chunks::new(webp_payload(data)?);
+    match chunks.next().transpose()? {
+        Some(chunk) if &chunk.fourcc == b"VP8X" => {}
+        _ => return Ok(None),
+    }
+    for chunk in chunks {
+        let chunk = chunk?;
+        if &chunk.fourcc == b"ICCP" {
+            return Ok(Some(chunk.data));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod test {
+    use image::{DynamicImage, Rgb, RgbImage, Rgba, RgbaImage};
+
+    use crate::{
+        Decoder, FrameOptions, WebPAnimator, WorkingSpace,
+        test_util::{lossless_webp, params},
+    };
+
+    /// A still WebP image with an `ICCP` chunk.
+    fn tagged_webp(color: [u8; 3], icc_profile: &[u8]) -> Vec<u8> {
+        let simple = lossless_webp(&RgbImage::from_pixel(4, 4, Rgb(color)));
+        let chunk = |fourcc: &[u8], data: &[u8]| {
+            let mut out = [fourcc, &(data.len() as u32).to_le_bytes(), data].concat();
+            if data.len() & 1 != 0 {
+                out.push(0);
+            }
+            out
+        };
+        let vp8x = chunk(b"VP8X", &[0x20, 0, 0, 0, 3, 0, 0, 3, 0, 0]);
+        let iccp = chunk(b"ICCP", icc_profile);
+        let payload = [&b"WEBP"[..], &vp8x, &iccp, &simple[12..]].concat();
+        [
+            &b"RIFF"[..],
+            &(payload.len() as u32).to_le_bytes(),
+            &payload,
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_working_space() {
+        let params = params(4, 4);
+        let p3 = WorkingSpace::display_p3();
+        let mut writer = WebPAnimator::new(params).unwrap();
+        let red = DynamicImage::from(RgbImage::from_pixel(4, 4, Rgb([255, 0, 0])));
+        // Untagged frames are sRGB, which is the default working space.
+        writer
+            .add_tagged_image(&red, None, FrameOptions::with_duration(100))
+            .unwrap();
+        let p3_red = tagged_webp([255, 0, 0], p3.icc_profile());
+        writer
+            .add_tagged_frame(&p3_red, FrameOptions::with_duration(100))
+            .unwrap();
+        let data = writer.into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+        let srgb = WorkingSpace::srgb();
+        assert_eq!(decoder.icc_profile(), Some(srgb.icc_profile()));
+        let frames: Vec<_> = decoder.canvas_frames().map(|f| f.unwrap().image).collect();
+        assert_eq!(frames[0].get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+        // Display P3 red is outside sRGB, so it is clipped.
+        assert_eq!(frames[1].get_pixel(0, 0).0[0], 255);
+
+        let mut writer = WebPAnimator::builder()
+            .width(4)
+            .height(4)
+            .working_space(p3.clone())
+            .build()
+            .unwrap();
+        writer
+            .add_tagged_image(&red, None, FrameOptions::with_duration(100))
+            .unwrap();
+        writer
+            .add_tagged_frame(&p3_red, FrameOptions::with_duration(100))
+            .unwrap();
+        let data = writer.into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+        assert_eq!(decoder.icc_profile(), Some(p3.icc_profile()));
+        let frames: Vec<_> = decoder.canvas_frames().map(|f| f.unwrap().image).collect();
+        // sRGB red is less saturated in Display P3.
+        let Rgba([r, g, b, a]) = *frames[0].get_pixel(0, 0);
+        assert!(r < 255 && g > 0 && b > 0 && a == 255, "{r} {g} {b}");
+        // The frame that was already in the working space was copied.
+        assert_eq!(
+            frames[1],
+            RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]))
+        );
+    }
+}
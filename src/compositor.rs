@@ -0,0 +1,312 @@
+use std::num::NonZeroU32;
+
+use image::{AnimationDecoder, Delay, Frames, ImageError, ImageFormat, Rgba, RgbaImage};
+
+use crate::{
+    Blend, DecodedFrame, Decoder, Disposal, EncodingError, FrameRect, LoopCount,
+    encode::encode_lossless,
+};
+
+/// Renders the frames of an animation onto a canvas, producing the images
+/// that a viewer would display.
+///
+/// Like libwebp, the canvas starts out transparent and disposed frames are
+/// cleared to transparent; the background color in the file is ignored.
+pub struct Compositor {
+    canvas: RgbaImage,
+    /// The rectangle to clear before drawing the next frame.
+    dispose: Option<FrameRect>,
+}
+
+impl Compositor {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            canvas: RgbaImage::new(width, height),
+            dispose: None,
+        }
+    }
+
+    /// Draws the next frame of the animation and returns the canvas.
+    pub fn add_frame(&mut self, frame: &DecodedFrame) -> Result<&RgbaImage, ImageError> {
+        let image = frame.decode()?;
+        self.draw(&image, frame.rect, frame.blend, frame.dispose);
+        Ok(&self.canvas)
+    }
+
+    /// Draws `image` in `rect`, after clearing the rectangle of the previous
+    /// frame if it was disposed.
+    pub(crate) fn draw(
+        &mut self,
+        image: &RgbaImage,
+        rect: FrameRect,
+        blend_method: Blend,
+        dispose: Disposal,
+    ) {
+        if let Some(rect) = self.dispose.take() {
+            self.fill(rect, Rgba([0; 4]));
+        }
+        for (x, y, src) in image.enumerate_pixels() {
+            let Some(dst) = self.canvas.get_pixel_mut_checked(rect.x + x, rect.y + y) else {
+                continue;
+            };
+            *dst = match blend_method {
+                Blend::Alpha => blend(*src, *dst),
+                Blend::Overwrite => *src,
+            };
+        }
+        if dispose == Disposal::Background {
+            self.dispose = Some(rect);
+        }
+    }
+
+    /// The most recently drawn frame.
+    pub fn canvas(&self) -> &RgbaImage {
+        &self.canvas
+    }
+
+    fn fill(&mut self, rect: FrameRect, color: Rgba<u8>) {
+        for y in rect.y..(rect.y + rect.height).min(self.canvas.height()) {
+            for x in rect.x..(rect.x + rect.width).min(self.canvas.width()) {
+                self.canvas.put_pixel(x, y, color);
+            }
+        }
+    }
+}
+
+/// A frame as it would be displayed, covering the whole canvas.
+#[derive(Clone, Debug)]
+pub struct CanvasFrame {
+    pub image: RgbaImage,
+    /// The duration in milliseconds.
+    pub duration: u32,
+}
+
+/// An iterator over the frames of an animation as they would be displayed.
+/// See [`Decoder::canvas_frames`].
+pub struct CanvasFrames<'a> {
+    frames: std::vec::IntoIter<DecodedFrame<'a>>,
+    compositor: Compositor,
+}
+
+impl Iterator for CanvasFrames<'_> {
+    type Item = Result<CanvasFrame, ImageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame = self.frames.next()?;
+        Some(self.compositor.add_frame(&frame).map(|image| CanvasFrame {
+            image: image.clone(),
+            duration: frame.duration,
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.frames.size_hint()
+    }
+}
+
+impl<'a> Decoder<'a> {
+    /// Decodes the frames and applies blending and disposal, producing the
+    /// full-canvas images that a viewer would display.  See [`Compositor`].
+    pub fn canvas_frames(&self) -> CanvasFrames<'a> {
+        CanvasFrames {
+            frames: self.frames().to_vec().into_iter(),
+            compositor: Compositor::new(self.width(), self.height()),
+        }
+    }
+
+    /// Renders the canvas as it is displayed `ms` milliseconds after the
+    /// animation starts, taking the loop count into account.  Once the
+    /// animation has finished, the last frame stays on screen.
+    pub fn frame_at(&self, ms: u64) -> Result<RgbaImage, EncodingError> {
+        let frames = self.frames();
+        if frames.is_empty() {
+            return Err(EncodingError::NoFrames);
+        }
+        let total: u64 = frames.iter().map(|f| f.duration as u64).sum();
+        let playing = match self.loop_count() {
+            LoopCount::Infinite => total > 0,
+            LoopCount::Finite(n) => ms < total * n.get() as u64,
+        };
+        let count = if playing {
+            let t = ms % total;
+            let mut end = 0;
+            1 + frames
+                .iter()
+                .position(|f| {
+                    end += f.duration as u64;
+                    end > t
+                })
+                .unwrap()
+        } else {
+            frames.len()
+        };
+        let mut compositor = Compositor::new(self.width(), self.height());
+        for frame in &frames[..count] {
+            compositor.add_frame(frame)?;
+        }
+        Ok(compositor.canvas)
+    }
+
+    /// Like [`frame_at`](Self::frame_at), but returns the canvas as a
+    /// lossless still WebP image, for example to use as a poster image.
+    pub fn frame_at_webp(&self, ms: u64) -> Result<Vec<u8>, EncodingError> {
+        encode_lossless(&self.frame_at(ms)?.into())
+    }
+}
+
+impl<'a> AnimationDecoder<'a> for Decoder<'a> {
+    /// Returns the full-canvas frames.  See [`Decoder::canvas_frames`].
+    fn into_frames(self) -> Frames<'a> {
+        let frames = self.canvas_frames().map(|frame| {
+            frame.map(|frame| {
+                let delay = Delay::from_numer_denom_ms(frame.duration, 1);
+                image::Frame::from_parts(frame.image, 0, 0, delay)
+            })
+        });
+        Frames::new(Box::new(frames))
+    }
+
+    fn loop_count(&self) -> image::metadata::LoopCount {
+        match Decoder::loop_count(self) {
+            LoopCount::Infinite => image::metadata::LoopCount::Infinite,
+            LoopCount::Finite(n) => image::metadata::LoopCount::Finite(NonZeroU32::from(n)),
+        }
+    }
+}
+
+impl DecodedFrame<'_> {
+    /// Decodes the image data of the frame.
+    pub fn decode(&self) -> Result<RgbaImage, ImageError> {
+        Ok(image::load_from_memory_with_format(&self.to_webp(), ImageFormat::WebP)?.into_rgba8())
+    }
+}
+
+/// Alpha-blends `src` onto `dst` using the same integer arithmetic as
+/// libwebp, since the colors are not premultiplied.
+pub(crate) fn blend(src: Rgba<u8>, dst: Rgba<u8>) -> Rgba<u8> {
+    let src_a = src[3] as u32;
+    match src_a {
+        0 => return dst,
+        255 => return src,
+        _ => {}
+    }
+    let dst_a = (dst[3] as u32 * (256 - src_a)) >> 8;
+    let blend_a = src_a + dst_a;
+    let scale = (1 << 24) / blend_a;
+    let channel =
+        |i: usize| (((src[i] as u32 * src_a + dst[i] as u32 * dst_a) * scale) >> 24) as u8;
+    Rgba([channel(0), channel(1), channel(2), blend_a as u8])
+}
+
+/// Interpolates between `a` and `b`, giving `b` the weight `t`, which is
+/// between 0 and 1.  The images must be the same size.
+pub(crate) fn mix(a: &RgbaImage, b: &RgbaImage, t: f32) -> RgbaImage {
+    RgbaImage::from_fn(a.width(), a.height(), |x, y| {
+        mix_pixel(*a.get_pixel(x, y), *b.get_pixel(x, y), t)
+    })
+}
+
+/// Interpolates between two pixels.  The colors are weighted by alpha, so
+/// the colors of transparent pixels don't bleed into the result.
+pub(crate) fn mix_pixel(p: Rgba<u8>, q: Rgba<u8>, t: f32) -> Rgba<u8> {
+    let (wp, wq) = (p[3] as f32 * (1.0 - t), q[3] as f32 * t);
+    let alpha = wp + wq;
+    if alpha == 0.0 {
+        return Rgba([0; 4]);
+    }
+    let channel = |i: usize| ((p[i] as f32 * wp + q[i] as f32 * wq) / alpha).round() as u8;
+    Rgba([channel(0), channel(1), channel(2), alpha.round() as u8])
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use image::{AnimationDecoder, Delay, metadata::LoopCount};
+
+    use crate::{Compositor, Decoder, remux};
+
+    #[test]
+    fn test_compositor() {
+        let mut encoder = webp_animation::Encoder::new((32, 16)).unwrap();
+        let mut frames = Vec::new();
+        let mut frame = [255, 0, 0, 255].repeat(32 * 16);
+        frames.push(frame.clone());
+        for i in 0..64 {
+            frame[4 * (i + 100)..4 * (i + 101)].copy_from_slice(&[0, 0, 255, 128]);
+        }
+        frames.push(frame.clone());
+        for i in 0..32 {
+            frame[4 * (i + 300)..4 * (i + 301)].copy_from_slice(&[0, 0, 0, 0]);
+        }
+        frames.push(frame.clone());
+        for (i, frame) in frames.iter().enumerate() {
+            encoder.add_frame(frame, 100 * i as i32).unwrap();
+        }
+        let data = encoder.finalize(300).unwrap();
+        let expected: Vec<_> = webp_animation::Decoder::new(&data)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.data().to_vec())
+            .collect();
+        let decoder = Decoder::new(&data).unwrap();
+        let mut compositor = Compositor::new(decoder.width(), decoder.height());
+        for (frame, expected) in decoder.frames().iter().zip(&expected) {
+            let canvas = compositor.add_frame(frame).unwrap();
+            assert_eq!(canvas.as_raw(), expected);
+        }
+        let canvas_frames: Vec<_> = decoder.canvas_frames().map(|f| f.unwrap()).collect();
+        assert_eq!(canvas_frames.len(), 3);
+        for (frame, expected) in canvas_frames.iter().zip(&expected) {
+            assert_eq!(frame.image.as_raw(), expected);
+            assert_eq!(frame.duration, 100);
+        }
+        assert_eq!(expected.len(), 3);
+        assert_eq!(expected[2], frames[2]);
+    }
+
+    #[test]
+    fn test_animation_decoder() {
+        let mut encoder = webp_animation::Encoder::new((8, 8)).unwrap();
+        encoder.add_frame(&[255, 0, 0, 255].repeat(64), 0).unwrap();
+        encoder.add_frame(&[0, 0, 255, 128].repeat(64), 40).unwrap();
+        let data = encoder.finalize(100).unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+        assert!(matches!(
+            AnimationDecoder::loop_count(&decoder),
+            LoopCount::Infinite
+        ));
+        let expected: Vec<_> = decoder.canvas_frames().map(|f| f.unwrap()).collect();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), 2);
+        for (frame, expected) in frames.iter().zip(&expected) {
+            assert_eq!((frame.left(), frame.top()), (0, 0));
+            assert_eq!(frame.buffer(), &expected.image);
+        }
+        assert_eq!(frames[1].delay(), Delay::from_numer_denom_ms(60, 1));
+    }
+
+    #[test]
+    fn test_frame_at() {
+        let mut encoder = webp_animation::Encoder::new((4, 4)).unwrap();
+        for (i, color) in [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]]
+            .iter()
+            .enumerate()
+        {
+            encoder
+                .add_frame(&color.repeat(16), 100 * i as i32)
+                .unwrap();
+        }
+        let mut data = encoder.finalize(300).unwrap().to_vec();
+        let red = |data: &[u8], ms| Decoder::new(data).unwrap().frame_at(ms).unwrap()[(0, 0)][0];
+        assert_eq!(red(&data, 0), 255);
+        assert_eq!(red(&data, 150), 0);
+        assert_eq!(red(&data, 300), 255);
+        remux::set_loop_count(&mut Cursor::new(&mut data), 2.into()).unwrap();
+        assert_eq!(red(&data, 300), 255);
+        assert_eq!(red(&data, 600), 0);
+        let webp = Decoder::new(&data).unwrap().frame_at_webp(250).unwrap();
+        let image = image::load_from_memory(&webp).unwrap().into_rgba8();
+        assert_eq!(image[(3, 3)].0, [0, 0, 255, 255]);
+    }
+}
@@ -0,0 +1,307 @@
+use crate::{Blend, DecodedFrame, Decoder, EncodingError, FrameRect, WebPAnimator};
+
+/// Joins animations end to end.  The frames are copied without being
+/// re-encoded, and keep their durations.
+///
+/// Every input must have the same canvas size, or this fails with
+/// [`EncodingError::InvalidDimensions`].  The loop count, background color
+/// and metadata are taken from the first input, and the result has an alpha
+/// channel if any input does.
+///
+/// Each input starts on a transparent canvas, as it would on its own.  If
+/// the first frame of a later input covers the canvas, it is copied with
+/// [`Blend::Overwrite`], which gives the same result on a transparent
+/// canvas.  Otherwise it is rendered on a transparent canvas and encoded
+/// losslessly, which needs the `image` feature; without it, this fails with
+/// [`EncodingError::InvalidDimensions`].
+pub fn concat(inputs: &[&[u8]]) -> Result<Vec<u8>, EncodingError> {
+    let decoders = decode_all(inputs)?;
+    let mut animator = first_animator(&decoders)?;
+    animator.set_detect_alpha(true);
+    for (i, decoder) in decoders.iter().enumerate() {
+        if (decoder.width(), decoder.height()) != (animator.width, animator.height) {
+            return Err(EncodingError::InvalidDimensions);
+        }
+        add_frames(&mut animator, decoder, i > 0)?;
+    }
+    animator.into_bytes()
+}
+
+/// Copies the frames of an input.  If it follows another input, its first
+/// frame is changed so that it replaces whatever the previous input left
+/// behind, as described for [`concat`].
+fn add_frames(
+    animator: &mut WebPAnimator,
+    decoder: &Decoder,
+    seam: bool,
+) -> Result<(), EncodingError> {
+    let canvas = FrameRect {
+        x: 0,
+        y: 0,
+        width: animator.width,
+        height: animator.height,
+    };
+    for (i, frame) in decoder.frames().iter().enumerate() {
+        let mut options = frame.options();
+        if seam && i == 0 {
+            if frame.rect != canvas {
+                render_seam(animator, frame)?;
+                continue;
+            }
+            options.blend = Blend::Overwrite;
+        }
+        animator.add_chunk(frame.data, options)?;
+    }
+    Ok(())
+}
+
+/// Adds a frame that doesn't cover the canvas as a full-canvas frame, drawn
+/// on a transparent canvas.  It keeps its disposal, since the canvas outside
+/// its rectangle is transparent either way.
+#[cfg(feature = "image")]
+fn render_seam(animator: &mut WebPAnimator, frame: &DecodedFrame) -> Result<(), EncodingError> {
+    use image::DynamicImage;
+
+    use crate::{Compositor, FrameOptions};
+
+    let mut compositor = Compositor::new(animator.width, animator.height);
+    let canvas = compositor.add_frame(frame)?.clone();
+    let options = FrameOptions {
+        dispose: frame.dispose,
+        blend: Blend::Overwrite,
+        ..FrameOptions::with_duration(frame.duration)
+    };
+    animator.add_image(&DynamicImage::from(canvas), options)
+}
+
+#[cfg(not(feature = "image"))]
+fn render_seam(_: &mut WebPAnimator, _: &DecodedFrame) -> Result<(), EncodingError> {
+    Err(EncodingError::InvalidDimensions)
+}
+
+/// Like [`concat`], but inputs whose canvas size differs from the first are
+/// rendered, scaled to fit the canvas with their aspect ratio preserved, and
+/// centered with transparent bars.  The scaled frames are encoded
+/// losslessly.
+#[cfg(feature = "image")]
+pub fn concat_fit(inputs: &[&[u8]]) -> Result<Vec<u8>, EncodingError> {
+    use image::{
+        DynamicImage, RgbaImage,
+        imageops::{self, FilterType},
+    };
+
+    use crate::FrameOptions;
+
+    let decoders = decode_all(inputs)?;
+    let mut animator = first_animator(&decoders)?;
+    animator.set_detect_alpha(true);
+    let (width, height) = (animator.width, animator.height);
+    for (i, decoder) in decoders.iter().enumerate() {
+        let (w, h) = (decoder.width(), decoder.height());
+        if (w, h) == (width, height) {
+            add_frames(&mut animator, decoder, i > 0)?;
+            continue;
+        }
+        let scale = f64::min(width as f64 / w as f64, height as f64 / h as f64);
+        let scaled = |len: u32, max: u32| ((len as f64 * scale).round() as u32).clamp(1, max);
+        let (scaled_width, scaled_height) = (scaled(w, width), scaled(h, height));
+        let (x, y) = ((width - scaled_width) / 2, (height - scaled_height) / 2);
+        for frame in decoder.canvas_frames() {
+            let frame = frame?;
+            let image = imageops::resize(
+                &frame.image,
+                scaled_width,
+                scaled_height,
+                FilterType::Triangle,
+            );
+            let mut canvas = RgbaImage::new(width, height);
+            imageops::replace(&mut canvas, &image, x.into(), y.into());
+            // The frame must replace whatever the previous input left behind.
+            let options = FrameOptions {
+                blend: Blend::Overwrite,
+                ..FrameOptions::with_duration(frame.duration)
+            };
+            animator.add_image(&DynamicImage::from(canvas), options)?;
+        }
+    }
+    animator.into_bytes()
+}
+
+/// The transition generated by [`concat_crossfade`] between consecutive
+/// inputs.
+#[cfg(feature = "image")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Crossfade {
+    /// The number of blended frames inserted at each seam.
+    pub frames: u32,
+    /// The duration of each blended frame in milliseconds.
+    pub duration: u32,
+}
+
+/// Like [`concat`], but inserts frames that dissolve from the last frame of
+/// each input into the first frame of the next.  A still image can be passed
+/// as a single-frame animation.  The rendered frames are re-encoded
+/// losslessly with an [`Optimizer`](crate::Optimizer).
+#[cfg(feature = "image")]
+pub fn concat_crossfade(inputs: &[&[u8]], crossfade: Crossfade) -> Result<Vec<u8>, EncodingError> {
+    use crate::{Optimizer, compositor::mix};
+
+    let decoders = decode_all(inputs)?;
+    let mut animator = first_animator(&decoders)?;
+    animator.set_detect_alpha(true);
+    let mut optimizer = Optimizer::new(animator);
+    let mut last = None;
+    for decoder in &decoders {
+        if (decoder.width(), decoder.height()) != (decoders[0].width(), decoders[0].height()) {
+            return Err(EncodingError::InvalidDimensions);
+        }
+        for (i, frame) in decoder.canvas_frames().enumerate() {
+            let frame = frame?;
+            if let Some(previous) = last.as_ref().filter(|_| i == 0) {
+                let steps = crossfade.frames + 1;
+                for k in 1..steps {
+                    let image = mix(previous, &frame.image, k as f32 / steps as f32);
+                    optimizer.add_frame(&image, crossfade.duration)?;
+                }
+            }
+            optimizer.add_frame(&frame.image, frame.duration)?;
+            last = Some(frame.image);
+        }
+    }
+    optimizer.into_inner().into_bytes()
+}
+
+fn decode_all<'a>(inputs: &[&'a [u8]]) -> Result<Vec<Decoder<'a>>, EncodingError> {
+    Ok(inputs
+        .iter()
+        .map(|data| Decoder::new(data))
+        .collect::<Result<_, _>>()?)
+}
+
+/// Creates an empty animation with the header of the first input.
+fn first_animator(decoders: &[Decoder]) -> Result<WebPAnimator, EncodingError> {
+    let first = decoders.first().ok_or(EncodingError::NoFrames)?;
+    first
+        .animator_builder()
+        .has_alpha(decoders.iter().any(|d| d.has_alpha()))
+        .build()
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgba, RgbaImage};
+
+    use crate::{
+        Blend, Decoder, EncodingError, FrameOptions, LoopCount, Params, WebPAnimator, concat,
+        test_util::lossless_webp,
+    };
+
+    fn animation(width: u32, height: u32, colors: &[[u8; 4]], loop_count: u16) -> Vec<u8> {
+        let params = Params {
+            width,
+            height,
+            background_bgra: [0; 4],
+            loop_count: LoopCount::from(loop_count),
+            has_alpha: false,
+        };
+        let mut animator = WebPAnimator::new(params).unwrap();
+        for (i, color) in colors.iter().enumerate() {
+            let buf = lossless_webp(&RgbaImage::from_pixel(width, height, Rgba(*color)));
+            animator
+                .add_webp_image(&buf, None, 10 * (i as u32 + 1))
+                .unwrap();
+        }
+        animator.into_bytes().unwrap()
+    }
+
+    #[test]
+    fn test_concat() {
+        let a = animation(8, 4, &[[255, 0, 0, 255], [0, 255, 0, 255]], 2);
+        let b = animation(8, 4, &[[0, 0, 255, 255]], 0);
+        let out = concat(&[&a, &b]).unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        assert_eq!(decoder.loop_count(), LoopCount::from(2));
+        let durations: Vec<_> = decoder.frames().iter().map(|f| f.duration).collect();
+        assert_eq!(durations, [10, 20, 10]);
+        let b_decoder = Decoder::new(&b).unwrap();
+        assert_eq!(decoder.frames()[2].data, b_decoder.frames()[0].data);
+
+        let c = animation(4, 4, &[[0, 0, 255, 255]], 0);
+        assert!(matches!(
+            concat(&[&a, &c]),
+            Err(EncodingError::InvalidDimensions)
+        ));
+
+        #[cfg(feature = "image")]
+        {
+            let out = crate::concat_fit(&[&a, &c]).unwrap();
+            let decoder = Decoder::new(&out).unwrap();
+            assert_eq!((decoder.width(), decoder.height()), (8, 4));
+            let last = decoder.canvas_frames().last().unwrap().unwrap().image;
+            assert_eq!(last.get_pixel(0, 0), &Rgba([0; 4]));
+            assert_eq!(last.get_pixel(4, 2), &Rgba([0, 0, 255, 255]));
+            assert_eq!(last.get_pixel(7, 3), &Rgba([0; 4]));
+
+            let crossfade = crate::Crossfade {
+                frames: 3,
+                duration: 5,
+            };
+            let out = crate::concat_crossfade(&[&a, &b], crossfade).unwrap();
+            let decoder = Decoder::new(&out).unwrap();
+            let durations: Vec<_> = decoder.frames().iter().map(|f| f.duration).collect();
+            assert_eq!(durations, [10, 20, 5, 5, 5, 10]);
+            let middle = decoder.canvas_frames().nth(3).unwrap().unwrap().image;
+            assert_eq!(middle.get_pixel(0, 0), &Rgba([0, 128, 128, 255]));
+            assert!(matches!(
+                crate::concat_crossfade(&[&a, &c], crossfade),
+                Err(EncodingError::InvalidDimensions)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_concat_seams() {
+        let a = animation(8, 4, &[[255, 0, 0, 255]], 0);
+        let input = |width: u32, color: [u8; 4]| {
+            let mut animator = WebPAnimator::builder().width(8).height(4).build().unwrap();
+            let pixels = color.repeat(width as usize * 4);
+            animator
+                .add_rgba_frame(&pixels, width, 4, FrameOptions::with_duration(10))
+                .unwrap();
+            animator.into_bytes().unwrap()
+        };
+
+        // A translucent frame covering the canvas mustn't be blended with the
+        // red frame before it.
+        let translucent = input(8, [0, 0, 255, 128]);
+        let out = concat(&[&a, &translucent]).unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        assert_eq!(decoder.frames()[1].blend, Blend::Overwrite);
+        assert!(decoder.has_alpha());
+        #[cfg(feature = "image")]
+        {
+            let last = decoder.canvas_frames().last().unwrap().unwrap().image;
+            assert_eq!(last.get_pixel(3, 1), &Rgba([0, 0, 255, 128]));
+        }
+
+        // Nor may the red frame show around a frame covering part of it.
+        let partial = input(4, [0, 0, 255, 255]);
+        #[cfg(feature = "image")]
+        {
+            let out = concat(&[&a, &partial]).unwrap();
+            let decoder = Decoder::new(&out).unwrap();
+            let last = decoder.canvas_frames().last().unwrap().unwrap().image;
+            assert_eq!(last.get_pixel(1, 1), &Rgba([0, 0, 255, 255]));
+            assert_eq!(last.get_pixel(6, 1), &Rgba([0; 4]));
+            let out = crate::concat_fit(&[&a, &partial]).unwrap();
+            let decoder = Decoder::new(&out).unwrap();
+            let last = decoder.canvas_frames().last().unwrap().unwrap().image;
+            assert_eq!(last.get_pixel(6, 1), &Rgba([0; 4]));
+        }
+        #[cfg(not(feature = "image"))]
+        assert!(matches!(
+            concat(&[&a, &partial]),
+            Err(EncodingError::InvalidDimensions)
+        ));
+    }
+}
@@ -0,0 +1,157 @@
+//! Conversion from formats whose frames are drawn in the same way as WebP,
+//! except for an extra "restore to previous" disposal method.
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::{Blend, Disposal, EncodingError, FrameOptions, FrameRect, WebPAnimator, compositor};
+
+/// What to do with a frame's rectangle after it has been displayed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SourceDisposal {
+    None,
+    /// Clear the rectangle to transparent.
+    Background,
+    /// Restore the contents of the canvas before the frame was drawn.
+    Previous,
+}
+
+/// A frame of the source animation.
+pub(crate) struct SourceFrame<'a> {
+    pub image: &'a RgbaImage,
+    pub x: u32,
+    pub y: u32,
+    pub blend: Blend,
+    pub dispose: SourceDisposal,
+    /// The duration in milliseconds.
+    pub duration: u32,
+}
+
+/// Adds frames to a [`WebPAnimator`], keeping track of the canvas for the
+/// cases that the WebP format can't express directly.
+///
+/// Frames are added with the same rectangle as in the source where
+/// possible.  Odd offsets are rounded down by extending the frame, and the
+/// frame following one that can't be disposed exactly covers the whole
+/// canvas.
+pub(crate) struct Converter {
+    canvas: RgbaImage,
+    /// The canvas to restore for [`SourceDisposal::Previous`].
+    saved: Option<RgbaImage>,
+    /// The disposal of the previous frame, which is applied before drawing
+    /// the next one.
+    dispose: Option<(FrameRect, SourceDisposal)>,
+    /// Whether the WebP canvas differs from the source canvas after
+    /// disposal, so that the next frame must cover the whole canvas.
+    full_frame: bool,
+}
+
+impl Converter {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            canvas: RgbaImage::new(width, height),
+            saved: None,
+            dispose: None,
+            full_frame: false,
+        }
+    }
+
+    pub fn add_frame(
+        &mut self,
+        animator: &mut WebPAnimator,
+        frame: &SourceFrame,
+    ) -> Result<(), EncodingError> {
+        match self.dispose.take() {
+            Some((rect, SourceDisposal::Background)) => {
+                for y in rect.y..rect.y + rect.height {
+                    for x in rect.x..rect.x + rect.width {
+                        self.canvas.put_pixel(x, y, Rgba([0; 4]));
+                    }
+                }
+            }
+            Some((_, SourceDisposal::Previous)) => {
+                if let Some(saved) = self.saved.take() {
+                    self.canvas = saved;
+                }
+            }
+            _ => {}
+        }
+        if frame.dispose == SourceDisposal::Previous {
+            self.saved = Some(self.canvas.clone());
+        }
+        // The part of the frame that lies on the canvas.
+        let x = frame.x.min(self.canvas.width());
+        let y = frame.y.min(self.canvas.height());
+        let rect = FrameRect {
+            x,
+            y,
+            width: (frame.image.width()).min(self.canvas.width() - x),
+            height: (frame.image.height()).min(self.canvas.height() - y),
+        };
+        let pixel = |px: u32, py: u32| *frame.image.get_pixel(px - frame.x, py - frame.y);
+        let restores = frame.dispose != SourceDisposal::None;
+        let full_frame = self.full_frame || rect.width == 0 || rect.height == 0;
+        // WebP offsets must be even, so odd offsets are rounded down by
+        // extending the frame with pixels that don't change the canvas.
+        let (ex, ey) = (rect.x & !1, rect.y & !1);
+        let mut partial = None;
+        if !full_frame {
+            let fill = match frame.blend {
+                Blend::Alpha => None,
+                Blend::Overwrite => Some(&self.canvas),
+            };
+            let width = rect.x + rect.width - ex;
+            let height = rect.y + rect.height - ey;
+            partial = Some(RgbaImage::from_fn(width, height, |px, py| {
+                let (px, py) = (px + ex, py + ey);
+                if px >= rect.x && py >= rect.y {
+                    pixel(px, py)
+                } else {
+                    fill.map_or(Rgba([0; 4]), |canvas| *canvas.get_pixel(px, py))
+                }
+            }));
+        }
+        for py in rect.y..rect.y + rect.height {
+            for px in rect.x..rect.x + rect.width {
+                let dst = self.canvas.get_pixel_mut(px, py);
+                *dst = match frame.blend {
+                    Blend::Alpha => compositor::blend(pixel(px, py), *dst),
+                    Blend::Overwrite => pixel(px, py),
+                };
+            }
+        }
+        match partial {
+            None => {
+                let options = FrameOptions {
+                    blend: Blend::Overwrite,
+                    ..FrameOptions::with_duration(frame.duration)
+                };
+                animator.add_image(&DynamicImage::from(self.canvas.clone()), options)?;
+                self.full_frame = restores;
+            }
+            Some(image) => {
+                // Disposing an extended rectangle would clear too much.
+                let exact = (ex, ey) == (rect.x, rect.y);
+                let dispose = if frame.dispose == SourceDisposal::Background && exact {
+                    Disposal::Background
+                } else {
+                    Disposal::None
+                };
+                let options = FrameOptions {
+                    rect: Some(FrameRect {
+                        x: ex,
+                        y: ey,
+                        width: image.width(),
+                        height: image.height(),
+                    }),
+                    dispose,
+                    blend: frame.blend,
+                    ..FrameOptions::with_duration(frame.duration)
+                };
+                animator.add_image(&DynamicImage::from(image), options)?;
+                self.full_frame = restores && dispose == Disposal::None;
+            }
+        }
+        self.dispose = Some((rect, frame.dispose));
+        Ok(())
+    }
+}
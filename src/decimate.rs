@@ -0,0 +1,253 @@
+use crate::{CanvasFrame, Decoder, EncodingError, Optimizer, WebPAnimator, presets::Violation};
+
+/// How frames are chosen to be dropped.  The duration of a dropped frame is
+/// given to the frame before it, so the total duration stays the same.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Keep frames evenly spaced by index.
+    #[default]
+    Uniform,
+    /// Repeatedly drop the frame that differs least from the frame before
+    /// it, so that motion is kept where it matters most.
+    Adaptive,
+}
+
+impl Decoder<'_> {
+    /// Drops frames until at most `max_frames` are left, re-encoding the
+    /// rendered frames losslessly with an [`Optimizer`].
+    pub fn drop_frames(
+        &self,
+        max_frames: usize,
+        policy: DropPolicy,
+    ) -> Result<WebPAnimator, EncodingError> {
+        let frames = self.canvas_frames().collect::<Result<Vec<_>, _>>()?;
+        self.encode_lossless_frames(decimate(frames, max_frames.max(1), policy))
+    }
+
+    /// Drops as few frames as it can so that the losslessly re-encoded
+    /// animation fits in `max_bytes`.  Fails with
+    /// [`EncodingError::Constraints`] if even a single frame is too large.
+    pub fn drop_frames_to_size(
+        &self,
+        max_bytes: usize,
+        policy: DropPolicy,
+    ) -> Result<WebPAnimator, EncodingError> {
+        let frames = self.canvas_frames().collect::<Result<Vec<_>, _>>()?;
+        let encode = |count| self.encode_lossless_frames(decimate(frames.clone(), count, policy));
+        let smallest = encode(1)?;
+        let bytes = smallest.estimated_size();
+        if bytes > max_bytes {
+            let violation = Violation::Size {
+                bytes,
+                max: max_bytes,
+            };
+            return Err(EncodingError::Constraints(vec![violation]));
+        }
+        // Bisect on the number of frames, assuming that more frames never
+        // make the output smaller.
+        let (mut best, mut low, mut high) = (smallest, 1, frames.len());
+        while low < high {
+            let count = (low + high).div_ceil(2);
+            let animator = encode(count)?;
+            if animator.estimated_size() <= max_bytes {
+                (best, low) = (animator, count);
+            } else {
+                high = count - 1;
+            }
+        }
+        Ok(best)
+    }
+
+    /// Resamples the animation so that every frame lasts `frame_duration`
+    /// milliseconds, for players that only accept a constant frame rate.
+    /// Each output frame shows the rendered frame whose start time is
+    /// nearest, so frames are repeated or dropped as needed, and the total
+    /// duration is rounded to a whole number of frames.  The frames are
+    /// re-encoded losslessly with an [`Optimizer`], with repeated frames
+    /// kept separate.
+    pub fn to_constant_rate(&self, frame_duration: u32) -> Result<WebPAnimator, EncodingError> {
+        if frame_duration == 0 {
+            return Err(EncodingError::InvalidDuration);
+        }
+        let frames = self.canvas_frames().collect::<Result<Vec<_>, _>>()?;
+        let starts: Vec<u64> = frames
+            .iter()
+            .scan(0, |t, f| {
+                let start = *t;
+                *t += f.duration as u64;
+                Some(start)
+            })
+            .collect();
+        let total: u64 = frames.iter().map(|f| f.duration as u64).sum();
+        let period = frame_duration as u64;
+        let count = ((total + period / 2) / period).max(1);
+        let animator = self.animator_builder().detect_alpha(true).build()?;
+        let mut optimizer = Optimizer::new(animator);
+        optimizer.set_merge_unchanged(false);
+        for k in 0..count {
+            let t = k * period;
+            // The last frame starting at or before `t`, or the next one if
+            // it starts closer to `t`.
+            let mut i = starts.partition_point(|&s| s <= t).saturating_sub(1);
+            if starts
+                .get(i + 1)
+                .is_some_and(|&next| next - t < t - starts[i])
+            {
+                i += 1;
+            }
+            optimizer.add_frame(&frames[i].image, frame_duration)?;
+        }
+        Ok(optimizer.into_inner())
+    }
+
+    fn encode_lossless_frames(
+        &self,
+        frames: Vec<CanvasFrame>,
+    ) -> Result<WebPAnimator, EncodingError> {
+        let animator = self.animator_builder().detect_alpha(true).build()?;
+        let mut optimizer = Optimizer::new(animator);
+        for frame in frames {
+            optimizer.add_frame(&frame.image, frame.duration)?;
+        }
+        Ok(optimizer.into_inner())
+    }
+}
+
+/// Keeps at most `count` of the frames, adding the duration of each dropped
+/// frame to the frame before it.  The first frame is always kept.
+pub(crate) fn decimate(
+    frames: Vec<CanvasFrame>,
+    count: usize,
+    policy: DropPolicy,
+) -> Vec<CanvasFrame> {
+    let n = frames.len();
+    if count >= n {
+        return frames;
+    }
+    let keep: Vec<bool> = match policy {
+        DropPolicy::Uniform => {
+            let mut keep = vec![false; n];
+            for k in 0..count {
+                keep[k * n / count] = true;
+            }
+            keep
+        }
+        DropPolicy::Adaptive => adaptive_keep(&frames, count),
+    };
+    let mut kept: Vec<CanvasFrame> = Vec::with_capacity(count);
+    for (frame, keep) in frames.into_iter().zip(keep) {
+        match kept.last_mut() {
+            Some(last) if !keep => last.duration += frame.duration,
+            _ => kept.push(frame),
+        }
+    }
+    kept
+}
+
+fn adaptive_keep(frames: &[CanvasFrame], count: usize) -> Vec<bool> {
+    let n = frames.len();
+    // `previous[i]` is the index of the kept frame before frame `i`, and
+    // `next[i]` the one after it.
+    let mut previous: Vec<usize> = (0..n).map(|i| i.saturating_sub(1)).collect();
+    let mut next: Vec<usize> = (1..=n).collect();
+    let mut diffs: Vec<u64> = (0..n)
+        .map(|i| match i {
+            0 => u64::MAX,
+            _ => difference(&frames[i - 1], &frames[i]),
+        })
+        .collect();
+    let mut keep = vec![true; n];
+    for _ in count..n {
+        let (i, _) = diffs
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| keep[i])
+            .min_by_key(|&(_, &d)| d)
+            .unwrap();
+        keep[i] = false;
+        diffs[i] = u64::MAX;
+        let (p, q) = (previous[i], next[i]);
+        next[p] = q;
+        if q < n {
+            previous[q] = p;
+            diffs[q] = difference(&frames[p], &frames[q]);
+        }
+    }
+    keep
+}
+
+/// The sum of the absolute differences of the channels of every pixel.
+fn difference(a: &CanvasFrame, b: &CanvasFrame) -> u64 {
+    a.image
+        .as_raw()
+        .iter()
+        .zip(b.image.as_raw())
+        .map(|(&x, &y)| x.abs_diff(y) as u64)
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgba, RgbaImage};
+
+    use crate::{Decoder, DropPolicy, EncodingError, Optimizer, WebPAnimator, test_util::params};
+
+    #[test]
+    fn test_drop_frames() {
+        let params = params(16, 16);
+        let mut optimizer = Optimizer::new(WebPAnimator::new(params).unwrap());
+        let mut image = RgbaImage::from_pixel(16, 16, Rgba([0, 0, 0, 255]));
+        // Frames 1 and 2 differ by a single pixel, and the others by a row.
+        for i in 0..6u32 {
+            match i {
+                2 => image.put_pixel(0, 0, Rgba([255, 255, 255, 255])),
+                _ => (0..16).for_each(|x| image.put_pixel(x, i, Rgba([255, 0, 0, 255]))),
+            }
+            optimizer.add_frame(&image, 100).unwrap();
+        }
+        let data = optimizer.into_inner().into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+
+        let uniform = decoder.drop_frames(3, DropPolicy::Uniform).unwrap();
+        let durations: Vec<u32> = uniform.frames().iter().map(|f| f.duration()).collect();
+        assert_eq!(durations, [200, 200, 200]);
+        let adaptive = decoder.drop_frames(5, DropPolicy::Adaptive).unwrap();
+        let durations: Vec<u32> = adaptive.frames().iter().map(|f| f.duration()).collect();
+        assert_eq!(durations, [100, 200, 100, 100, 100]);
+
+        let size = data.len() - 1;
+        let fitted = decoder
+            .drop_frames_to_size(size, DropPolicy::Uniform)
+            .unwrap();
+        assert!(fitted.estimated_size() <= size);
+        assert_eq!(fitted.total_duration().as_millis(), 600);
+        assert!(matches!(
+            decoder.drop_frames_to_size(10, DropPolicy::Uniform),
+            Err(EncodingError::Constraints(_))
+        ));
+    }
+
+    #[test]
+    fn test_to_constant_rate() {
+        let params = params(4, 4);
+        let mut optimizer = Optimizer::new(WebPAnimator::new(params).unwrap());
+        for (i, duration) in [95, 10, 15, 100].into_iter().enumerate() {
+            let image = RgbaImage::from_pixel(4, 4, Rgba([i as u8, 0, 0, 255]));
+            optimizer.add_frame(&image, duration).unwrap();
+        }
+        let data = optimizer.into_inner().into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+        let out = decoder.to_constant_rate(50).unwrap().into_bytes().unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        let frames: Vec<_> = decoder.canvas_frames().map(Result::unwrap).collect();
+        assert!(frames.iter().all(|f| f.duration == 50));
+        // The frames start at 0, 95, 105 and 120 ms, so the outputs at 0,
+        // 50, 100 and 150 ms show frames 0, 1, 1 (a tie) and 3.
+        let shown: Vec<u8> = frames.iter().map(|f| f.image.get_pixel(0, 0)[0]).collect();
+        assert_eq!(shown, [0, 1, 1, 3]);
+        assert!(matches!(
+            decoder.to_constant_rate(0),
+            Err(EncodingError::InvalidDuration)
+        ));
+    }
+}
@@ -0,0 +1,306 @@
+use crate::{
+    Blend, CancellationToken, Disposal, FrameOptions, FrameRect, LoopCount, VP8X_HEADER_LEN,
+    padded_len, padding,
+    riff::{Chunks, u24_from_le, webp_payload},
+    u24_bytes,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodingError {
+    NotWebP,
+    NotAnimated,
+    Truncated,
+    InvalidChunk,
+}
+
+impl core::fmt::Display for DecodingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotWebP => write!(f, "not a WebP image"),
+            Self::NotAnimated => write!(f, "not an animated WebP image"),
+            Self::Truncated => write!(f, "truncated data"),
+            Self::InvalidChunk => write!(f, "invalid chunk"),
+        }
+    }
+}
+
+impl core::error::Error for DecodingError {}
+
+/// A frame of an animated WebP image.
+#[derive(Clone, Copy, Debug)]
+pub struct DecodedFrame<'a> {
+    pub rect: FrameRect,
+    /// The duration in milliseconds.
+    pub duration: u32,
+    pub dispose: Disposal,
+    pub blend: Blend,
+    /// The chunks making up the frame, usually an optional `ALPH` chunk
+    /// followed by a `VP8 ` or `VP8L` chunk.
+    pub data: &'a [u8],
+}
+
+impl DecodedFrame<'_> {
+    /// Returns the frame as a standalone still WebP image of the size of the
+    /// frame rectangle.
+    pub fn to_webp(&self) -> Vec<u8> {
+        let alpha = self.data.starts_with(b"ALPH");
+        let vp8x_len = if alpha { VP8X_HEADER_LEN } else { 0 };
+        let riff_size = 4 + vp8x_len + padded_len(self.data.len());
+        let mut out = Vec::with_capacity(riff_size + 8);
+        out.extend_from_slice(b"RIFF");
+        out.extend_from_slice(&(riff_size as u32).to_le_bytes());
+        out.extend_from_slice(b"WEBP");
+        if alpha {
+            out.extend_from_slice(b"VP8X");
+            out.extend_from_slice(&10u32.to_le_bytes());
+            out.extend_from_slice(&[0x10, 0, 0, 0]);
+            out.extend_from_slice(&u24_bytes(self.rect.width - 1));
+            out.extend_from_slice(&u24_bytes(self.rect.height - 1));
+        }
+        out.extend_from_slice(self.data);
+        out.extend_from_slice(padding(self.data.len()));
+        out
+    }
+
+    /// The options that would be used to add this frame to an animation.
+    pub fn options(&self) -> FrameOptions {
+        FrameOptions {
+            rect: Some(self.rect),
+            duration: self.duration,
+            dispose: self.dispose,
+            blend: self.blend,
+            ..FrameOptions::default()
+        }
+    }
+}
+
+/// Parses the container structure of an animated WebP image.  The frames
+/// themselves are not decoded.
+#[derive(Clone, Debug)]
+pub struct Decoder<'a> {
+    width: u32,
+    height: u32,
+    flags: u8,
+    background_bgra: [u8; 4],
+    loop_count: LoopCount,
+    icc_profile: Option<&'a [u8]>,
+    exif_metadata: Option<&'a [u8]>,
+    xmp_metadata: Option<&'a [u8]>,
+    frames: Vec<DecodedFrame<'a>>,
+    cancel: Option<CancellationToken>,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, DecodingError> {
+        let mut chunks = Chunks::new(webp_payload(data)?);
+        let vp8x = match chunks.next() {
+            Some(Ok(chunk)) if &chunk.fourcc == b"VP8X" => chunk.data,
+            Some(Ok(_)) => return Err(DecodingError::NotAnimated),
+            Some(Err(e)) => return Err(e),
+            None => return Err(DecodingError::Truncated),
+        };
+        if vp8x.len() < 10 {
+            return Err(DecodingError::InvalidChunk);
+        }
+        let flags = vp8x[0];
+        if flags & 0x2 == 0 {
+            return Err(DecodingError::NotAnimated);
+        }
+        let mut decoder = Self {
+            width: u24_from_le(&vp8x[4..]) + 1,
+            height: u24_from_le(&vp8x[7..]) + 1,
+            flags,
+            background_bgra: [0; 4],
+            loop_count: LoopCount::Infinite,
+            icc_profile: None,
+            exif_metadata: None,
+            xmp_metadata: None,
+            frames: Vec::new(),
+            cancel: None,
+        };
+        let mut has_anim = false;
+        for chunk in chunks {
+            let chunk = chunk?;
+            match &chunk.fourcc {
+                b"ICCP" => decoder.icc_profile = Some(chunk.data),
+                b"EXIF" => decoder.exif_metadata = Some(chunk.data),
+                b"XMP " => decoder.xmp_metadata = Some(chunk.data),
+                b"ANIM" => {
+                    if chunk.data.len() < 6 {
+                        return Err(DecodingError::InvalidChunk);
+                    }
+                    decoder.background_bgra = chunk.data[..4].try_into().unwrap();
+                    decoder.loop_count = u16::from_le_bytes([chunk.data[4], chunk.data[5]]).into();
+                    has_anim = true;
+                }
+                b"ANMF" => {
+                    if !has_anim || chunk.data.len() < 16 {
+                        return Err(DecodingError::InvalidChunk);
+                    }
+                    let d = chunk.data;
+                    let (dispose, blend) = FrameOptions::from_anmf_flags(d[15]);
+                    decoder.frames.push(DecodedFrame {
+                        rect: FrameRect {
+                            x: u24_from_le(&d[0..]) * 2,
+                            y: u24_from_le(&d[3..]) * 2,
+                            width: u24_from_le(&d[6..]) + 1,
+                            height: u24_from_le(&d[9..]) + 1,
+                        },
+                        duration: u24_from_le(&d[12..]),
+                        dispose,
+                        blend,
+                        data: &d[16..],
+                    });
+                }
+                _ => {}
+            }
+        }
+        if !has_anim {
+            return Err(DecodingError::NotAnimated);
+        }
+        Ok(decoder)
+    }
+
+    /// The canvas width.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The canvas height.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Stop operations that render or re-encode the frames, such as `crop`
+    /// or `to_gif`, once `token` is cancelled.  They then fail with
+    /// [`EncodingError::Cancelled`](crate::EncodingError::Cancelled).
+    /// Defaults to `None`.
+    pub fn set_cancellation_token(&mut self, token: Option<CancellationToken>) {
+        self.cancel = token;
+    }
+
+    pub fn cancellation_token(&self) -> Option<&CancellationToken> {
+        self.cancel.as_ref()
+    }
+
+    /// Fails with [`EncodingError::Cancelled`](crate::EncodingError::Cancelled)
+    /// if the cancellation token has been cancelled.
+    #[cfg(any(feature = "apng", feature = "gif"))]
+    pub(crate) fn check_cancelled(&self) -> Result<(), crate::EncodingError> {
+        crate::cancel::check(&self.cancel)
+    }
+
+    /// Whether the VP8X header indicates that the image has transparency.
+    pub fn has_alpha(&self) -> bool {
+        self.flags & 0x10 != 0
+    }
+
+    pub fn background_bgra(&self) -> [u8; 4] {
+        self.background_bgra
+    }
+
+    pub fn loop_count(&self) -> LoopCount {
+        self.loop_count
+    }
+
+    pub fn icc_profile(&self) -> Option<&'a [u8]> {
+        self.icc_profile
+    }
+
+    pub fn exif_metadata(&self) -> Option<&'a [u8]> {
+        self.exif_metadata
+    }
+
+    pub fn xmp_metadata(&self) -> Option<&'a [u8]> {
+        self.xmp_metadata
+    }
+
+    pub fn frames(&self) -> &[DecodedFrame<'a>] {
+        &self.frames
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgba, RgbaImage};
+
+    use crate::{
+        Blend, Decoder, DecodingError, Disposal, FrameOptions, FrameRect, LoopCount, Params,
+        WebPAnimator, test_util::lossless_webp,
+    };
+
+    #[test]
+    fn test_decode_libwebp() {
+        let mut encoder = webp_animation::Encoder::new((32, 16)).unwrap();
+        let red = [255, 0, 0, 255].repeat(32 * 16);
+        let blue = [0, 0, 255, 255].repeat(32 * 16);
+        encoder.add_frame(&red, 0).unwrap();
+        encoder.add_frame(&blue, 100).unwrap();
+        let data = encoder.finalize(250).unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (32, 16));
+        assert_eq!(decoder.loop_count(), LoopCount::Infinite);
+        let frames = decoder.frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].duration, 100);
+        assert_eq!(frames[1].duration, 150);
+        assert_eq!(
+            frames[0].rect,
+            FrameRect {
+                x: 0,
+                y: 0,
+                width: 32,
+                height: 16
+            }
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let params = Params {
+            width: 64,
+            height: 32,
+            background_bgra: [1, 2, 3, 4],
+            loop_count: LoopCount::from(3),
+            has_alpha: true,
+        };
+        let mut writer = WebPAnimator::new(params).unwrap();
+        let buf = lossless_webp(&RgbaImage::from_pixel(32, 16, Rgba([0, 255, 0, 128])));
+        let rect = FrameRect {
+            x: 8,
+            y: 4,
+            width: 32,
+            height: 16,
+        };
+        let options = FrameOptions {
+            rect: Some(rect),
+            duration: 70,
+            dispose: Disposal::Background,
+            blend: Blend::Overwrite,
+            ..FrameOptions::default()
+        };
+        writer.add_frame(&buf, options).unwrap();
+        let mut out = Vec::new();
+        writer.write(&mut out).unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (64, 32));
+        assert_eq!(decoder.background_bgra(), [1, 2, 3, 4]);
+        assert_eq!(u16::from(decoder.loop_count()), 3);
+        assert!(decoder.has_alpha());
+        let frames = decoder.frames();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].rect, rect);
+        assert_eq!(frames[0].duration, 70);
+        assert_eq!(frames[0].options(), options);
+        assert_eq!(frames[0].data, &buf[12..]);
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert_eq!(Decoder::new(b"RIFF").unwrap_err(), DecodingError::NotWebP);
+        let mut data = b"RIFF\x0c\0\0\0WEBPVP8L\0\0\0\0".to_vec();
+        assert_eq!(Decoder::new(&data).unwrap_err(), DecodingError::NotAnimated);
+        data[4] = 0x20;
+        assert_eq!(Decoder::new(&data).unwrap_err(), DecodingError::Truncated);
+    }
+}
@@ -0,0 +1,217 @@
+use std::fmt;
+
+use crate::{Decoder, EncodingError, LoopCount};
+
+/// A way in which two animations differ, found by [`diff`].  The values are
+/// those of the first animation, then the second.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Difference {
+    CanvasSize((u32, u32), (u32, u32)),
+    /// The background colors in BGRA order.
+    Background([u8; 4], [u8; 4]),
+    LoopCount(LoopCount, LoopCount),
+    HasAlpha(bool, bool),
+    /// The `ICCP`, `EXIF` or `XMP ` chunks differ, or only one animation has
+    /// one.
+    Metadata([u8; 4]),
+    FrameCount(usize, usize),
+    /// The durations of a frame, in milliseconds.
+    Duration {
+        frame: usize,
+        durations: (u32, u32),
+    },
+    /// Some pixels of a rendered frame differ by more than the tolerance.
+    Pixels {
+        frame: usize,
+        /// The number of pixels that differ.
+        count: usize,
+        /// The largest difference in any channel.
+        max_difference: u8,
+    },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CanvasSize((w1, h1), (w2, h2)) => {
+                write!(f, "canvas size: {w1}x{h1} vs {w2}x{h2}")
+            }
+            Self::Background(a, b) => write!(f, "background: {a:?} vs {b:?}"),
+            Self::LoopCount(a, b) => write!(f, "loop count: {a:?} vs {b:?}"),
+            Self::HasAlpha(a, b) => write!(f, "alpha: {a} vs {b}"),
+            Self::Metadata(fourcc) => {
+                write!(
+                    f,
+                    "{} chunk differs",
+                    String::from_utf8_lossy(fourcc).trim_end()
+                )
+            }
+            Self::FrameCount(a, b) => write!(f, "frame count: {a} vs {b}"),
+            Self::Duration {
+                frame,
+                durations: (a, b),
+            } => write!(f, "frame {frame}: duration {a} ms vs {b} ms"),
+            Self::Pixels {
+                frame,
+                count,
+                max_difference,
+            } => write!(
+                f,
+                "frame {frame}: {count} pixels differ, by up to {max_difference}"
+            ),
+        }
+    }
+}
+
+/// The result of [`diff`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DiffReport {
+    pub differences: Vec<Difference>,
+}
+
+impl DiffReport {
+    /// Whether the animations match.
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+/// Compares two animations: the canvas parameters, the metadata, the number
+/// of frames, their durations and their rendered pixels.
+///
+/// Frames are compared by index, up to the length of the shorter animation.
+/// A pixel differs if any channel differs by more than `tolerance`, except
+/// that fully transparent pixels always match.  Pixels aren't compared if
+/// the canvas sizes differ.  Since frames are rendered, animations that look
+/// the same match even if their frames are encoded differently.
+pub fn diff(a: &[u8], b: &[u8], tolerance: u8) -> Result<DiffReport, EncodingError> {
+    let (a, b) = (Decoder::new(a)?, Decoder::new(b)?);
+    let mut differences = Vec::new();
+    let size = |d: &Decoder| (d.width(), d.height());
+    if size(&a) != size(&b) {
+        differences.push(Difference::CanvasSize(size(&a), size(&b)));
+    }
+    if a.background_bgra() != b.background_bgra() {
+        differences.push(Difference::Background(
+            a.background_bgra(),
+            b.background_bgra(),
+        ));
+    }
+    if a.loop_count() != b.loop_count() {
+        differences.push(Difference::LoopCount(a.loop_count(), b.loop_count()));
+    }
+    if a.has_alpha() != b.has_alpha() {
+        differences.push(Difference::HasAlpha(a.has_alpha(), b.has_alpha()));
+    }
+    for (fourcc, x, y) in [
+        (*b"ICCP", a.icc_profile(), b.icc_profile()),
+        (*b"EXIF", a.exif_metadata(), b.exif_metadata()),
+        (*b"XMP ", a.xmp_metadata(), b.xmp_metadata()),
+    ] {
+        if x != y {
+            differences.push(Difference::Metadata(fourcc));
+        }
+    }
+    let (count_a, count_b) = (a.frames().len(), b.frames().len());
+    if count_a != count_b {
+        differences.push(Difference::FrameCount(count_a, count_b));
+    }
+    for (frame, (x, y)) in a.frames().iter().zip(b.frames()).enumerate() {
+        if x.duration != y.duration {
+            differences.push(Difference::Duration {
+                frame,
+                durations: (x.duration, y.duration),
+            });
+        }
+    }
+    if size(&a) == size(&b) {
+        for (frame, (x, y)) in a.canvas_frames().zip(b.canvas_frames()).enumerate() {
+            let (x, y) = (x?, y?);
+            let (mut count, mut max_difference) = (0, 0);
+            for (p, q) in x.image.pixels().zip(y.image.pixels()) {
+                if p.0[3] == 0 && q.0[3] == 0 {
+                    continue;
+                }
+                let largest = (0..4).map(|c| p.0[c].abs_diff(q.0[c])).max().unwrap_or(0);
+                if largest > tolerance {
+                    count += 1;
+                }
+                max_difference = max_difference.max(largest);
+            }
+            if count > 0 {
+                differences.push(Difference::Pixels {
+                    frame,
+                    count,
+                    max_difference,
+                });
+            }
+        }
+    }
+    Ok(DiffReport { differences })
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgba, RgbaImage};
+
+    use crate::{Difference, LoopCount, Optimizer, Params, WebPAnimator, diff};
+
+    fn animation(colors: &[([u8; 4], u32)], loop_count: u16) -> Vec<u8> {
+        let params = Params {
+            width: 4,
+            height: 4,
+            background_bgra: [0; 4],
+            loop_count: LoopCount::from(loop_count),
+            has_alpha: false,
+        };
+        let mut optimizer = Optimizer::new(WebPAnimator::new(params).unwrap());
+        for &(color, duration) in colors {
+            let image = RgbaImage::from_pixel(4, 4, Rgba(color));
+            optimizer.add_frame(&image, duration).unwrap();
+        }
+        optimizer.into_inner().into_bytes().unwrap()
+    }
+
+    #[test]
+    fn test_diff() {
+        let a = animation(&[([255, 0, 0, 255], 100), ([0, 0, 255, 255], 100)], 0);
+        assert!(diff(&a, &a, 0).unwrap().is_empty());
+
+        let b = animation(
+            &[
+                ([250, 0, 0, 255], 100),
+                ([0, 0, 255, 255], 50),
+                ([0, 255, 0, 255], 50),
+            ],
+            1,
+        );
+        let report = diff(&a, &b, 0).unwrap();
+        assert_eq!(
+            report.differences,
+            [
+                Difference::LoopCount(LoopCount::Infinite, LoopCount::from(1)),
+                Difference::FrameCount(2, 3),
+                Difference::Duration {
+                    frame: 1,
+                    durations: (100, 50)
+                },
+                Difference::Pixels {
+                    frame: 0,
+                    count: 16,
+                    max_difference: 5
+                },
+            ]
+        );
+        assert_eq!(
+            report.differences[3].to_string(),
+            "frame 0: 16 pixels differ, by up to 5"
+        );
+        let report = diff(&a, &b, 5).unwrap();
+        assert!(
+            !report
+                .differences
+                .iter()
+                .any(|d| matches!(d, Difference::Pixels { .. }))
+        );
+    }
+}
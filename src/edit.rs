@@ -0,0 +1,522 @@
+//! Operations that rebuild an existing animation.
+
+use image::{
+    DynamicImage, GenericImageView,
+    imageops::{self, FilterType},
+};
+
+use crate::{
+    Blend, Compositor, DecodedFrame, Decoder, Disposal, EncodingError, FrameOptions, FrameRect,
+    Optimizer, WebPAnimator, bitstream,
+    filters::{Filter, Transform},
+};
+
+impl Decoder<'_> {
+    /// Crops the animation to `rect`, which must lie within the canvas and
+    /// have even offsets.
+    ///
+    /// Frames inside `rect` are copied as they are, frames that cross its
+    /// edge are decoded, cropped and re-encoded losslessly, and frames
+    /// entirely outside it are dropped, with their duration added to the
+    /// previous frame.
+    pub fn crop(&self, rect: FrameRect) -> Result<WebPAnimator, EncodingError> {
+        let inside = (rect.x as u64 + rect.width as u64) <= self.width() as u64
+            && (rect.y as u64 + rect.height as u64) <= self.height() as u64;
+        if !inside || (rect.x | rect.y) & 1 != 0 {
+            return Err(EncodingError::InvalidDimensions);
+        }
+        let mut animator = self
+            .animator_builder()
+            .width(rect.width)
+            .height(rect.height)
+            .build()?;
+        for frame in self.frames() {
+            let Some(part) = intersect(frame.rect, rect) else {
+                if !animator.extend_last_duration(frame.duration) {
+                    // There is nothing to extend, so keep a transparent
+                    // pixel instead.
+                    let pixel = DynamicImage::new_rgba8(1, 1);
+                    animator.add_image(&pixel, FrameOptions::with_duration(frame.duration))?;
+                }
+                continue;
+            };
+            let options = FrameOptions {
+                rect: Some(FrameRect {
+                    x: part.x - rect.x,
+                    y: part.y - rect.y,
+                    ..part
+                }),
+                ..frame.options()
+            };
+            if part == frame.rect {
+                animator.add_chunk(frame.data, options)?;
+            } else {
+                animator.add_image(&crop_frame(frame, part)?, options)?;
+            }
+        }
+        Ok(animator)
+    }
+
+    /// Scales the animation to the given canvas size.  Each frame is
+    /// rendered, resized with `filter`, and re-encoded losslessly with an
+    /// [`Optimizer`].
+    pub fn resize(
+        &self,
+        width: u32,
+        height: u32,
+        filter: FilterType,
+    ) -> Result<WebPAnimator, EncodingError> {
+        let animator = self
+            .animator_builder()
+            .width(width)
+            .height(height)
+            .detect_alpha(true)
+            .merge_duplicate_frames(true)
+            .build()?;
+        let mut optimizer = Optimizer::new(animator);
+        for frame in self.canvas_frames() {
+            let frame = frame?;
+            let image = imageops::resize(&frame.image, width, height, filter);
+            optimizer.add_frame(&image, frame.duration)?;
+        }
+        Ok(optimizer.into_inner())
+    }
+
+    /// Rotates or flips the animation, swapping the canvas width and height
+    /// for quarter turns.
+    ///
+    /// Each frame is decoded, transformed and re-encoded losslessly, and
+    /// moved to where its rectangle ends up, keeping its disposal and
+    /// blending.  If that would put any frame at an odd offset, which the
+    /// format doesn't allow, each frame is rendered instead, and the result
+    /// is re-encoded with an [`Optimizer`].
+    pub fn transform(&self, transform: Transform) -> Result<WebPAnimator, EncodingError> {
+        let canvas = (self.width(), self.height());
+        let (width, height) = transform.size(canvas.0, canvas.1);
+        let builder = self.animator_builder().width(width).height(height);
+        let rects: Vec<_> = self
+            .frames()
+            .iter()
+            .map(|frame| transform.rect(frame.rect, canvas))
+            .collect();
+        if rects.iter().all(|rect| (rect.x | rect.y) & 1 == 0) {
+            let mut animator = builder.build()?;
+            for (frame, rect) in self.frames().iter().zip(rects) {
+                let image = transform.image(&frame.decode()?);
+                let options = FrameOptions {
+                    rect: Some(rect),
+                    ..frame.options()
+                };
+                animator.add_image(&image.into(), options)?;
+            }
+            return Ok(animator);
+        }
+        let mut optimizer = Optimizer::new(builder.detect_alpha(true).build()?);
+        for frame in self.canvas_frames() {
+            let frame = frame?;
+            optimizer.add_frame(&transform.image(&frame.image), frame.duration)?;
+        }
+        Ok(optimizer.into_inner())
+    }
+
+    /// Plays the animation backwards, keeping the duration of each frame.
+    /// See [`pingpong`](Self::pingpong) for how frames are handled.
+    pub fn reverse(&self) -> Result<WebPAnimator, EncodingError> {
+        self.reorder((0..self.frames().len()).rev())
+    }
+
+    /// Plays the animation forwards and then backwards, without repeating
+    /// the first and last frames, keeping the duration of each frame.
+    ///
+    /// If every frame covers the whole canvas without depending on the
+    /// frame before it, the frames are copied as they are.  Otherwise, each
+    /// frame is rendered and re-encoded losslessly with an [`Optimizer`].
+    pub fn pingpong(&self) -> Result<WebPAnimator, EncodingError> {
+        let n = self.frames().len();
+        self.reorder((0..n).chain((1..n.saturating_sub(1)).rev()))
+    }
+
+    /// Keeps the part of the animation from `start_ms` to `end_ms`,
+    /// shortening the frames at either end.
+    ///
+    /// The first frame is rendered and re-encoded losslessly to cover the
+    /// whole canvas, along with any following frames that depend on a
+    /// disposal that happened before them.  The remaining frames are copied
+    /// as they are.  Fails with [`EncodingError::NoFrames`] if no frame is
+    /// shown during the time range.
+    pub fn trim(&self, start_ms: u64, end_ms: u64) -> Result<WebPAnimator, EncodingError> {
+        if start_ms >= end_ms {
+            return Err(EncodingError::InvalidDuration);
+        }
+        let mut animator = self.animator_builder().detect_alpha(true).build()?;
+        let mut compositor = Compositor::new(self.width(), self.height());
+        // The rectangle that was cleared before the current frame but not
+        // in the output, if the output still needs to be rendered.
+        let mut pending = Some(FrameRect {
+            x: 0,
+            y: 0,
+            width: self.width(),
+            height: self.height(),
+        });
+        let mut time = 0;
+        for frame in self.frames() {
+            let (frame_start, frame_end) = (time, time + frame.duration as u64);
+            time = frame_end;
+            if frame_start >= end_ms {
+                break;
+            }
+            let shown = frame_end > start_ms || (frame.duration == 0 && frame_start >= start_ms);
+            if !shown {
+                compositor.add_frame(frame)?;
+                continue;
+            }
+            let duration = (frame_end.min(end_ms) - frame_start.max(start_ms)) as u32;
+            let Some(rect) = pending else {
+                let options = FrameOptions {
+                    duration,
+                    ..frame.options()
+                };
+                animator.add_chunk(frame.data, options)?;
+                continue;
+            };
+            let canvas = compositor.add_frame(frame)?;
+            let rect = union(rect, frame.rect);
+            let image = canvas.view(rect.x, rect.y, rect.width, rect.height);
+            let options = FrameOptions {
+                rect: Some(rect),
+                duration,
+                dispose: Disposal::None,
+                blend: Blend::Overwrite,
+                ..FrameOptions::default()
+            };
+            animator.add_image(&DynamicImage::from(image.to_image()), options)?;
+            pending = (frame.dispose == Disposal::Background).then_some(frame.rect);
+        }
+        if animator.frame_count() == 0 {
+            return Err(EncodingError::NoFrames);
+        }
+        Ok(animator)
+    }
+
+    fn reorder<I: Iterator<Item = usize>>(&self, order: I) -> Result<WebPAnimator, EncodingError> {
+        let canvas = FrameRect {
+            x: 0,
+            y: 0,
+            width: self.width(),
+            height: self.height(),
+        };
+        let independent = self.frames().iter().all(|frame| {
+            frame.rect == canvas
+                && (frame.blend == Blend::Overwrite || !bitstream::has_alpha(frame.data))
+        });
+        let mut animator = self.animator_builder().detect_alpha(true).build()?;
+        if independent {
+            for i in order {
+                let frame = &self.frames()[i];
+                animator.add_chunk(frame.data, frame.options())?;
+            }
+            return Ok(animator);
+        }
+        let frames = self.canvas_frames().collect::<Result<Vec<_>, _>>()?;
+        let mut optimizer = Optimizer::new(animator);
+        for i in order {
+            optimizer.add_frame(&frames[i].image, frames[i].duration)?;
+        }
+        Ok(optimizer.into_inner())
+    }
+}
+
+/// The part of `frame` that lies inside `part`, which must be contained in
+/// the frame rectangle.
+fn crop_frame(frame: &DecodedFrame, part: FrameRect) -> Result<DynamicImage, EncodingError> {
+    let image = frame.decode()?;
+    let view = image.view(
+        part.x - frame.rect.x,
+        part.y - frame.rect.y,
+        part.width,
+        part.height,
+    );
+    Ok(DynamicImage::from(view.to_image()))
+}
+
+/// The smallest rectangle containing two rectangles.
+fn union(a: FrameRect, b: FrameRect) -> FrameRect {
+    let x0 = a.x.min(b.x);
+    let y0 = a.y.min(b.y);
+    let x1 = (a.x + a.width).max(b.x + b.width);
+    let y1 = (a.y + a.height).max(b.y + b.height);
+    FrameRect {
+        x: x0,
+        y: y0,
+        width: x1 - x0,
+        height: y1 - y0,
+    }
+}
+
+/// The intersection of two rectangles, or `None` if it is empty.
+fn intersect(a: FrameRect, b: FrameRect) -> Option<FrameRect> {
+    let x0 = a.x.max(b.x);
+    let y0 = a.y.max(b.y);
+    let x1 = (a.x + a.width).min(b.x + b.width);
+    let y1 = (a.y + a.height).min(b.y + b.height);
+    (x0 < x1 && y0 < y1).then(|| FrameRect {
+        x: x0,
+        y: y0,
+        width: x1 - x0,
+        height: y1 - y0,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use image::{
+        DynamicImage, Rgba, RgbaImage,
+        imageops::{self, FilterType},
+    };
+
+    use crate::{
+        Decoder, Disposal, EncodingError, FrameOptions, FrameRect, LoopCount, Params, WebPAnimator,
+        filters::Transform, test_util::params,
+    };
+
+    fn rect(x: u32, y: u32, width: u32, height: u32) -> FrameRect {
+        FrameRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn test_crop() {
+        let params = Params {
+            width: 16,
+            height: 16,
+            background_bgra: [0; 4],
+            loop_count: LoopCount::from(2),
+            has_alpha: false,
+        };
+        let mut animator = WebPAnimator::new(params).unwrap();
+        let frames = [
+            (rect(0, 0, 16, 16), [255, 0, 0, 255]),
+            (rect(6, 6, 4, 4), [0, 255, 0, 255]),
+            (rect(0, 0, 2, 2), [0, 0, 255, 255]),
+            (rect(2, 8, 8, 8), [9, 9, 9, 255]),
+        ];
+        for (rect, color) in frames {
+            let image = RgbaImage::from_pixel(rect.width, rect.height, Rgba(color));
+            let options = FrameOptions {
+                rect: Some(rect),
+                ..FrameOptions::with_duration(100)
+            };
+            animator
+                .add_image(&DynamicImage::from(image), options)
+                .unwrap();
+        }
+        let data = animator.into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+        let crop = rect(4, 4, 8, 6);
+        let out = decoder.crop(crop).unwrap().into_bytes().unwrap();
+        let cropped = Decoder::new(&out).unwrap();
+        assert_eq!((cropped.width(), cropped.height()), (8, 6));
+        assert_eq!(cropped.loop_count(), LoopCount::from(2));
+        let durations: Vec<_> = cropped.frames().iter().map(|f| f.duration).collect();
+        assert_eq!(durations, [100, 200, 100]);
+        assert_eq!(cropped.frames()[1].rect, rect(2, 2, 4, 4));
+        assert_eq!(cropped.frames()[1].data, decoder.frames()[1].data);
+        let expected: Vec<_> = decoder
+            .canvas_frames()
+            .map(|f| imageops::crop_imm(&f.unwrap().image, 4, 4, 8, 6).to_image())
+            .collect();
+        let actual: Vec<_> = cropped.canvas_frames().map(|f| f.unwrap().image).collect();
+        assert_eq!(actual, [&expected[..2], &expected[3..]].concat());
+
+        assert!(matches!(
+            decoder.crop(rect(1, 0, 4, 4)),
+            Err(EncodingError::InvalidDimensions)
+        ));
+        assert!(matches!(
+            decoder.crop(rect(14, 0, 4, 4)),
+            Err(EncodingError::InvalidDimensions)
+        ));
+    }
+
+    #[test]
+    fn test_transform() {
+        let params = params(6, 4);
+        let mut animator = WebPAnimator::new(params).unwrap();
+        let image = RgbaImage::from_fn(6, 4, |x, y| Rgba([40 * x as u8, 60 * y as u8, 0, 255]));
+        animator
+            .add_image(&image.into(), FrameOptions::with_duration(100))
+            .unwrap();
+        let mut partial = |rect, dispose| {
+            let options = FrameOptions {
+                rect: Some(rect),
+                dispose,
+                ..FrameOptions::with_duration(100)
+            };
+            let image = RgbaImage::from_pixel(rect.width, rect.height, Rgba([0, 0, 255, 255]));
+            animator.add_image(&image.into(), options).unwrap();
+        };
+        partial(rect(2, 0, 2, 2), Disposal::Background);
+        partial(rect(0, 2, 3, 2), Disposal::None);
+        let data = animator.into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+        let expected = |transform: Transform| -> Vec<_> {
+            decoder
+                .canvas_frames()
+                .map(|f| transform.image(&f.unwrap().image))
+                .collect()
+        };
+
+        let out = decoder
+            .transform(Transform::Rotate90)
+            .unwrap()
+            .into_bytes()
+            .unwrap();
+        let rotated = Decoder::new(&out).unwrap();
+        assert_eq!((rotated.width(), rotated.height()), (4, 6));
+        let rects: Vec<_> = rotated.frames().iter().map(|f| f.rect).collect();
+        assert_eq!(
+            rects,
+            [rect(0, 0, 4, 6), rect(2, 2, 2, 2), rect(0, 0, 2, 3)]
+        );
+        assert_eq!(rotated.frames()[1].dispose, Disposal::Background);
+        let actual: Vec<_> = rotated.canvas_frames().map(|f| f.unwrap().image).collect();
+        assert_eq!(actual, expected(Transform::Rotate90));
+
+        // The last frame would end up at x = 3, so the frames are rendered.
+        let out = decoder
+            .transform(Transform::FlipHorizontal)
+            .unwrap()
+            .into_bytes()
+            .unwrap();
+        let flipped = Decoder::new(&out).unwrap();
+        assert_eq!((flipped.width(), flipped.height()), (6, 4));
+        let actual: Vec<_> = flipped.canvas_frames().map(|f| f.unwrap().image).collect();
+        assert_eq!(actual, expected(Transform::FlipHorizontal));
+    }
+
+    #[test]
+    fn test_resize() {
+        let params = params(16, 8);
+        let mut animator = WebPAnimator::new(params).unwrap();
+        let image = RgbaImage::from_fn(16, 8, |x, y| Rgba([x as u8 * 16, y as u8 * 32, 0, 255]));
+        animator
+            .add_image(&DynamicImage::from(image), FrameOptions::with_duration(70))
+            .unwrap();
+        let small = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 255, 255]));
+        let options = FrameOptions {
+            rect: Some(rect(4, 2, 4, 4)),
+            ..FrameOptions::with_duration(30)
+        };
+        animator
+            .add_image(&DynamicImage::from(small), options)
+            .unwrap();
+        let data = animator.into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+        for filter in [
+            FilterType::Nearest,
+            FilterType::Triangle,
+            FilterType::Lanczos3,
+        ] {
+            let out = decoder.resize(8, 4, filter).unwrap().into_bytes().unwrap();
+            let resized = Decoder::new(&out).unwrap();
+            assert_eq!((resized.width(), resized.height()), (8, 4));
+            let durations: Vec<_> = resized.frames().iter().map(|f| f.duration).collect();
+            assert_eq!(durations, [70, 30]);
+            for (actual, expected) in resized.canvas_frames().zip(decoder.canvas_frames()) {
+                let expected = imageops::resize(&expected.unwrap().image, 8, 4, filter);
+                assert_eq!(actual.unwrap().image, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reverse() {
+        let params = params(8, 8);
+        let mut full = WebPAnimator::new(params).unwrap();
+        let mut delta = WebPAnimator::new(params).unwrap();
+        for i in 0..4u8 {
+            let image = RgbaImage::from_pixel(8, 8, Rgba([i * 60, 0, 0, 255]));
+            let options = FrameOptions::with_duration(10 * (i as u32 + 1));
+            full.add_image(&DynamicImage::from(image), options).unwrap();
+            let image = RgbaImage::from_pixel(2, 2, Rgba([0, i * 60, 0, 255]));
+            let options = FrameOptions {
+                rect: Some(rect(2 * i as u32, 0, 2, 2)),
+                ..options
+            };
+            delta
+                .add_image(&DynamicImage::from(image), options)
+                .unwrap();
+        }
+        let full = full.into_bytes().unwrap();
+        let full = Decoder::new(&full).unwrap();
+        let out = full.pingpong().unwrap().into_bytes().unwrap();
+        let pingpong = Decoder::new(&out).unwrap();
+        let data: Vec<_> = pingpong.frames().iter().map(|f| f.data).collect();
+        let order = [0, 1, 2, 3, 2, 1];
+        let expected: Vec<_> = order.iter().map(|&i| full.frames()[i].data).collect();
+        assert_eq!(data, expected);
+
+        let delta = delta.into_bytes().unwrap();
+        let delta = Decoder::new(&delta).unwrap();
+        let expected: Vec<_> = delta.canvas_frames().map(|f| f.unwrap()).collect();
+        let out = delta.reverse().unwrap().into_bytes().unwrap();
+        let reversed = Decoder::new(&out).unwrap();
+        let actual: Vec<_> = reversed.canvas_frames().map(|f| f.unwrap()).collect();
+        assert_eq!(actual.len(), 4);
+        for (actual, expected) in actual.iter().zip(expected.iter().rev()) {
+            assert_eq!(actual.image, expected.image);
+            assert_eq!(actual.duration, expected.duration);
+        }
+    }
+
+    #[test]
+    fn test_trim() {
+        let params = Params {
+            width: 8,
+            height: 8,
+            background_bgra: [0; 4],
+            loop_count: LoopCount::Infinite,
+            has_alpha: true,
+        };
+        let mut animator = WebPAnimator::new(params).unwrap();
+        let frames = [
+            (rect(0, 0, 8, 8), [255, 0, 0, 255], Disposal::None),
+            (rect(2, 2, 4, 4), [0, 255, 0, 255], Disposal::Background),
+            (rect(4, 0, 2, 6), [0, 0, 255, 128], Disposal::Background),
+            (rect(0, 6, 8, 2), [9, 9, 9, 255], Disposal::None),
+            (rect(0, 0, 2, 2), [0, 0, 255, 255], Disposal::None),
+        ];
+        for (rect, color, dispose) in frames {
+            let image = RgbaImage::from_pixel(rect.width, rect.height, Rgba(color));
+            let options = FrameOptions {
+                rect: Some(rect),
+                dispose,
+                ..FrameOptions::with_duration(100)
+            };
+            animator
+                .add_image(&DynamicImage::from(image), options)
+                .unwrap();
+        }
+        let data = animator.into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+        let expected: Vec<_> = decoder.canvas_frames().map(|f| f.unwrap().image).collect();
+        let out = decoder.trim(150, 460).unwrap().into_bytes().unwrap();
+        let trimmed = Decoder::new(&out).unwrap();
+        let actual: Vec<_> = trimmed.canvas_frames().map(|f| f.unwrap()).collect();
+        let durations: Vec<_> = actual.iter().map(|f| f.duration).collect();
+        assert_eq!(durations, [50, 100, 100, 60]);
+        for (actual, expected) in actual.iter().zip(&expected[1..]) {
+            assert_eq!(&actual.image, expected);
+        }
+        // Only the frames up to the disposals are re-encoded.
+        assert_eq!(trimmed.frames()[3].data, decoder.frames()[4].data);
+        assert!(matches!(
+            decoder.trim(500, 600),
+            Err(EncodingError::NoFrames)
+        ));
+    }
+}
@@ -0,0 +1,478 @@
+use std::borrow::Cow;
+
+use image::{
+    Delay, DynamicImage, Frame, GenericImageView, Rgba, RgbaImage, codecs::webp::WebPEncoder,
+};
+
+use crate::{Blend, Disposal, EncodingError, FrameOptions, FrameRect, OddOffsets, WebPAnimator};
+
+/// Rounds `delay` to the nearest millisecond.
+pub(crate) fn delay_ms(delay: Delay) -> u32 {
+    let (num, den) = delay.numer_denom_ms();
+    ((num as u64 * 2 + den as u64) / (den as u64 * 2)) as u32
+}
+
+/// Encodes `image` as a lossless WebP image.  Color types not supported by
+/// the WebP encoder are converted to 8-bit RGB or RGBA first.
+pub(crate) fn encode_lossless(image: &DynamicImage) -> Result<Vec<u8>, EncodingError> {
+    trace_span!(
+        TRACE,
+        "encode_lossless",
+        width = image.width(),
+        height = image.height()
+    );
+    let mut buf = Vec::new();
+    let encoder = WebPEncoder::new_lossless(&mut buf);
+    match image {
+        DynamicImage::ImageLuma8(_)
+        | DynamicImage::ImageLumaA8(_)
+        | DynamicImage::ImageRgb8(_)
+        | DynamicImage::ImageRgba8(_) => image.write_with_encoder(encoder)?,
+        _ if image.color().has_alpha() => image.to_rgba8().write_with_encoder(encoder)?,
+        _ => image.to_rgb8().write_with_encoder(encoder)?,
+    }
+    trace_event!(
+        trace,
+        "encoded {}x{} image losslessly in {} bytes",
+        image.width(),
+        image.height(),
+        buf.len()
+    );
+    Ok(buf)
+}
+
+/// Pads `image` if it has an odd offset and `odd_offsets` asks for padding.
+fn pad_odd_offset(
+    image: &DynamicImage,
+    options: FrameOptions,
+    odd_offsets: OddOffsets,
+) -> (Cow<'_, DynamicImage>, FrameOptions) {
+    let Some(rect) = options.rect else {
+        return (Cow::Borrowed(image), options);
+    };
+    let (left, top) = (rect.x & 1, rect.y & 1);
+    let edge = match odd_offsets {
+        _ if left | top == 0 => return (Cow::Borrowed(image), options),
+        OddOffsets::PadTransparent => false,
+        OddOffsets::PadEdge => true,
+        OddOffsets::Reject | OddOffsets::Snap => return (Cow::Borrowed(image), options),
+    };
+    let padded = pad(&image.to_rgba8(), left, top, edge);
+    let options = FrameOptions {
+        rect: Some(FrameRect {
+            x: rect.x - left,
+            y: rect.y - top,
+            width: padded.width(),
+            height: padded.height(),
+        }),
+        ..options
+    };
+    (Cow::Owned(padded.into()), options)
+}
+
+/// Adds `left` columns and `top` rows on the top left of `image`, either
+/// transparent or copied from the nearest edge pixel.
+fn pad(image: &RgbaImage, left: u32, top: u32, edge: bool) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    RgbaImage::from_fn(width + left, height + top, |x, y| {
+        match (x.checked_sub(left), y.checked_sub(top)) {
+            (Some(x), Some(y)) => *image.get_pixel(x, y),
+            (x, y) if edge => *image.get_pixel(x.unwrap_or(0), y.unwrap_or(0)),
+            _ => Rgba([0; 4]),
+        }
+    })
+}
+
+/// Returns the smallest rectangle containing every pixel of `image` that
+/// isn't fully transparent, with even offsets, or `None` if there is none.
+fn visible_rect(image: &RgbaImage) -> Option<FrameRect> {
+    let (mut x0, mut y0, mut x1, mut y1) = (u32::MAX, u32::MAX, 0, 0);
+    for (x, y, p) in image.enumerate_pixels() {
+        if p[3] != 0 {
+            x0 = x0.min(x);
+            y0 = y0.min(y);
+            x1 = x1.max(x + 1);
+            y1 = y1.max(y + 1);
+        }
+    }
+    if x0 == u32::MAX {
+        return None;
+    }
+    x0 &= !1;
+    y0 &= !1;
+    Some(FrameRect {
+        x: x0,
+        y: y0,
+        width: x1 - x0,
+        height: y1 - y0,
+    })
+}
+
+impl WebPAnimator {
+    /// Checks that the frame rectangle, if given, has the same size as
+    /// `image`, so that mismatches are caught before encoding, then applies
+    /// the odd offset policy and trims transparent borders if enabled.
+    pub(crate) fn image_options<'a>(
+        &self,
+        image: &'a DynamicImage,
+        options: FrameOptions,
+    ) -> Result<(Cow<'a, DynamicImage>, FrameOptions), EncodingError> {
+        if options
+            .rect
+            .is_some_and(|rect| (rect.width, rect.height) != image.dimensions())
+        {
+            return Err(EncodingError::InvalidDimensions);
+        }
+        let (image, options) = pad_odd_offset(image, options, self.odd_offsets);
+        if !self.trim_transparent
+            || options.blend != Blend::Alpha
+            || options.dispose != Disposal::None
+            || !image.color().has_alpha()
+            || (options.rect.is_none() && self.auto_canvas)
+        {
+            return Ok((image, options));
+        }
+        let placed = options.rect.or_else(|| {
+            let canvas = (self.width, self.height);
+            options.anchor.place(canvas, image.dimensions())
+        });
+        let Some(rect) = placed else {
+            return Ok((image, options));
+        };
+        let rgba = image.to_rgba8();
+        // A fully transparent frame still needs a pixel.
+        let visible = visible_rect(&rgba).unwrap_or(FrameRect {
+            x: 0,
+            y: 0,
+            width: 1,
+            height: 1,
+        });
+        if (visible.width, visible.height) == image.dimensions() {
+            return Ok((image, options));
+        }
+        let trimmed = rgba
+            .view(visible.x, visible.y, visible.width, visible.height)
+            .to_image();
+        let options = FrameOptions {
+            rect: Some(FrameRect {
+                x: rect.x + visible.x,
+                y: rect.y + visible.y,
+                ..visible
+            }),
+            ..options
+        };
+        Ok((Cow::Owned(trimmed.into()), options))
+    }
+
+    /// Encode an image losslessly and add it to the animation.
+    ///
+    /// * `options` - The frame settings.  If `options.rect` is given, then
+    ///   its width and height must match the image.  Otherwise, the frame is
+    ///   placed according to `options.anchor`.
+    pub fn add_image(
+        &mut self,
+        image: &DynamicImage,
+        options: FrameOptions,
+    ) -> Result<(), EncodingError> {
+        let (image, options) = self.image_options(image, options)?;
+        let data = encode_lossless(&image)?;
+        self.add_frame(&data, options)
+    }
+
+    /// Like [`add_image`](Self::add_image), but adds several images,
+    /// encoding them in parallel.  The frames are added in the order of the
+    /// iterator.
+    #[cfg(feature = "rayon")]
+    pub fn par_add_images<'a, I>(&mut self, frames: I) -> Result<(), EncodingError>
+    where
+        I: rayon::iter::IntoParallelIterator<Item = (&'a DynamicImage, FrameOptions)>,
+    {
+        use rayon::iter::ParallelIterator;
+
+        let encoded: Vec<_> = frames
+            .into_par_iter()
+            .map(|(image, options)| {
+                let (image, options) = self.image_options(image, options)?;
+                Ok::<_, EncodingError>((options, encode_lossless(&image)?))
+            })
+            .collect();
+        for result in encoded {
+            let (options, data) = result?;
+            self.add_frame(&data, options)?;
+        }
+        Ok(())
+    }
+
+    /// Encode frames from the `image` crate losslessly, like
+    /// [`GifEncoder::encode_frames`](image::codecs::gif::GifEncoder::encode_frames).
+    ///
+    /// Each frame is alpha-blended onto the canvas at its offset, and the
+    /// delay is rounded to the nearest millisecond.  Frames at odd offsets
+    /// are padded with transparent pixels on the top and left.
+    pub fn encode_frames<F>(&mut self, frames: F) -> Result<(), EncodingError>
+    where
+        F: IntoIterator<Item = Frame>,
+    {
+        for frame in frames {
+            let duration = delay_ms(frame.delay());
+            let (left, top) = (frame.left(), frame.top());
+            let mut image = frame.into_buffer();
+            if (left | top) & 1 != 0 {
+                image = pad(&image, left & 1, top & 1, false);
+            }
+            let options = FrameOptions {
+                rect: Some(FrameRect {
+                    x: left & !1,
+                    y: top & !1,
+                    width: image.width(),
+                    height: image.height(),
+                }),
+                duration,
+                dispose: Disposal::None,
+                blend: Blend::Alpha,
+                ..FrameOptions::default()
+            };
+            self.add_image(&DynamicImage::from(image), options)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Delay, DynamicImage, Frame, Rgb, Rgb32FImage, Rgba, RgbaImage};
+
+    use crate::{
+        Anchor, Blend, Decoder, EncodingError, FrameOptions, FrameRect, LoopCount, OddOffsets,
+        Params, Validation, WebPAnimator,
+    };
+
+    #[test]
+    fn test_add_image() {
+        let params = Params {
+            width: 32,
+            height: 32,
+            background_bgra: [0; 4],
+            loop_count: LoopCount::Infinite,
+            has_alpha: true,
+        };
+        let mut writer = WebPAnimator::new(params).unwrap();
+        let opaque = DynamicImage::from(Rgb32FImage::from_pixel(32, 32, Rgb([1.0, 0.5, 0.0])));
+        writer
+            .add_image(&opaque, FrameOptions::with_duration(100))
+            .unwrap();
+        let small = DynamicImage::from(RgbaImage::new(8, 8));
+        let options = FrameOptions {
+            rect: Some(FrameRect {
+                x: 2,
+                y: 4,
+                width: 8,
+                height: 8,
+            }),
+            duration: 100,
+            ..FrameOptions::default()
+        };
+        writer.add_image(&small, options).unwrap();
+        assert!(matches!(
+            writer.add_image(&opaque, options),
+            Err(EncodingError::InvalidDimensions)
+        ));
+        let mut out = Vec::new();
+        writer.write(&mut out).unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        assert_eq!(decoder.frames().len(), 2);
+        assert_eq!(decoder.frames()[1].rect, options.rect.unwrap());
+        webp_animation::Decoder::new(&out).unwrap();
+    }
+
+    #[test]
+    fn test_odd_offsets() {
+        let mut writer = WebPAnimator::builder()
+            .width(16)
+            .height(16)
+            .validation(Validation::Strict)
+            .build()
+            .unwrap();
+        let image = DynamicImage::from(RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255])));
+        let options = FrameOptions {
+            rect: Some(FrameRect {
+                x: 3,
+                y: 2,
+                width: 4,
+                height: 4,
+            }),
+            ..FrameOptions::with_duration(100)
+        };
+        assert!(matches!(
+            writer.add_image(&image, options),
+            Err(EncodingError::InvalidDimensions)
+        ));
+        for odd_offsets in [
+            OddOffsets::Snap,
+            OddOffsets::PadTransparent,
+            OddOffsets::PadEdge,
+        ] {
+            writer.set_odd_offsets(odd_offsets);
+            writer.add_image(&image, options).unwrap();
+        }
+        let rects: Vec<_> = writer.frames().iter().map(|f| f.rect()).collect();
+        let padded = FrameRect {
+            x: 2,
+            y: 2,
+            width: 5,
+            height: 4,
+        };
+        assert_eq!(
+            rects[0],
+            FrameRect {
+                x: 2,
+                ..options.rect.unwrap()
+            }
+        );
+        assert_eq!(rects[1..], [padded, padded]);
+        let out = writer.into_bytes().unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        let corner = |i: usize| {
+            let frame = image::load_from_memory(&decoder.frames()[i].to_webp()).unwrap();
+            frame.to_rgba8().get_pixel(0, 0).0
+        };
+        assert_eq!(corner(1), [0, 0, 0, 0]);
+        assert_eq!(corner(2), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_trim_transparent() {
+        let mut writer = WebPAnimator::builder()
+            .width(32)
+            .height(32)
+            .has_alpha(true)
+            .trim_transparent(true)
+            .build()
+            .unwrap();
+        let mut sticker = RgbaImage::new(16, 16);
+        for y in 5..9 {
+            for x in 7..12 {
+                sticker.put_pixel(x, y, Rgba([0, 255, 0, 255]));
+            }
+        }
+        let options = FrameOptions {
+            anchor: Anchor::Center,
+            ..FrameOptions::with_duration(100)
+        };
+        writer.add_image(&sticker.clone().into(), options).unwrap();
+        writer
+            .add_image(&RgbaImage::new(16, 16).into(), options)
+            .unwrap();
+        let overwrite = FrameOptions {
+            blend: Blend::Overwrite,
+            ..options
+        };
+        writer
+            .add_image(&sticker.clone().into(), overwrite)
+            .unwrap();
+        let rects: Vec<_> = writer.frames().iter().map(|f| f.rect()).collect();
+        assert_eq!(
+            rects,
+            [
+                FrameRect {
+                    x: 14,
+                    y: 12,
+                    width: 6,
+                    height: 5
+                },
+                FrameRect {
+                    x: 8,
+                    y: 8,
+                    width: 1,
+                    height: 1
+                },
+                FrameRect {
+                    x: 8,
+                    y: 8,
+                    width: 16,
+                    height: 16
+                },
+            ]
+        );
+        let out = writer.into_bytes().unwrap();
+        let first = webp_animation::Decoder::new(&out)
+            .unwrap()
+            .into_iter()
+            .next()
+            .unwrap();
+        let pixel = |x: usize, y: usize| first.data()[(y * 32 + x) * 4..][..4].to_vec();
+        assert_eq!(pixel(15, 13), [0, 255, 0, 255]);
+        assert_eq!(pixel(14, 13), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_encode_frames() {
+        let params = Params {
+            width: 16,
+            height: 16,
+            background_bgra: [0; 4],
+            loop_count: LoopCount::Infinite,
+            has_alpha: true,
+        };
+        let mut writer = WebPAnimator::new(params).unwrap();
+        let red = RgbaImage::from_pixel(16, 16, Rgba([255, 0, 0, 255]));
+        let blue = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 255, 255]));
+        writer
+            .encode_frames([
+                Frame::from_parts(red, 0, 0, Delay::from_numer_denom_ms(100, 3)),
+                Frame::from_parts(blue, 3, 5, Delay::from_numer_denom_ms(50, 1)),
+            ])
+            .unwrap();
+        let mut out = Vec::new();
+        writer.write(&mut out).unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        let frames = decoder.frames();
+        assert_eq!((frames[0].duration, frames[1].duration), (33, 50));
+        assert_eq!(
+            frames[1].rect,
+            FrameRect {
+                x: 2,
+                y: 4,
+                width: 5,
+                height: 5
+            }
+        );
+        let canvas = decoder.canvas_frames().last().unwrap().unwrap().image;
+        assert_eq!(canvas.get_pixel(2, 4), &Rgba([255, 0, 0, 255]));
+        assert_eq!(canvas.get_pixel(3, 5), &Rgba([0, 0, 255, 255]));
+        assert_eq!(canvas.get_pixel(6, 8), &Rgba([0, 0, 255, 255]));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_par_add_images() {
+        use image::RgbImage;
+        use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+        use crate::test_util::params;
+
+        let params = params(16, 16);
+        let images: Vec<_> = (0..8)
+            .map(|i| DynamicImage::from(RgbImage::from_pixel(16, 16, Rgb([i * 30, 0, 0]))))
+            .collect();
+        let mut sequential = WebPAnimator::new(params).unwrap();
+        for (i, image) in images.iter().enumerate() {
+            sequential
+                .add_image(image, FrameOptions::with_duration(i as u32))
+                .unwrap();
+        }
+        let mut parallel = WebPAnimator::new(params).unwrap();
+        parallel
+            .par_add_images(
+                images
+                    .par_iter()
+                    .enumerate()
+                    .map(|(i, image)| (image, FrameOptions::with_duration(i as u32))),
+            )
+            .unwrap();
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        sequential.write(&mut a).unwrap();
+        parallel.write(&mut b).unwrap();
+        assert_eq!(a, b);
+    }
+}
@@ -0,0 +1,442 @@
+//! Transforms applied to each frame of an animation before it is encoded.
+//!
+//! [`Filtered`] wraps a [`FrameSource`], renders its frames onto the canvas
+//! and passes each one through a chain of [`Filter`]s, lazily, as frames are
+//! requested.  Filters are added like iterator adapters:
+//!
+//! ```no_run
+//! # fn main() -> Result<(), webp_animator::EncodingError> {
+//! use image::imageops::FilterType;
+//! use webp_animator::{
+//!     Decoder, FrameRect, FrameSink, Optimizer, WebPAnimator,
+//!     filters::{ColorAdjust, FilterExt},
+//! };
+//! let data = std::fs::read("in.webp")?;
+//! let decoder = Decoder::new(&data)?;
+//! let frames = decoder
+//!     .canvas_frames()
+//!     .filtered(decoder.width(), decoder.height())
+//!     .crop(FrameRect { x: 0, y: 0, width: 200, height: 100 })
+//!     .resize(100, 50, FilterType::Triangle)
+//!     .adjust_colors(ColorAdjust { saturation: 0.0, ..ColorAdjust::default() });
+//! let (width, height) = frames.size();
+//! let animator = WebPAnimator::builder().width(width).height(height).build()?;
+//! let mut optimizer = Optimizer::new(animator);
+//! optimizer.add_frames_from(frames)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use image::{
+    ImageFormat, Rgba, RgbaImage,
+    imageops::{self, FilterType},
+};
+
+use crate::{
+    Blend, Compositor, Disposal, EncodingError, FrameRect, FrameSource, InputFrame, Overlay,
+};
+
+/// A transform of full-canvas frames.
+///
+/// Closures taking and returning an [`RgbaImage`] are filters that keep the
+/// canvas size.
+pub trait Filter {
+    /// The canvas size after the filter, given the size before it.
+    fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        (width, height)
+    }
+
+    /// Transforms the frame shown from `start_ms` milliseconds after the
+    /// start.  The result must have the size returned by
+    /// [`size`](Self::size).
+    fn apply(&mut self, image: RgbaImage, start_ms: u64) -> Result<RgbaImage, EncodingError>;
+}
+
+impl<F: FnMut(RgbaImage) -> RgbaImage> Filter for F {
+    fn apply(&mut self, image: RgbaImage, _start_ms: u64) -> Result<RgbaImage, EncodingError> {
+        Ok(self(image))
+    }
+}
+
+/// Keeps the part of the canvas inside the rectangle.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Crop(pub FrameRect);
+
+impl Filter for Crop {
+    fn size(&self, _width: u32, _height: u32) -> (u32, u32) {
+        (self.0.width, self.0.height)
+    }
+
+    /// Fails with [`EncodingError::InvalidDimensions`] if the rectangle
+    /// extends past the canvas.
+    fn apply(&mut self, image: RgbaImage, _start_ms: u64) -> Result<RgbaImage, EncodingError> {
+        let FrameRect {
+            x,
+            y,
+            width,
+            height,
+        } = self.0;
+        if x as u64 + width as u64 > image.width() as u64
+            || y as u64 + height as u64 > image.height() as u64
+        {
+            return Err(EncodingError::InvalidDimensions);
+        }
+        Ok(imageops::crop_imm(&image, x, y, width, height).to_image())
+    }
+}
+
+/// Scales the canvas to the given size, ignoring the aspect ratio.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Resize {
+    pub width: u32,
+    pub height: u32,
+    pub filter: FilterType,
+}
+
+impl Filter for Resize {
+    fn size(&self, _width: u32, _height: u32) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn apply(&mut self, image: RgbaImage, _start_ms: u64) -> Result<RgbaImage, EncodingError> {
+        Ok(imageops::resize(
+            &image,
+            self.width,
+            self.height,
+            self.filter,
+        ))
+    }
+}
+
+/// A rotation or flip of the canvas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transform {
+    /// Rotates 90 degrees clockwise.
+    Rotate90,
+    Rotate180,
+    /// Rotates 270 degrees clockwise, or 90 degrees counterclockwise.
+    Rotate270,
+    /// Mirrors left to right.
+    FlipHorizontal,
+    /// Mirrors top to bottom.
+    FlipVertical,
+}
+
+impl Transform {
+    /// Whether the width and height are swapped.
+    fn swaps_sides(self) -> bool {
+        matches!(self, Self::Rotate90 | Self::Rotate270)
+    }
+
+    /// Applies the transform to `image`.
+    pub(crate) fn image(self, image: &RgbaImage) -> RgbaImage {
+        match self {
+            Self::Rotate90 => imageops::rotate90(image),
+            Self::Rotate180 => imageops::rotate180(image),
+            Self::Rotate270 => imageops::rotate270(image),
+            Self::FlipHorizontal => imageops::flip_horizontal(image),
+            Self::FlipVertical => imageops::flip_vertical(image),
+        }
+    }
+
+    /// Where `rect` on a canvas of size `canvas` ends up.
+    pub(crate) fn rect(self, rect: FrameRect, (width, height): (u32, u32)) -> FrameRect {
+        let FrameRect { x, y, .. } = rect;
+        let right = width - x - rect.width;
+        let bottom = height - y - rect.height;
+        let (x, y) = match self {
+            Self::Rotate90 => (bottom, x),
+            Self::Rotate180 => (right, bottom),
+            Self::Rotate270 => (y, right),
+            Self::FlipHorizontal => (right, y),
+            Self::FlipVertical => (x, bottom),
+        };
+        let (width, height) = self.size(rect.width, rect.height);
+        FrameRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+impl Filter for Transform {
+    fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        match self.swaps_sides() {
+            true => (height, width),
+            false => (width, height),
+        }
+    }
+
+    fn apply(&mut self, image: RgbaImage, _start_ms: u64) -> Result<RgbaImage, EncodingError> {
+        Ok(self.image(&image))
+    }
+}
+
+/// Adjusts the colors of each pixel.  Alpha is unchanged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ColorAdjust {
+    /// Added to each channel, as a fraction of the full range, so that `0.1`
+    /// brightens by 10% and `-0.1` darkens.
+    pub brightness: f32,
+    /// Scales the distance of each channel from the middle of the range.
+    /// `1` keeps the contrast and `0` makes everything gray.
+    pub contrast: f32,
+    /// Scales the distance of each channel from the pixel's luma.  `1` keeps
+    /// the colors and `0` makes the image grayscale.
+    pub saturation: f32,
+}
+
+impl Default for ColorAdjust {
+    /// No change.
+    fn default() -> Self {
+        Self {
+            brightness: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+        }
+    }
+}
+
+impl Filter for ColorAdjust {
+    fn apply(&mut self, mut image: RgbaImage, _start_ms: u64) -> Result<RgbaImage, EncodingError> {
+        for Rgba([r, g, b, _]) in image.pixels_mut() {
+            let luma = 0.299 * *r as f32 + 0.587 * *g as f32 + 0.114 * *b as f32;
+            for c in [r, g, b] {
+                let saturated = luma + (*c as f32 - luma) * self.saturation;
+                let value = (saturated - 127.5) * self.contrast + 127.5 + self.brightness * 255.0;
+                *c = value.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+        Ok(image)
+    }
+}
+
+/// Draws the overlay on the frames shown while it is.  Unlike
+/// [`Decoder::overlay`](crate::Decoder::overlay), frames aren't split where
+/// the overlay appears or disappears.
+impl Filter for Overlay {
+    fn apply(&mut self, mut image: RgbaImage, start_ms: u64) -> Result<RgbaImage, EncodingError> {
+        if self.shown_at(start_ms) {
+            self.draw(&mut image);
+        }
+        Ok(image)
+    }
+}
+
+/// The frames of a [`FrameSource`], rendered onto the canvas and passed
+/// through a chain of [`Filter`]s.  Created with [`Filtered::new`] or
+/// [`FilterExt::filtered`].
+///
+/// It is itself a [`FrameSource`] of full-canvas images of size
+/// [`size`](Self::size), so it is best written to an
+/// [`Optimizer`](crate::Optimizer).  Encoded frames without a rectangle
+/// are placed according to their anchor, and parts of frames outside the
+/// canvas are clipped.
+pub struct Filtered<S> {
+    source: S,
+    compositor: Compositor,
+    filters: Vec<Box<dyn Filter>>,
+    size: (u32, u32),
+    start_ms: u64,
+}
+
+impl<S: FrameSource> Filtered<S> {
+    /// Renders the frames of `source` onto a canvas of the given size.
+    pub fn new(source: S, width: u32, height: u32) -> Self {
+        Self {
+            source,
+            compositor: Compositor::new(width, height),
+            filters: Vec::new(),
+            size: (width, height),
+            start_ms: 0,
+        }
+    }
+
+    /// Adds a filter to the end of the chain.
+    pub fn filter<F: Filter + 'static>(mut self, filter: F) -> Self {
+        self.size = filter.size(self.size.0, self.size.1);
+        self.filters.push(Box::new(filter));
+        self
+    }
+
+    /// Adds a [`Crop`] filter.
+    pub fn crop(self, rect: FrameRect) -> Self {
+        self.filter(Crop(rect))
+    }
+
+    /// Adds a [`Resize`] filter.
+    pub fn resize(self, width: u32, height: u32, filter: FilterType) -> Self {
+        self.filter(Resize {
+            width,
+            height,
+            filter,
+        })
+    }
+
+    /// Adds a [`Transform`] filter.
+    pub fn transform(self, transform: Transform) -> Self {
+        self.filter(transform)
+    }
+
+    /// Adds a [`ColorAdjust`] filter.
+    pub fn adjust_colors(self, adjust: ColorAdjust) -> Self {
+        self.filter(adjust)
+    }
+
+    /// Adds an [`Overlay`] filter.
+    pub fn overlay(self, overlay: Overlay) -> Self {
+        self.filter(overlay)
+    }
+
+    /// The canvas size after all of the filters.
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    fn render(&mut self, frame: InputFrame) -> Result<(RgbaImage, u32), EncodingError> {
+        let canvas = self.compositor.canvas().dimensions();
+        let (image, rect, blend, dispose, duration) = match frame {
+            InputFrame::Encoded { data, options } => {
+                let image = image::load_from_memory_with_format(&data, ImageFormat::WebP)?;
+                let image = image.into_rgba8();
+                let rect = match options.rect {
+                    Some(rect) => rect,
+                    None => options
+                        .anchor
+                        .place(canvas, image.dimensions())
+                        .ok_or(EncodingError::InvalidDimensions)?,
+                };
+                (
+                    image,
+                    rect,
+                    options.blend,
+                    options.dispose,
+                    options.duration,
+                )
+            }
+            InputFrame::Image { image, duration } => {
+                let image = image.into_rgba8();
+                let rect = FrameRect {
+                    x: 0,
+                    y: 0,
+                    width: image.width(),
+                    height: image.height(),
+                };
+                (image, rect, Blend::Overwrite, Disposal::None, duration)
+            }
+        };
+        self.compositor.draw(&image, rect, blend, dispose);
+        Ok((self.compositor.canvas().clone(), duration))
+    }
+}
+
+impl<S: FrameSource> FrameSource for Filtered<S> {
+    fn next_frame(&mut self) -> Option<Result<InputFrame, EncodingError>> {
+        let frame = match self.source.next_frame()? {
+            Ok(frame) => frame,
+            Err(e) => return Some(Err(e)),
+        };
+        let result = self.render(frame).and_then(|(mut image, duration)| {
+            for filter in &mut self.filters {
+                image = filter.apply(image, self.start_ms)?;
+            }
+            self.start_ms += duration as u64;
+            Ok(InputFrame::Image {
+                image: image.into(),
+                duration,
+            })
+        });
+        Some(result)
+    }
+}
+
+/// Adds [`filtered`](Self::filtered) to every [`FrameSource`].
+pub trait FilterExt: FrameSource + Sized {
+    /// Renders the frames onto a canvas of the given size, to pass them
+    /// through filters.  See [`Filtered`].
+    fn filtered(self, width: u32, height: u32) -> Filtered<Self> {
+        Filtered::new(self, width, height)
+    }
+}
+
+impl<S: FrameSource> FilterExt for S {}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgba, RgbaImage, imageops::FilterType};
+
+    use super::{ColorAdjust, FilterExt, Transform};
+    use crate::{
+        Decoder, FrameRect, FrameSink, InputFrame, Optimizer, Overlay, WebPAnimator,
+        test_util::params,
+    };
+
+    #[test]
+    fn test_filters() {
+        let params = params(16, 8);
+        let mut optimizer = Optimizer::new(WebPAnimator::new(params).unwrap());
+        // The left half is red and the right half is blue.
+        let image = RgbaImage::from_fn(16, 8, |x, _| match x {
+            0..8 => Rgba([255, 0, 0, 255]),
+            _ => Rgba([0, 0, 255, 255]),
+        });
+        optimizer.add_frame(&image, 100).unwrap();
+        // A change outside the cropped area.
+        let mut image = image;
+        image.put_pixel(15, 7, Rgba([0; 4]));
+        optimizer.add_frame(&image, 100).unwrap();
+        let data = optimizer.into_inner().into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+
+        let logo = Overlay {
+            start_ms: 100,
+            ..Overlay::new(RgbaImage::from_pixel(1, 1, Rgba([0, 255, 0, 255])), 0, 0)
+        };
+        let rect = FrameRect {
+            x: 4,
+            y: 0,
+            width: 8,
+            height: 8,
+        };
+        let grayscale = ColorAdjust {
+            saturation: 0.0,
+            ..ColorAdjust::default()
+        };
+        let filtered = decoder
+            .canvas_frames()
+            .filtered(16, 8)
+            .crop(rect)
+            .resize(4, 4, FilterType::Nearest)
+            .filter(|mut image: RgbaImage| {
+                image.put_pixel(3, 3, Rgba([255; 4]));
+                image
+            })
+            .overlay(logo)
+            .adjust_colors(grayscale)
+            .transform(Transform::Rotate90)
+            .transform(Transform::FlipHorizontal);
+        assert_eq!(filtered.size(), (4, 4));
+        let mut frames = Vec::new();
+        frames.add_frames_from(filtered).unwrap();
+        let images: Vec<_> = frames
+            .into_iter()
+            .map(|frame| match frame {
+                InputFrame::Image { image, duration } => (image.into_rgba8(), duration),
+                _ => panic!("expected an image"),
+            })
+            .collect();
+        assert_eq!(images.len(), 2);
+        assert!(images.iter().all(|(image, _)| image.dimensions() == (4, 4)));
+        assert_eq!(images[1].1, 100);
+        let (first, second) = (&images[0].0, &images[1].0);
+        // Rotating clockwise and then flipping swaps x and y.
+        assert_eq!(first.get_pixel(1, 0), &Rgba([76, 76, 76, 255]));
+        assert_eq!(first.get_pixel(1, 2), &Rgba([29, 29, 29, 255]));
+        assert_eq!(first.get_pixel(3, 3), &Rgba([255; 4]));
+        // The overlay appears on the second frame.
+        assert_eq!(first.get_pixel(0, 0), &Rgba([76, 76, 76, 255]));
+        assert_eq!(second.get_pixel(0, 0), &Rgba([150, 150, 150, 255]));
+    }
+}
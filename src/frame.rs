@@ -0,0 +1,382 @@
+use std::{sync::Arc, time::Duration};
+
+use crate::{EncodingError, padded_len};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// What to do with a frame's rectangle after it has been displayed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Disposal {
+    /// Leave the canvas as it is.
+    #[default]
+    None,
+    /// Fill the frame rectangle with the background color.
+    Background,
+}
+
+/// How a frame is combined with the existing contents of the canvas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Blend {
+    /// Alpha-blend the frame onto the canvas.
+    #[default]
+    Alpha,
+    /// Replace the contents of the frame rectangle, including transparency.
+    Overwrite,
+}
+
+/// Where a frame is placed on the canvas when no frame rectangle is given.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+/// How frames at odd offsets are handled.  The format can only represent
+/// even offsets.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum OddOffsets {
+    /// Fail with [`EncodingError::InvalidDimensions`].
+    #[default]
+    Reject,
+    /// Round the offsets down to even numbers, moving the frame by a pixel.
+    Snap,
+    /// Add a transparent row or column on the top or left of the image and
+    /// place it at the even offset before, so that the image stays where it
+    /// was.  The padding leaves the canvas unchanged if the frame uses
+    /// [`Blend::Alpha`].  Only images that are encoded by the animator, such
+    /// as with [`add_image`](crate::WebPAnimator::add_image), can be padded;
+    /// encoded frames are rejected.
+    PadTransparent,
+    /// Like [`PadTransparent`](Self::PadTransparent), but the padding repeats
+    /// the pixels on the edge of the image.
+    PadEdge,
+}
+
+/// How frames with a duration of 0 are handled.  Zero durations are
+/// allowed by the format, but players differ in how they show them: some
+/// skip the frame, and others show it for a default duration.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ZeroDurationPolicy {
+    /// Write the duration as it is.
+    #[default]
+    Allow,
+    /// Use the given duration in milliseconds instead.
+    Coerce(u32),
+    /// Fail with [`EncodingError::InvalidDuration`].
+    Reject,
+}
+
+impl ZeroDurationPolicy {
+    pub(crate) fn apply(self, duration: u32) -> Result<u32, EncodingError> {
+        match (self, duration) {
+            (Self::Coerce(min), 0) => Ok(min),
+            (Self::Reject, 0) => Err(EncodingError::InvalidDuration),
+            _ => Ok(duration),
+        }
+    }
+}
+
+impl Anchor {
+    /// Returns the rectangle of the given size anchored on a canvas of size
+    /// `canvas`, or `None` if it doesn't fit.  Offsets are rounded down to
+    /// even numbers, as required by the format.
+    pub(crate) fn place(self, canvas: (u32, u32), size: (u32, u32)) -> Option<FrameRect> {
+        let dx = canvas.0.checked_sub(size.0)?;
+        let dy = canvas.1.checked_sub(size.1)?;
+        let (x, y) = match self {
+            Self::TopLeft => (0, 0),
+            Self::Top => (dx / 2, 0),
+            Self::TopRight => (dx, 0),
+            Self::Left => (0, dy / 2),
+            Self::Center => (dx / 2, dy / 2),
+            Self::Right => (dx, dy / 2),
+            Self::BottomLeft => (0, dy),
+            Self::Bottom => (dx / 2, dy),
+            Self::BottomRight => (dx, dy),
+        };
+        Some(FrameRect {
+            x: x & !1,
+            y: y & !1,
+            width: size.0,
+            height: size.1,
+        })
+    }
+}
+
+/// Per-frame settings.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct FrameOptions {
+    /// The frame rectangle.  If `None`, then the size is read from the
+    /// image data and the frame is placed according to `anchor`.
+    pub rect: Option<FrameRect>,
+    /// The duration in milliseconds.
+    pub duration: u32,
+    pub dispose: Disposal,
+    pub blend: Blend,
+    /// Where to place the frame if `rect` is `None`.
+    pub anchor: Anchor,
+    /// Overrides the quality of lossy encoding, between 0 and 100, for
+    /// [`add_image_lossy`](crate::WebPAnimator::add_image_lossy) and
+    /// [`Optimizer`](crate::Optimizer).
+    #[cfg(feature = "lossy")]
+    pub quality: Option<u8>,
+    /// Overrides the libwebp compression method of lossy encoding, between
+    /// 0 (fastest) and 6 (smallest output).  The default is 4.
+    #[cfg(feature = "lossy")]
+    pub method: Option<u8>,
+}
+
+impl FrameOptions {
+    /// Returns options with the given duration in milliseconds and default
+    /// values for everything else.
+    pub fn with_duration(duration: u32) -> Self {
+        Self {
+            duration,
+            ..Self::default()
+        }
+    }
+
+    /// Like [`with_duration`](Self::with_duration), but takes a
+    /// [`Duration`].  The duration is rounded to the nearest millisecond,
+    /// with halves rounded up, and fails with
+    /// [`EncodingError::InvalidDuration`] if the result exceeds
+    /// [`MAX_DURATION_MS`].
+    pub fn from_duration(duration: Duration) -> Result<Self, EncodingError> {
+        Ok(Self::with_duration(duration_to_ms(duration)?))
+    }
+
+    /// The duration as a [`Duration`].
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration.into())
+    }
+
+    pub(crate) fn anmf_flags(&self) -> u8 {
+        let dispose_flag = match self.dispose {
+            Disposal::None => 0,
+            Disposal::Background => 1,
+        };
+        let blend_flag = match self.blend {
+            Blend::Alpha => 0,
+            Blend::Overwrite => 2,
+        };
+        dispose_flag | blend_flag
+    }
+
+    pub(crate) fn from_anmf_flags(flags: u8) -> (Disposal, Blend) {
+        let dispose = if flags & 1 != 0 {
+            Disposal::Background
+        } else {
+            Disposal::None
+        };
+        let blend = if flags & 2 != 0 {
+            Blend::Overwrite
+        } else {
+            Blend::Alpha
+        };
+        (dispose, blend)
+    }
+}
+
+/// The longest frame duration that the format can represent, in
+/// milliseconds.
+pub const MAX_DURATION_MS: u32 = 0xffffff;
+
+/// Converts `duration` to milliseconds, rounding to the nearest millisecond.
+pub(crate) fn duration_to_ms(duration: Duration) -> Result<u32, EncodingError> {
+    let ms = (duration.as_nanos() + 500_000) / 1_000_000;
+    if ms > MAX_DURATION_MS as u128 {
+        return Err(EncodingError::InvalidDuration);
+    }
+    Ok(ms as u32)
+}
+
+/// The storage for the data of a frame, which may be shared with other
+/// frames or with the caller.
+#[derive(Clone, Debug)]
+pub(crate) enum FrameData {
+    Shared(Arc<[u8]>),
+    #[cfg(feature = "bytes")]
+    Bytes(bytes::Bytes),
+    /// A range of a memory-mapped file.
+    #[cfg(feature = "memmap")]
+    Mapped(Arc<memmap2::Mmap>, std::ops::Range<usize>),
+}
+
+impl std::ops::Deref for FrameData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Shared(data) => data,
+            #[cfg(feature = "bytes")]
+            Self::Bytes(data) => data,
+            #[cfg(feature = "memmap")]
+            Self::Mapped(map, range) => &map[range.clone()],
+        }
+    }
+}
+
+impl PartialEq for FrameData {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for FrameData {}
+
+/// A frame that has been added to a [`WebPAnimator`](crate::WebPAnimator).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub(crate) rect: FrameRect,
+    pub(crate) duration: u32,
+    pub(crate) dispose: Disposal,
+    pub(crate) blend: Blend,
+    pub(crate) data: FrameData,
+}
+
+impl Frame {
+    pub fn rect(&self) -> FrameRect {
+        self.rect
+    }
+
+    /// The duration in milliseconds.
+    pub fn duration(&self) -> u32 {
+        self.duration
+    }
+
+    /// Set the duration in milliseconds.
+    pub fn set_duration(&mut self, duration: u32) -> Result<(), EncodingError> {
+        if duration > MAX_DURATION_MS {
+            return Err(EncodingError::InvalidDuration);
+        }
+        self.duration = duration;
+        Ok(())
+    }
+
+    pub fn dispose(&self) -> Disposal {
+        self.dispose
+    }
+
+    pub fn set_dispose(&mut self, dispose: Disposal) {
+        self.dispose = dispose;
+    }
+
+    pub fn blend(&self) -> Blend {
+        self.blend
+    }
+
+    pub fn set_blend(&mut self, blend: Blend) {
+        self.blend = blend;
+    }
+
+    /// The options that would be used to add this frame to an animation.
+    pub fn options(&self) -> FrameOptions {
+        FrameOptions {
+            rect: Some(self.rect),
+            duration: self.duration,
+            dispose: self.dispose,
+            blend: self.blend,
+            ..FrameOptions::default()
+        }
+    }
+
+    /// The chunks making up the frame: a `VP8 ` or `VP8L` chunk, possibly
+    /// preceded by an `ALPH` chunk.
+    pub fn data(&self) -> &[u8] {
+        &self.data[..]
+    }
+
+    /// The size of the `ANMF` chunk for this frame, including its header.
+    pub(crate) fn chunk_len(&self) -> usize {
+        padded_len(self.data.len()) + 24
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::{EncodingError, FrameOptions, MAX_DURATION_MS};
+
+    #[test]
+    fn test_from_duration() {
+        let options = FrameOptions::from_duration(Duration::from_micros(1500)).unwrap();
+        assert_eq!(options.duration, 2);
+        let options = FrameOptions::from_duration(Duration::from_micros(1499)).unwrap();
+        assert_eq!(options.duration, 1);
+        assert_eq!(options.duration(), Duration::from_millis(1));
+        assert!(FrameOptions::from_duration(Duration::from_millis(MAX_DURATION_MS as u64)).is_ok());
+        assert!(matches!(
+            FrameOptions::from_duration(Duration::from_secs(20000)),
+            Err(EncodingError::InvalidDuration)
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        use crate::{Blend, FrameRect, LoopCount, Params};
+
+        let options = FrameOptions {
+            rect: Some(FrameRect {
+                x: 2,
+                y: 4,
+                width: 10,
+                height: 20,
+            }),
+            blend: Blend::Overwrite,
+            ..FrameOptions::with_duration(100)
+        };
+        let json = serde_json::to_string(&options).unwrap();
+        assert!(json.contains(r#""blend":"overwrite""#));
+        assert_eq!(
+            serde_json::from_str::<FrameOptions>(&json).unwrap(),
+            options
+        );
+        // Missing fields take their default values.
+        let options: FrameOptions = serde_json::from_str(r#"{"duration":40}"#).unwrap();
+        assert_eq!(options, FrameOptions::with_duration(40));
+
+        let params = Params {
+            width: 64,
+            height: 32,
+            background_bgra: [1, 2, 3, 4],
+            loop_count: LoopCount::Infinite,
+            has_alpha: true,
+        };
+        let json = serde_json::to_string(&params).unwrap();
+        assert_eq!(
+            json,
+            r#"{"width":64,"height":32,"background_bgra":[1,2,3,4],"loop_count":0,"has_alpha":true}"#
+        );
+        let params: Params = serde_json::from_str(&json.replace(":0,", ":5,")).unwrap();
+        assert_eq!(params.loop_count, LoopCount::from(5));
+    }
+}
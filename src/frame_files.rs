@@ -0,0 +1,80 @@
+use crate::{
+    DirSource, EncodingError, FrameSink, WebPAnimator,
+    source::{read_frame, sort_naturally},
+};
+
+impl WebPAnimator {
+    /// Adds the files matching the glob `pattern`, such as
+    /// `"frames/*.webp"`, as frames at `fps` frames per second, and returns
+    /// the number of frames added.
+    ///
+    /// The files are sorted naturally, so that `frame2` comes before
+    /// `frame10`, and read as with [`DirSource`].  An error reading or adding
+    /// a file is returned as [`EncodingError::InFile`], naming the file.
+    pub fn add_frames_from_dir(&mut self, pattern: &str, fps: f64) -> Result<usize, EncodingError> {
+        let mut paths = Vec::new();
+        for entry in glob::glob(pattern)? {
+            let path = entry.map_err(|e| {
+                let path = e.path().to_path_buf();
+                EncodingError::InFile(path, Box::new(EncodingError::Io(e.into())))
+            })?;
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+        sort_naturally(&mut paths);
+        let source = DirSource::from_paths(paths, fps)?;
+        for (i, path) in source.paths().iter().enumerate() {
+            read_frame(path, source.duration(i)?)
+                .and_then(|frame| self.add_input_frame(frame))
+                .map_err(|e| EncodingError::InFile(path.clone(), Box::new(e)))?;
+        }
+        Ok(source.paths().len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_add_frames_from_dir() {
+        use std::fs;
+
+        use image::{Rgb, RgbImage};
+
+        use crate::{Decoder, EncodingError, WebPAnimator};
+
+        let dir = std::env::temp_dir().join(format!("webp-animator-dir-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (i, color) in [(2, [255, 0, 0]), (10, [0, 0, 255]), (1, [0, 255, 0])] {
+            RgbImage::from_pixel(8, 8, Rgb(color))
+                .save(dir.join(format!("frame{i}.png")))
+                .unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "not a frame").unwrap();
+        let pattern = dir.join("frame*.png");
+        let mut writer = WebPAnimator::builder().width(8).height(8).build().unwrap();
+        let added = writer.add_frames_from_dir(pattern.to_str().unwrap(), 30.0);
+        let mut bad = WebPAnimator::builder().width(8).height(8).build().unwrap();
+        let error = bad.add_frames_from_dir(dir.join("*.txt").to_str().unwrap(), 30.0);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(added.unwrap(), 3);
+        let data = writer.into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+        let durations: Vec<u32> = decoder.frames().iter().map(|f| f.duration).collect();
+        assert_eq!(durations, [33, 34, 33]);
+        let colors: Vec<_> = decoder
+            .canvas_frames()
+            .map(|f| f.unwrap().image.get_pixel(0, 0).0)
+            .collect();
+        assert_eq!(
+            colors,
+            [[0, 255, 0, 255], [255, 0, 0, 255], [0, 0, 255, 255]]
+        );
+        match error {
+            Err(EncodingError::InFile(path, _)) => assert!(path.ends_with("notes.txt")),
+            _ => panic!("expected an error naming the file"),
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::io::{BufRead, Seek};
+
+use image::{GrayAlphaImage, GrayImage, RgbImage, RgbaImage};
+use png::{BitDepth, BlendOp, ColorType, DisposeOp, Transformations};
+
+use crate::{
+    Blend, CancellationToken, DecodingError, EncodingError, LoopCount, Params, WebPAnimator,
+    convert::{Converter, SourceDisposal, SourceFrame},
+};
+
+impl WebPAnimator {
+    /// Converts an APNG animation.
+    ///
+    /// Frames are encoded losslessly, each covering the same rectangle as in
+    /// the APNG where possible.  The `APNG_DISPOSE_OP_PREVIOUS` disposal
+    /// method has no WebP equivalent, so the frame following such a frame
+    /// covers the whole canvas.  A PNG without an `acTL` chunk fails with
+    /// [`DecodingError::NotAnimated`].
+    pub fn from_apng<R: BufRead + Seek>(reader: R) -> Result<Self, EncodingError> {
+        Self::convert_apng(reader, None)
+    }
+
+    /// Like [`from_apng`](Self::from_apng), but stops with
+    /// [`EncodingError::Cancelled`] between frames once `token` is
+    /// cancelled.  The returned animator keeps the token.
+    pub fn from_apng_cancellable<R: BufRead + Seek>(
+        reader: R,
+        token: CancellationToken,
+    ) -> Result<Self, EncodingError> {
+        Self::convert_apng(reader, Some(token))
+    }
+
+    fn convert_apng<R: BufRead + Seek>(
+        reader: R,
+        token: Option<CancellationToken>,
+    ) -> Result<Self, EncodingError> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let info = reader.info();
+        let control = info.animation_control.ok_or(DecodingError::NotAnimated)?;
+        let (width, height) = (info.width, info.height);
+        // The default image is only part of the animation if it has a fcTL
+        // chunk.
+        let skip_default_image = info.frame_control.is_none();
+        let params = Params {
+            width,
+            height,
+            background_bgra: [0; 4],
+            loop_count: LoopCount::from(u16::try_from(control.num_plays).unwrap_or(0)),
+            has_alpha: false,
+        };
+        let mut animator = WebPAnimator::new(params)?;
+        animator.set_detect_alpha(true);
+        animator.set_cancellation_token(token);
+        let mut converter = Converter::new(width, height);
+        let mut buf = vec![0; reader.output_buffer_size().ok_or(EncodingError::TooLarge)?];
+        if skip_default_image {
+            reader.next_frame(&mut buf)?;
+        }
+        for i in 0..control.num_frames {
+            let output = reader.next_frame(&mut buf)?;
+            let fc = *reader
+                .info()
+                .frame_control()
+                .ok_or(DecodingError::InvalidChunk)?;
+            let image = to_rgba(
+                reader.output_color_type(),
+                output.width,
+                output.height,
+                &buf,
+            )?;
+            let dispose = match fc.dispose_op {
+                DisposeOp::None => SourceDisposal::None,
+                DisposeOp::Background => SourceDisposal::Background,
+                // The specification says to treat this as background for
+                // the first frame.
+                DisposeOp::Previous if i == 0 => SourceDisposal::Background,
+                DisposeOp::Previous => SourceDisposal::Previous,
+            };
+            let blend = match fc.blend_op {
+                BlendOp::Source => Blend::Overwrite,
+                BlendOp::Over => Blend::Alpha,
+            };
+            let den = if fc.delay_den == 0 {
+                100
+            } else {
+                fc.delay_den as u32
+            };
+            let frame = SourceFrame {
+                image: &image,
+                x: fc.x_offset,
+                y: fc.y_offset,
+                blend,
+                dispose,
+                duration: (fc.delay_num as u32 * 1000 + den / 2) / den,
+            };
+            converter.add_frame(&mut animator, &frame)?;
+        }
+        Ok(animator)
+    }
+}
+
+/// Converts decoded PNG data with 8 bits per channel to RGBA.
+fn to_rgba(
+    (color, depth): (ColorType, BitDepth),
+    width: u32,
+    height: u32,
+    buf: &[u8],
+) -> Result<RgbaImage, EncodingError> {
+    let channels = color.samples();
+    let data = buf.get(..width as usize * height as usize * channels);
+    let data = data.ok_or(DecodingError::Truncated)?.to_vec();
+    let invalid = || EncodingError::UnrecognizedImage;
+    if depth != BitDepth::Eight {
+        return Err(invalid());
+    }
+    Ok(match color {
+        ColorType::Grayscale => {
+            image::DynamicImage::from(GrayImage::from_raw(width, height, data).ok_or_else(invalid)?)
+                .into_rgba8()
+        }
+        ColorType::GrayscaleAlpha => image::DynamicImage::from(
+            GrayAlphaImage::from_raw(width, height, data).ok_or_else(invalid)?,
+        )
+        .into_rgba8(),
+        ColorType::Rgb => {
+            image::DynamicImage::from(RgbImage::from_raw(width, height, data).ok_or_else(invalid)?)
+                .into_rgba8()
+        }
+        ColorType::Rgba => RgbaImage::from_raw(width, height, data).ok_or_else(invalid)?,
+        ColorType::Indexed => return Err(invalid()),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use image::{AnimationDecoder, codecs::png::PngDecoder};
+    use png::{BlendOp, ColorType, DisposeOp, Encoder};
+
+    use crate::{Compositor, Decoder, LoopCount, WebPAnimator};
+
+    #[test]
+    fn test_from_apng() {
+        let mut apng = Vec::new();
+        let frames = [
+            (
+                0,
+                0,
+                9,
+                7,
+                [255, 0, 0, 255],
+                DisposeOp::None,
+                BlendOp::Source,
+            ),
+            (
+                3,
+                1,
+                4,
+                3,
+                [0, 255, 0, 128],
+                DisposeOp::Previous,
+                BlendOp::Over,
+            ),
+            (
+                1,
+                3,
+                5,
+                3,
+                [0, 0, 255, 255],
+                DisposeOp::Background,
+                BlendOp::Source,
+            ),
+            (2, 2, 3, 3, [0, 0, 0, 0], DisposeOp::None, BlendOp::Source),
+            (5, 1, 3, 5, [9, 9, 9, 200], DisposeOp::None, BlendOp::Over),
+        ];
+        {
+            let mut encoder = Encoder::new(&mut apng, 9, 7);
+            encoder.set_color(ColorType::Rgba);
+            encoder.set_animated(frames.len() as u32, 2).unwrap();
+            let mut writer = encoder.write_header().unwrap();
+            for (x, y, width, height, color, dispose, blend) in frames {
+                writer.set_frame_dimension(width, height).unwrap();
+                writer.set_frame_position(x, y).unwrap();
+                writer.set_frame_delay(1, 20).unwrap();
+                writer.set_dispose_op(dispose).unwrap();
+                writer.set_blend_op(blend).unwrap();
+                writer
+                    .write_image_data(&color.repeat((width * height) as usize))
+                    .unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        let expected: Vec<_> = PngDecoder::new(Cursor::new(&apng))
+            .unwrap()
+            .apng()
+            .unwrap()
+            .into_frames()
+            .map(|f| f.unwrap().into_buffer())
+            .collect();
+        let out = WebPAnimator::from_apng(Cursor::new(&apng))
+            .unwrap()
+            .into_bytes()
+            .unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (9, 7));
+        assert_eq!(decoder.loop_count(), LoopCount::from(2));
+        assert_eq!(decoder.frames().len(), expected.len());
+        let mut compositor = Compositor::new(9, 7);
+        for (frame, expected) in decoder.frames().iter().zip(&expected) {
+            assert_eq!(frame.duration, 50);
+            let canvas = compositor.add_frame(frame).unwrap();
+            // Alpha blending may be rounded differently.
+            for (a, b) in canvas.as_raw().iter().zip(expected.as_raw()) {
+                assert!(a.abs_diff(*b) <= 1, "{canvas:?} {expected:?}");
+            }
+        }
+    }
+}
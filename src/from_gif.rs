@@ -0,0 +1,153 @@
+use std::io::Read;
+
+use gif::{ColorOutput, DecodeOptions, DisposalMethod, Repeat};
+use image::RgbaImage;
+
+use crate::{
+    Blend, CancellationToken, EncodingError, LoopCount, Params, WebPAnimator,
+    convert::{Converter, SourceDisposal, SourceFrame},
+};
+
+impl WebPAnimator {
+    /// Converts a GIF animation.
+    ///
+    /// Frames are encoded losslessly, each covering the same rectangle as in
+    /// the GIF where possible.  The GIF "restore to previous" disposal method
+    /// has no WebP equivalent, so the frame following such a frame covers the
+    /// whole canvas.  A GIF that repeats `n` times is played `n + 1` times,
+    /// as browsers do.
+    pub fn from_gif<R: Read>(reader: R) -> Result<Self, EncodingError> {
+        Self::convert_gif(reader, None)
+    }
+
+    /// Like [`from_gif`](Self::from_gif), but stops with
+    /// [`EncodingError::Cancelled`] between frames once `token` is
+    /// cancelled.  The returned animator keeps the token.
+    pub fn from_gif_cancellable<R: Read>(
+        reader: R,
+        token: CancellationToken,
+    ) -> Result<Self, EncodingError> {
+        Self::convert_gif(reader, Some(token))
+    }
+
+    fn convert_gif<R: Read>(
+        reader: R,
+        token: Option<CancellationToken>,
+    ) -> Result<Self, EncodingError> {
+        let mut options = DecodeOptions::new();
+        options.set_color_output(ColorOutput::RGBA);
+        let mut decoder = options.read_info(reader)?;
+        let (width, height) = (decoder.width() as u32, decoder.height() as u32);
+        let params = Params {
+            width,
+            height,
+            background_bgra: [0; 4],
+            loop_count: LoopCount::Infinite,
+            has_alpha: false,
+        };
+        let mut animator = WebPAnimator::new(params)?;
+        animator.set_detect_alpha(true);
+        animator.set_cancellation_token(token);
+        let mut converter = Converter::new(width, height);
+        while let Some(frame) = decoder.read_next_frame()? {
+            let image = RgbaImage::from_raw(
+                frame.width as u32,
+                frame.height as u32,
+                frame.buffer.to_vec(),
+            )
+            .ok_or(EncodingError::InvalidDimensions)?;
+            let dispose = match frame.dispose {
+                DisposalMethod::Any | DisposalMethod::Keep => SourceDisposal::None,
+                DisposalMethod::Background => SourceDisposal::Background,
+                DisposalMethod::Previous => SourceDisposal::Previous,
+            };
+            let frame = SourceFrame {
+                image: &image,
+                x: frame.left as u32,
+                y: frame.top as u32,
+                // GIF transparency is all or nothing, so blending is exact.
+                blend: Blend::Alpha,
+                dispose,
+                duration: frame.delay as u32 * 10,
+            };
+            converter.add_frame(&mut animator, &frame)?;
+        }
+        // The loop count and metadata may follow the frames.
+        let loop_count = match decoder.repeat() {
+            Repeat::Infinite => LoopCount::Infinite,
+            Repeat::Finite(n) => LoopCount::from(n.saturating_add(1)),
+        };
+        animator.loop_count = loop_count.into();
+        if let Some(icc_profile) = decoder.icc_profile() {
+            animator.set_icc_profile(icc_profile.to_vec())?;
+        }
+        if let Some(xmp_metadata) = decoder.xmp_metadata() {
+            animator.set_xmp_metadata(xmp_metadata.to_vec())?;
+        }
+        Ok(animator)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{borrow::Cow, io::Cursor};
+
+    use gif::{DisposalMethod, Encoder, Frame, Repeat};
+    use image::{AnimationDecoder, codecs::gif::GifDecoder};
+
+    use crate::{Compositor, Decoder, LoopCount, WebPAnimator};
+
+    #[test]
+    fn test_from_gif() {
+        let palette = [255, 0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0];
+        let mut gif = Vec::new();
+        {
+            let mut encoder = Encoder::new(&mut gif, 9, 7, &palette).unwrap();
+            encoder.set_repeat(Repeat::Finite(2)).unwrap();
+            let frames = [
+                (0, 0, 9, 7, 0, DisposalMethod::Keep),
+                (3, 1, 4, 3, 1, DisposalMethod::Previous),
+                (1, 3, 5, 3, 2, DisposalMethod::Background),
+                (2, 2, 3, 3, 3, DisposalMethod::Background),
+                (5, 1, 3, 5, 1, DisposalMethod::Keep),
+            ];
+            for (left, top, width, height, color, dispose) in frames {
+                let mut buffer = vec![color; (width * height) as usize];
+                // Make part of each frame transparent.
+                buffer[0] = 3;
+                encoder
+                    .write_frame(&Frame {
+                        left,
+                        top,
+                        width,
+                        height,
+                        delay: 5,
+                        dispose,
+                        transparent: Some(3),
+                        buffer: Cow::Owned(buffer),
+                        ..Frame::default()
+                    })
+                    .unwrap();
+            }
+        }
+        let expected: Vec<_> = GifDecoder::new(Cursor::new(&gif))
+            .unwrap()
+            .into_frames()
+            .map(|f| f.unwrap().into_buffer())
+            .collect();
+        let out = WebPAnimator::from_gif(Cursor::new(&gif))
+            .unwrap()
+            .into_bytes()
+            .unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (9, 7));
+        assert_eq!(u16::from(decoder.loop_count()), 3);
+        assert_eq!(decoder.loop_count(), LoopCount::from(3));
+        assert_eq!(decoder.frames().len(), expected.len());
+        let mut compositor = Compositor::new(9, 7);
+        for (frame, expected) in decoder.frames().iter().zip(&expected) {
+            assert_eq!(frame.duration, 50);
+            assert_eq!(compositor.add_frame(frame).unwrap(), expected);
+        }
+    }
+}
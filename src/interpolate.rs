@@ -0,0 +1,156 @@
+use image::{Rgba, RgbaImage};
+
+use crate::{
+    Decoder, EncodingError, Optimizer, WebPAnimator,
+    compositor::{mix, mix_pixel},
+};
+
+/// How intermediate frames are computed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Cross-fade between the two frames.  Moving objects appear twice,
+    /// fading out in one place and in at the other.
+    #[default]
+    Blend,
+    /// Estimate how each `block_size` × `block_size` block of the canvas
+    /// moves, by up to `search_radius` pixels in each direction, and draw
+    /// it part of the way along.  Slower than `Blend`, but keeps moving
+    /// objects sharp when the motion is simple.
+    Motion { block_size: u32, search_radius: u32 },
+}
+
+impl Decoder<'_> {
+    /// Inserts `steps` frames between each pair of consecutive rendered
+    /// frames, splitting the duration of the first frame of the pair evenly,
+    /// and re-encodes the result losslessly with an [`Optimizer`].  The total
+    /// duration stays the same.  The last frame isn't interpolated, since
+    /// there is nothing after it.
+    pub fn interpolate(
+        &self,
+        steps: u32,
+        interpolation: Interpolation,
+    ) -> Result<WebPAnimator, EncodingError> {
+        let animator = self.animator_builder().detect_alpha(true).build()?;
+        let mut optimizer = Optimizer::new(animator);
+        let mut frames = self.canvas_frames().peekable();
+        while let Some(frame) = frames.next() {
+            let frame = frame?;
+            let Some(Ok(next)) = frames.peek() else {
+                optimizer.add_frame(&frame.image, frame.duration)?;
+                continue;
+            };
+            let parts = steps as u64 + 1;
+            let end = |k: u64| (frame.duration as u64 * k / parts) as u32;
+            optimizer.add_frame(&frame.image, end(1))?;
+            for k in 1..parts {
+                let t = k as f32 / parts as f32;
+                let image = match interpolation {
+                    Interpolation::Blend => mix(&frame.image, &next.image, t),
+                    Interpolation::Motion {
+                        block_size,
+                        search_radius,
+                    } => motion_mix(&frame.image, &next.image, t, block_size, search_radius),
+                };
+                optimizer.add_frame(&image, end(k + 1) - end(k))?;
+            }
+        }
+        Ok(optimizer.into_inner())
+    }
+}
+
+/// Interpolates with bilateral block matching: for each block of the output,
+/// finds the motion `v` for which the block at `-t v` in `a` best matches the
+/// block at `(1 - t) v` in `b`, then mixes the two.
+fn motion_mix(a: &RgbaImage, b: &RgbaImage, t: f32, block_size: u32, radius: u32) -> RgbaImage {
+    let (width, height) = a.dimensions();
+    let block_size = block_size.max(1);
+    let radius = radius as i64;
+    let sample = |image: &RgbaImage, x: i64, y: i64| -> Rgba<u8> {
+        let x = x.clamp(0, width as i64 - 1) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+        *image.get_pixel(x, y)
+    };
+    // The offsets into `a` and `b` of a pixel in the output for motion `v`.
+    let offsets = |(vx, vy): (i64, i64)| {
+        let scaled = |v: i64, s: f32| (v as f32 * s).round() as i64;
+        (
+            (-scaled(vx, t), -scaled(vy, t)),
+            (scaled(vx, 1.0 - t), scaled(vy, 1.0 - t)),
+        )
+    };
+    let mut out = RgbaImage::new(width, height);
+    for by in (0..height).step_by(block_size as usize) {
+        for bx in (0..width).step_by(block_size as usize) {
+            let (w, h) = (block_size.min(width - bx), block_size.min(height - by));
+            let pixels =
+                || (by..by + h).flat_map(move |y| (bx..bx + w).map(move |x| (x as i64, y as i64)));
+            let cost = |v| {
+                let ((ax, ay), (cx, cy)) = offsets(v);
+                pixels()
+                    .map(|(x, y)| {
+                        let (p, q) = (sample(a, x + ax, y + ay), sample(b, x + cx, y + cy));
+                        p.0.iter()
+                            .zip(q.0)
+                            .map(|(&m, n)| m.abs_diff(n) as u64)
+                            .sum::<u64>()
+                    })
+                    .sum::<u64>()
+            };
+            // Prefer no motion, then short vectors, when costs are tied.
+            let v = (-radius..=radius)
+                .flat_map(|vy| (-radius..=radius).map(move |vx| (vx, vy)))
+                .min_by_key(|&(vx, vy)| (cost((vx, vy)), vx.abs() + vy.abs()))
+                .unwrap_or((0, 0));
+            let ((ax, ay), (cx, cy)) = offsets(v);
+            for (x, y) in pixels() {
+                let pixel = mix_pixel(sample(a, x + ax, y + ay), sample(b, x + cx, y + cy), t);
+                out.put_pixel(x as u32, y as u32, pixel);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgba, RgbaImage};
+
+    use crate::{Decoder, Interpolation, Optimizer, WebPAnimator, test_util::params};
+
+    #[test]
+    fn test_interpolate() {
+        let params = params(16, 8);
+        let mut optimizer = Optimizer::new(WebPAnimator::new(params).unwrap());
+        // A white square moving 4 pixels to the right over a gradient.
+        for x0 in [2, 6] {
+            let image = RgbaImage::from_fn(16, 8, |x, y| match (x, y) {
+                (x, 0..4) if (x0..x0 + 4).contains(&x) => Rgba([255; 4]),
+                _ => Rgba([16 * x as u8, 0, 0, 255]),
+            });
+            optimizer.add_frame(&image, 100).unwrap();
+        }
+        let data = optimizer.into_inner().into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+
+        let blended = decoder.interpolate(2, Interpolation::Blend).unwrap();
+        let durations: Vec<u32> = blended.frames().iter().map(|f| f.duration()).collect();
+        assert_eq!(durations, [33, 33, 34, 100]);
+
+        let motion = Interpolation::Motion {
+            block_size: 4,
+            search_radius: 4,
+        };
+        let out = decoder
+            .interpolate(1, motion)
+            .unwrap()
+            .into_bytes()
+            .unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        let middle = decoder.canvas_frames().nth(1).unwrap().unwrap().image;
+        // The square is halfway along, at x = 4..8, rather than faded.
+        for x in 4..8 {
+            assert_eq!(middle.get_pixel(x, 1), &Rgba([255; 4]));
+        }
+        assert_eq!(middle.get_pixel(4, 5), &Rgba([64, 0, 0, 255]));
+    }
+}
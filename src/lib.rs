@@ -5,7 +5,7 @@
 //! ```no_run
 //! use std::fs::File;
 //! use image::{Rgb, RgbImage, codecs::webp::WebPEncoder};
-//! use webp_animator::{Params, WebPAnimator};
+//! use webp_animator::{LoopCount, Params, WebPAnimator};
 //! let mut f = File::create("test.webp").unwrap();
 //! let img1 = RgbImage::from_pixel(64, 64, Rgb([255, 0, 0]));
 //! let img2 = RgbImage::from_pixel(64, 64, Rgb([0, 0, 255]));
@@ -13,7 +13,7 @@
 //!     width: 64,
 //!     height: 64,
 //!     background_bgra: [255, 255, 255, 255],
-//!     loop_count: 0,
+//!     loop_count: LoopCount::Infinite,
 //!     has_alpha: false,
 //! };
 //! let mut writer = WebPAnimator::new(params).unwrap();
@@ -28,32 +28,247 @@
 //! writer.write(&mut f).unwrap();
 //! ```
 
-use std::io::Write;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{BufWriter, IoSlice, Write},
+    num::NonZeroU16,
+    path::{Path, PathBuf},
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+/// Records an event with the [`tracing`](https://docs.rs/tracing) crate if
+/// the `tracing` feature is enabled, and does nothing otherwise.
+macro_rules! trace_event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::$level!($($arg)+);
+    };
+}
+
+/// Enters a [`tracing`](https://docs.rs/tracing) span until the end of the
+/// enclosing block if the `tracing` feature is enabled, and does nothing
+/// otherwise.  The level is the name of a `tracing::Level`, such as `DEBUG`.
+macro_rules! trace_span {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        let _span = ::tracing::span!(::tracing::Level::$level, $($arg)+).entered();
+    };
+}
+
+#[cfg(feature = "image")]
+mod background;
+mod bitstream;
+mod builder;
+mod cancel;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(all(feature = "image", not(target_family = "wasm")))]
+mod capture;
+#[cfg(feature = "color")]
+mod color;
+#[cfg(feature = "image")]
+mod compositor;
+mod concat;
+#[cfg(any(feature = "apng", feature = "gif"))]
+mod convert;
+#[cfg(feature = "image")]
+mod decimate;
+mod decoder;
+#[cfg(feature = "image")]
+mod diff;
+#[cfg(feature = "image")]
+mod edit;
+#[cfg(feature = "image")]
+mod encode;
+#[cfg(feature = "image")]
+pub mod filters;
+mod frame;
+#[cfg(feature = "glob")]
+mod frame_files;
+#[cfg(feature = "apng")]
+mod from_apng;
+#[cfg(feature = "gif")]
+mod from_gif;
+#[cfg(feature = "image")]
+mod interpolate;
+mod lossless;
+#[cfg(feature = "lossy")]
+mod lossy;
+#[cfg(feature = "manifest")]
+pub mod manifest;
+pub mod metadata;
+#[cfg(feature = "image")]
+mod montage;
+#[cfg(feature = "image")]
+mod optimize;
+#[cfg(feature = "image")]
+mod overlay;
+#[cfg(feature = "image")]
+mod pan_zoom;
+mod pool;
+pub mod presets;
+mod progress;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "image")]
+mod quality;
+pub mod remux;
+mod retime;
+mod riff;
+mod sink;
+mod source;
+mod spool;
+#[cfg(feature = "image")]
+mod sprite;
+mod stats;
+mod stream;
+#[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+mod temp_file;
+#[cfg(test)]
+mod test_util;
+#[cfg(feature = "apng")]
+mod to_apng;
+#[cfg(feature = "gif")]
+mod to_gif;
+mod validation;
+mod verify;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "lossy")]
+mod yuv;
+
+#[cfg(feature = "image")]
+pub use background::border_color;
+pub use builder::WebPAnimatorBuilder;
+pub use cancel::CancellationToken;
+#[cfg(all(feature = "image", not(target_family = "wasm")))]
+pub use capture::CaptureWriter;
+#[cfg(feature = "color")]
+pub use color::WorkingSpace;
+#[cfg(feature = "image")]
+pub use compositor::{CanvasFrame, CanvasFrames, Compositor};
+pub use concat::concat;
+#[cfg(feature = "image")]
+pub use concat::{Crossfade, concat_crossfade, concat_fit};
+#[cfg(feature = "image")]
+pub use decimate::DropPolicy;
+pub use decoder::{DecodedFrame, Decoder, DecodingError};
+#[cfg(feature = "image")]
+pub use diff::{DiffReport, Difference, diff};
+pub use frame::{
+    Anchor, Blend, Disposal, Frame, FrameOptions, FrameRect, MAX_DURATION_MS, OddOffsets,
+    ZeroDurationPolicy,
+};
+#[cfg(feature = "image")]
+pub use interpolate::Interpolation;
+#[cfg(feature = "image")]
+pub use montage::{Montage, PictureInPicture, montage, montage_frames};
+#[cfg(feature = "image")]
+pub use optimize::Optimizer;
+#[cfg(feature = "image")]
+pub use overlay::Overlay;
+#[cfg(feature = "image")]
+pub use pan_zoom::PanZoom;
+pub use pool::BufferPool;
+pub use progress::{Progress, ProgressSink, Stage};
+#[cfg(feature = "image")]
+pub use quality::{FrameQuality, Quality, frame_quality};
+pub use retime::{BrowserSafe, DurationChange, Retime, Rounding};
+pub use sink::FrameSink;
+pub use source::{DirSource, FrameSource, InputFrame, IntoInputFrame};
+pub use spool::WebPSpoolWriter;
+#[cfg(feature = "image")]
+pub use sprite::{ContactSheet, SpriteSheet, TileOrder};
+pub use stats::{FrameFormat, FrameStats, Stats};
+pub use stream::WebPStreamWriter;
+#[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+pub use temp_file::TempFile;
+pub use validation::{Validation, Warning};
+#[cfg(feature = "lossy")]
+pub use yuv::{Yuv420, YuvRange};
+
+use frame::FrameData;
+use progress::report;
 
 pub struct WebPAnimator {
     width: u32,
     height: u32,
-    icc_profile: Vec<u8>,
-    exif_metadata: Vec<u8>,
-    xmp_metadata: Vec<u8>,
-    frame_data: Vec<u8>,
+    metadata: Metadata,
+    frames: Vec<Frame>,
+    /// The frame data that has been added, by hash, so that repeated data
+    /// is stored once.
+    chunks: HashMap<u64, Weak<[u8]>>,
+    merge_duplicates: bool,
     background_bgra: [u8; 4],
     loop_count: u16,
     has_alpha: bool,
-}
-
-pub struct FrameRect {
-    pub x: u32,
-    pub y: u32,
-    pub width: u32,
-    pub height: u32,
+    detect_alpha: bool,
+    auto_canvas: bool,
+    odd_offsets: OddOffsets,
+    zero_duration: ZeroDurationPolicy,
+    metadata_placement: MetadataPlacement,
+    #[cfg(feature = "image")]
+    trim_transparent: bool,
+    #[cfg(feature = "color")]
+    working_space: Option<WorkingSpace>,
+    /// The timestamp of the last frame added with
+    /// [`add_frame_at`](WebPAnimator::add_frame_at), and its index.
+    last_timestamp: Option<(u64, usize)>,
+    validation: Validation,
+    warnings: Vec<Warning>,
+    progress: Option<Box<dyn ProgressSink>>,
+    cancel: Option<CancellationToken>,
 }
 
 #[derive(Debug)]
 pub enum EncodingError {
     InvalidDimensions,
     InvalidDuration,
+    /// The animation has no frames.
+    NoFrames,
     UnrecognizedImage,
+    /// Metadata is malformed, such as a chunk of the wrong size or an ICC
+    /// profile with a bad header.
+    InvalidMetadata,
+    /// The file would exceed the 4 GiB size limit of the RIFF format.
+    TooLarge,
+    /// The size of the frame rectangle differs from the size of the image.
+    FrameSizeMismatch,
+    HeaderAlreadyWritten,
+    InvalidQuality,
+    EncoderFailed,
+    /// The operation was stopped by a [`CancellationToken`].
+    Cancelled,
+    /// The output of [`WebPAnimator::verify`] didn't match the animation.
+    /// The message names the part that differed.
+    VerificationFailed(&'static str),
+    /// A [`manifest`] couldn't be parsed.
+    #[cfg(feature = "manifest")]
+    Manifest(manifest::ParseError),
+    /// An error reading or adding the file at the path.
+    InFile(PathBuf, Box<EncodingError>),
+    #[cfg(feature = "glob")]
+    Glob(glob::PatternError),
+    /// The animation doesn't meet the constraints of a
+    /// [`Preset`](presets::Preset).
+    Constraints(Vec<presets::Violation>),
+    Decoding(DecodingError),
+    #[cfg(feature = "gif")]
+    Gif(gif::DecodingError),
+    #[cfg(feature = "gif")]
+    GifEncoding(gif::EncodingError),
+    #[cfg(feature = "apng")]
+    Png(png::DecodingError),
+    #[cfg(feature = "apng")]
+    PngEncoding(png::EncodingError),
+    #[cfg(feature = "image")]
+    Image(image::ImageError),
+    /// An ICC profile couldn't be read, or a color conversion failed.
+    #[cfg(feature = "color")]
+    Color(moxcms::CmsError),
     Io(std::io::Error),
 }
 
@@ -62,7 +277,41 @@ impl core::fmt::Display for EncodingError {
         match self {
             Self::InvalidDimensions => write!(f, "invalid dimensions"),
             Self::InvalidDuration => write!(f, "invalid duration"),
+            Self::NoFrames => write!(f, "no frames"),
             Self::UnrecognizedImage => write!(f, "unrecognized image"),
+            Self::InvalidMetadata => write!(f, "invalid metadata"),
+            Self::TooLarge => write!(f, "file too large"),
+            Self::FrameSizeMismatch => write!(f, "frame size does not match image size"),
+            Self::HeaderAlreadyWritten => write!(f, "header already written"),
+            Self::InvalidQuality => write!(f, "invalid quality"),
+            Self::EncoderFailed => write!(f, "encoder failed"),
+            Self::Cancelled => write!(f, "cancelled"),
+            Self::VerificationFailed(what) => write!(f, "verification failed: {what}"),
+            #[cfg(feature = "manifest")]
+            Self::Manifest(e) => write!(f, "{e}"),
+            Self::InFile(path, e) => write!(f, "{}: {e}", path.display()),
+            #[cfg(feature = "glob")]
+            Self::Glob(e) => write!(f, "{e}"),
+            Self::Constraints(violations) => {
+                write!(f, "constraints not met")?;
+                for (i, v) in violations.iter().enumerate() {
+                    write!(f, "{}{v}", if i == 0 { ": " } else { "; " })?;
+                }
+                Ok(())
+            }
+            Self::Decoding(e) => write!(f, "{e}"),
+            #[cfg(feature = "gif")]
+            Self::Gif(e) => write!(f, "{e}"),
+            #[cfg(feature = "gif")]
+            Self::GifEncoding(e) => write!(f, "{e}"),
+            #[cfg(feature = "apng")]
+            Self::Png(e) => write!(f, "{e}"),
+            #[cfg(feature = "apng")]
+            Self::PngEncoding(e) => write!(f, "{e}"),
+            #[cfg(feature = "image")]
+            Self::Image(e) => write!(f, "{e}"),
+            #[cfg(feature = "color")]
+            Self::Color(e) => write!(f, "{e}"),
             Self::Io(e) => write!(f, "{e}"),
         }
     }
@@ -70,18 +319,125 @@ impl core::fmt::Display for EncodingError {
 
 impl core::error::Error for EncodingError {}
 
+impl From<DecodingError> for EncodingError {
+    fn from(value: DecodingError) -> Self {
+        Self::Decoding(value)
+    }
+}
+
+#[cfg(feature = "gif")]
+impl From<gif::DecodingError> for EncodingError {
+    fn from(value: gif::DecodingError) -> Self {
+        Self::Gif(value)
+    }
+}
+
+#[cfg(feature = "gif")]
+impl From<gif::EncodingError> for EncodingError {
+    fn from(value: gif::EncodingError) -> Self {
+        Self::GifEncoding(value)
+    }
+}
+
+#[cfg(feature = "apng")]
+impl From<png::DecodingError> for EncodingError {
+    fn from(value: png::DecodingError) -> Self {
+        Self::Png(value)
+    }
+}
+
+#[cfg(feature = "apng")]
+impl From<png::EncodingError> for EncodingError {
+    fn from(value: png::EncodingError) -> Self {
+        Self::PngEncoding(value)
+    }
+}
+
+#[cfg(feature = "image")]
+impl From<image::ImageError> for EncodingError {
+    fn from(value: image::ImageError) -> Self {
+        Self::Image(value)
+    }
+}
+
+#[cfg(feature = "color")]
+impl From<moxcms::CmsError> for EncodingError {
+    fn from(value: moxcms::CmsError) -> Self {
+        Self::Color(value)
+    }
+}
+
+#[cfg(feature = "glob")]
+impl From<glob::PatternError> for EncodingError {
+    fn from(value: glob::PatternError) -> Self {
+        Self::Glob(value)
+    }
+}
+
+#[cfg(feature = "manifest")]
+impl From<manifest::ParseError> for EncodingError {
+    fn from(value: manifest::ParseError) -> Self {
+        Self::Manifest(value)
+    }
+}
+
 impl From<std::io::Error> for EncodingError {
     fn from(value: std::io::Error) -> Self {
         Self::Io(value)
     }
 }
 
+/// The number of times an animation plays.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "u16", into = "u16"))]
+pub enum LoopCount {
+    #[default]
+    Infinite,
+    Finite(NonZeroU16),
+}
+
+impl From<u16> for LoopCount {
+    /// Converts the raw value stored in the file, where 0 means forever.
+    fn from(value: u16) -> Self {
+        match NonZeroU16::new(value) {
+            Some(n) => Self::Finite(n),
+            None => Self::Infinite,
+        }
+    }
+}
+
+impl From<LoopCount> for u16 {
+    fn from(value: LoopCount) -> Self {
+        match value {
+            LoopCount::Infinite => 0,
+            LoopCount::Finite(n) => n.get(),
+        }
+    }
+}
+
+/// Where the `EXIF` and `XMP ` chunks are written.  The `ICCP` chunk always
+/// precedes the `ANIM` chunk, as the format requires.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum MetadataPlacement {
+    /// After the frames, in the order given by the WebP container
+    /// specification.
+    #[default]
+    AfterFrames,
+    /// Between the `ANIM` chunk and the first frame, for readers that only
+    /// look for metadata near the start of the file.
+    BeforeFrames,
+}
+
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Params {
     pub width: u32,
     pub height: u32,
     pub background_bgra: [u8; 4],
-    pub loop_count: u16,
+    pub loop_count: LoopCount,
     pub has_alpha: bool,
 }
 
@@ -93,43 +449,159 @@ fn u24_bytes(x: u32) -> [u8; 3] {
 
 impl WebPAnimator {
     pub fn new(params: Params) -> Result<Self, EncodingError> {
-        if params.width > 0x1000000 || params.height > 0x1000000 {
-            return Err(EncodingError::InvalidDimensions);
-        }
-        let area = (params.width as u64) * (params.height as u64);
-        if area == 0 || (area >> 32) != 0 {
-            return Err(EncodingError::InvalidDimensions);
-        };
+        check_dimensions(params.width, params.height)?;
         Ok(Self {
             width: params.width,
             height: params.height,
-            icc_profile: Vec::new(),
-            exif_metadata: Vec::new(),
-            xmp_metadata: Vec::new(),
-            frame_data: Vec::new(),
+            metadata: Metadata::default(),
+            frames: Vec::new(),
+            chunks: HashMap::new(),
+            merge_duplicates: false,
             background_bgra: params.background_bgra,
-            loop_count: params.loop_count,
+            loop_count: params.loop_count.into(),
             has_alpha: params.has_alpha,
+            detect_alpha: false,
+            auto_canvas: false,
+            odd_offsets: OddOffsets::default(),
+            zero_duration: ZeroDurationPolicy::default(),
+            metadata_placement: MetadataPlacement::default(),
+            #[cfg(feature = "image")]
+            trim_transparent: false,
+            #[cfg(feature = "color")]
+            working_space: None,
+            last_timestamp: None,
+            validation: Validation::default(),
+            warnings: Vec::new(),
+            progress: None,
+            cancel: None,
         })
     }
 
-    pub fn set_icc_profile(&mut self, icc_profile: Vec<u8>) {
-        self.icc_profile = icc_profile;
+    /// Set the ICC profile.  `icc_profile` may be either the profile itself
+    /// or a complete `ICCP` chunk.  An empty profile is omitted.
+    pub fn set_icc_profile(&mut self, icc_profile: Vec<u8>) -> Result<(), EncodingError> {
+        let frame_len = self.frame_len();
+        self.metadata.set_icc_profile(icc_profile, frame_len)
+    }
+
+    /// Reads an ICC profile from a file and sets it, after checking its header
+    /// with [`metadata::validate_icc_profile`].
+    pub fn set_icc_profile_from_file(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<(), EncodingError> {
+        let icc_profile = std::fs::read(path)?;
+        metadata::validate_icc_profile(&icc_profile)?;
+        self.set_icc_profile(icc_profile)
+    }
+
+    /// Set the EXIF metadata.  `exif_metadata` may be either the metadata
+    /// itself or a complete `EXIF` chunk.  Empty metadata is omitted.
+    pub fn set_exif_metadata(&mut self, exif_metadata: Vec<u8>) -> Result<(), EncodingError> {
+        let frame_len = self.frame_len();
+        self.metadata.set_exif_metadata(exif_metadata, frame_len)
+    }
+
+    /// Set the XMP metadata.  `xmp_metadata` may be either the metadata
+    /// itself or a complete `XMP ` chunk.  Empty metadata is omitted.
+    pub fn set_xmp_metadata(&mut self, xmp_metadata: Vec<u8>) -> Result<(), EncodingError> {
+        let frame_len = self.frame_len();
+        self.metadata.set_xmp_metadata(xmp_metadata, frame_len)
+    }
+
+    /// If `true`, then adding a frame identical to the previous one (same
+    /// data, rectangle and options) extends the duration of the previous
+    /// frame instead of adding a new frame.  Defaults to `false`.
+    pub fn set_merge_duplicate_frames(&mut self, merge: bool) {
+        self.merge_duplicates = merge;
     }
 
-    pub fn set_exif_metadata(&mut self, exif_metadata: Vec<u8>) {
-        self.exif_metadata = exif_metadata;
+    /// Set how input that violates the WebP specification is handled.
+    /// Defaults to [`Validation::Lenient`].
+    pub fn set_validation(&mut self, validation: Validation) {
+        self.validation = validation;
     }
 
-    pub fn set_xmp_metadata(&mut self, xmp_metadata: Vec<u8>) {
-        self.xmp_metadata = xmp_metadata;
+    /// The corrections made so far in [`Validation::Lenient`] mode.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// If `true`, then the VP8X alpha flag is set when writing if any frame
+    /// has an `ALPH` chunk or is a `VP8L` image that uses alpha, even if
+    /// `has_alpha` was `false`.  Defaults to `false`.
+    pub fn set_detect_alpha(&mut self, detect: bool) {
+        self.detect_alpha = detect;
+    }
+
+    /// Grow the canvas to fit each frame that is added, instead of rejecting
+    /// frames that extend past it, so that the final size doesn't need to be
+    /// known in advance.  The canvas becomes the union of the frame
+    /// rectangles and the top left corner, and a frame without a rectangle
+    /// grows it to at least the size of the image before being placed.  The
+    /// size is only fixed when the animation is written.  Defaults to
+    /// `false`.
+    pub fn set_auto_canvas(&mut self, auto: bool) {
+        self.auto_canvas = auto;
+    }
+
+    /// Set how frames at odd offsets are handled.  Defaults to
+    /// [`OddOffsets::Reject`].
+    pub fn set_odd_offsets(&mut self, odd_offsets: OddOffsets) {
+        self.odd_offsets = odd_offsets;
+    }
+
+    /// Set how frames with a duration of 0 are handled, both when they are
+    /// added and by [`set_duration`](Self::set_duration).  Defaults to
+    /// [`ZeroDurationPolicy::Allow`].
+    pub fn set_zero_duration_policy(&mut self, policy: ZeroDurationPolicy) {
+        self.zero_duration = policy;
+    }
+
+    /// Crop fully transparent margins from images before encoding them,
+    /// moving the frame rectangle to match.  This only applies to images
+    /// that are encoded by the animator, such as with
+    /// [`add_image`](Self::add_image), and to frames that use
+    /// [`Blend::Alpha`] and [`Disposal::None`], since the result would
+    /// differ otherwise.  Defaults to `false`.
+    #[cfg(feature = "image")]
+    pub fn set_trim_transparent(&mut self, trim: bool) {
+        self.trim_transparent = trim;
+    }
+
+    /// Where to write the EXIF and XMP metadata.  Defaults to
+    /// [`MetadataPlacement::AfterFrames`].
+    pub fn set_metadata_placement(&mut self, placement: MetadataPlacement) {
+        self.metadata_placement = placement;
+    }
+
+    /// Stop adding frames, failing with [`EncodingError::Cancelled`], once
+    /// `token` is cancelled.  This also stops operations that add frames
+    /// one at a time, such as an [`Optimizer`] wrapping the animator, and
+    /// `write_async`.  Defaults to `None`.
+    pub fn set_cancellation_token(&mut self, token: Option<CancellationToken>) {
+        self.cancel = token;
+    }
+
+    /// Fails with [`EncodingError::Cancelled`] if the cancellation token has
+    /// been cancelled.
+    pub(crate) fn check_cancelled(&self) -> Result<(), EncodingError> {
+        cancel::check(&self.cancel)
+    }
+
+    /// Report progress to `sink` as frames are added and written, or stop
+    /// reporting if `None`.  Defaults to `None`.
+    pub fn set_progress_sink(&mut self, sink: Option<Box<dyn ProgressSink>>) {
+        self.progress = sink;
     }
 
     /// Add an image to the animation.
     ///
-    /// * `data` - A `VP8 ` or `VP8L` chunk.
-    /// * `frame` - The frame rectangle.  If `None`, then the frame rectangle
-    ///   is assumed to be the entire image.
+    /// * `data` - A `VP8 ` or `VP8L` chunk.  A `VP8 ` chunk may be preceded
+    ///   by an `ALPH` chunk.
+    /// * `frame` - The frame rectangle.  Its size must match the size of the
+    ///   encoded image.  If `None`, then the frame is placed at the top left
+    ///   corner of the canvas.
     /// * `duration` - The duration in milliseconds.
     pub fn add_webp_chunk(
         &mut self,
@@ -137,48 +609,225 @@ impl WebPAnimator {
         frame: Option<FrameRect>,
         duration: u32,
     ) -> Result<(), EncodingError> {
-        if !matches!(&data[..4], b"VP8L" | b"VP8 ") {
-            return Err(EncodingError::UnrecognizedImage);
-        }
-        if duration >> 24 != 0 {
-            return Err(EncodingError::InvalidDuration);
+        let options = FrameOptions {
+            rect: frame,
+            ..FrameOptions::with_duration(duration)
+        };
+        self.add_chunk(data, options)
+    }
+
+    /// Add an image to the animation.
+    ///
+    /// * `data` - A `VP8 ` or `VP8L` chunk.  A `VP8 ` chunk may be preceded
+    ///   by an `ALPH` chunk.
+    pub fn add_chunk(&mut self, data: &[u8], options: FrameOptions) -> Result<(), EncodingError> {
+        self.push_chunk(data, options, |this| FrameData::Shared(this.intern(data)))
+    }
+
+    /// Like [`add_chunk`](Self::add_chunk), but keeps a reference to `data`
+    /// instead of copying it, unless an earlier frame has the same data.
+    pub fn add_shared_chunk(
+        &mut self,
+        data: Arc<[u8]>,
+        options: FrameOptions,
+    ) -> Result<(), EncodingError> {
+        let view = Arc::clone(&data);
+        self.push_chunk(&view, options, |this| {
+            FrameData::Shared(this.intern_shared(data))
+        })
+    }
+
+    /// Like [`add_chunk`](Self::add_chunk), but keeps a reference to `data`
+    /// instead of copying it.
+    #[cfg(feature = "bytes")]
+    pub fn add_bytes_chunk(
+        &mut self,
+        data: bytes::Bytes,
+        options: FrameOptions,
+    ) -> Result<(), EncodingError> {
+        let view = data.clone();
+        self.push_chunk(&view, options, |_| FrameData::Bytes(data))
+    }
+
+    /// Like [`add_frame`](Self::add_frame), but keeps a reference to the
+    /// image data within `data` instead of copying it, except for extended
+    /// images whose `ALPH` chunk isn't directly before the `VP8 ` chunk.
+    #[cfg(feature = "bytes")]
+    pub fn add_bytes_frame(
+        &mut self,
+        data: bytes::Bytes,
+        options: FrameOptions,
+    ) -> Result<(), EncodingError> {
+        let chunks = match riff::still_image_chunks(&data)? {
+            std::borrow::Cow::Borrowed(chunks) => data.slice_ref(chunks),
+            std::borrow::Cow::Owned(chunks) => bytes::Bytes::from(chunks),
+        };
+        self.add_bytes_chunk(chunks, options)
+    }
+
+    /// Checks and adds a frame, using `store` to get the storage for `data`
+    /// once it has been accepted.
+    fn push_chunk(
+        &mut self,
+        data: &[u8],
+        options: FrameOptions,
+        store: impl FnOnce(&mut Self) -> FrameData,
+    ) -> Result<(), EncodingError> {
+        trace_span!(DEBUG, "add_frame", index = self.frames.len());
+        self.check_cancelled()?;
+        let mut warnings = Vec::new();
+        let lenient = (self.validation == Validation::Lenient).then_some(&mut warnings);
+        let mut options = options;
+        options.duration = self.zero_duration.apply(options.duration)?;
+        if let (OddOffsets::Snap, Some(rect)) = (self.odd_offsets, &mut options.rect) {
+            rect.x &= !1;
+            rect.y &= !1;
         }
-        let frame = frame.unwrap_or(FrameRect {
-            x: 0,
-            y: 0,
-            width: self.width,
-            height: self.height,
-        });
-        if frame.x & 1 != 0
-            || frame.y & 1 != 0
-            || frame.x + frame.width > self.width
-            || frame.x + frame.height > self.height
+        let (width, height) = if self.auto_canvas {
+            self.grown_canvas(data, &options)?
+        } else {
+            (self.width, self.height)
+        };
+        let (rect, options) =
+            check_frame(width, height, data, &options, self.frames.len(), lenient)?;
+        check_riff_size(self.riff_size() + padded_len(data.len()) + 24)?;
+        (self.width, self.height) = (width, height);
+        self.warnings.extend(warnings);
+        let frame = Frame {
+            rect,
+            duration: options.duration,
+            dispose: options.dispose,
+            blend: options.blend,
+            data: store(self),
+        };
+        if self.merge_duplicates
+            && self.is_last_frame(&frame)
+            && self.extend_last_duration(frame.duration)
         {
-            return Err(EncodingError::InvalidDimensions);
+            trace_event!(
+                debug,
+                "merged duplicate frame into frame {}",
+                self.frames.len() - 1
+            );
+        } else {
+            trace_event!(
+                debug,
+                "added frame {}: {} bytes, {:?}, {} ms",
+                self.frames.len(),
+                frame.data.len(),
+                frame.rect,
+                frame.duration
+            );
+            self.frames.push(frame);
         }
-        self.frame_data.write_all(b"ANMF")?;
-        let chunk_len = data.len() + 16;
-        self.frame_data
-            .write_all(&(chunk_len as u32).to_le_bytes())?;
-        self.frame_data.write_all(&u24_bytes(frame.x >> 1))?;
-        self.frame_data.write_all(&u24_bytes(frame.y >> 1))?;
-        self.frame_data.write_all(&u24_bytes(frame.width - 1))?;
-        self.frame_data.write_all(&u24_bytes(frame.height - 1))?;
-        self.frame_data.write_all(&u24_bytes(duration))?;
-        self.frame_data.write_all(&[0])?;
-        self.frame_data.write_all(data)?;
+        let progress = Progress {
+            stage: Stage::Adding,
+            frames: self.frames.len(),
+            total_frames: None,
+            bytes_written: 0,
+            total_bytes: None,
+        };
+        report(&mut self.progress, progress);
         Ok(())
     }
 
+    /// The canvas size after growing it to fit a frame, for
+    /// [`set_auto_canvas`](Self::set_auto_canvas).
+    fn grown_canvas(
+        &self,
+        data: &[u8],
+        options: &FrameOptions,
+    ) -> Result<(u32, u32), EncodingError> {
+        let (right, bottom) = match options.rect {
+            Some(rect) => (
+                rect.x.saturating_add(rect.width),
+                rect.y.saturating_add(rect.height),
+            ),
+            None => {
+                let image = riff::check_image_chunks(data)?;
+                bitstream::dimensions(&image).ok_or(EncodingError::UnrecognizedImage)?
+            }
+        };
+        let (width, height) = (self.width.max(right), self.height.max(bottom));
+        check_dimensions(width, height)?;
+        Ok((width, height))
+    }
+
+    /// Returns shared storage for `data`, reusing that of an earlier frame
+    /// with the same data if there is one.
+    fn intern(&mut self, data: &[u8]) -> Arc<[u8]> {
+        let hash = chunk_hash(data);
+        match self.lookup_chunk(hash, data) {
+            Some(shared) => shared,
+            None => self.remember_chunk(hash, Arc::from(data)),
+        }
+    }
+
+    /// Like [`intern`](Self::intern), but uses `data` itself as the storage
+    /// if there is no earlier frame with the same data.
+    fn intern_shared(&mut self, data: Arc<[u8]>) -> Arc<[u8]> {
+        let hash = chunk_hash(&data);
+        match self.lookup_chunk(hash, &data) {
+            Some(shared) => shared,
+            None => self.remember_chunk(hash, data),
+        }
+    }
+
+    fn lookup_chunk(&self, hash: u64, data: &[u8]) -> Option<Arc<[u8]>> {
+        self.chunks
+            .get(&hash)
+            .and_then(Weak::upgrade)
+            .filter(|shared| **shared == *data)
+    }
+
+    fn remember_chunk(&mut self, hash: u64, shared: Arc<[u8]>) -> Arc<[u8]> {
+        if self.chunks.len() > 2 * self.frames.len() {
+            self.chunks.retain(|_, chunk| chunk.strong_count() > 0);
+        }
+        self.chunks.insert(hash, Arc::downgrade(&shared));
+        shared
+    }
+
+    /// The number of bytes of frame data that repeat the data of an earlier
+    /// frame.  Repeated data is stored once in memory, but written out for
+    /// every frame that uses it.
+    pub fn shared_data_len(&self) -> usize {
+        let mut seen = HashSet::new();
+        self.frames
+            .iter()
+            .filter(|f| !seen.insert(f.data.as_ptr()))
+            .map(|f| f.data.len())
+            .sum()
+    }
+
+    fn is_last_frame(&self, frame: &Frame) -> bool {
+        self.frames.last().is_some_and(|last| {
+            last.rect == frame.rect
+                && last.dispose == frame.dispose
+                && last.blend == frame.blend
+                && last.data == frame.data
+        })
+    }
+
+    /// Adds `extra` milliseconds to the duration of the last frame.  Returns
+    /// `false` if there is no frame or the new duration would be too long.
+    pub(crate) fn extend_last_duration(&mut self, extra: u32) -> bool {
+        let Some(last) = self.frames.last_mut() else {
+            return false;
+        };
+        let Some(duration) = last.duration.checked_add(extra) else {
+            return false;
+        };
+        last.set_duration(duration).is_ok()
+    }
+
     /// Add an image to the animation.
     ///
-    /// * `data` - A WebP image.  Currently, only the simple WebP file format
-    ///   is supported, meaning that `data` should consist of a header plus
-    ///   a single `VP8 ` or `VP8L` chunk.
-    /// * `frame` - The frame rectangle.  If `None`, then the frame rectangle
-    ///   is assumed to be the entire image.  Frames must have even width and
-    ///   height.  In particular, calling this function with `frame=None` will
-    ///   fail if the image width or height is odd.
+    /// * `data` - A still WebP image, in either the simple or the extended
+    ///   file format.  Only the image data is used; any metadata in `data` is
+    ///   ignored.
+    /// * `frame` - The frame rectangle.  If `None`, then the frame is placed
+    ///   at the top left corner of the canvas.  Frames must have even offsets.
     /// * `duration` - The duration in milliseconds.
     pub fn add_webp_image(
         &mut self,
@@ -186,87 +835,1466 @@ impl WebPAnimator {
         frame: Option<FrameRect>,
         duration: u32,
     ) -> Result<(), EncodingError> {
-        self.add_webp_chunk(&data[12..], frame, duration)
+        let options = FrameOptions {
+            rect: frame,
+            ..FrameOptions::with_duration(duration)
+        };
+        self.add_frame(data, options)
+    }
+
+    /// Add an image to the animation.
+    ///
+    /// * `data` - A still WebP image, in either the simple or the extended
+    ///   file format.  Only the image data is used; any metadata in `data` is
+    ///   ignored.
+    pub fn add_frame(&mut self, data: &[u8], options: FrameOptions) -> Result<(), EncodingError> {
+        let data = riff::still_image_chunks(data)?;
+        self.add_chunk(&data, options)
+    }
+
+    /// Add the still WebP image in the file at `path`, as with
+    /// [`add_frame`](Self::add_frame).  Only the chunk headers and the image
+    /// data are read, and the image data is read directly into the storage
+    /// of the frame.  The exception is an extended image whose `ALPH` chunk
+    /// isn't directly before the `VP8 ` chunk, which is read in full first.
+    pub fn add_webp_file<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        options: FrameOptions,
+    ) -> Result<(), EncodingError> {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let mut file = std::fs::File::open(path)?;
+        let Some(span) = riff::still_image_span(&mut file)? else {
+            let mut data = Vec::new();
+            file.rewind()?;
+            file.read_to_end(&mut data)?;
+            return self.add_frame(&data, options);
+        };
+        let mut data: Arc<[u8]> =
+            std::iter::repeat_n(0, (span.end - span.start) as usize).collect();
+        file.seek(SeekFrom::Start(span.start))?;
+        file.read_exact(Arc::get_mut(&mut data).unwrap())?;
+        self.add_shared_chunk(data, options)
     }
 
-    const WEBP_HEADER_LEN: usize = 4;
-    const VP8X_HEADER_LEN: usize = 18;
-    const ANIM_HEADER_LEN: usize = 14;
-    const TOTAL_HEADER_LEN: usize =
-        Self::WEBP_HEADER_LEN + Self::VP8X_HEADER_LEN + Self::ANIM_HEADER_LEN;
+    /// Like [`add_webp_file`](Self::add_webp_file), but maps the file into
+    /// memory and keeps a reference to the image data within the mapping
+    /// instead of reading it, except for extended images whose `ALPH` chunk
+    /// isn't directly before the `VP8 ` chunk.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated until the animator and
+    /// every [`Frame`] taken from it have been dropped.
+    #[cfg(feature = "memmap")]
+    pub unsafe fn add_webp_mmap<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        options: FrameOptions,
+    ) -> Result<(), EncodingError> {
+        let file = File::open(path)?;
+        // SAFETY: The caller guarantees that the file isn't modified.
+        let map = Arc::new(unsafe { memmap2::Mmap::map(&file)? });
+        let range = match riff::still_image_chunks(&map)? {
+            std::borrow::Cow::Borrowed(chunks) => {
+                let start = chunks.as_ptr() as usize - map.as_ptr() as usize;
+                start..start + chunks.len()
+            }
+            std::borrow::Cow::Owned(chunks) => return self.add_chunk(&chunks, options),
+        };
+        let view = Arc::clone(&map);
+        self.push_chunk(&view[range.clone()], options, |_| {
+            FrameData::Mapped(map, range)
+        })
+    }
 
+    /// Add an image that is shown starting `pts_ms` milliseconds into the
+    /// stream, as delivered by video decoders and screen recorders.  The
+    /// duration of the previous frame added this way becomes the gap between
+    /// the two timestamps, so `options.duration` only matters for the last
+    /// frame; it can be changed afterwards with
+    /// [`set_duration`](Self::set_duration).  The first timestamp is the
+    /// start of the animation.
+    ///
+    /// Fails with [`EncodingError::InvalidDuration`] if `pts_ms` is earlier
+    /// than the previous timestamp or the gap is too long for a frame.
+    pub fn add_frame_at(
+        &mut self,
+        pts_ms: u64,
+        data: &[u8],
+        options: FrameOptions,
+    ) -> Result<(), EncodingError> {
+        // Only set the duration if no other frame has been added since.
+        let previous = self
+            .last_timestamp
+            .filter(|&(_, index)| index + 1 == self.frames.len());
+        let restore = match previous {
+            Some((start, index)) => {
+                let gap = pts_ms
+                    .checked_sub(start)
+                    .and_then(|gap| u32::try_from(gap).ok())
+                    .ok_or(EncodingError::InvalidDuration)?;
+                let gap = self.zero_duration.apply(gap)?;
+                let duration = self.frames[index].duration;
+                self.frames[index].set_duration(gap)?;
+                Some((index, duration))
+            }
+            None => None,
+        };
+        let count = self.frames.len();
+        if let Err(e) = self.add_frame(data, options) {
+            if let Some((index, duration)) = restore {
+                self.frames[index].duration = duration;
+            }
+            return Err(e);
+        }
+        // A merged duplicate extends the frame that started earlier.
+        if self.frames.len() > count || previous.is_none() {
+            self.last_timestamp = Some((pts_ms, self.frames.len() - 1));
+        }
+        Ok(())
+    }
+
+    /// Append the frames of an existing animated WebP image without
+    /// re-encoding them.  If the canvas of `data` is smaller than this
+    /// animation's canvas, then its frames are centered (rounding the offset
+    /// down to an even number, as required by the format).
+    pub fn append_animation(&mut self, data: &[u8]) -> Result<(), EncodingError> {
+        let decoder = Decoder::new(data)?;
+        if decoder.width() > self.width || decoder.height() > self.height {
+            return Err(EncodingError::InvalidDimensions);
+        }
+        let dx = ((self.width - decoder.width()) / 2) & !1;
+        let dy = ((self.height - decoder.height()) / 2) & !1;
+        for frame in decoder.frames() {
+            let rect = FrameRect {
+                x: frame.rect.x + dx,
+                y: frame.rect.y + dy,
+                ..frame.rect
+            };
+            let options = FrameOptions {
+                rect: Some(rect),
+                ..frame.options()
+            };
+            self.add_chunk(frame.data, options)?;
+        }
+        Ok(())
+    }
+
+    /// The frames that have been added so far.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// Mutable access to the frames, for adjusting their settings before
+    /// writing.
+    pub fn frames_mut(&mut self) -> &mut [Frame] {
+        &mut self.frames
+    }
+
+    /// Removes and returns the frame at position `index`.
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove_frame(&mut self, index: usize) -> Frame {
+        self.frames.remove(index)
+    }
+
+    /// Swaps the frames at positions `a` and `b`.
+    ///
+    /// Panics if `a` or `b` is out of bounds.
+    pub fn swap_frames(&mut self, a: usize, b: usize) {
+        self.frames.swap(a, b);
+    }
+
+    /// Sets the duration in milliseconds of the frame at position `index`.
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn set_duration(&mut self, index: usize, duration: u32) -> Result<(), EncodingError> {
+        let duration = self.zero_duration.apply(duration)?;
+        self.frames[index].set_duration(duration)
+    }
+
+    /// Removes all frames and warnings, so that the animator can be reused
+    /// for another animation with the same settings and metadata.  The
+    /// memory used to track the frames is kept.  The canvas keeps its
+    /// current size, which may have grown with
+    /// [`set_auto_canvas`](Self::set_auto_canvas).
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.chunks.clear();
+        self.last_timestamp = None;
+        self.warnings.clear();
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The sum of the durations of all frames.
+    pub fn total_duration(&self) -> Duration {
+        let ms: u64 = self.frames.iter().map(|f| f.duration as u64).sum();
+        Duration::from_millis(ms)
+    }
+
+    /// The exact number of bytes that [`write`](Self::write) would produce
+    /// if called now.
+    pub fn estimated_size(&self) -> usize {
+        self.riff_size() + 8
+    }
+
+    /// The number of bytes that can still be added before the file reaches
+    /// the 4 GiB size limit of the RIFF format.  A frame whose data is `n`
+    /// bytes long takes up `n + 24` bytes, plus a padding byte if `n` is
+    /// odd.
+    pub fn headroom(&self) -> usize {
+        MAX_RIFF_SIZE.saturating_sub(self.riff_size())
+    }
+
+    /// Whether the VP8X alpha flag will be set when writing.
+    pub fn has_alpha(&self) -> bool {
+        self.has_alpha
+            || (self.detect_alpha && self.frames.iter().any(|f| bitstream::has_alpha(&f.data)))
+    }
+
+    /// The length of the `ANMF` chunks.
+    pub(crate) fn frame_len(&self) -> usize {
+        self.frames.iter().map(Frame::chunk_len).sum()
+    }
+
+    /// The value of the RIFF size field, which counts everything after it.
+    fn riff_size(&self) -> usize {
+        self.metadata.riff_size(self.frame_len())
+    }
+
+    fn header(&mut self) -> Result<(Header, u32), EncodingError> {
+        self.validation
+            .check_frame_count(self.frames.len(), &mut self.warnings)?;
+        let size = self.riff_size();
+        check_riff_size(size)?;
+        let header = Header {
+            width: self.width,
+            height: self.height,
+            background_bgra: self.background_bgra,
+            loop_count: self.loop_count,
+            flags: self.metadata.flags(self.has_alpha()),
+        };
+        Ok((header, size as u32))
+    }
+
+    /// Writes the animation.  The output is passed to the writer in a few
+    /// large vectored writes, consisting of slices of the frame data and
+    /// their headers, rather than a call for every chunk.
+    ///
+    /// The output depends only on the frames, settings and metadata, and not
+    /// on how or where the frame data is stored, so an animation built the
+    /// same way is always written with the same bytes.  The VP8X header and
+    /// `ICCP` chunk come first, then the `ANIM` chunk and the frames in
+    /// order, and then the `EXIF` and `XMP ` chunks, unless moved with
+    /// [`set_metadata_placement`](Self::set_metadata_placement).
     pub fn write<W: Write + ?Sized>(&mut self, writer: &mut W) -> Result<(), EncodingError> {
+        trace_span!(DEBUG, "write", frames = self.frames.len());
+        let (header, size) = self.header()?;
+        let mut head = Vec::with_capacity(self.metadata.header_len());
+        header.write(&mut head, size, &self.metadata.icc_profile)?;
+        let anmf_headers: Vec<[u8; 24]> = self
+            .frames
+            .iter()
+            .map(|f| anmf_header(f.data.len(), &f.rect, &f.options()))
+            .collect();
+        let metadata = [
+            (b"EXIF", &self.metadata.exif),
+            (b"XMP ", &self.metadata.xmp),
+        ]
+        .map(|(fourcc, payload)| (riff::chunk_header(fourcc, payload.len()), payload));
+        let mut progress = Progress {
+            stage: Stage::Writing,
+            frames: 0,
+            total_frames: Some(self.frames.len()),
+            bytes_written: 0,
+            total_bytes: Some(size as u64 + 8),
+        };
+        report(&mut self.progress, progress);
+        trace_event!(
+            debug,
+            "writing {} frames, {} bytes",
+            self.frames.len(),
+            size as u64 + 8
+        );
+        let mut metadata_slices = Vec::new();
+        for (header, payload) in metadata.iter().filter(|(_, p)| !p.is_empty()) {
+            metadata_slices.push(IoSlice::new(header));
+            metadata_slices.push(IoSlice::new(payload));
+            if payload.len() % 2 == 1 {
+                metadata_slices.push(IoSlice::new(&[0]));
+            }
+        }
+        let mut slices = vec![IoSlice::new(&head)];
+        if self.metadata_placement == MetadataPlacement::BeforeFrames {
+            slices.append(&mut metadata_slices);
+        }
+        for (header, frame) in anmf_headers.iter().zip(&self.frames) {
+            if slices.len() + 3 > MAX_IO_SLICES {
+                progress.bytes_written += write_all_vectored(writer, &mut slices)?;
+                report(&mut self.progress, progress);
+                slices.clear();
+            }
+            slices.push(IoSlice::new(header));
+            slices.push(IoSlice::new(&frame.data));
+            if frame.data.len() % 2 == 1 {
+                slices.push(IoSlice::new(&[0]));
+            }
+            progress.frames += 1;
+        }
+        slices.append(&mut metadata_slices);
+        progress.bytes_written += write_all_vectored(writer, &mut slices)?;
+        progress.stage = Stage::Finished;
+        report(&mut self.progress, progress);
+        trace_event!(debug, "finished writing {} bytes", progress.bytes_written);
+        Ok(())
+    }
+
+    /// Write the animation to a new file at `path`, replacing any existing
+    /// file.
+    pub fn write_to_file<P: AsRef<Path>>(&mut self, path: P) -> Result<(), EncodingError> {
+        self.write_file(path.as_ref(), false)
+    }
+
+    /// Like [`write_to_file`](Self::write_to_file), but also waits for the
+    /// data to reach the disk before returning.
+    pub fn write_to_file_synced<P: AsRef<Path>>(&mut self, path: P) -> Result<(), EncodingError> {
+        self.write_file(path.as_ref(), true)
+    }
+
+    fn write_file(&mut self, path: &Path, sync: bool) -> Result<(), EncodingError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        let file = writer.into_inner().map_err(|e| e.into_error())?;
+        if sync {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the animation as a WebP file in memory.
+    pub fn into_bytes(mut self) -> Result<Vec<u8>, EncodingError> {
+        let mut buf = Vec::with_capacity(self.estimated_size());
+        self.write(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Like [`write`](Self::write), but writes to a [`tokio::io::AsyncWrite`].
+    /// Progress is reported in the same way.  Since writing to a socket can
+    /// take a while, this also fails with [`EncodingError::Cancelled`],
+    /// leaving the output incomplete, if the cancellation token is cancelled
+    /// before a frame is written.
+    #[cfg(feature = "tokio")]
+    pub async fn write_async<W: tokio::io::AsyncWrite + Unpin + ?Sized>(
+        &mut self,
+        writer: &mut W,
+    ) -> Result<(), EncodingError> {
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!("write_async", frames = self.frames.len());
+        let write = self.write_frames_async(writer);
+        // A span entered in an async function would stay entered while it
+        // waits, so the future is instrumented instead.
+        #[cfg(feature = "tracing")]
+        let write = tracing::Instrument::instrument(write, span);
+        write.await
+    }
+
+    #[cfg(feature = "tokio")]
+    async fn write_frames_async<W: tokio::io::AsyncWrite + Unpin + ?Sized>(
+        &mut self,
+        writer: &mut W,
+    ) -> Result<(), EncodingError> {
+        use tokio::io::AsyncWriteExt;
+
+        let (header, size) = self.header()?;
+        let mut buf = Vec::with_capacity(self.metadata.header_len());
+        header.write(&mut buf, size, &self.metadata.icc_profile)?;
+        let mut metadata = Vec::new();
+        self.metadata.write_exif_xmp(&mut metadata)?;
+        let before = self.metadata_placement == MetadataPlacement::BeforeFrames;
+        if before {
+            buf.append(&mut metadata);
+        }
+        let mut progress = Progress {
+            stage: Stage::Writing,
+            frames: 0,
+            total_frames: Some(self.frames.len()),
+            bytes_written: 0,
+            total_bytes: Some(size as u64 + 8),
+        };
+        report(&mut self.progress, progress);
+        writer.write_all(&buf).await?;
+        progress.bytes_written += buf.len() as u64;
+        for frame in &self.frames {
+            cancel::check(&self.cancel)?;
+            let header = anmf_header(frame.data.len(), &frame.rect, &frame.options());
+            let padding = padding(frame.data.len());
+            writer.write_all(&header).await?;
+            writer.write_all(&frame.data).await?;
+            writer.write_all(padding).await?;
+            progress.frames += 1;
+            progress.bytes_written += (header.len() + frame.data.len() + padding.len()) as u64;
+            report(&mut self.progress, progress);
+        }
+        writer.write_all(&metadata).await?;
+        progress.bytes_written += metadata.len() as u64;
+        progress.stage = Stage::Finished;
+        report(&mut self.progress, progress);
+        Ok(())
+    }
+}
+
+fn chunk_hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub(crate) fn check_dimensions(width: u32, height: u32) -> Result<(), EncodingError> {
+    if width > 0x1000000 || height > 0x1000000 {
+        return Err(EncodingError::InvalidDimensions);
+    }
+    let area = (width as u64) * (height as u64);
+    if area == 0 || (area >> 32) != 0 {
+        return Err(EncodingError::InvalidDimensions);
+    };
+    Ok(())
+}
+
+/// The largest allowed value of the RIFF size field.  It must be even, so
+/// this is one less than `u32::MAX`.
+pub(crate) const MAX_RIFF_SIZE: usize = u32::MAX as usize - 1;
+
+pub(crate) fn check_riff_size(size: usize) -> Result<(), EncodingError> {
+    if size > MAX_RIFF_SIZE {
+        return Err(EncodingError::TooLarge);
+    }
+    Ok(())
+}
+
+/// Checks that `data` and `options` are acceptable for frame number `index`
+/// on a canvas of the given size, and returns the frame rectangle and options
+/// to use.  If `lenient` is given, then problems are corrected where possible
+/// and recorded there.
+pub(crate) fn check_frame(
+    width: u32,
+    height: u32,
+    data: &[u8],
+    options: &FrameOptions,
+    index: usize,
+    lenient: Option<&mut Vec<Warning>>,
+) -> Result<(FrameRect, FrameOptions), EncodingError> {
+    let image = riff::check_image_chunks(data)?;
+    let size = bitstream::dimensions(&image).ok_or(EncodingError::UnrecognizedImage)?;
+    let mut options = *options;
+    let mut frame = match options.rect {
+        Some(rect) => rect,
+        None => options
+            .anchor
+            .place((width, height), size)
+            .ok_or(EncodingError::InvalidDimensions)?,
+    };
+    if let Some(warnings) = lenient {
+        if options.duration > MAX_DURATION_MS {
+            options.duration = MAX_DURATION_MS;
+            warnings.push(Warning::DurationClamped(index));
+        }
+        if (frame.width, frame.height) != size {
+            (frame.width, frame.height) = size;
+            warnings.push(Warning::FrameSizeCorrected(index));
+        }
+        if frame.x & 1 != 0 || frame.y & 1 != 0 {
+            frame.x &= !1;
+            frame.y &= !1;
+            warnings.push(Warning::OffsetRounded(index));
+        }
+        if frame.x as u64 + frame.width as u64 > width as u64
+            || frame.y as u64 + frame.height as u64 > height as u64
+        {
+            frame.x = frame.x.min(width.saturating_sub(frame.width)) & !1;
+            frame.y = frame.y.min(height.saturating_sub(frame.height)) & !1;
+            warnings.push(Warning::FrameMoved(index));
+        }
+    }
+    if options.duration > MAX_DURATION_MS {
+        return Err(EncodingError::InvalidDuration);
+    }
+    if frame.x & 1 != 0
+        || frame.y & 1 != 0
+        || !(1..=0x1000000).contains(&frame.width)
+        || !(1..=0x1000000).contains(&frame.height)
+        || frame.x as u64 + frame.width as u64 > width as u64
+        || frame.y as u64 + frame.height as u64 > height as u64
+    {
+        return Err(EncodingError::InvalidDimensions);
+    }
+    if size != (frame.width, frame.height) {
+        return Err(EncodingError::FrameSizeMismatch);
+    }
+    options.rect = Some(frame);
+    Ok((frame, options))
+}
+
+/// The length of `len` bytes of RIFF data after padding to an even length.
+pub(crate) fn padded_len(len: usize) -> usize {
+    len + (len & 1)
+}
+
+/// The padding that follows `len` bytes of RIFF data.
+pub(crate) fn padding(len: usize) -> &'static [u8] {
+    &[0][..len & 1]
+}
+
+/// Writes `data` followed by a padding byte if its length is odd.
+pub(crate) fn write_padded<W: Write + ?Sized>(writer: &mut W, data: &[u8]) -> std::io::Result<()> {
+    writer.write_all(data)?;
+    writer.write_all(padding(data.len()))
+}
+
+/// The most slices passed to a single vectored write.  Operating systems
+/// limit the number, commonly to 1024.
+const MAX_IO_SLICES: usize = 1024;
+
+/// Writes all of `slices`, continuing after partial writes, and returns the
+/// number of bytes written.  The slices are modified in the process.
+pub(crate) fn write_all_vectored<W: Write + ?Sized>(
+    writer: &mut W,
+    mut slices: &mut [IoSlice],
+) -> std::io::Result<u64> {
+    let len: usize = slices.iter().map(|s| s.len()).sum();
+    while !slices.is_empty() {
+        match writer.write_vectored(slices) {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(n) => IoSlice::advance_slices(&mut slices, n),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len as u64)
+}
+
+/// Writes a metadata chunk, unless `payload` is empty.
+pub(crate) fn write_metadata_chunk<W: Write + ?Sized>(
+    writer: &mut W,
+    fourcc: &[u8; 4],
+    payload: &[u8],
+) -> std::io::Result<()> {
+    if payload.is_empty() {
+        return Ok(());
+    }
+    writer.write_all(&riff::chunk_header(fourcc, payload.len()))?;
+    write_padded(writer, payload)
+}
+
+/// The header of an `ANMF` chunk whose frame data is `data_len` bytes long.
+/// Frame data of odd length is padded when it is written.
+pub(crate) fn anmf_header(data_len: usize, frame: &FrameRect, options: &FrameOptions) -> [u8; 24] {
+    let mut header = [0; 24];
+    header[..4].copy_from_slice(b"ANMF");
+    let chunk_len = padded_len(data_len) + 16;
+    header[4..8].copy_from_slice(&(chunk_len as u32).to_le_bytes());
+    header[8..11].copy_from_slice(&u24_bytes(frame.x >> 1));
+    header[11..14].copy_from_slice(&u24_bytes(frame.y >> 1));
+    header[14..17].copy_from_slice(&u24_bytes(frame.width - 1));
+    header[17..20].copy_from_slice(&u24_bytes(frame.height - 1));
+    header[20..23].copy_from_slice(&u24_bytes(options.duration));
+    header[23] = options.anmf_flags();
+    header
+}
+
+pub(crate) fn write_anmf_chunk<W: Write + ?Sized>(
+    writer: &mut W,
+    data: &[u8],
+    frame: &FrameRect,
+    options: &FrameOptions,
+) -> std::io::Result<()> {
+    writer.write_all(&anmf_header(data.len(), frame, options))?;
+    write_padded(writer, data)
+}
+
+const WEBP_HEADER_LEN: usize = 4;
+pub(crate) const VP8X_HEADER_LEN: usize = 18;
+const ANIM_HEADER_LEN: usize = 14;
+pub(crate) const TOTAL_HEADER_LEN: usize = WEBP_HEADER_LEN + VP8X_HEADER_LEN + ANIM_HEADER_LEN;
+/// Offset of the VP8X flags byte from the start of the file.
+pub(crate) const VP8X_FLAGS_OFFSET: u64 = 20;
+
+/// Everything that goes in the file before the first `ANMF` chunk.
+pub(crate) struct Header {
+    pub width: u32,
+    pub height: u32,
+    pub background_bgra: [u8; 4],
+    pub loop_count: u16,
+    pub flags: u8,
+}
+
+impl Header {
+    pub fn write<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        riff_size: u32,
+        icc_profile: &[u8],
+    ) -> std::io::Result<()> {
         writer.write_all(b"RIFF")?;
-        let size = Self::TOTAL_HEADER_LEN
-            + self.frame_data.len()
-            + self.icc_profile.len()
-            + self.exif_metadata.len()
-            + self.xmp_metadata.len();
-        writer.write_all(&(size as u32).to_le_bytes())?;
+        writer.write_all(&riff_size.to_le_bytes())?;
         writer.write_all(b"WEBPVP8X")?;
         writer.write_all(&10u32.to_le_bytes())?;
-        let icc_flag = if !self.icc_profile.is_empty() {
-            0x20
-        } else {
-            0
-        };
-        let alpha_flag = if self.has_alpha { 0x10 } else { 0 };
-        let exif_flag = if !self.exif_metadata.is_empty() {
-            0x8
-        } else {
-            0
-        };
-        let xmp_flag = if !self.xmp_metadata.is_empty() {
-            0x4
-        } else {
-            0
-        };
-        let animation_flag = 0x2;
-        let flags = icc_flag | alpha_flag | exif_flag | xmp_flag | animation_flag;
-        writer.write_all(&[flags])?;
+        writer.write_all(&[self.flags])?;
         writer.write_all(&[0; 3])?;
         writer.write_all(&u24_bytes(self.width - 1))?;
         writer.write_all(&u24_bytes(self.height - 1))?;
-        writer.write_all(&self.icc_profile)?;
+        write_metadata_chunk(writer, b"ICCP", icc_profile)?;
         writer.write_all(b"ANIM")?;
         writer.write_all(&6u32.to_le_bytes())?;
         writer.write_all(&self.background_bgra)?;
         writer.write_all(&self.loop_count.to_le_bytes())?;
-        writer.write_all(&self.frame_data)?;
-        writer.write_all(&self.exif_metadata)?;
-        writer.write_all(&self.xmp_metadata)?;
         Ok(())
     }
 }
 
+/// The metadata of an animation, as kept by all of the writers.  The ICC
+/// profile is written with the [`Header`], and the EXIF and XMP metadata
+/// usually after the frames.
+#[derive(Default)]
+pub(crate) struct Metadata {
+    pub icc_profile: Vec<u8>,
+    pub exif: Vec<u8>,
+    pub xmp: Vec<u8>,
+}
+
+impl Metadata {
+    /// Replaces the ICC profile of an animation whose `ANMF` chunks are
+    /// `frame_len` bytes long.
+    pub fn set_icc_profile(
+        &mut self,
+        icc_profile: Vec<u8>,
+        frame_len: usize,
+    ) -> Result<(), EncodingError> {
+        self.icc_profile = self.replacement(b"ICCP", &self.icc_profile, icc_profile, frame_len)?;
+        Ok(())
+    }
+
+    /// Like [`set_icc_profile`](Self::set_icc_profile), for the EXIF
+    /// metadata.
+    pub fn set_exif_metadata(
+        &mut self,
+        exif_metadata: Vec<u8>,
+        frame_len: usize,
+    ) -> Result<(), EncodingError> {
+        self.exif = self.replacement(b"EXIF", &self.exif, exif_metadata, frame_len)?;
+        Ok(())
+    }
+
+    /// Like [`set_icc_profile`](Self::set_icc_profile), for the XMP metadata.
+    pub fn set_xmp_metadata(
+        &mut self,
+        xmp_metadata: Vec<u8>,
+        frame_len: usize,
+    ) -> Result<(), EncodingError> {
+        self.xmp = self.replacement(b"XMP ", &self.xmp, xmp_metadata, frame_len)?;
+        Ok(())
+    }
+
+    /// Returns the payload of `data`, the replacement for `old`, after
+    /// checking that the file won't become too large.
+    fn replacement(
+        &self,
+        fourcc: &[u8; 4],
+        old: &[u8],
+        data: Vec<u8>,
+        frame_len: usize,
+    ) -> Result<Vec<u8>, EncodingError> {
+        let data = riff::metadata_payload(fourcc, data)?;
+        let size = self.riff_size(frame_len) - riff::metadata_chunk_len(old.len());
+        check_riff_size(size + riff::metadata_chunk_len(data.len()))?;
+        Ok(data)
+    }
+
+    /// The length of the metadata chunks.
+    pub fn len(&self) -> usize {
+        [&self.icc_profile, &self.exif, &self.xmp]
+            .iter()
+            .map(|m| riff::metadata_chunk_len(m.len()))
+            .sum()
+    }
+
+    /// The value of the RIFF size field of an animation whose `ANMF` chunks
+    /// are `frame_len` bytes long.
+    pub fn riff_size(&self, frame_len: usize) -> usize {
+        TOTAL_HEADER_LEN + frame_len + self.len()
+    }
+
+    /// The length of everything before the first `ANMF` chunk, when the
+    /// EXIF and XMP metadata come after the frames.
+    pub fn header_len(&self) -> usize {
+        TOTAL_HEADER_LEN + 8 + riff::metadata_chunk_len(self.icc_profile.len())
+    }
+
+    /// The VP8X flags.
+    pub fn flags(&self, has_alpha: bool) -> u8 {
+        let icc_flag = if !self.icc_profile.is_empty() {
+            0x20
+        } else {
+            0
+        };
+        let alpha_flag = if has_alpha { 0x10 } else { 0 };
+        let exif_flag = if !self.exif.is_empty() { 0x8 } else { 0 };
+        let xmp_flag = if !self.xmp.is_empty() { 0x4 } else { 0 };
+        let animation_flag = 0x2;
+        icc_flag | alpha_flag | exif_flag | xmp_flag | animation_flag
+    }
+
+    /// Writes the `EXIF` and `XMP ` chunks.
+    pub fn write_exif_xmp<W: Write + ?Sized>(&self, writer: &mut W) -> std::io::Result<()> {
+        write_metadata_chunk(writer, b"EXIF", &self.exif)?;
+        write_metadata_chunk(writer, b"XMP ", &self.xmp)
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use image::{Rgb, RgbImage, codecs::webp::WebPEncoder};
+    use std::{io::Cursor, time::Duration};
+
+    use image::{Rgb, RgbImage, Rgba, RgbaImage, codecs::webp::WebPEncoder};
 
-    use crate::{Params, WebPAnimator};
+    use crate::{
+        Anchor, BufferPool, Decoder, Disposal, EncodingError, FrameOptions, FrameRect,
+        MAX_DURATION_MS, MetadataPlacement, Params, VP8X_FLAGS_OFFSET, Validation, Warning,
+        WebPAnimator, WebPStreamWriter, ZeroDurationPolicy, check_riff_size,
+        test_util::{lossless_webp, params},
+    };
 
     #[test]
     fn test_write() {
         let img1 = RgbImage::from_pixel(64, 64, Rgb([255, 0, 0]));
         let img2 = RgbImage::from_pixel(64, 64, Rgb([0, 0, 255]));
         let params = Params {
-            width: 64,
-            height: 64,
-            background_bgra: [255, 255, 255, 255],
-            loop_count: 0,
-            has_alpha: false,
+            background_bgra: [255; 4],
+            ..params(64, 64)
         };
         let mut writer = WebPAnimator::new(params).unwrap();
-        let mut buf = Vec::new();
-        img1.write_with_encoder(WebPEncoder::new_lossless(&mut buf))
-            .unwrap();
+        let mut buf = lossless_webp(&img1);
         writer.add_webp_image(&buf, None, 500).unwrap();
-        buf.clear();
-        img2.write_with_encoder(WebPEncoder::new_lossless(&mut buf))
-            .unwrap();
+        buf = lossless_webp(&img2);
         writer.add_webp_image(&buf, None, 500).unwrap();
         buf.clear();
         writer.write(&mut buf).unwrap();
         webp_animation::Decoder::new(&buf).unwrap();
     }
+
+    #[test]
+    fn test_append_animation() {
+        let small = params(32, 32);
+        let mut clip = WebPAnimator::new(small).unwrap();
+        let buf = lossless_webp(&RgbImage::from_pixel(32, 32, Rgb([255, 0, 0])));
+        clip.add_webp_image(&buf, None, 100).unwrap();
+        clip.add_webp_image(&buf, None, 200).unwrap();
+        let mut clip_data = Vec::new();
+        clip.write(&mut clip_data).unwrap();
+        let mut writer = WebPAnimator::new(Params {
+            width: 66,
+            height: 64,
+            ..small
+        })
+        .unwrap();
+        writer.append_animation(&clip_data).unwrap();
+        let mut out = Vec::new();
+        writer.write(&mut out).unwrap();
+        assert!(matches!(
+            clip.append_animation(&out),
+            Err(EncodingError::InvalidDimensions)
+        ));
+        let decoder = Decoder::new(&out).unwrap();
+        let frames = decoder.frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].duration, 200);
+        assert_eq!(
+            frames[0].rect,
+            FrameRect {
+                x: 16,
+                y: 16,
+                width: 32,
+                height: 32
+            }
+        );
+        webp_animation::Decoder::new(&out).unwrap();
+    }
+
+    #[test]
+    fn test_merge_duplicate_frames() {
+        let params = params(16, 16);
+        let red = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([255, 0, 0])));
+        let blue = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([0, 0, 255])));
+        let mut writer = WebPAnimator::new(params).unwrap();
+        writer.set_merge_duplicate_frames(true);
+        for (img, duration) in [(&red, 100), (&red, 50), (&blue, 100), (&red, 10)] {
+            writer.add_webp_image(img, None, duration).unwrap();
+        }
+        // The third frame repeats the data of the first.
+        let frames = writer.frames();
+        assert_eq!(frames[0].data.as_ptr(), frames[2].data.as_ptr());
+        assert_eq!(writer.shared_data_len(), frames[0].data().len());
+        let mut out = Vec::new();
+        writer.write(&mut out).unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        let durations: Vec<_> = decoder.frames().iter().map(|f| f.duration).collect();
+        assert_eq!(durations, [150, 100, 10]);
+        assert_eq!(decoder.frames()[2].data, decoder.frames()[0].data);
+    }
+
+    #[test]
+    fn test_add_webp_file() {
+        let path =
+            std::env::temp_dir().join(format!("webp-animator-file-{}.webp", std::process::id()));
+        let animator = || {
+            WebPAnimator::builder()
+                .width(21)
+                .height(40)
+                .build()
+                .unwrap()
+        };
+        let (mut writer, mut expected) = (animator(), animator());
+        let red = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([255, 0, 0])));
+        std::fs::write(&path, &red).unwrap();
+        writer
+            .add_webp_file(&path, FrameOptions::with_duration(100))
+            .unwrap();
+        // A lossy image with alpha, in the extended format.
+        let config = webp_animation::EncoderOptions {
+            encoding_config: Some(webp_animation::EncodingConfig::new_lossy(75.0)),
+            ..Default::default()
+        };
+        let mut encoder = webp_animation::Encoder::new_with_options((21, 40), config).unwrap();
+        encoder
+            .add_frame(&[9, 8, 7, 128].repeat(21 * 40), 0)
+            .unwrap();
+        let lossy = encoder.finalize(100).unwrap();
+        std::fs::write(&path, &lossy).unwrap();
+        writer
+            .add_webp_file(&path, FrameOptions::with_duration(50))
+            .unwrap();
+        std::fs::write(&path, b"RIFF\x04\0\0\0WEBQ").unwrap();
+        let invalid = writer.add_webp_file(&path, FrameOptions::with_duration(50));
+        std::fs::remove_file(&path).unwrap();
+
+        expected
+            .add_frame(&red, FrameOptions::with_duration(100))
+            .unwrap();
+        expected
+            .add_frame(&lossy, FrameOptions::with_duration(50))
+            .unwrap();
+        assert_eq!(writer.frame_count(), 2);
+        assert_eq!(writer.frames(), expected.frames());
+        assert!(matches!(
+            invalid,
+            Err(EncodingError::Decoding(crate::DecodingError::NotWebP))
+        ));
+        assert!(matches!(
+            writer.add_webp_file(&path, FrameOptions::with_duration(100)),
+            Err(EncodingError::Io(_))
+        ));
+    }
+
+    #[cfg(feature = "memmap")]
+    #[test]
+    fn test_add_webp_mmap() {
+        let path = |name| {
+            std::env::temp_dir().join(format!("webp-animator-{name}-{}.webp", std::process::id()))
+        };
+        let (path, invalid_path) = (path("mmap"), path("mmap-invalid"));
+        let params = params(16, 16);
+        let (mut writer, mut expected) = (
+            WebPAnimator::new(params).unwrap(),
+            WebPAnimator::new(params).unwrap(),
+        );
+        let red = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([255, 0, 0])));
+        std::fs::write(&path, &red).unwrap();
+        let options = FrameOptions::with_duration(100);
+        std::fs::write(&invalid_path, b"RIFF\x04\0\0\0WEBQ").unwrap();
+        // SAFETY: The files aren't written to while they are mapped.
+        unsafe { writer.add_webp_mmap(&path, options) }.unwrap();
+        let invalid = unsafe { writer.add_webp_mmap(&invalid_path, options) };
+        std::fs::remove_file(&invalid_path).unwrap();
+
+        expected.add_frame(&red, options).unwrap();
+        assert_eq!(writer.frames(), expected.frames());
+        assert!(matches!(
+            invalid,
+            Err(EncodingError::Decoding(crate::DecodingError::NotWebP))
+        ));
+        assert!(matches!(
+            unsafe { writer.add_webp_mmap(&invalid_path, options) },
+            Err(EncodingError::Io(_))
+        ));
+        assert_eq!(writer.into_bytes().unwrap(), expected.into_bytes().unwrap());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_add_frame_at() {
+        let params = params(16, 16);
+        let red = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([255, 0, 0])));
+        let blue = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([0, 0, 255])));
+        let mut writer = WebPAnimator::new(params).unwrap();
+        writer.set_merge_duplicate_frames(true);
+        let options = FrameOptions::with_duration(40);
+        for (pts, img) in [(1000, &red), (1033, &blue), (1050, &blue), (1100, &red)] {
+            writer.add_frame_at(pts, img, options).unwrap();
+        }
+        let durations: Vec<_> = writer.frames().iter().map(|f| f.duration()).collect();
+        assert_eq!(durations, [33, 67, 40]);
+        assert!(matches!(
+            writer.add_frame_at(1099, &blue, options),
+            Err(EncodingError::InvalidDuration)
+        ));
+        assert!(writer.add_frame_at(1100, b"junk", options).is_err());
+        assert_eq!(writer.frames()[2].duration(), 40);
+
+        // Frames with the same timestamp follow the zero duration policy.
+        let mut writer = WebPAnimator::new(params).unwrap();
+        writer.set_zero_duration_policy(ZeroDurationPolicy::Reject);
+        writer.add_frame_at(0, &red, options).unwrap();
+        assert!(matches!(
+            writer.add_frame_at(0, &blue, options),
+            Err(EncodingError::InvalidDuration)
+        ));
+        assert_eq!(writer.frame_count(), 1);
+        assert_eq!(writer.frames()[0].duration(), 40);
+        writer.set_zero_duration_policy(ZeroDurationPolicy::Coerce(20));
+        writer.add_frame_at(0, &blue, options).unwrap();
+        let durations: Vec<_> = writer.frames().iter().map(|f| f.duration()).collect();
+        assert_eq!(durations, [20, 40]);
+    }
+
+    #[test]
+    fn test_shared_chunks() {
+        let buf = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([255, 0, 0])));
+        let mut writer = WebPAnimator::builder()
+            .width(16)
+            .height(16)
+            .build()
+            .unwrap();
+        let options = FrameOptions::with_duration(100);
+        writer.add_frame(&buf, options).unwrap();
+        let chunk: std::sync::Arc<[u8]> = writer.remove_frame(0).data().into();
+        writer.add_shared_chunk(chunk.clone(), options).unwrap();
+        writer.add_chunk(&chunk, options).unwrap();
+        assert_eq!(writer.frames()[0].data().as_ptr(), chunk.as_ptr());
+        assert_eq!(writer.frames()[1].data().as_ptr(), chunk.as_ptr());
+        assert!(
+            writer
+                .add_shared_chunk(b"junk"[..].into(), options)
+                .is_err()
+        );
+
+        #[cfg(feature = "bytes")]
+        {
+            let bytes = bytes::Bytes::from(buf.clone());
+            writer.add_bytes_frame(bytes.clone(), options).unwrap();
+            let range = bytes.as_ptr_range();
+            assert!(range.contains(&writer.frames()[2].data().as_ptr()));
+        }
+        let out = writer.into_bytes().unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        assert!(decoder.frames().iter().all(|f| f.data == &chunk[..]));
+    }
+
+    #[test]
+    fn test_vectored_write() {
+        /// Accepts at most 5 bytes per call.
+        struct Trickle(Vec<u8>);
+
+        impl std::io::Write for Trickle {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                let n = buf.len().min(5);
+                self.0.extend_from_slice(&buf[..n]);
+                Ok(n)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut writer = WebPAnimator::builder()
+            .width(16)
+            .height(16)
+            .xmp_metadata(b"<x/>".to_vec())
+            .build()
+            .unwrap();
+        for color in [[255, 0, 0], [0, 255, 0], [0, 0, 255]] {
+            let buf = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb(color)));
+            writer.add_webp_image(&buf, None, 100).unwrap();
+        }
+        let mut expected = Vec::new();
+        writer.write(&mut expected).unwrap();
+        assert_eq!(expected.len(), writer.estimated_size());
+        let mut trickle = Trickle(Vec::new());
+        writer.write(&mut trickle).unwrap();
+        assert_eq!(trickle.0, expected);
+        Decoder::new(&expected).unwrap();
+    }
+
+    #[test]
+    fn test_clear() {
+        let pool = BufferPool::new(1);
+        let mut writer = WebPAnimator::builder()
+            .width(16)
+            .height(16)
+            .merge_duplicate_frames(true)
+            .build()
+            .unwrap();
+        let buf = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([255, 0, 0])));
+        writer.add_webp_image(&buf, None, 100).unwrap();
+        let first = writer.to_pooled_bytes(&pool).unwrap();
+        let capacity = first.capacity();
+        pool.give(first.clone());
+        assert_eq!(pool.available(), 1);
+
+        writer.clear();
+        assert_eq!(writer.frame_count(), 0);
+        assert_eq!(writer.shared_data_len(), 0);
+        writer.add_webp_image(&buf, None, 100).unwrap();
+        let second = writer.to_pooled_bytes(&pool).unwrap();
+        assert_eq!(pool.available(), 0);
+        assert_eq!(second, first);
+        assert!(second.capacity() >= capacity);
+        // Merging only considers frames added after clearing.
+        assert_eq!(writer.frame_count(), 1);
+    }
+
+    #[test]
+    fn test_deterministic_output() {
+        let build = |placement, shared: bool| {
+            let mut writer = WebPAnimator::builder()
+                .width(16)
+                .height(16)
+                .exif_metadata(b"Exif\0\0MM".to_vec())
+                .xmp_metadata(b"<x/>".to_vec())
+                .metadata_placement(placement)
+                .build()
+                .unwrap();
+            for color in [[255, 0, 0], [0, 0, 255], [255, 0, 0]] {
+                let buf = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb(color)));
+                let chunk = &buf[12..];
+                let options = FrameOptions::with_duration(100);
+                match shared {
+                    true => writer.add_shared_chunk(chunk.into(), options).unwrap(),
+                    false => writer.add_chunk(chunk, options).unwrap(),
+                }
+            }
+            writer.verify().unwrap()
+        };
+        let after = build(MetadataPlacement::AfterFrames, false);
+        assert_eq!(after, build(MetadataPlacement::AfterFrames, true));
+        let exif = |data: &[u8]| data.windows(4).position(|w| w == b"EXIF").unwrap();
+        let anmf = |data: &[u8]| data.windows(4).position(|w| w == b"ANMF").unwrap();
+        assert!(exif(&after) > anmf(&after));
+
+        let before = build(MetadataPlacement::BeforeFrames, false);
+        assert_eq!(before.len(), after.len());
+        assert!(exif(&before) < anmf(&before));
+        let decoder = Decoder::new(&before).unwrap();
+        assert_eq!(decoder.xmp_metadata(), Some(&b"<x/>"[..]));
+        assert_eq!(decoder.frames().len(), 3);
+    }
+
+    #[test]
+    fn test_output_helpers() {
+        let params = params(16, 16);
+        let mut writer = WebPAnimator::new(params).unwrap();
+        let buf = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([255, 0, 0])));
+        writer.add_webp_image(&buf, None, 100).unwrap();
+        writer.add_webp_image(&buf, None, 150).unwrap();
+        assert_eq!(writer.frame_count(), 2);
+        assert_eq!(writer.total_duration(), Duration::from_millis(250));
+        let estimated_size = writer.estimated_size();
+        let path =
+            std::env::temp_dir().join(format!("webp-animator-output-{}.webp", std::process::id()));
+        writer.write_to_file_synced(&path).unwrap();
+        let from_file = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(from_file.len(), estimated_size);
+        assert_eq!(from_file, writer.into_bytes().unwrap());
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        let params = params(16, 16);
+        let mut writer = WebPAnimator::new(params).unwrap();
+        writer.set_validation(Validation::Strict);
+        let buf = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([255, 0, 0])));
+        for data in [&b""[..], b"VP8", b"RIFF", &buf[..20], &buf[..buf.len() - 2]] {
+            assert!(writer.add_webp_image(data, None, 100).is_err());
+            assert!(writer.add_webp_chunk(data, None, 100).is_err());
+        }
+        let mut trailing = buf[12..].to_vec();
+        trailing.extend_from_slice(b"EXIF\0\0\0\0");
+        assert!(matches!(
+            writer.add_webp_chunk(&trailing, None, 100),
+            Err(EncodingError::UnrecognizedImage)
+        ));
+        for rect in [
+            FrameRect {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 16,
+            },
+            FrameRect {
+                x: 0,
+                y: 8,
+                width: 16,
+                height: 16,
+            },
+            FrameRect {
+                x: 0xfffffffe,
+                y: 0,
+                width: 16,
+                height: 16,
+            },
+        ] {
+            assert!(matches!(
+                writer.add_webp_image(&buf, Some(rect), 100),
+                Err(EncodingError::InvalidDimensions)
+            ));
+        }
+        assert!(matches!(
+            writer.add_webp_image(
+                &buf,
+                Some(FrameRect {
+                    x: 0,
+                    y: 0,
+                    width: 16,
+                    height: 8
+                }),
+                100
+            ),
+            Err(EncodingError::FrameSizeMismatch)
+        ));
+        assert_eq!(writer.frame_count(), 0);
+    }
+
+    #[test]
+    fn test_infer_rect() {
+        let params = params(64, 48);
+        let mut writer = WebPAnimator::new(params).unwrap();
+        let buf = lossless_webp(&RgbImage::from_pixel(21, 16, Rgb([255, 0, 0])));
+        writer.add_webp_image(&buf, None, 100).unwrap();
+        for anchor in [Anchor::Center, Anchor::BottomRight] {
+            let options = FrameOptions {
+                anchor,
+                ..FrameOptions::with_duration(100)
+            };
+            writer.add_frame(&buf, options).unwrap();
+        }
+        let rects: Vec<_> = writer
+            .frames()
+            .iter()
+            .map(|f| (f.rect().x, f.rect().y))
+            .collect();
+        assert_eq!(rects, [(0, 0), (20, 16), (42, 32)]);
+        assert!(writer.frames().iter().all(|f| f.rect().width == 21));
+        let mut out = Vec::new();
+        writer.write(&mut out).unwrap();
+        webp_animation::Decoder::new(&out).unwrap();
+    }
+
+    #[test]
+    fn test_auto_canvas() {
+        let mut writer = WebPAnimator::builder()
+            .auto_canvas(true)
+            .validation(Validation::Strict)
+            .build()
+            .unwrap();
+        let buf = lossless_webp(&RgbImage::from_pixel(16, 8, Rgb([255, 0, 0])));
+        writer.add_webp_image(&buf, None, 100).unwrap();
+        let rect = FrameRect {
+            x: 4,
+            y: 10,
+            width: 16,
+            height: 8,
+        };
+        writer.add_webp_image(&buf, Some(rect), 100).unwrap();
+        // A frame that is rejected doesn't grow the canvas.
+        let bad = FrameRect {
+            x: 100,
+            width: 4,
+            ..rect
+        };
+        assert!(matches!(
+            writer.add_webp_image(&buf, Some(bad), 100),
+            Err(EncodingError::FrameSizeMismatch)
+        ));
+        let mut out = Vec::new();
+        writer.write(&mut out).unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (20, 18));
+        webp_animation::Decoder::new(&out).unwrap();
+    }
+
+    #[test]
+    fn test_detect_alpha() {
+        let params = params(16, 16);
+        let mut writer = WebPAnimator::new(params).unwrap();
+        writer.set_detect_alpha(true);
+        let mut buf = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([255, 0, 0])));
+        writer.add_webp_image(&buf, None, 100).unwrap();
+        assert!(!writer.has_alpha());
+        buf = lossless_webp(&RgbaImage::from_pixel(16, 16, Rgba([255, 0, 0, 128])));
+        writer.add_webp_image(&buf, None, 100).unwrap();
+        assert!(writer.has_alpha());
+        let mut out = Vec::new();
+        writer.write(&mut out).unwrap();
+        assert!(Decoder::new(&out).unwrap().has_alpha());
+    }
+
+    #[test]
+    fn test_odd_length_frames() {
+        let params = params(16, 16);
+        let mut writer = WebPAnimator::new(params).unwrap();
+        let mut stream = WebPStreamWriter::new(Cursor::new(Vec::new()), params).unwrap();
+        let mut count = 0;
+        for i in 0..=255 {
+            let mut buf = Vec::new();
+            let mut img = RgbImage::from_pixel(16, 16, Rgb([i, 0, 0]));
+            img.put_pixel(3, 5, Rgb([0, i, 255 - i]));
+            img.write_with_encoder(WebPEncoder::new_lossless(&mut buf))
+                .unwrap();
+            let len = u32::from_le_bytes(buf[16..20].try_into().unwrap()) as usize;
+            if len & 1 != 0 {
+                // Drop the padding byte, which is optional at the end of a file.
+                let chunk = &buf[12..20 + len];
+                writer.add_webp_chunk(chunk, None, 100).unwrap();
+                stream.add_webp_chunk(chunk, None, 100).unwrap();
+                count += 1;
+            }
+        }
+        assert!(count > 1);
+        let expected = writer.into_bytes().unwrap();
+        assert_eq!(expected.len() & 1, 0);
+        let riff_len = u32::from_le_bytes(expected[4..8].try_into().unwrap()) as usize;
+        assert_eq!(riff_len + 8, expected.len());
+        assert_eq!(Decoder::new(&expected).unwrap().frames().len(), count);
+        assert_eq!(stream.finish().unwrap().into_inner(), expected);
+        webp_animation::Decoder::new(&expected).unwrap();
+    }
+
+    #[test]
+    fn test_metadata() {
+        let params = params(16, 16);
+        let mut writer = WebPAnimator::new(params).unwrap();
+        let buf = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([255, 0, 0])));
+        writer.add_webp_image(&buf, None, 100).unwrap();
+        let icc = b"not really an ICC profile".to_vec();
+        let exif = b"II*\0\x08\0\0\0\0\0\0\0\0".to_vec();
+        let xmp = b"<x:xmpmeta xmlns:x='adobe:ns:meta/'/>".to_vec();
+        writer.set_icc_profile(icc.clone()).unwrap();
+        writer
+            .set_exif_metadata([&b"EXIF\x0d\0\0\0"[..], &exif, b"\0"].concat())
+            .unwrap();
+        writer.set_xmp_metadata(xmp.clone()).unwrap();
+        assert!(matches!(
+            writer.set_exif_metadata([&b"EXIF\x0e\0\0\0"[..], &exif].concat()),
+            Err(EncodingError::InvalidMetadata)
+        ));
+        let expected_size = writer.estimated_size();
+        let out = writer.into_bytes().unwrap();
+        assert_eq!(out.len(), expected_size);
+        let decoder = Decoder::new(&out).unwrap();
+        assert_eq!(decoder.icc_profile(), Some(&icc[..]));
+        assert_eq!(decoder.exif_metadata(), Some(&exif[..]));
+        assert_eq!(decoder.xmp_metadata(), Some(&xmp[..]));
+        assert_eq!(out[VP8X_FLAGS_OFFSET as usize], 0x2e);
+        webp_animation::Decoder::new(&out).unwrap();
+    }
+
+    #[test]
+    fn test_headroom() {
+        let params = params(16, 16);
+        let mut writer = WebPAnimator::new(params).unwrap();
+        let start = writer.headroom();
+        assert_eq!(start + writer.estimated_size(), u32::MAX as usize + 7);
+        let buf = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([255, 0, 0])));
+        writer.add_webp_image(&buf, None, 100).unwrap();
+        assert_eq!(
+            writer.headroom(),
+            start - writer.frames()[0].data().len() - 24
+        );
+        writer.set_xmp_metadata(vec![b'x'; 5]).unwrap();
+        assert_eq!(
+            writer.headroom(),
+            start - writer.frames()[0].data().len() - 38
+        );
+        assert!(check_riff_size(u32::MAX as usize - 1).is_ok());
+        assert!(matches!(
+            check_riff_size(u32::MAX as usize),
+            Err(EncodingError::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_lenient_validation() {
+        let params = params(32, 32);
+        let buf = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([255, 0, 0])));
+        let options = FrameOptions {
+            rect: Some(FrameRect {
+                x: 21,
+                y: 3,
+                width: 8,
+                height: 8,
+            }),
+            duration: MAX_DURATION_MS + 1,
+            ..FrameOptions::default()
+        };
+        let mut strict = WebPAnimator::new(params).unwrap();
+        strict.set_validation(Validation::Strict);
+        assert!(strict.add_frame(&buf, options).is_err());
+        assert!(matches!(
+            strict.write(&mut Vec::new()),
+            Err(EncodingError::NoFrames)
+        ));
+        let mut lenient = WebPAnimator::new(params).unwrap();
+        lenient.add_frame(&buf, options).unwrap();
+        assert_eq!(
+            lenient.frames()[0].rect(),
+            FrameRect {
+                x: 16,
+                y: 2,
+                width: 16,
+                height: 16
+            }
+        );
+        assert_eq!(lenient.frames()[0].duration(), MAX_DURATION_MS);
+        assert_eq!(
+            lenient.warnings(),
+            [
+                Warning::DurationClamped(0),
+                Warning::FrameSizeCorrected(0),
+                Warning::OffsetRounded(0),
+                Warning::FrameMoved(0)
+            ]
+        );
+        let out = lenient.into_bytes().unwrap();
+        webp_animation::Decoder::new(&out).unwrap();
+    }
+
+    #[test]
+    fn test_edit_frames() {
+        let params = params(16, 16);
+        let mut writer = WebPAnimator::new(params).unwrap();
+        for color in [[255, 0, 0], [0, 255, 0], [0, 0, 255]] {
+            let buf = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb(color)));
+            writer.add_webp_image(&buf, None, 100).unwrap();
+        }
+        let green = writer.frames()[1].data().to_vec();
+        let red = writer.remove_frame(0);
+        writer.swap_frames(0, 1);
+        writer.set_duration(0, 30).unwrap();
+        assert!(matches!(
+            writer.set_duration(1, 1 << 24),
+            Err(EncodingError::InvalidDuration)
+        ));
+        writer.frames_mut()[1].set_dispose(Disposal::Background);
+        let mut out = Vec::new();
+        writer.write(&mut out).unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        let frames = decoder.frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].duration, 30);
+        assert_eq!(frames[1].data, green);
+        assert_eq!(frames[1].dispose, Disposal::Background);
+        assert_ne!(frames[0].data, red.data());
+    }
+
+    #[test]
+    fn test_zero_duration() {
+        let buf = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([255, 0, 0])));
+        let mut writer = WebPAnimator::builder()
+            .width(16)
+            .height(16)
+            .zero_duration_policy(ZeroDurationPolicy::Coerce(20))
+            .build()
+            .unwrap();
+        writer.add_webp_image(&buf, None, 0).unwrap();
+        writer.add_webp_image(&buf, None, 50).unwrap();
+        writer.set_duration(1, 0).unwrap();
+        let durations: Vec<_> = writer.frames().iter().map(|f| f.duration()).collect();
+        assert_eq!(durations, [20, 20]);
+        writer.set_zero_duration_policy(ZeroDurationPolicy::Reject);
+        assert!(matches!(
+            writer.add_webp_image(&buf, None, 0),
+            Err(EncodingError::InvalidDuration)
+        ));
+        assert_eq!(writer.frame_count(), 2);
+        writer.set_zero_duration_policy(ZeroDurationPolicy::Allow);
+        writer.add_webp_image(&buf, None, 0).unwrap();
+        assert_eq!(writer.frames()[2].duration(), 0);
+    }
+
+    #[test]
+    fn test_extended_input() {
+        let simple = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([0, 255, 0])));
+        let vp8l = &simple[12..];
+        let mut extended = b"RIFF\0\0\0\0WEBPVP8X\x0a\0\0\0\x08\0\0\0\x0f\0\0\x0f\0\0".to_vec();
+        extended.extend_from_slice(vp8l);
+        extended.extend_from_slice(b"EXIF\x02\0\0\0II");
+        let len = (extended.len() - 8) as u32;
+        extended[4..8].copy_from_slice(&len.to_le_bytes());
+        let params = params(16, 16);
+        let mut from_simple = WebPAnimator::new(params).unwrap();
+        from_simple.add_webp_image(&simple, None, 100).unwrap();
+        let mut from_extended = WebPAnimator::new(params).unwrap();
+        from_extended.add_webp_image(&extended, None, 100).unwrap();
+        let (mut a, mut b) = (Vec::new(), Vec::new());
+        from_simple.write(&mut a).unwrap();
+        from_extended.write(&mut b).unwrap();
+        assert_eq!(a, b);
+        let animated = a;
+        assert!(matches!(
+            from_extended.add_webp_image(&animated, None, 100),
+            Err(EncodingError::UnrecognizedImage)
+        ));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_write_async() {
+        let params = Params {
+            background_bgra: [255; 4],
+            ..params(64, 64)
+        };
+        let mut writer = WebPAnimator::new(params).unwrap();
+        let buf = lossless_webp(&RgbImage::from_pixel(64, 64, Rgb([255, 0, 0])));
+        writer.add_webp_image(&buf, None, 500).unwrap();
+        writer.set_icc_profile(vec![1; 3]).unwrap();
+        writer.set_xmp_metadata(b"<x:xmpmeta/>".to_vec()).unwrap();
+        let mut expected = Vec::new();
+        writer.write(&mut expected).unwrap();
+        let reports = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = reports.clone();
+        writer.set_progress_sink(Some(Box::new(move |p: &crate::Progress| {
+            sink.lock().unwrap().push(*p)
+        })));
+        let mut actual = Vec::new();
+        writer.write_async(&mut actual).await.unwrap();
+        assert_eq!(actual, expected);
+        let last = *reports.lock().unwrap().last().unwrap();
+        assert_eq!(last.stage, crate::Stage::Finished);
+        assert_eq!(
+            (last.frames, last.bytes_written),
+            (1, expected.len() as u64)
+        );
+
+        let token = crate::CancellationToken::from_fn(|| true);
+        writer.set_cancellation_token(Some(token));
+        assert!(matches!(
+            writer.write_async(&mut Vec::new()).await,
+            Err(EncodingError::Cancelled)
+        ));
+    }
 }
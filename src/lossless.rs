@@ -0,0 +1,493 @@
+//! A simple VP8L encoder for raw pixels, so that frames can be encoded
+//! losslessly without the `image` crate.
+//!
+//! The encoder uses the subtract green transform and backward references to
+//! the pixel to the left and the pixel above, which is enough for flat
+//! regions and repeated rows, as in screen captures.  It isn't as compact as
+//! libwebp.
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::{EncodingError, FrameOptions, WebPAnimator, riff};
+
+/// The order in which the code lengths of the code length code are stored.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+/// The longest backward reference.
+const MAX_COPY_LEN: usize = 4096;
+
+/// The distance codes of the pixel above and the pixel to the left.
+const UP: u32 = 1;
+const LEFT: u32 = 2;
+
+impl WebPAnimator {
+    /// Encodes 8-bit RGBA pixels losslessly and adds them to the animation,
+    /// without going through the `image` crate.
+    ///
+    /// * `pixels` - The pixels row by row, 4 bytes each.  Fails with
+    ///   [`EncodingError::InvalidDimensions`] if its length doesn't match
+    ///   `width` and `height`.
+    /// * `options` - The frame settings, as for
+    ///   [`add_frame`](Self::add_frame).
+    pub fn add_rgba_frame(
+        &mut self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        options: FrameOptions,
+    ) -> Result<(), EncodingError> {
+        self.add_raw_frame(pixels, width, height, [0, 1, 2, 3], options)
+    }
+
+    /// Like [`add_rgba_frame`](Self::add_rgba_frame), but with the channels
+    /// in BGRA order, as screen capture APIs often return them.
+    pub fn add_bgra_frame(
+        &mut self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        options: FrameOptions,
+    ) -> Result<(), EncodingError> {
+        self.add_raw_frame(pixels, width, height, [2, 1, 0, 3], options)
+    }
+
+    /// `channels` gives the offsets of red, green, blue and alpha.
+    fn add_raw_frame(
+        &mut self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        channels: [usize; 4],
+        options: FrameOptions,
+    ) -> Result<(), EncodingError> {
+        let size_ok = (1..=16384).contains(&width)
+            && (1..=16384).contains(&height)
+            && pixels.len() as u64 == width as u64 * height as u64 * 4
+            && options
+                .rect
+                .is_none_or(|rect| (rect.width, rect.height) == (width, height));
+        if !size_ok {
+            return Err(EncodingError::InvalidDimensions);
+        }
+        let [r, g, b, a] = channels;
+        let argb: Vec<u32> = pixels
+            .chunks_exact(4)
+            .map(|p| u32::from_be_bytes([p[a], p[r], p[g], p[b]]))
+            .collect();
+        trace_span!(TRACE, "encode_lossless", width, height);
+        let data = encode(&argb, width, height);
+        trace_event!(
+            trace,
+            "encoded {}x{} raw frame losslessly in {} bytes",
+            width,
+            height,
+            data.len()
+        );
+        let mut chunk = riff::chunk_header(b"VP8L", data.len()).to_vec();
+        chunk.extend_from_slice(&data);
+        if data.len() & 1 != 0 {
+            chunk.push(0);
+        }
+        self.add_chunk(&chunk, options)
+    }
+}
+
+/// Writes bits starting with the least significant.
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    buffer: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, bits: u32) {
+        self.buffer |= (value as u64) << self.bits;
+        self.bits += bits;
+        while self.bits >= 8 {
+            self.out.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.out.push(self.buffer as u8);
+        }
+        self.out
+    }
+}
+
+/// A canonical prefix code.
+struct PrefixCode {
+    lengths: Vec<u8>,
+    /// The codes, bit-reversed, since they are read starting with the most
+    /// significant bit.
+    codes: Vec<u16>,
+    /// Whether only one symbol is used, in which case decoders read no bits.
+    single: bool,
+}
+
+impl PrefixCode {
+    fn new(lengths: Vec<u8>) -> Self {
+        let mut counts = [0u16; 16];
+        for &len in &lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut next = [0u16; 16];
+        let mut code = 0;
+        for len in 1..16 {
+            code = (code + counts[len - 1]) << 1;
+            next[len] = code;
+        }
+        let codes = lengths
+            .iter()
+            .map(|&len| {
+                if len == 0 {
+                    return 0;
+                }
+                let code = next[len as usize];
+                next[len as usize] += 1;
+                code.reverse_bits() >> (16 - len)
+            })
+            .collect();
+        let single = lengths.iter().filter(|&&len| len > 0).count() == 1;
+        Self {
+            lengths,
+            codes,
+            single,
+        }
+    }
+
+    fn write(&self, w: &mut BitWriter, symbol: usize) {
+        if !self.single {
+            w.write(self.codes[symbol].into(), self.lengths[symbol].into());
+        }
+    }
+}
+
+/// The code lengths of a Huffman code for symbols occurring `counts` times,
+/// at most `max_len` bits long.  Unused symbols get length 0.
+fn code_lengths(counts: &[u32], max_len: u8) -> Vec<u8> {
+    let mut counts: Vec<u64> = counts.iter().map(|&c| c.into()).collect();
+    loop {
+        let lengths = huffman_lengths(&counts);
+        if lengths.iter().all(|&len| len <= max_len) {
+            return lengths;
+        }
+        // Flattening the distribution makes the tree shallower.
+        for count in counts.iter_mut().filter(|c| **c > 0) {
+            *count = (*count >> 1) + 1;
+        }
+    }
+}
+
+fn huffman_lengths(counts: &[u64]) -> Vec<u8> {
+    let mut lengths = vec![0; counts.len()];
+    let used: Vec<usize> = (0..counts.len()).filter(|&i| counts[i] > 0).collect();
+    if let [symbol] = used[..] {
+        lengths[symbol] = 1;
+    }
+    if used.len() < 2 {
+        return lengths;
+    }
+    // The leaves come first, then the internal nodes as they are created.
+    let mut parents = vec![usize::MAX; used.len()];
+    let mut heap: BinaryHeap<_> = used
+        .iter()
+        .enumerate()
+        .map(|(node, &symbol)| Reverse((counts[symbol], node)))
+        .collect();
+    while let (Some(Reverse((a, x))), Some(Reverse((b, y)))) = (heap.pop(), heap.pop()) {
+        let node = parents.len();
+        parents.push(usize::MAX);
+        (parents[x], parents[y]) = (node, node);
+        heap.push(Reverse((a + b, node)));
+    }
+    for (leaf, &symbol) in used.iter().enumerate() {
+        let (mut node, mut depth) = (leaf, 0);
+        while parents[node] != usize::MAX {
+            node = parents[node];
+            depth += 1;
+        }
+        lengths[symbol] = depth;
+    }
+    lengths
+}
+
+/// Writes a prefix code for symbols occurring `counts` times, and returns
+/// it.
+fn write_prefix_code(w: &mut BitWriter, counts: &[u32]) -> PrefixCode {
+    let used: Vec<usize> = (0..counts.len()).filter(|&i| counts[i] > 0).collect();
+    if used.len() <= 2 && used.iter().all(|&symbol| symbol < 256) {
+        // A simple code, listing the symbols.
+        let symbols = if used.is_empty() { vec![0] } else { used };
+        w.write(1, 1);
+        w.write(symbols.len() as u32 - 1, 1);
+        if symbols[0] < 2 {
+            w.write(0, 1);
+            w.write(symbols[0] as u32, 1);
+        } else {
+            w.write(1, 1);
+            w.write(symbols[0] as u32, 8);
+        }
+        if let Some(&second) = symbols.get(1) {
+            w.write(second as u32, 8);
+        }
+        let mut lengths = vec![0; counts.len()];
+        for symbol in symbols {
+            lengths[symbol] = 1;
+        }
+        return PrefixCode::new(lengths);
+    }
+
+    w.write(0, 1);
+    let lengths = code_lengths(counts, 15);
+    // The code lengths, with runs of zeros shortened.
+    let mut tokens: Vec<(usize, u32, u32)> = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let zeros = lengths[i..].iter().take_while(|&&len| len == 0).count();
+        if zeros >= 11 {
+            let run = zeros.min(138);
+            tokens.push((18, 7, run as u32 - 11));
+            i += run;
+        } else if zeros >= 3 {
+            tokens.push((17, 3, zeros as u32 - 3));
+            i += zeros;
+        } else {
+            tokens.push((lengths[i].into(), 0, 0));
+            i += 1;
+        }
+    }
+    let mut token_counts = [0; 19];
+    for &(symbol, _, _) in &tokens {
+        token_counts[symbol] += 1;
+    }
+    let token_lengths = code_lengths(&token_counts, 7);
+    let stored = CODE_LENGTH_ORDER
+        .iter()
+        .rposition(|&symbol| token_lengths[symbol] > 0)
+        .map_or(0, |i| i + 1)
+        .max(4);
+    w.write(stored as u32 - 4, 4);
+    for &symbol in &CODE_LENGTH_ORDER[..stored] {
+        w.write(token_lengths[symbol].into(), 3);
+    }
+    // All code lengths are stored, rather than up to a maximum symbol.
+    w.write(0, 1);
+    let token_code = PrefixCode::new(token_lengths);
+    for (symbol, bits, extra) in tokens {
+        token_code.write(w, symbol);
+        w.write(extra, bits);
+    }
+    PrefixCode::new(lengths)
+}
+
+/// Splits a length or distance code into a prefix symbol, the number of
+/// extra bits and their value.
+fn prefix(value: u32) -> (usize, u32, u32) {
+    let d = value - 1;
+    if d < 4 {
+        return (d as usize, 0, 0);
+    }
+    let high = 31 - d.leading_zeros();
+    let second = (d >> (high - 1)) & 1;
+    let bits = high - 1;
+    ((2 * high + second) as usize, bits, d & ((1 << bits) - 1))
+}
+
+enum Token {
+    Literal(u32),
+    Copy { len: u32, distance: u32 },
+}
+
+/// Encodes ARGB pixels as the payload of a `VP8L` chunk.
+fn encode(argb: &[u32], width: u32, height: u32) -> Vec<u8> {
+    let has_alpha = argb.iter().any(|&p| p >> 24 != 0xff);
+    // The subtract green transform.
+    let pixels: Vec<u32> = argb
+        .iter()
+        .map(|&p| {
+            let g = (p >> 8) & 0xff;
+            let r = ((p >> 16).wrapping_sub(g)) & 0xff;
+            let b = (p.wrapping_sub(g)) & 0xff;
+            (p & 0xff00ff00) | (r << 16) | b
+        })
+        .collect();
+
+    let stride = width as usize;
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < pixels.len() {
+        let run = |offset: usize| {
+            if i < offset {
+                return 0;
+            }
+            let limit = (pixels.len() - i).min(MAX_COPY_LEN);
+            (0..limit)
+                .take_while(|&k| pixels[i + k] == pixels[i + k - offset])
+                .count()
+        };
+        let (left, up) = (run(1), run(stride));
+        let (len, distance) = if up >= left { (up, UP) } else { (left, LEFT) };
+        if len >= 3 {
+            tokens.push(Token::Copy {
+                len: len as u32,
+                distance,
+            });
+            i += len;
+        } else {
+            tokens.push(Token::Literal(pixels[i]));
+            i += 1;
+        }
+    }
+
+    let mut green = vec![0; 256 + 24];
+    let (mut red, mut blue, mut alpha) = (vec![0; 256], vec![0; 256], vec![0; 256]);
+    let mut distances = vec![0; 40];
+    for token in &tokens {
+        match *token {
+            Token::Literal(p) => {
+                green[(p >> 8 & 0xff) as usize] += 1;
+                red[(p >> 16 & 0xff) as usize] += 1;
+                blue[(p & 0xff) as usize] += 1;
+                alpha[(p >> 24) as usize] += 1;
+            }
+            Token::Copy { len, distance } => {
+                green[256 + prefix(len).0] += 1;
+                distances[prefix(distance).0] += 1;
+            }
+        }
+    }
+
+    let mut w = BitWriter::default();
+    w.write(0x2f, 8);
+    w.write(width - 1, 14);
+    w.write(height - 1, 14);
+    w.write(has_alpha.into(), 1);
+    w.write(0, 3);
+    // A subtract green transform, and no others.
+    w.write(1, 1);
+    w.write(2, 2);
+    w.write(0, 1);
+    // No color cache and a single group of prefix codes.
+    w.write(0, 1);
+    w.write(0, 1);
+    let codes =
+        [&green, &red, &blue, &alpha, &distances].map(|counts| write_prefix_code(&mut w, counts));
+    let [green, red, blue, alpha, distances] = &codes;
+    for token in tokens {
+        match token {
+            Token::Literal(p) => {
+                green.write(&mut w, (p >> 8 & 0xff) as usize);
+                red.write(&mut w, (p >> 16 & 0xff) as usize);
+                blue.write(&mut w, (p & 0xff) as usize);
+                alpha.write(&mut w, (p >> 24) as usize);
+            }
+            Token::Copy { len, distance } => {
+                let (symbol, bits, extra) = prefix(len);
+                green.write(&mut w, 256 + symbol);
+                w.write(extra, bits);
+                let (symbol, bits, extra) = prefix(distance);
+                distances.write(&mut w, symbol);
+                w.write(extra, bits);
+            }
+        }
+    }
+    w.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgba, RgbaImage};
+
+    use crate::{
+        Decoder, EncodingError, FrameOptions, FrameRect, WebPAnimator, bitstream::has_alpha,
+    };
+
+    use super::prefix;
+
+    #[test]
+    fn test_prefix() {
+        assert_eq!(prefix(1), (0, 0, 0));
+        assert_eq!(prefix(4), (3, 0, 0));
+        assert_eq!(prefix(5), (4, 1, 0));
+        assert_eq!(prefix(7), (5, 1, 0));
+        assert_eq!(prefix(4096), (23, 10, 1023));
+    }
+
+    #[test]
+    fn test_add_rgba_frame() {
+        // Noise, to exercise full prefix codes, above flat and repeated
+        // regions, for backward references.
+        let mut seed = 1u32;
+        let image = RgbaImage::from_fn(37, 21, |x, y| {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            let noise = (seed >> 24) as u8;
+            match y {
+                0..8 => Rgba([noise, noise / 2, 255 - noise, 255]),
+                8..14 => Rgba([10, 200, 30, 255]),
+                _ => Rgba([(x * 7) as u8, 0, 0, (x * 5) as u8]),
+            }
+        });
+        let (width, height) = image.dimensions();
+        let mut bgra = image.as_raw().clone();
+        for pixel in bgra.chunks_exact_mut(4) {
+            pixel.swap(0, 2);
+        }
+        let mut writer = WebPAnimator::builder()
+            .width(width)
+            .height(height)
+            .build()
+            .unwrap();
+        let options = FrameOptions::with_duration(100);
+        writer
+            .add_rgba_frame(image.as_raw(), width, height, options)
+            .unwrap();
+        writer
+            .add_bgra_frame(&bgra, width, height, options)
+            .unwrap();
+        let flat = RgbaImage::from_pixel(2, 2, Rgba([1, 2, 3, 255]));
+        writer.add_rgba_frame(flat.as_raw(), 2, 2, options).unwrap();
+        assert!(matches!(
+            writer.add_rgba_frame(&bgra[4..], width, height, options),
+            Err(EncodingError::InvalidDimensions)
+        ));
+        let wrong_rect = FrameOptions {
+            rect: Some(FrameRect {
+                x: 0,
+                y: 0,
+                width: 4,
+                height: 4,
+            }),
+            ..options
+        };
+        assert!(matches!(
+            writer.add_rgba_frame(flat.as_raw(), 2, 2, wrong_rect),
+            Err(EncodingError::InvalidDimensions)
+        ));
+        let out = writer.into_bytes().unwrap();
+
+        let decoder = Decoder::new(&out).unwrap();
+        assert!(has_alpha(decoder.frames()[0].data));
+        assert!(!has_alpha(decoder.frames()[2].data));
+        for frame in &decoder.frames()[..2] {
+            let decoded = image::load_from_memory(&frame.to_webp())
+                .unwrap()
+                .to_rgba8();
+            assert_eq!(decoded, image);
+        }
+        // libwebp reads the frames the same way.
+        let rendered: Vec<_> = webp_animation::Decoder::new(&out)
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(rendered[0].data(), image.as_raw().as_slice());
+        assert_eq!(&rendered[2].data()[..4], [1, 2, 3, 255]);
+    }
+}
@@ -0,0 +1,268 @@
+use std::{ffi::c_int, mem::MaybeUninit};
+
+use image::DynamicImage;
+use libwebp_sys::{
+    WEBP_PRESET_DEFAULT, WebPConfig, WebPConfigPreset, WebPEncode, WebPPicture, WebPPictureFree,
+    WebPPictureImportRGB, WebPPictureImportRGBA, WebPPictureInit,
+};
+
+use crate::{
+    CanvasFrame, Decoder, DropPolicy, EncodingError, FrameOptions, Optimizer, WebPAnimator,
+    decimate::decimate, presets::Violation,
+};
+
+/// The libwebp compression method used when none is given.
+pub(crate) const DEFAULT_METHOD: u8 = 4;
+
+/// Encodes `image` as a lossy WebP image using libwebp.
+///
+/// * `quality` - Between 0 (smallest output) and 100 (highest quality).
+/// * `method` - Between 0 (fastest) and 6 (smallest output).
+pub(crate) fn encode_lossy(
+    image: &DynamicImage,
+    quality: f32,
+    method: u8,
+) -> Result<Vec<u8>, EncodingError> {
+    trace_span!(
+        TRACE,
+        "encode_lossy",
+        width = image.width(),
+        height = image.height()
+    );
+    let data = encode_picture(image.width(), image.height(), quality, method, |picture| {
+        let width = picture.width;
+        // SAFETY: the pixel buffers have exactly `stride * height` bytes.
+        unsafe {
+            if image.color().has_alpha() {
+                let rgba = image.to_rgba8();
+                WebPPictureImportRGBA(picture, rgba.as_ptr(), width * 4) != 0
+            } else {
+                let rgb = image.to_rgb8();
+                WebPPictureImportRGB(picture, rgb.as_ptr(), width * 3) != 0
+            }
+        }
+    })?;
+    trace_event!(
+        trace,
+        "encoded {}x{} image lossily at quality {quality} in {} bytes",
+        image.width(),
+        image.height(),
+        data.len()
+    );
+    Ok(data)
+}
+
+/// Encodes a picture of the given size with libwebp, after `import` has
+/// filled in its pixels.  `import` returns `false` if it fails.
+pub(crate) fn encode_picture(
+    width: u32,
+    height: u32,
+    quality: f32,
+    method: u8,
+    import: impl FnOnce(&mut WebPPicture) -> bool,
+) -> Result<Vec<u8>, EncodingError> {
+    if !(0.0..=100.0).contains(&quality) || method > 6 {
+        return Err(EncodingError::InvalidQuality);
+    }
+    let width = c_int::try_from(width).map_err(|_| EncodingError::InvalidDimensions)?;
+    let height = c_int::try_from(height).map_err(|_| EncodingError::InvalidDimensions)?;
+    let mut config = MaybeUninit::<WebPConfig>::uninit();
+    let mut picture = MaybeUninit::<WebPPicture>::uninit();
+    let mut data = Vec::new();
+    // SAFETY: each structure is initialized by libwebp before it is used,
+    // and the picture is freed below.
+    let ok = unsafe {
+        if WebPConfigPreset(config.as_mut_ptr(), WEBP_PRESET_DEFAULT, quality) == 0
+            || WebPPictureInit(picture.as_mut_ptr()) == 0
+        {
+            return Err(EncodingError::EncoderFailed);
+        }
+        let mut config = config.assume_init();
+        config.method = method.into();
+        let mut picture = picture.assume_init();
+        picture.width = width;
+        picture.height = height;
+        let imported = import(&mut picture);
+        picture.writer = Some(write_to_vec);
+        picture.custom_ptr = (&mut data as *mut Vec<u8>).cast();
+        let ok = imported && WebPEncode(&config, &mut picture) != 0;
+        WebPPictureFree(&mut picture);
+        ok
+    };
+    if !ok {
+        return Err(EncodingError::EncoderFailed);
+    }
+    Ok(data)
+}
+
+/// A libwebp writer that appends to the `Vec<u8>` in `custom_ptr`.
+extern "C" fn write_to_vec(data: *const u8, len: usize, picture: *const WebPPicture) -> c_int {
+    // SAFETY: `encode_picture` points `custom_ptr` at a `Vec<u8>` that
+    // outlives the encoder, and libwebp passes `len` bytes at `data`.
+    unsafe {
+        let out = &mut *(*picture).custom_ptr.cast::<Vec<u8>>();
+        out.extend_from_slice(std::slice::from_raw_parts(data, len));
+    }
+    1
+}
+
+impl WebPAnimator {
+    /// Like [`add_image`](Self::add_image), but encodes the image lossily
+    /// using libwebp.
+    ///
+    /// * `quality` - Between 0 (smallest output) and 100 (highest quality).
+    ///   Overridden by [`FrameOptions::quality`] if it is set.
+    pub fn add_image_lossy(
+        &mut self,
+        image: &DynamicImage,
+        quality: f32,
+        options: FrameOptions,
+    ) -> Result<(), EncodingError> {
+        let (image, options) = self.image_options(image, options)?;
+        let quality = options.quality.map_or(quality, f32::from);
+        let method = options.method.unwrap_or(DEFAULT_METHOD);
+        let data = encode_lossy(&image, quality, method)?;
+        self.add_frame(&data, options)
+    }
+}
+
+impl Decoder<'_> {
+    /// Re-encodes the animation lossily with the highest quality whose
+    /// output fits in `max_bytes`, similar to `cwebp -size`.  The quality is
+    /// found by bisection, using the exact size from
+    /// [`WebPAnimator::estimated_size`].
+    ///
+    /// If even the lowest quality is too large and `drop_frames` is set,
+    /// half of the frames are dropped according to the policy, until the
+    /// animation fits or only one frame is left.  Fails
+    /// with [`EncodingError::Constraints`] if the animation can't be made to
+    /// fit.
+    pub fn fit_to_size(
+        &self,
+        max_bytes: usize,
+        drop_frames: Option<DropPolicy>,
+    ) -> Result<WebPAnimator, EncodingError> {
+        let mut frames = self.canvas_frames().collect::<Result<Vec<_>, _>>()?;
+        loop {
+            let smallest = self.encode_canvas_frames(&frames, 0.0)?;
+            let bytes = smallest.estimated_size();
+            if bytes > max_bytes {
+                if let Some(policy) = drop_frames.filter(|_| frames.len() > 1) {
+                    let count = frames.len().div_ceil(2);
+                    frames = decimate(frames, count, policy);
+                    continue;
+                }
+                let violation = Violation::Size {
+                    bytes,
+                    max: max_bytes,
+                };
+                return Err(EncodingError::Constraints(vec![violation]));
+            }
+            let (mut best, mut low, mut high) = (smallest, 0.0, 100.0);
+            for _ in 0..7 {
+                let quality = (low + high) / 2.0;
+                let animator = self.encode_canvas_frames(&frames, quality)?;
+                if animator.estimated_size() <= max_bytes {
+                    (best, low) = (animator, quality);
+                } else {
+                    high = quality;
+                }
+            }
+            return Ok(best);
+        }
+    }
+
+    fn encode_canvas_frames(
+        &self,
+        frames: &[CanvasFrame],
+        quality: f32,
+    ) -> Result<WebPAnimator, EncodingError> {
+        let animator = self.animator_builder().detect_alpha(true).build()?;
+        let mut optimizer = Optimizer::new(animator);
+        optimizer.set_lossy_quality(Some(quality));
+        for frame in frames {
+            optimizer.add_frame(&frame.image, frame.duration)?;
+        }
+        Ok(optimizer.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{DynamicImage, Rgb, RgbImage, Rgba, RgbaImage};
+
+    use crate::{
+        Decoder, DropPolicy, EncodingError, FrameOptions, LoopCount, Params, WebPAnimator,
+        test_util::params,
+    };
+
+    #[test]
+    fn test_add_image_lossy() {
+        let params = Params {
+            width: 32,
+            height: 32,
+            background_bgra: [0; 4],
+            loop_count: LoopCount::Infinite,
+            has_alpha: true,
+        };
+        let mut writer = WebPAnimator::new(params).unwrap();
+        let opaque = DynamicImage::from(RgbImage::from_pixel(32, 32, Rgb([200, 30, 30])));
+        let translucent = DynamicImage::from(RgbaImage::from_pixel(32, 32, Rgba([0, 0, 255, 100])));
+        let options = FrameOptions::with_duration(100);
+        writer.add_image_lossy(&opaque, 80.0, options).unwrap();
+        writer.add_image_lossy(&translucent, 80.0, options).unwrap();
+        assert!(matches!(
+            writer.add_image_lossy(&opaque, 101.0, options),
+            Err(EncodingError::InvalidQuality)
+        ));
+        let bad_method = FrameOptions {
+            method: Some(7),
+            ..options
+        };
+        assert!(matches!(
+            writer.add_image_lossy(&opaque, 80.0, bad_method),
+            Err(EncodingError::InvalidQuality)
+        ));
+        let mut out = Vec::new();
+        writer.write(&mut out).unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        assert_eq!(&decoder.frames()[0].data[..4], b"VP8 ");
+        assert_eq!(&decoder.frames()[1].data[..4], b"ALPH");
+        webp_animation::Decoder::new(&out).unwrap();
+    }
+
+    #[test]
+    fn test_fit_to_size() {
+        let params = params(64, 64);
+        let mut writer = WebPAnimator::new(params).unwrap();
+        for i in 0..4u32 {
+            let image = RgbImage::from_fn(64, 64, |x, y| {
+                let v = (x * 31 + y * 17 + i * 7).wrapping_mul(2654435761) >> 24;
+                Rgb([v as u8, (v * 3) as u8, (v * 7) as u8])
+            });
+            let options = FrameOptions::with_duration(100);
+            writer.add_image(&image.into(), options).unwrap();
+        }
+        let data = writer.into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+
+        let small = decoder.fit_to_size(data.len() / 2, None).unwrap();
+        assert!(small.estimated_size() <= data.len() / 2);
+        assert_eq!(small.frame_count(), 4);
+        assert!(matches!(
+            decoder.fit_to_size(100, None),
+            Err(EncodingError::Constraints(_))
+        ));
+
+        let frames = decoder
+            .canvas_frames()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let lowest = decoder.encode_canvas_frames(&frames, 0.0).unwrap();
+        let dropped = decoder
+            .fit_to_size(lowest.estimated_size() - 1, Some(DropPolicy::Uniform))
+            .unwrap();
+        assert!(dropped.frame_count() < 4);
+        assert_eq!(dropped.total_duration().as_millis(), 400);
+    }
+}
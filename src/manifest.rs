@@ -0,0 +1,559 @@
+//! Assembling animations from a manifest that lists the frame files.
+//!
+//! A manifest is a TOML or JSON file of the following form.  Only the keys
+//! shown are allowed.
+//!
+//! ```toml
+//! width = 64
+//! height = 64
+//! # Optional.  RGBA or RGB, defaults to transparent black.
+//! background = "#ffffffff"
+//! # Optional.  0, the default, loops forever.
+//! loop_count = 0
+//! # Optional, default false.
+//! has_alpha = false
+//! # Optional files holding the metadata.
+//! icc_profile = "profile.icc"
+//! exif_metadata = "exif.bin"
+//! xmp_metadata = "xmp.xml"
+//!
+//! [[frame]]
+//! # A still WebP image.
+//! file = "frame0.webp"
+//! # In milliseconds.
+//! duration = 100
+//!
+//! [[frame]]
+//! file = "frame1.webp"
+//! duration = 100
+//! # Optional, default 0.  The offsets must be even.
+//! x = 16
+//! y = 8
+//! # Optional, "none" (the default) or "background".
+//! dispose = "background"
+//! # Optional, "alpha" (the default) or "overwrite".
+//! blend = "overwrite"
+//! ```
+//!
+//! In JSON, the frames are an array under the key `"frame"`:
+//!
+//! ```json
+//! {
+//!   "width": 64,
+//!   "height": 64,
+//!   "frame": [{ "file": "frame0.webp", "duration": 100 }]
+//! }
+//! ```
+//!
+//! Relative paths are relative to the directory containing the manifest.
+//! [`Decoder::export_manifest`] writes an existing animation in this form,
+//! so that it can be edited and put back together without re-encoding.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Blend, Decoder, Disposal, EncodingError, FrameOptions, FrameRect, FrameSink, InputFrame,
+    LoopCount, Params, WebPAnimator, bitstream,
+    riff::{check_image_chunks, still_image_chunks},
+};
+
+/// The contents of a manifest.
+///
+/// The [`Serialize`] and [`Deserialize`] implementations define the file
+/// format, so other formats supported by serde can be used too.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub width: u32,
+    pub height: u32,
+    /// In BGRA order, like [`Params::background_bgra`].  Written as an RGBA
+    /// hex string in the file.
+    #[serde(
+        rename = "background",
+        default,
+        with = "hex_color",
+        skip_serializing_if = "is_default"
+    )]
+    pub background_bgra: [u8; 4],
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub loop_count: LoopCount,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub has_alpha: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icc_profile: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exif_metadata: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub xmp_metadata: Option<PathBuf>,
+    #[serde(rename = "frame", default)]
+    pub frames: Vec<ManifestFrame>,
+}
+
+/// A frame of a [`Manifest`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestFrame {
+    /// A still WebP image.
+    pub file: PathBuf,
+    /// The duration in milliseconds.
+    pub duration: u32,
+    /// The position of the top left corner.  The size is read from the file.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub x: u32,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub y: u32,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub dispose: Disposal,
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub blend: Blend,
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+/// Writes a BGRA color as an RGBA hex string, and reads `#rrggbbaa` or
+/// `#rrggbb`.
+mod hex_color {
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer>(bgra: &[u8; 4], serializer: S) -> Result<S::Ok, S::Error> {
+        let [b, g, r, a] = bgra;
+        serializer.serialize_str(&format!("#{r:02x}{g:02x}{b:02x}{a:02x}"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 4], D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let [r, g, b, a] = parse_color(&text).map_err(D::Error::custom)?;
+        Ok([b, g, r, a])
+    }
+
+    /// Parses `#rrggbbaa` or `#rrggbb` into RGBA.
+    fn parse_color(text: &str) -> Result<[u8; 4], String> {
+        let error = || format!("invalid color `{text}`");
+        let hex = text.strip_prefix('#').ok_or_else(error)?;
+        if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+            return Err(error());
+        }
+        let mut rgba = [255; 4];
+        for (i, c) in rgba.iter_mut().enumerate().take(hex.len() / 2) {
+            *c = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| error())?;
+        }
+        Ok(rgba)
+    }
+}
+
+/// An error in the syntax or contents of a manifest.
+#[derive(Debug)]
+pub enum ParseError {
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+}
+
+impl core::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Toml(e) => write!(f, "{e}"),
+            Self::Json(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl core::error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Self::Toml(e) => Some(e),
+            Self::Json(e) => Some(e),
+        }
+    }
+}
+
+impl Manifest {
+    /// Parses a manifest in TOML.
+    pub fn from_toml(text: &str) -> Result<Self, ParseError> {
+        toml::from_str(text).map_err(ParseError::Toml)
+    }
+
+    /// Parses a manifest in JSON.
+    pub fn from_json(text: &str) -> Result<Self, ParseError> {
+        serde_json::from_str(text).map_err(ParseError::Json)
+    }
+
+    /// Returns the manifest in TOML.  Settings with default values are left
+    /// out.  Fails if a path isn't valid UTF-8.
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string(self)
+    }
+
+    /// Builds the animation, reading the files relative to `base`.
+    pub fn build(&self, base: &Path) -> Result<WebPAnimator, EncodingError> {
+        let params = Params {
+            width: self.width,
+            height: self.height,
+            background_bgra: self.background_bgra,
+            loop_count: self.loop_count,
+            has_alpha: self.has_alpha,
+        };
+        let mut animator = WebPAnimator::new(params)?;
+        let read = |path: &Option<PathBuf>| match path {
+            Some(path) => fs::read(base.join(path)),
+            None => Ok(Vec::new()),
+        };
+        animator.set_icc_profile(read(&self.icc_profile)?)?;
+        animator.set_exif_metadata(read(&self.exif_metadata)?)?;
+        animator.set_xmp_metadata(read(&self.xmp_metadata)?)?;
+        for frame in &self.frames {
+            let data = fs::read(base.join(&frame.file))?;
+            let chunks = still_image_chunks(&data)?;
+            let image = check_image_chunks(&chunks)?;
+            let (width, height) =
+                bitstream::dimensions(&image).ok_or(EncodingError::UnrecognizedImage)?;
+            let options = FrameOptions {
+                rect: Some(FrameRect {
+                    x: frame.x,
+                    y: frame.y,
+                    width,
+                    height,
+                }),
+                duration: frame.duration,
+                dispose: frame.dispose,
+                blend: frame.blend,
+                ..FrameOptions::default()
+            };
+            animator.add_chunk(&chunks, options)?;
+        }
+        Ok(animator)
+    }
+}
+
+impl WebPAnimator {
+    /// Builds an animation from the manifest at `path`, which is read as
+    /// JSON if its extension is `json` and as TOML otherwise.  See the
+    /// [`manifest`](crate::manifest) module for the format.
+    pub fn from_manifest<P: AsRef<Path>>(path: P) -> Result<Self, EncodingError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let manifest = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Manifest::from_json(&text)?,
+            _ => Manifest::from_toml(&text)?,
+        };
+        manifest.build(path.parent().unwrap_or(Path::new("")))
+    }
+}
+
+impl Decoder<'_> {
+    /// Writes each frame as a still WebP image, along with any metadata, to
+    /// the directory `dir`, which must exist, and a `manifest.toml` listing
+    /// them.  Existing files with the same names are replaced.  Passing
+    /// the manifest to [`WebPAnimator::from_manifest`] gives back the same
+    /// file, unless the frames or manifest were edited in between.
+    pub fn export_manifest<P: AsRef<Path>>(&self, dir: P) -> Result<Manifest, EncodingError> {
+        let params = Params {
+            width: self.width(),
+            height: self.height(),
+            background_bgra: self.background_bgra(),
+            loop_count: self.loop_count(),
+            has_alpha: self.has_alpha(),
+        };
+        let mut writer = ManifestWriter::new(dir, params);
+        writer.set_icc_profile(self.icc_profile())?;
+        writer.set_exif_metadata(self.exif_metadata())?;
+        writer.set_xmp_metadata(self.xmp_metadata())?;
+        for frame in self.frames() {
+            let data = frame.to_webp();
+            let options = frame.options();
+            writer.add_input_frame(InputFrame::Encoded { data, options })?;
+        }
+        writer.finish()
+    }
+}
+
+/// A [`FrameSink`] that writes each frame as a numbered still WebP image,
+/// `frame0000.webp` and so on, in a directory, and [`finish`](Self::finish)
+/// writes a `manifest.toml` listing them.  Existing files with the same
+/// names are replaced.
+///
+/// Encoded frames are written as they are, and images are encoded
+/// losslessly.
+#[derive(Clone, Debug)]
+pub struct ManifestWriter {
+    dir: PathBuf,
+    manifest: Manifest,
+}
+
+impl ManifestWriter {
+    /// Writes to the directory `dir`, which must exist.
+    pub fn new<P: AsRef<Path>>(dir: P, params: Params) -> Self {
+        Self {
+            dir: dir.as_ref().into(),
+            manifest: Manifest {
+                width: params.width,
+                height: params.height,
+                background_bgra: params.background_bgra,
+                loop_count: params.loop_count,
+                has_alpha: params.has_alpha,
+                icc_profile: None,
+                exif_metadata: None,
+                xmp_metadata: None,
+                frames: Vec::new(),
+            },
+        }
+    }
+
+    /// Writes `icc_profile.icc`, or forgets the ICC profile if `None`.
+    pub fn set_icc_profile(&mut self, icc_profile: Option<&[u8]>) -> Result<(), EncodingError> {
+        self.manifest.icc_profile = self.write_metadata("icc_profile.icc", icc_profile)?;
+        Ok(())
+    }
+
+    /// Writes `exif.bin`, or forgets the EXIF metadata if `None`.
+    pub fn set_exif_metadata(&mut self, exif_metadata: Option<&[u8]>) -> Result<(), EncodingError> {
+        self.manifest.exif_metadata = self.write_metadata("exif.bin", exif_metadata)?;
+        Ok(())
+    }
+
+    /// Writes `xmp.xml`, or forgets the XMP metadata if `None`.
+    pub fn set_xmp_metadata(&mut self, xmp_metadata: Option<&[u8]>) -> Result<(), EncodingError> {
+        self.manifest.xmp_metadata = self.write_metadata("xmp.xml", xmp_metadata)?;
+        Ok(())
+    }
+
+    fn write_metadata(
+        &self,
+        name: &str,
+        data: Option<&[u8]>,
+    ) -> Result<Option<PathBuf>, EncodingError> {
+        match data {
+            Some(data) => {
+                fs::write(self.dir.join(name), data)?;
+                Ok(Some(name.into()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// The manifest so far.
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// Writes `manifest.toml` and returns the manifest.
+    pub fn finish(self) -> Result<Manifest, EncodingError> {
+        let text = self
+            .manifest
+            .to_toml()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        fs::write(self.dir.join("manifest.toml"), text)?;
+        Ok(self.manifest)
+    }
+}
+
+impl FrameSink for ManifestWriter {
+    fn add_input_frame(&mut self, frame: InputFrame) -> Result<(), EncodingError> {
+        let (data, options) = match frame {
+            InputFrame::Encoded { data, options } => (data, options),
+            #[cfg(feature = "image")]
+            InputFrame::Image { image, duration } => (
+                crate::encode::encode_lossless(&image)?,
+                FrameOptions::with_duration(duration),
+            ),
+        };
+        let rect = match options.rect {
+            Some(rect) => rect,
+            None => {
+                let chunks = still_image_chunks(&data)?;
+                let image = check_image_chunks(&chunks)?;
+                let size = bitstream::dimensions(&image).ok_or(EncodingError::UnrecognizedImage)?;
+                let canvas = (self.manifest.width, self.manifest.height);
+                options
+                    .anchor
+                    .place(canvas, size)
+                    .ok_or(EncodingError::InvalidDimensions)?
+            }
+        };
+        let name = format!("frame{:04}.webp", self.manifest.frames.len());
+        fs::write(self.dir.join(&name), &data)?;
+        self.manifest.frames.push(ManifestFrame {
+            file: name.into(),
+            duration: options.duration,
+            x: rect.x,
+            y: rect.y,
+            dispose: options.dispose,
+            blend: options.blend,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use image::{Rgb, RgbImage};
+
+    use crate::{
+        Blend, Decoder, Disposal, EncodingError, FrameOptions, FrameRect, LoopCount, WebPAnimator,
+        manifest::{Manifest, ParseError},
+        test_util::lossless_webp,
+    };
+
+    #[test]
+    fn test_parse() {
+        let manifest = Manifest::from_toml(
+            "width = 64 # The canvas.\n\
+             height = 32\n\
+             background = \"#10203040\"\n\
+             loop_count = 2\n\
+             \n\
+             [[frame]]\n\
+             file = \"a # b.webp\"\n\
+             duration = 1_000\n\
+             [[frame]]\n\
+             file = \"c.webp\"\n\
+             duration = 50\n\
+             x = 2\n\
+             dispose = \"background\"\n\
+             blend = \"overwrite\"\n",
+        )
+        .unwrap();
+        assert_eq!((manifest.width, manifest.height), (64, 32));
+        assert_eq!(manifest.background_bgra, [0x30, 0x20, 0x10, 0x40]);
+        assert_eq!(manifest.loop_count, LoopCount::from(2));
+        assert_eq!(manifest.frames.len(), 2);
+        assert_eq!(manifest.frames[0].file.to_str(), Some("a # b.webp"));
+        assert_eq!(manifest.frames[0].duration, 1000);
+        let frame = &manifest.frames[1];
+        assert_eq!((frame.x, frame.y), (2, 0));
+        assert_eq!(
+            (frame.dispose, frame.blend),
+            (Disposal::Background, Blend::Overwrite)
+        );
+
+        let error = |text| match Manifest::from_toml(text) {
+            Err(ParseError::Toml(e)) => e.message().to_string(),
+            result => panic!("{result:?}"),
+        };
+        assert!(error("width = 1\nheight = 1\nsize = 2").starts_with("unknown field `size`"));
+        assert_eq!(
+            error("width = 1\nheight = 1\n[[frame]]\nfile = \"a.webp\""),
+            "missing field `duration`"
+        );
+        assert_eq!(error("width = 1"), "missing field `height`");
+        assert_eq!(
+            error("width = 1\nheight = 1\nbackground = \"red\""),
+            "invalid color `red`"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let toml = "width = 8\nheight = 4\nbackground = \"#10203040\"\n\
+                    [[frame]]\nfile = \"a.webp\"\nduration = 100\n\
+                    [[frame]]\nfile = \"b.webp\"\nduration = 50\nx = 2\nblend = \"overwrite\"\n";
+        let manifest = Manifest::from_toml(toml).unwrap();
+        let json = serde_json::to_string(&manifest).unwrap();
+        // Settings with default values are left out, as in TOML.
+        assert_eq!(
+            json,
+            r##"{"width":8,"height":4,"background":"#10203040","frame":[{"file":"a.webp","duration":100},{"file":"b.webp","duration":50,"x":2,"blend":"overwrite"}]}"##
+        );
+        assert_eq!(Manifest::from_json(&json).unwrap(), manifest);
+        assert!(matches!(
+            Manifest::from_json(r#"{"width":1,"height":1,"size":2}"#),
+            Err(ParseError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_manifest() {
+        let dir = std::env::temp_dir().join(format!("webp-animator-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        RgbImage::from_pixel(16, 16, Rgb([255, 0, 0]))
+            .save(dir.join("red.webp"))
+            .unwrap();
+        RgbImage::from_pixel(8, 8, Rgb([0, 0, 255]))
+            .save(dir.join("blue.webp"))
+            .unwrap();
+        fs::write(
+            dir.join("anim.toml"),
+            "width = 16\nheight = 16\n\
+             [[frame]]\nfile = \"red.webp\"\nduration = 100\n\
+             [[frame]]\nfile = \"blue.webp\"\nduration = 200\nx = 4\ny = 6\n",
+        )
+        .unwrap();
+        let result = WebPAnimator::from_manifest(dir.join("anim.toml"));
+        fs::write(
+            dir.join("anim.json"),
+            r#"{"width":16,"height":16,"frame":[{"file":"red.webp","duration":100}]}"#,
+        )
+        .unwrap();
+        let json = WebPAnimator::from_manifest(dir.join("anim.json"));
+        fs::write(dir.join("bad.toml"), "width = 16\nheight = 16\nwidth = 2\n").unwrap();
+        let bad = WebPAnimator::from_manifest(dir.join("bad.toml"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        let data = result.unwrap().into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+        let frames = decoder.frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].duration, 200);
+        assert_eq!((frames[1].rect.x, frames[1].rect.y), (4, 6));
+        assert_eq!((frames[1].rect.width, frames[1].rect.height), (8, 8));
+        assert_eq!(json.unwrap().frame_count(), 1);
+        assert!(matches!(
+            bad,
+            Err(EncodingError::Manifest(ParseError::Toml(_)))
+        ));
+    }
+
+    #[test]
+    fn test_export_manifest() {
+        let mut writer = WebPAnimator::builder()
+            .width(16)
+            .height(16)
+            .background([1, 2, 3, 4])
+            .loop_count(2)
+            .xmp_metadata(b"<x/>".to_vec())
+            .build()
+            .unwrap();
+        let mut buf = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb([255, 0, 0])));
+        writer.add_webp_image(&buf, None, 100).unwrap();
+        buf = lossless_webp(&RgbImage::from_pixel(4, 4, Rgb([0, 0, 255])));
+        let options = FrameOptions {
+            rect: Some(FrameRect {
+                x: 2,
+                y: 4,
+                width: 4,
+                height: 4,
+            }),
+            dispose: Disposal::Background,
+            blend: Blend::Overwrite,
+            ..FrameOptions::with_duration(50)
+        };
+        writer.add_frame(&buf, options).unwrap();
+        let data = writer.into_bytes().unwrap();
+
+        let dir = std::env::temp_dir().join(format!("webp-animator-export-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let manifest = Decoder::new(&data).unwrap().export_manifest(&dir).unwrap();
+        let rebuilt = WebPAnimator::from_manifest(dir.join("manifest.toml"));
+        let names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(names.len(), 4);
+        assert_eq!(manifest.frames[1].file.to_str(), Some("frame0001.webp"));
+        assert_eq!(
+            Manifest::from_toml(&manifest.to_toml().unwrap()).unwrap(),
+            manifest
+        );
+        assert_eq!(rebuilt.unwrap().into_bytes().unwrap(), data);
+    }
+}
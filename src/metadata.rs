@@ -0,0 +1,425 @@
+//! Builders for the metadata chunks, for setting common fields without
+//! writing the encoded metadata by hand.
+
+use crate::EncodingError;
+
+/// An sRGB ICC profile, for
+/// [`WebPAnimator::set_icc_profile`](crate::WebPAnimator::set_icc_profile).
+pub const SRGB_ICC_PROFILE: &[u8] = include_bytes!("../profiles/sRGB.icc");
+
+/// A Display P3 ICC profile, the color space of wide gamut Apple displays.
+pub const DISPLAY_P3_ICC_PROFILE: &[u8] = include_bytes!("../profiles/DisplayP3.icc");
+
+/// Checks the header of an ICC profile: the declared size must match the
+/// length of `icc_profile`, the `acsp` signature must be present, and the
+/// major version must be 2, 4 or 5.  The tags aren't checked.  Fails with
+/// [`EncodingError::InvalidMetadata`].
+pub fn validate_icc_profile(icc_profile: &[u8]) -> Result<(), EncodingError> {
+    const HEADER_LEN: usize = 128;
+    let valid = icc_profile.len() >= HEADER_LEN
+        && u32::from_be_bytes(icc_profile[0..4].try_into().unwrap()) as usize == icc_profile.len()
+        && matches!(icc_profile[8], 2 | 4 | 5)
+        && &icc_profile[36..40] == b"acsp";
+    if valid {
+        Ok(())
+    } else {
+        Err(EncodingError::InvalidMetadata)
+    }
+}
+
+/// How a viewer should rotate or flip the image for display, as stored in
+/// the EXIF `Orientation` tag.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Orientation {
+    /// Displayed as stored.
+    #[default]
+    Normal = 1,
+    FlipHorizontal = 2,
+    Rotate180 = 3,
+    FlipVertical = 4,
+    /// Flipped horizontally, then rotated 270 degrees clockwise.
+    Transpose = 5,
+    /// Rotated 90 degrees clockwise.
+    Rotate90 = 6,
+    /// Flipped horizontally, then rotated 90 degrees clockwise.
+    Transverse = 7,
+    /// Rotated 270 degrees clockwise.
+    Rotate270 = 8,
+}
+
+/// Builds EXIF metadata for
+/// [`WebPAnimator::set_exif_metadata`](crate::WebPAnimator::set_exif_metadata).
+///
+/// The fields are written to the first IFD of a little-endian TIFF
+/// structure, which is what the `EXIF` chunk contains.
+///
+/// ```
+/// use webp_animator::metadata::{ExifBuilder, Orientation};
+/// let exif = ExifBuilder::new()
+///     .orientation(Orientation::Rotate90)
+///     .software("webp-animator")
+///     .date_time("2024:01:31 12:00:00")
+///     .build()
+///     .unwrap();
+/// assert!(exif.starts_with(b"II*\0"));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExifBuilder {
+    orientation: Option<Orientation>,
+    software: Option<String>,
+    date_time: Option<String>,
+    artist: Option<String>,
+    copyright: Option<String>,
+}
+
+impl ExifBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn orientation(mut self, orientation: Orientation) -> Self {
+        self.orientation = Some(orientation);
+        self
+    }
+
+    /// The program that created the image.
+    pub fn software(mut self, software: impl Into<String>) -> Self {
+        self.software = Some(software.into());
+        self
+    }
+
+    /// When the image was created, in the EXIF format
+    /// `YYYY:MM:DD HH:MM:SS`.
+    pub fn date_time(mut self, date_time: impl Into<String>) -> Self {
+        self.date_time = Some(date_time.into());
+        self
+    }
+
+    /// The person who created the image.
+    pub fn artist(mut self, artist: impl Into<String>) -> Self {
+        self.artist = Some(artist.into());
+        self
+    }
+
+    pub fn copyright(mut self, copyright: impl Into<String>) -> Self {
+        self.copyright = Some(copyright.into());
+        self
+    }
+
+    /// Serializes the fields that were set.  Fails with
+    /// [`EncodingError::InvalidMetadata`] if a string contains a NUL
+    /// character or isn't ASCII, or if the date and time aren't in the EXIF
+    /// format.
+    pub fn build(&self) -> Result<Vec<u8>, EncodingError> {
+        if self
+            .date_time
+            .as_deref()
+            .is_some_and(|d| !is_exif_date_time(d))
+        {
+            return Err(EncodingError::InvalidMetadata);
+        }
+        // The entries must be sorted by tag.
+        let strings = [
+            (0x0131, &self.software),
+            (0x0132, &self.date_time),
+            (0x013b, &self.artist),
+            (0x8298, &self.copyright),
+        ];
+        let mut entries: Vec<(u16, Value)> = Vec::new();
+        if let Some(orientation) = self.orientation {
+            entries.push((0x0112, Value::Short(orientation as u16)));
+        }
+        for (tag, value) in strings {
+            let Some(value) = value else { continue };
+            if !value.is_ascii() || value.contains('\0') {
+                return Err(EncodingError::InvalidMetadata);
+            }
+            entries.push((tag, Value::Ascii([value.as_bytes(), b"\0"].concat())));
+        }
+
+        let ifd_len = 2 + 12 * entries.len() + 4;
+        let mut out = b"II*\0".to_vec();
+        out.extend(8u32.to_le_bytes());
+        out.extend((entries.len() as u16).to_le_bytes());
+        let mut data = Vec::new();
+        for (tag, value) in &entries {
+            out.extend(tag.to_le_bytes());
+            match value {
+                Value::Short(v) => {
+                    out.extend(3u16.to_le_bytes());
+                    out.extend(1u32.to_le_bytes());
+                    out.extend(v.to_le_bytes());
+                    out.extend([0, 0]);
+                }
+                Value::Ascii(bytes) => {
+                    out.extend(2u16.to_le_bytes());
+                    out.extend((bytes.len() as u32).to_le_bytes());
+                    if bytes.len() <= 4 {
+                        let mut inline = [0; 4];
+                        inline[..bytes.len()].copy_from_slice(bytes);
+                        out.extend(inline);
+                    } else {
+                        let offset = 8 + ifd_len + data.len();
+                        out.extend((offset as u32).to_le_bytes());
+                        data.extend(bytes);
+                        // Values start on word boundaries.
+                        if data.len() & 1 != 0 {
+                            data.push(0);
+                        }
+                    }
+                }
+            }
+        }
+        // There is no next IFD.
+        out.extend(0u32.to_le_bytes());
+        out.extend(data);
+        Ok(out)
+    }
+}
+
+/// Builds an XMP packet for
+/// [`WebPAnimator::set_xmp_metadata`](crate::WebPAnimator::set_xmp_metadata),
+/// using the Dublin Core `title`, `creator` and `description` properties and
+/// the XMP `Rating` property.
+///
+/// ```
+/// # fn main() -> Result<(), webp_animator::EncodingError> {
+/// use webp_animator::{WebPAnimator, metadata::XmpBuilder};
+/// let xmp = XmpBuilder::new()
+///     .title("Waves")
+///     .creator("A. Person")
+///     .rating(4)
+///     .build()?;
+/// let mut writer = WebPAnimator::builder().width(64).height(64).build()?;
+/// writer.set_xmp_metadata(xmp)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct XmpBuilder {
+    title: Option<String>,
+    creators: Vec<String>,
+    description: Option<String>,
+    rating: Option<i8>,
+}
+
+impl XmpBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Adds a creator.  Creators are listed in the order they are added.
+    pub fn creator(mut self, creator: impl Into<String>) -> Self {
+        self.creators.push(creator.into());
+        self
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Between 1 and 5 stars, 0 for no rating, or -1 for rejected.
+    pub fn rating(mut self, rating: i8) -> Self {
+        self.rating = Some(rating);
+        self
+    }
+
+    /// Serializes the properties that were set as UTF-8.  Fails with
+    /// [`EncodingError::InvalidMetadata`] if the rating is out of range or a
+    /// string contains a character that XML doesn't allow.
+    pub fn build(&self) -> Result<Vec<u8>, EncodingError> {
+        if self.rating.is_some_and(|r| !(-1..=5).contains(&r)) {
+            return Err(EncodingError::InvalidMetadata);
+        }
+        let mut properties = String::new();
+        let mut list = |name: &str, kind: &str, item: &str, values: &[&String]| {
+            if values.is_empty() {
+                return Ok(());
+            }
+            properties += &format!("   <{name}>\n    <rdf:{kind}>\n");
+            for value in values {
+                properties += &format!("     <rdf:li{item}>{}</rdf:li>\n", escape(value)?);
+            }
+            properties += &format!("    </rdf:{kind}>\n   </{name}>\n");
+            Ok::<_, EncodingError>(())
+        };
+        let lang = " xml:lang=\"x-default\"";
+        list(
+            "dc:title",
+            "Alt",
+            lang,
+            &self.title.iter().collect::<Vec<_>>(),
+        )?;
+        list(
+            "dc:creator",
+            "Seq",
+            "",
+            &self.creators.iter().collect::<Vec<_>>(),
+        )?;
+        list(
+            "dc:description",
+            "Alt",
+            lang,
+            &self.description.iter().collect::<Vec<_>>(),
+        )?;
+        if let Some(rating) = self.rating {
+            properties += &format!("   <xmp:Rating>{rating}</xmp:Rating>\n");
+        }
+        let packet = format!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n \
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n  \
+             <rdf:Description rdf:about=\"\"\n    \
+             xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n    \
+             xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\">\n\
+             {properties}  \
+             </rdf:Description>\n \
+             </rdf:RDF>\n\
+             </x:xmpmeta>\n\
+             <?xpacket end=\"w\"?>"
+        );
+        Ok(packet.into_bytes())
+    }
+}
+
+/// Escapes the XML special characters in `s`.  Fails with
+/// [`EncodingError::InvalidMetadata`] on control characters other than
+/// tabs and line breaks.
+fn escape(s: &str) -> Result<String, EncodingError> {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' | '\n' | '\r' => out.push(c),
+            c if c.is_control() => return Err(EncodingError::InvalidMetadata),
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+enum Value {
+    Short(u16),
+    Ascii(Vec<u8>),
+}
+
+/// Whether `s` has the form `YYYY:MM:DD HH:MM:SS`.
+fn is_exif_date_time(s: &str) -> bool {
+    s.len() == 19
+        && s.bytes().enumerate().all(|(i, b)| match i {
+            4 | 7 => b == b':',
+            10 => b == b' ',
+            13 | 16 => b == b':',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgb, RgbImage, metadata::Orientation as ImageOrientation};
+
+    use super::{
+        DISPLAY_P3_ICC_PROFILE, ExifBuilder, Orientation, SRGB_ICC_PROFILE, XmpBuilder,
+        validate_icc_profile,
+    };
+    use crate::{Decoder, EncodingError, FrameOptions, WebPAnimator, test_util::lossless_webp};
+
+    #[test]
+    fn test_exif_builder() {
+        let exif = ExifBuilder::new()
+            .orientation(Orientation::Rotate270)
+            .software("abc")
+            .copyright("Copyright 2024 Someone")
+            .date_time("2024:01:31 12:34:56")
+            .build()
+            .unwrap();
+        assert_eq!(
+            ImageOrientation::from_exif_chunk(&exif),
+            Some(ImageOrientation::Rotate270)
+        );
+        // The header, 4 entries and the next IFD offset, then the strings
+        // that don't fit in an entry.
+        assert_eq!(&exif[8..10], &4u16.to_le_bytes());
+        // "abc\0" fits in the entry.
+        assert_eq!(&exif[10 + 12 + 8..10 + 12 + 12], b"abc\0");
+        let strings = &exif[8 + 2 + 4 * 12 + 4..];
+        assert_eq!(strings, b"2024:01:31 12:34:56\0Copyright 2024 Someone\0\0");
+
+        assert_eq!(
+            ExifBuilder::new().build().unwrap(),
+            b"II*\0\x08\0\0\0\0\0\0\0\0\0"
+        );
+        assert!(matches!(
+            ExifBuilder::new().date_time("2024-01-31 12:34:56").build(),
+            Err(EncodingError::InvalidMetadata)
+        ));
+        assert!(matches!(
+            ExifBuilder::new().artist("Zoë").build(),
+            Err(EncodingError::InvalidMetadata)
+        ));
+    }
+
+    #[test]
+    fn test_xmp_builder() {
+        let xmp = XmpBuilder::new()
+            .title("Fish & <Chips>")
+            .creator("One")
+            .creator("Two")
+            .rating(5)
+            .build()
+            .unwrap();
+        let xmp = String::from_utf8(xmp).unwrap();
+        assert!(xmp.starts_with("<?xpacket begin=\"\u{feff}\""));
+        assert!(xmp.ends_with("<?xpacket end=\"w\"?>"));
+        assert!(xmp.contains("<rdf:li xml:lang=\"x-default\">Fish &amp; &lt;Chips&gt;</rdf:li>"));
+        assert!(xmp.contains("<rdf:Seq>\n     <rdf:li>One</rdf:li>\n     <rdf:li>Two</rdf:li>"));
+        assert!(xmp.contains("<xmp:Rating>5</xmp:Rating>"));
+        assert!(!xmp.contains("dc:description"));
+
+        assert!(matches!(
+            XmpBuilder::new().rating(6).build(),
+            Err(EncodingError::InvalidMetadata)
+        ));
+        assert!(matches!(
+            XmpBuilder::new().description("\u{7}").build(),
+            Err(EncodingError::InvalidMetadata)
+        ));
+    }
+
+    #[test]
+    fn test_icc_profiles() {
+        for profile in [SRGB_ICC_PROFILE, DISPLAY_P3_ICC_PROFILE] {
+            validate_icc_profile(profile).unwrap();
+        }
+        assert!(validate_icc_profile(&SRGB_ICC_PROFILE[..200]).is_err());
+        let mut bad_signature = SRGB_ICC_PROFILE.to_vec();
+        bad_signature[36] = b'x';
+        assert!(validate_icc_profile(&bad_signature).is_err());
+
+        let path = std::env::temp_dir().join(format!("webp-animator-icc-{}", std::process::id()));
+        std::fs::write(&path, DISPLAY_P3_ICC_PROFILE).unwrap();
+        let mut writer = WebPAnimator::builder().width(4).height(4).build().unwrap();
+        let set = writer.set_icc_profile_from_file(&path);
+        std::fs::write(&path, &bad_signature).unwrap();
+        let bad = writer.set_icc_profile_from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        set.unwrap();
+        assert!(matches!(bad, Err(EncodingError::InvalidMetadata)));
+        let frame = lossless_webp(&RgbImage::from_pixel(4, 4, Rgb([0, 0, 0])));
+        writer
+            .add_frame(&frame, FrameOptions::with_duration(100))
+            .unwrap();
+        let data = writer.into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+        assert_eq!(decoder.icc_profile(), Some(DISPLAY_P3_ICC_PROFILE));
+    }
+}
@@ -0,0 +1,443 @@
+use std::collections::BTreeSet;
+
+use image::{
+    Rgba, RgbaImage,
+    imageops::{self, FilterType},
+};
+
+use crate::{
+    CanvasFrame, Decoder, EncodingError, Optimizer, WebPAnimator, WebPAnimatorBuilder, overlay,
+};
+
+/// The layout of a montage created by [`montage`] or [`montage_frames`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Montage {
+    /// The maximum number of tiles in each row.  The inputs are placed row
+    /// by row.
+    pub columns: u32,
+    /// The space between neighboring tiles, in pixels.
+    pub gap: u32,
+    /// The color of the gaps and of the unused tiles in the last row, in
+    /// RGBA order.  It also shows through transparent parts of the inputs.
+    pub background: [u8; 4],
+    /// The size of a tile, or `None` for the largest width and height of the
+    /// inputs.  Inputs of a different size are scaled with a triangle filter
+    /// to fit the tile with their aspect ratio preserved, and centered.
+    pub tile_size: Option<(u32, u32)>,
+    /// If `true`, inputs shorter than the longest one start over when they
+    /// end.  Otherwise they stay on their last frame.
+    pub loop_shorter: bool,
+}
+
+impl Default for Montage {
+    fn default() -> Self {
+        Self {
+            columns: 2,
+            gap: 0,
+            background: [0; 4],
+            tile_size: None,
+            loop_shorter: true,
+        }
+    }
+}
+
+/// How [`Decoder::picture_in_picture`] places the inset animation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PictureInPicture {
+    /// The position of the top left corner of the inset on the canvas.
+    /// Parts outside the canvas are clipped.
+    pub x: i64,
+    pub y: i64,
+    /// The size of the inset relative to its own canvas.  It is scaled with
+    /// a triangle filter.
+    pub scale: f32,
+    /// Between 0 (invisible) and 1 (as opaque as the inset).
+    pub opacity: f32,
+    /// If `true`, whichever animation is shorter starts over when it ends.
+    /// Otherwise it stays on its last frame.
+    pub loop_shorter: bool,
+}
+
+impl PictureInPicture {
+    /// An inset at the given position, unscaled and fully opaque, that
+    /// loops if it is the shorter animation.
+    pub fn new(x: i64, y: i64) -> Self {
+        Self {
+            x,
+            y,
+            scale: 1.0,
+            opacity: 1.0,
+            loop_shorter: true,
+        }
+    }
+}
+
+impl Decoder<'_> {
+    /// Draws the frames of `inset` over the rendered frames of this
+    /// animation, and re-encodes the result losslessly with an
+    /// [`Optimizer`].
+    ///
+    /// The two animations are played together for as long as the longer
+    /// one, and a frame is emitted whenever either changes frame.  The canvas
+    /// size, loop count and metadata are those of this animation.  Fails
+    /// with [`EncodingError::InvalidDimensions`] unless the scale is
+    /// positive.
+    pub fn picture_in_picture(
+        &self,
+        inset: &Decoder,
+        placement: &PictureInPicture,
+    ) -> Result<WebPAnimator, EncodingError> {
+        if placement.scale.is_nan() || placement.scale <= 0.0 {
+            return Err(EncodingError::InvalidDimensions);
+        }
+        let scaled = |len: u32| ((len as f32 * placement.scale).round() as u32).max(1);
+        let (width, height) = (scaled(inset.width()), scaled(inset.height()));
+        let base_frames = self.canvas_frames().collect::<Result<Vec<_>, _>>()?;
+        let inset_frames = inset.canvas_frames().collect::<Result<Vec<_>, _>>()?;
+        if base_frames.is_empty() || inset_frames.is_empty() {
+            return Err(EncodingError::NoFrames);
+        }
+        let base = Track::new(&base_frames, RgbaImage::clone);
+        let inset = Track::new(&inset_frames, |image| {
+            if image.dimensions() == (width, height) {
+                image.clone()
+            } else {
+                imageops::resize(image, width, height, FilterType::Triangle)
+            }
+        });
+        let looping = placement.loop_shorter;
+        let animator = self.animator_builder().detect_alpha(true).build()?;
+        let mut optimizer = Optimizer::new(animator);
+        for (t, duration) in timeline(&[&base, &inset], looping)? {
+            let mut canvas = base.frame_at(t, looping).clone();
+            let image = inset.frame_at(t, looping);
+            overlay::draw(
+                &mut canvas,
+                image,
+                placement.x,
+                placement.y,
+                placement.opacity,
+            );
+            optimizer.add_frame(&canvas, duration)?;
+        }
+        Ok(optimizer.into_inner())
+    }
+}
+
+/// Renders animations side by side in a grid, for example to compare
+/// encodings of the same clip.
+///
+/// The inputs are played on a common timeline that lasts as long as the
+/// longest input, and a frame is emitted whenever any input changes frame.
+/// The loop count and metadata are taken from the first input.  The frames
+/// are re-encoded losslessly with an [`Optimizer`].  Fails with
+/// [`EncodingError::NoFrames`] if there are no inputs or an input has no
+/// frames, and with [`EncodingError::InvalidDimensions`] if the layout has
+/// no columns or an empty tile, or the montage would be too large.
+pub fn montage(inputs: &[&[u8]], layout: &Montage) -> Result<Vec<u8>, EncodingError> {
+    let first = Decoder::new(inputs.first().ok_or(EncodingError::NoFrames)?)?;
+    let mut frames = Vec::with_capacity(inputs.len());
+    for data in inputs {
+        let decoder = Decoder::new(data)?;
+        frames.push(decoder.canvas_frames().collect::<Result<Vec<_>, _>>()?);
+    }
+    let inputs: Vec<&[CanvasFrame]> = frames.iter().map(Vec::as_slice).collect();
+    compose(&inputs, layout, first.animator_builder())?.into_bytes()
+}
+
+/// Like [`montage`], but with inputs that are already rendered, such as the
+/// collected [`canvas_frames`](Decoder::canvas_frames) of decoded
+/// animations.  The result loops forever.
+pub fn montage_frames(
+    inputs: &[&[CanvasFrame]],
+    layout: &Montage,
+) -> Result<WebPAnimator, EncodingError> {
+    compose(inputs, layout, WebPAnimator::builder())
+}
+
+/// The rendered frames of an animation on a timeline.
+pub(crate) struct Track {
+    frames: Vec<RgbaImage>,
+    /// The start time of each frame.
+    starts: Vec<u64>,
+    total: u64,
+}
+
+impl Track {
+    /// Collects the frames, converting each with `image`.
+    pub(crate) fn new(
+        frames: &[CanvasFrame],
+        mut image: impl FnMut(&RgbaImage) -> RgbaImage,
+    ) -> Self {
+        let mut starts = Vec::with_capacity(frames.len());
+        let mut total = 0;
+        for frame in frames {
+            starts.push(total);
+            total += frame.duration as u64;
+        }
+        Self {
+            frames: frames.iter().map(|frame| image(&frame.image)).collect(),
+            starts,
+            total,
+        }
+    }
+
+    /// The frame shown at time `t`.  If `looping`, the track starts over
+    /// when it ends, and otherwise its last frame stays.
+    pub(crate) fn frame_at(&self, t: u64, looping: bool) -> &RgbaImage {
+        let t = if looping && self.total > 0 {
+            t % self.total
+        } else {
+            t
+        };
+        &self.frames[self.starts.partition_point(|&s| s <= t).saturating_sub(1)]
+    }
+}
+
+/// Plays `tracks` together for as long as the longest one, and returns the
+/// times at which any of them changes frame, each with the time until the
+/// next change.
+pub(crate) fn timeline(tracks: &[&Track], looping: bool) -> Result<Vec<(u64, u32)>, EncodingError> {
+    let end = tracks.iter().map(|track| track.total).max().unwrap_or(0);
+    let mut times = BTreeSet::from([0]);
+    for track in tracks {
+        let mut offset = 0;
+        loop {
+            times.extend(
+                track
+                    .starts
+                    .iter()
+                    .map(|s| offset + s)
+                    .take_while(|&t| t < end),
+            );
+            offset += track.total;
+            if !looping || track.total == 0 || offset >= end {
+                break;
+            }
+        }
+    }
+    let times: Vec<u64> = times.into_iter().collect();
+    let mut spans = Vec::with_capacity(times.len());
+    for (k, &t) in times.iter().enumerate() {
+        let next = times.get(k + 1).copied().unwrap_or(end.max(t));
+        let duration = u32::try_from(next - t).map_err(|_| EncodingError::InvalidDuration)?;
+        spans.push((t, duration));
+    }
+    Ok(spans)
+}
+
+fn compose(
+    inputs: &[&[CanvasFrame]],
+    layout: &Montage,
+    builder: WebPAnimatorBuilder,
+) -> Result<WebPAnimator, EncodingError> {
+    if inputs.is_empty() || inputs.iter().any(|frames| frames.is_empty()) {
+        return Err(EncodingError::NoFrames);
+    }
+    if layout.columns == 0 {
+        return Err(EncodingError::InvalidDimensions);
+    }
+    let (tile_width, tile_height) = layout.tile_size.unwrap_or_else(|| {
+        inputs.iter().fold((0, 0), |(w, h), frames| {
+            let (fw, fh) = frames[0].image.dimensions();
+            (w.max(fw), h.max(fh))
+        })
+    });
+    if tile_width == 0 || tile_height == 0 {
+        return Err(EncodingError::InvalidDimensions);
+    }
+    let count = inputs.len() as u32;
+    let columns = layout.columns.min(count);
+    let rows = count.div_ceil(columns);
+    let extent = |tiles: u32, len: u32| {
+        tiles
+            .checked_mul(len)?
+            .checked_add((tiles - 1).checked_mul(layout.gap)?)
+    };
+    let (Some(width), Some(height)) = (extent(columns, tile_width), extent(rows, tile_height))
+    else {
+        return Err(EncodingError::InvalidDimensions);
+    };
+
+    let mut tiles = Vec::with_capacity(inputs.len());
+    for (i, frames) in inputs.iter().enumerate() {
+        let (column, row) = (i as u32 % columns, i as u32 / columns);
+        let (w, h) = frames[0].image.dimensions();
+        let scale = f64::min(tile_width as f64 / w as f64, tile_height as f64 / h as f64);
+        let scaled = |len: u32, max: u32| ((len as f64 * scale).round() as u32).clamp(1, max);
+        let (scaled_width, scaled_height) = (scaled(w, tile_width), scaled(h, tile_height));
+        let track = Track::new(frames, |image| {
+            if image.dimensions() == (scaled_width, scaled_height) {
+                image.clone()
+            } else {
+                imageops::resize(image, scaled_width, scaled_height, FilterType::Triangle)
+            }
+        });
+        let x = column * (tile_width + layout.gap) + (tile_width - scaled_width) / 2;
+        let y = row * (tile_height + layout.gap) + (tile_height - scaled_height) / 2;
+        tiles.push((track, x.into(), y.into()));
+    }
+    let tracks: Vec<&Track> = tiles.iter().map(|(track, _, _)| track).collect();
+    let spans = timeline(&tracks, layout.loop_shorter)?;
+
+    let [r, g, b, a] = layout.background;
+    let animator = builder
+        .width(width)
+        .height(height)
+        .background([b, g, r, a])
+        .detect_alpha(true)
+        .build()?;
+    let mut optimizer = Optimizer::new(animator);
+    for (t, duration) in spans {
+        let mut canvas = RgbaImage::from_pixel(width, height, Rgba(layout.background));
+        for (track, x, y) in &tiles {
+            imageops::overlay(&mut canvas, track.frame_at(t, layout.loop_shorter), *x, *y);
+        }
+        optimizer.add_frame(&canvas, duration)?;
+    }
+    Ok(optimizer.into_inner())
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgba, RgbaImage};
+
+    use crate::{CanvasFrame, Decoder, EncodingError, Montage, PictureInPicture, montage_frames};
+
+    fn frames(width: u32, height: u32, colors: &[([u8; 4], u32)]) -> Vec<CanvasFrame> {
+        colors
+            .iter()
+            .map(|&(color, duration)| CanvasFrame {
+                image: RgbaImage::from_pixel(width, height, Rgba(color)),
+                duration,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_montage() {
+        let (red, green, blue) = ([255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]);
+        let a = frames(4, 4, &[(red, 30), (green, 30)]);
+        let b = frames(2, 2, &[(blue, 20)]);
+        let c = frames(4, 4, &[(green, 25), (blue, 25)]);
+        let layout = Montage {
+            gap: 2,
+            background: [255; 4],
+            ..Montage::default()
+        };
+        let out = montage_frames(&[&a, &b, &c], &layout)
+            .unwrap()
+            .into_bytes()
+            .unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (10, 10));
+        let rendered: Vec<_> = decoder.canvas_frames().map(|f| f.unwrap()).collect();
+        let durations: Vec<_> = rendered.iter().map(|f| f.duration).collect();
+        // `b` starts over at 20 and 40 without changing, so those frames are
+        // merged with the previous ones.
+        assert_eq!(durations, [25, 5, 20, 10]);
+        let first = &rendered[0].image;
+        assert_eq!(first.get_pixel(0, 0), &Rgba(red));
+        assert_eq!(first.get_pixel(4, 0), &Rgba([255; 4]));
+        // `b` is scaled up to fill its tile.
+        assert_eq!(first.get_pixel(6, 0), &Rgba(blue));
+        assert_eq!(first.get_pixel(0, 6), &Rgba(green));
+        assert_eq!(first.get_pixel(6, 6), &Rgba([255; 4]));
+        assert_eq!(rendered[1].image.get_pixel(0, 6), &Rgba(blue));
+        assert_eq!(rendered[2].image.get_pixel(0, 0), &Rgba(green));
+        // `c` starts over at 50.
+        assert_eq!(rendered[3].image.get_pixel(0, 6), &Rgba(green));
+
+        let held = Montage {
+            loop_shorter: false,
+            columns: 3,
+            ..Montage::default()
+        };
+        let out = montage_frames(&[&a, &b], &held)
+            .unwrap()
+            .into_bytes()
+            .unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (8, 4));
+        let durations: Vec<_> = decoder.frames().iter().map(|f| f.duration).collect();
+        assert_eq!(durations, [30, 30]);
+
+        assert!(matches!(
+            montage_frames(&[], &layout),
+            Err(EncodingError::NoFrames)
+        ));
+        let no_columns = Montage {
+            columns: 0,
+            ..layout
+        };
+        assert!(matches!(
+            montage_frames(&[&a], &no_columns),
+            Err(EncodingError::InvalidDimensions)
+        ));
+    }
+
+    #[test]
+    fn test_picture_in_picture() {
+        let (red, green, blue) = ([255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]);
+        let encode = |frames: &[CanvasFrame]| {
+            let (width, height) = frames[0].image.dimensions();
+            montage_frames(
+                &[frames],
+                &Montage {
+                    tile_size: Some((width, height)),
+                    ..Montage::default()
+                },
+            )
+            .unwrap()
+            .into_bytes()
+            .unwrap()
+        };
+        let base = encode(&frames(8, 8, &[(red, 40), (green, 40)]));
+        let inset = encode(&frames(4, 4, &[(blue, 30)]));
+        let (base, inset) = (Decoder::new(&base).unwrap(), Decoder::new(&inset).unwrap());
+        let placement = PictureInPicture {
+            scale: 0.5,
+            ..PictureInPicture::new(6, -1)
+        };
+        let out = base
+            .picture_in_picture(&inset, &placement)
+            .unwrap()
+            .into_bytes()
+            .unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (8, 8));
+        let rendered: Vec<_> = decoder.canvas_frames().map(|f| f.unwrap()).collect();
+        let durations: Vec<_> = rendered.iter().map(|f| f.duration).collect();
+        assert_eq!(durations, [40, 40]);
+        let last = &rendered[1].image;
+        assert_eq!(last.get_pixel(5, 0), &Rgba(green));
+        assert_eq!(last.get_pixel(6, 0), &Rgba(blue));
+        assert_eq!(last.get_pixel(7, 1), &Rgba(green));
+
+        // The shorter base animation stays on its last frame.
+        let clamped = PictureInPicture {
+            loop_shorter: false,
+            opacity: 0.0,
+            ..PictureInPicture::new(0, 0)
+        };
+        let long_inset = encode(&frames(4, 4, &[(blue, 100)]));
+        let long_inset = Decoder::new(&long_inset).unwrap();
+        let out = base
+            .picture_in_picture(&long_inset, &clamped)
+            .unwrap()
+            .into_bytes()
+            .unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        let durations: Vec<_> = decoder.frames().iter().map(|f| f.duration).collect();
+        assert_eq!(durations, [40, 60]);
+
+        let invalid = PictureInPicture {
+            scale: 0.0,
+            ..placement
+        };
+        assert!(matches!(
+            base.picture_in_picture(&inset, &invalid),
+            Err(EncodingError::InvalidDimensions)
+        ));
+    }
+}
@@ -0,0 +1,392 @@
+use std::num::NonZeroUsize;
+
+use image::{DynamicImage, GenericImageView, RgbaImage};
+
+use crate::{
+    Blend, Disposal, EncodingError, FrameOptions, FrameRect, FrameSink, InputFrame, WebPAnimator,
+    encode::encode_lossless,
+};
+
+/// Adds full-canvas frames to an animation, encoding only the smallest
+/// rectangle that differs from the previous frame.
+///
+/// Frames that are identical to the previous frame are merged into it by
+/// extending its duration, unless disabled with
+/// [`set_merge_unchanged`](Self::set_merge_unchanged).
+pub struct Optimizer {
+    animator: WebPAnimator,
+    previous: Option<RgbaImage>,
+    keyframe_interval: Option<NonZeroUsize>,
+    merge_tolerance: f32,
+    merge_unchanged: bool,
+    /// The number of frames added since the last full-canvas frame,
+    /// including it.
+    since_keyframe: usize,
+    #[cfg(feature = "lossy")]
+    quality: Option<f32>,
+}
+
+impl Optimizer {
+    pub fn new(animator: WebPAnimator) -> Self {
+        Self {
+            animator,
+            previous: None,
+            keyframe_interval: None,
+            merge_tolerance: 0.0,
+            merge_unchanged: true,
+            since_keyframe: 0,
+            #[cfg(feature = "lossy")]
+            quality: None,
+        }
+    }
+
+    /// Merge frames into the previous frame when the mean absolute
+    /// difference of their channels, between 0 and 255, is at most
+    /// `tolerance`.  The previous frame keeps being shown, so small changes
+    /// add up until they exceed the tolerance.  Defaults to 0, which only
+    /// merges identical frames.
+    pub fn set_merge_tolerance(&mut self, tolerance: f32) {
+        self.merge_tolerance = tolerance;
+    }
+
+    /// If `false`, then a frame that is unchanged from the previous one is
+    /// added as a separate frame repeating a single pixel, instead of
+    /// extending the duration of the previous frame, so that every frame
+    /// keeps the duration it was given.  Defaults to `true`.
+    pub fn set_merge_unchanged(&mut self, merge: bool) {
+        self.merge_unchanged = merge;
+    }
+
+    /// Force a full-canvas frame at least every `interval` frames, even when
+    /// encoding only the changed rectangle would be smaller, so that players
+    /// can seek without rendering every frame from the start.  Frames merged
+    /// into the previous frame don't count.  Defaults to `None`.
+    pub fn set_keyframe_interval(&mut self, interval: Option<NonZeroUsize>) {
+        self.keyframe_interval = interval;
+    }
+
+    /// Encode frames lossily using libwebp, with a quality between 0
+    /// (smallest output) and 100 (highest quality), or losslessly if `None`.
+    /// Defaults to `None`.
+    #[cfg(feature = "lossy")]
+    pub fn set_lossy_quality(&mut self, quality: Option<f32>) {
+        self.quality = quality;
+    }
+
+    /// Add a frame to the animation.
+    ///
+    /// * `image` - The frame.  It must be the same size as the canvas.
+    /// * `duration` - The duration in milliseconds.
+    pub fn add_frame(&mut self, image: &RgbaImage, duration: u32) -> Result<(), EncodingError> {
+        self.add_frame_with_options(image, FrameOptions::with_duration(duration))
+    }
+
+    /// Like [`add_frame`](Self::add_frame), but takes the duration from
+    /// `options`, along with the quality and method of lossy encoding if they
+    /// are set.  The frame rectangle, disposal and blending are chosen by the
+    /// optimizer.
+    pub fn add_frame_with_options(
+        &mut self,
+        image: &RgbaImage,
+        options: FrameOptions,
+    ) -> Result<(), EncodingError> {
+        trace_span!(DEBUG, "optimize_frame", index = self.animator.frames.len());
+        self.animator.check_cancelled()?;
+        let duration = options.duration;
+        if image.dimensions() != (self.animator.width, self.animator.height) {
+            return Err(EncodingError::InvalidDimensions);
+        }
+        let near_duplicate;
+        let image = match &self.previous {
+            Some(previous)
+                if self.merge_tolerance > 0.0
+                    && mean_difference(previous, image) <= self.merge_tolerance =>
+            {
+                near_duplicate = previous.clone();
+                &near_duplicate
+            }
+            _ => image,
+        };
+        let full = FrameRect {
+            x: 0,
+            y: 0,
+            width: image.width(),
+            height: image.height(),
+        };
+        let changed = match &self.previous {
+            None => Some(full),
+            Some(previous) => changed_rect(previous, image),
+        };
+        let keyframe_due = self
+            .keyframe_interval
+            .is_some_and(|n| self.since_keyframe >= n.get());
+        let rect = match changed {
+            None if self.merge_unchanged && self.animator.extend_last_duration(duration) => {
+                trace_event!(trace, "frame unchanged, extended the previous frame");
+                return Ok(());
+            }
+            _ if keyframe_due => {
+                trace_event!(trace, "keyframe due, encoding the full canvas");
+                full
+            }
+            Some(rect) => rect,
+            // The previous frame can't be extended, so repeat a pixel of it.
+            None => FrameRect {
+                x: 0,
+                y: 0,
+                width: 1,
+                height: 1,
+            },
+        };
+        let region = DynamicImage::from(
+            image
+                .view(rect.x, rect.y, rect.width, rect.height)
+                .to_image(),
+        );
+        trace_event!(trace, "encoding changed rectangle {rect:?}");
+        let buf = self.encode(&region, &options)?;
+        let options = FrameOptions {
+            rect: Some(rect),
+            duration,
+            dispose: Disposal::None,
+            blend: Blend::Overwrite,
+            ..FrameOptions::default()
+        };
+        self.animator.add_frame(&buf, options)?;
+        self.previous = Some(image.clone());
+        self.since_keyframe = if rect == full {
+            1
+        } else {
+            self.since_keyframe + 1
+        };
+        Ok(())
+    }
+
+    fn encode(
+        &self,
+        image: &DynamicImage,
+        options: &FrameOptions,
+    ) -> Result<Vec<u8>, EncodingError> {
+        #[cfg(feature = "lossy")]
+        if let Some(quality) = options.quality.map(f32::from).or(self.quality) {
+            let method = options.method.unwrap_or(crate::lossy::DEFAULT_METHOD);
+            return crate::lossy::encode_lossy(image, quality, method);
+        }
+        #[cfg(not(feature = "lossy"))]
+        let _ = options;
+        encode_lossless(image)
+    }
+
+    /// Removes all frames with [`WebPAnimator::clear`], and forgets the
+    /// previous frame, so that the optimizer can be reused for another
+    /// animation with the same settings.
+    pub fn clear(&mut self) {
+        self.animator.clear();
+        self.previous = None;
+        self.since_keyframe = 0;
+    }
+
+    pub fn animator(&self) -> &WebPAnimator {
+        &self.animator
+    }
+
+    pub fn into_inner(self) -> WebPAnimator {
+        self.animator
+    }
+}
+
+/// Images are added with [`add_frame`](Optimizer::add_frame), and encoded
+/// frames are added to the animator as they are, so the image after an
+/// encoded frame covers the whole canvas.
+impl FrameSink for Optimizer {
+    fn add_input_frame(&mut self, frame: InputFrame) -> Result<(), EncodingError> {
+        match frame {
+            InputFrame::Image { image, duration } => self.add_frame(&image.into_rgba8(), duration),
+            frame => {
+                self.animator.add_input_frame(frame)?;
+                self.previous = None;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The mean absolute difference of the channels of `a` and `b`.
+fn mean_difference(a: &RgbaImage, b: &RgbaImage) -> f32 {
+    let sum: u64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(&x, &y)| x.abs_diff(y) as u64)
+        .sum();
+    sum as f32 / a.as_raw().len() as f32
+}
+
+/// Returns the smallest rectangle with even offsets containing every pixel
+/// that differs between `a` and `b`, or `None` if they are identical.
+fn changed_rect(a: &RgbaImage, b: &RgbaImage) -> Option<FrameRect> {
+    let (mut x0, mut y0, mut x1, mut y1) = (u32::MAX, u32::MAX, 0, 0);
+    for (x, y, p) in b.enumerate_pixels() {
+        if a.get_pixel(x, y) != p {
+            x0 = x0.min(x);
+            y0 = y0.min(y);
+            x1 = x1.max(x + 1);
+            y1 = y1.max(y + 1);
+        }
+    }
+    if x0 == u32::MAX {
+        return None;
+    }
+    x0 &= !1;
+    y0 &= !1;
+    Some(FrameRect {
+        x: x0,
+        y: y0,
+        width: x1 - x0,
+        height: y1 - y0,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::num::NonZeroUsize;
+
+    use image::{AnimationDecoder, DynamicImage, Rgba, RgbaImage};
+
+    use crate::{
+        Decoder, FrameOptions, FrameRect, FrameSink, Optimizer, WebPAnimator, test_util::params,
+    };
+
+    #[test]
+    fn test_delta_frames() {
+        let params = params(64, 64);
+        let mut optimizer = Optimizer::new(WebPAnimator::new(params).unwrap());
+        let mut img = RgbaImage::from_pixel(64, 64, Rgba([255, 0, 0, 255]));
+        optimizer.add_frame(&img, 100).unwrap();
+        for x in 11..20 {
+            img.put_pixel(x, 31, Rgba([0, 0, 255, 255]));
+        }
+        optimizer.add_frame(&img, 100).unwrap();
+        optimizer.add_frame(&img, 50).unwrap();
+        let mut out = Vec::new();
+        optimizer.into_inner().write(&mut out).unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        let frames = decoder.frames();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(
+            frames[1].rect,
+            FrameRect {
+                x: 10,
+                y: 30,
+                width: 10,
+                height: 2
+            }
+        );
+        assert_eq!(frames[1].duration, 150);
+        let mut libwebp = webp_animation::Decoder::new(&out).unwrap().into_iter();
+        libwebp.next().unwrap();
+        let last = libwebp.next().unwrap();
+        assert_eq!(last.data(), img.as_raw().as_slice());
+    }
+
+    #[test]
+    fn test_merge_tolerance() {
+        let params = params(16, 16);
+        let mut optimizer = Optimizer::new(WebPAnimator::new(params).unwrap());
+        optimizer.set_merge_tolerance(0.15);
+        let mut img = RgbaImage::from_pixel(16, 16, Rgba([100, 100, 100, 255]));
+        optimizer.add_frame(&img, 100).unwrap();
+        // Each change is small, but the second one adds up to more than the
+        // tolerance.
+        img.put_pixel(0, 0, Rgba([200, 100, 100, 255]));
+        optimizer.add_frame(&img, 100).unwrap();
+        img.put_pixel(1, 0, Rgba([200, 100, 100, 255]));
+        optimizer.add_frame(&img, 100).unwrap();
+        let durations: Vec<u32> = optimizer
+            .animator()
+            .frames()
+            .iter()
+            .map(|f| f.duration())
+            .collect();
+        assert_eq!(durations, [200, 100]);
+    }
+
+    #[test]
+    fn test_keyframe_interval() {
+        let params = params(16, 16);
+        let mut optimizer = Optimizer::new(WebPAnimator::new(params).unwrap());
+        optimizer.set_keyframe_interval(NonZeroUsize::new(3));
+        let mut img = RgbaImage::from_pixel(16, 16, Rgba([255, 0, 0, 255]));
+        for i in 0..7 {
+            img.put_pixel(i, i, Rgba([0, 0, 255, 255]));
+            optimizer.add_frame(&img, 100).unwrap();
+            // Merged into the frame before, so not counted.
+            optimizer.add_frame(&img, 100).unwrap();
+        }
+        let full: Vec<bool> = optimizer
+            .animator()
+            .frames()
+            .iter()
+            .map(|f| f.rect().width == 16 && f.rect().height == 16)
+            .collect();
+        assert_eq!(full, [true, false, false, true, false, false, true]);
+    }
+
+    #[cfg(feature = "lossy")]
+    #[test]
+    fn test_lossy_delta_frames() {
+        let params = params(32, 32);
+        let mut optimizer = Optimizer::new(WebPAnimator::new(params).unwrap());
+        optimizer.set_lossy_quality(Some(80.0));
+        let mut img = RgbaImage::from_pixel(32, 32, Rgba([255, 0, 0, 255]));
+        optimizer.add_frame(&img, 100).unwrap();
+        img.put_pixel(5, 5, Rgba([0, 0, 255, 255]));
+        optimizer.add_frame(&img, 100).unwrap();
+        // A lossless frame in an otherwise lossy animation.
+        img.put_pixel(7, 7, Rgba([0, 255, 0, 255]));
+        let options = FrameOptions {
+            quality: Some(100),
+            method: Some(6),
+            ..FrameOptions::with_duration(100)
+        };
+        optimizer.add_frame_with_options(&img, options).unwrap();
+        let animator = optimizer.into_inner();
+        assert!(
+            animator
+                .frames()
+                .iter()
+                .all(|f| f.data().starts_with(b"VP8 "))
+        );
+        let out = animator.into_bytes().unwrap();
+        assert_eq!(
+            webp_animation::Decoder::new(&out)
+                .unwrap()
+                .into_iter()
+                .count(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_add_frames_from() {
+        let params = params(8, 8);
+        let mut writer = WebPAnimator::new(params).unwrap();
+        for color in [[255, 0, 0, 255], [255, 0, 0, 255], [0, 0, 255, 255]] {
+            let image = DynamicImage::from(RgbaImage::from_pixel(8, 8, Rgba(color)));
+            writer
+                .add_image(&image, FrameOptions::with_duration(100))
+                .unwrap();
+        }
+        let data = writer.into_bytes().unwrap();
+        let frames = Decoder::new(&data).unwrap().into_frames();
+        let mut optimizer = Optimizer::new(WebPAnimator::new(params).unwrap());
+        assert_eq!(optimizer.add_frames_from(frames).unwrap(), 3);
+        let durations: Vec<u32> = optimizer
+            .animator()
+            .frames()
+            .iter()
+            .map(|f| f.duration())
+            .collect();
+        assert_eq!(durations, [200, 100]);
+    }
+}
@@ -0,0 +1,139 @@
+use image::{Rgba, RgbaImage};
+
+use crate::{Decoder, EncodingError, Optimizer, WebPAnimator, compositor::blend};
+
+/// A static image, such as a logo or watermark, drawn on top of the frames
+/// of an animation.
+#[derive(Clone, Debug)]
+pub struct Overlay {
+    pub image: RgbaImage,
+    /// The position of the top left corner on the canvas.  Parts outside the
+    /// canvas are clipped.
+    pub x: i64,
+    pub y: i64,
+    /// Between 0 (invisible) and 1 (as opaque as the image).
+    pub opacity: f32,
+    /// When the overlay appears, in milliseconds from the start.
+    pub start_ms: u64,
+    /// When the overlay disappears, or `None` to keep it until the end.
+    pub end_ms: Option<u64>,
+}
+
+impl Overlay {
+    /// An overlay at the given position, fully opaque, for the whole
+    /// animation.
+    pub fn new(image: RgbaImage, x: i64, y: i64) -> Self {
+        Self {
+            image,
+            x,
+            y,
+            opacity: 1.0,
+            start_ms: 0,
+            end_ms: None,
+        }
+    }
+
+    /// Alpha-blends the overlay onto `canvas`.
+    pub fn draw(&self, canvas: &mut RgbaImage) {
+        draw(canvas, &self.image, self.x, self.y, self.opacity);
+    }
+
+    /// Whether the overlay is shown at time `ms`.
+    pub(crate) fn shown_at(&self, ms: u64) -> bool {
+        ms >= self.start_ms && self.end_ms.is_none_or(|end| ms < end)
+    }
+}
+
+/// Alpha-blends `image` onto `canvas` with its top left corner at `(x, y)`,
+/// with its alpha multiplied by `opacity`.
+pub(crate) fn draw(canvas: &mut RgbaImage, image: &RgbaImage, x: i64, y: i64, opacity: f32) {
+    let opacity = opacity.clamp(0.0, 1.0);
+    for (ix, iy, src) in image.enumerate_pixels() {
+        let (Ok(cx), Ok(cy)) = (u32::try_from(x + ix as i64), u32::try_from(y + iy as i64)) else {
+            continue;
+        };
+        let Some(dst) = canvas.get_pixel_mut_checked(cx, cy) else {
+            continue;
+        };
+        let Rgba([r, g, b, a]) = *src;
+        let a = (a as f32 * opacity + 0.5) as u8;
+        *dst = blend(Rgba([r, g, b, a]), *dst);
+    }
+}
+
+impl Decoder<'_> {
+    /// Draws `overlays` on every rendered frame, in order, and re-encodes
+    /// the result losslessly with an [`Optimizer`].  Frames are split where
+    /// an overlay appears or disappears, so the overlays are shown for
+    /// exactly their time range.
+    pub fn overlay(&self, overlays: &[Overlay]) -> Result<WebPAnimator, EncodingError> {
+        let animator = self.animator_builder().detect_alpha(true).build()?;
+        let mut optimizer = Optimizer::new(animator);
+        let mut cuts: Vec<u64> = overlays
+            .iter()
+            .flat_map(|overlay| [Some(overlay.start_ms), overlay.end_ms])
+            .flatten()
+            .collect();
+        cuts.sort_unstable();
+        let mut start = 0;
+        for frame in self.canvas_frames() {
+            let frame = frame?;
+            let end = start + frame.duration as u64;
+            let inner = cuts.iter().copied().filter(|&t| start < t && t < end);
+            let mut from = start;
+            for to in inner.chain([end]) {
+                if to == from {
+                    continue;
+                }
+                let mut image = frame.image.clone();
+                for overlay in overlays.iter().filter(|o| o.shown_at(from)) {
+                    overlay.draw(&mut image);
+                }
+                optimizer.add_frame(&image, (to - from) as u32)?;
+                from = to;
+            }
+            start = end;
+        }
+        Ok(optimizer.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgba, RgbaImage};
+
+    use crate::{Decoder, Optimizer, Overlay, WebPAnimator, test_util::params};
+
+    #[test]
+    fn test_overlay() {
+        let params = params(8, 8);
+        let mut optimizer = Optimizer::new(WebPAnimator::new(params).unwrap());
+        optimizer
+            .add_frame(&RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 255])), 100)
+            .unwrap();
+        optimizer
+            .add_frame(&RgbaImage::from_pixel(8, 8, Rgba([0, 0, 255, 255])), 100)
+            .unwrap();
+        let data = optimizer.into_inner().into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+
+        let logo = Overlay {
+            opacity: 0.5,
+            start_ms: 50,
+            ..Overlay::new(
+                RgbaImage::from_pixel(4, 4, Rgba([255, 255, 255, 255])),
+                -2,
+                6,
+            )
+        };
+        let out = decoder.overlay(&[logo]).unwrap().into_bytes().unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        let durations: Vec<u32> = decoder.frames().iter().map(|f| f.duration).collect();
+        assert_eq!(durations, [50, 50, 100]);
+        let frames: Vec<_> = decoder.canvas_frames().map(Result::unwrap).collect();
+        assert_eq!(frames[0].image.get_pixel(0, 7), &Rgba([255, 0, 0, 255]));
+        assert_eq!(frames[1].image.get_pixel(0, 7), &Rgba([254, 127, 127, 255]));
+        assert_eq!(frames[1].image.get_pixel(2, 7), &Rgba([255, 0, 0, 255]));
+        assert_eq!(frames[2].image.get_pixel(1, 6), &Rgba([127, 127, 254, 255]));
+    }
+}
@@ -0,0 +1,171 @@
+use image::{
+    DynamicImage, Rgba, RgbaImage,
+    imageops::{self, FilterType},
+};
+
+use crate::{EncodingError, FrameRect, LoopCount, Optimizer, Params, WebPAnimator};
+
+/// A camera move over a still image, also known as the Ken Burns effect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PanZoom {
+    /// The part of the image shown in the first frame.
+    pub start: FrameRect,
+    /// The part of the image shown in the last frame.  The rectangle moves
+    /// linearly from `start` to `end`.
+    pub end: FrameRect,
+    /// The size of the canvas.
+    pub width: u32,
+    pub height: u32,
+    /// The total duration in milliseconds.
+    pub duration: u32,
+    pub frames_per_second: u32,
+}
+
+impl WebPAnimator {
+    /// Renders a camera move over `image`, encoding the frames losslessly
+    /// with an [`Optimizer`].  The animation loops forever.  Fails with
+    /// [`EncodingError::InvalidDimensions`] if a rectangle is empty or
+    /// extends past the image, or if the frame rate is 0.
+    ///
+    /// The image is first scaled with a triangle filter so that the smaller
+    /// rectangle is about the size of the canvas, and the frames are then
+    /// sampled bilinearly, so that slow moves are smooth.
+    pub fn from_pan_zoom(image: &DynamicImage, camera: &PanZoom) -> Result<Self, EncodingError> {
+        let inside = |r: FrameRect| {
+            r.width > 0
+                && r.height > 0
+                && r.x as u64 + r.width as u64 <= image.width() as u64
+                && r.y as u64 + r.height as u64 <= image.height() as u64
+        };
+        if !inside(camera.start) || !inside(camera.end) || camera.frames_per_second == 0 {
+            return Err(EncodingError::InvalidDimensions);
+        }
+        let params = Params {
+            width: camera.width,
+            height: camera.height,
+            background_bgra: [0; 4],
+            loop_count: LoopCount::Infinite,
+            has_alpha: false,
+        };
+        let mut animator = WebPAnimator::new(params)?;
+        animator.set_detect_alpha(true);
+        let mut optimizer = Optimizer::new(animator);
+
+        let smallest = |r: FrameRect| r.width as f32 / camera.width as f32;
+        let scale = f32::min(smallest(camera.start), smallest(camera.end)).max(1.0);
+        let scaled = |len: u32| ((len as f32 / scale).round() as u32).max(1);
+        let source = imageops::resize(
+            &image.to_rgba8(),
+            scaled(image.width()),
+            scaled(image.height()),
+            FilterType::Triangle,
+        );
+        let count = (camera.duration as u64 * camera.frames_per_second as u64)
+            .div_ceil(1000)
+            .max(1);
+        let end = |k: u64| (camera.duration as u64 * k / count) as u32;
+        for k in 0..count {
+            let t = match count {
+                1 => 0.0,
+                _ => k as f32 / (count - 1) as f32,
+            };
+            let lerp = |a: u32, b: u32| (a as f32 + (b as f32 - a as f32) * t) / scale;
+            let (start, stop) = (camera.start, camera.end);
+            let view = [
+                lerp(start.x, stop.x),
+                lerp(start.y, stop.y),
+                lerp(start.width, stop.width),
+                lerp(start.height, stop.height),
+            ];
+            let frame = render(&source, view, camera.width, camera.height);
+            optimizer.add_frame(&frame, end(k + 1) - end(k))?;
+        }
+        Ok(optimizer.into_inner())
+    }
+}
+
+/// Samples the rectangle `[x, y, width, height]` of `source` bilinearly onto
+/// a new image of the given size.
+fn render(source: &RgbaImage, [x, y, w, h]: [f32; 4], width: u32, height: u32) -> RgbaImage {
+    let (max_x, max_y) = (source.width() - 1, source.height() - 1);
+    RgbaImage::from_fn(width, height, |i, j| {
+        let sx = (x + (i as f32 + 0.5) * w / width as f32 - 0.5).clamp(0.0, max_x as f32);
+        let sy = (y + (j as f32 + 0.5) * h / height as f32 - 0.5).clamp(0.0, max_y as f32);
+        let (x0, y0) = (sx as u32, sy as u32);
+        let (x1, y1) = ((x0 + 1).min(max_x), (y0 + 1).min(max_y));
+        let (fx, fy) = (sx - x0 as f32, sy - y0 as f32);
+        let p = [
+            source.get_pixel(x0, y0),
+            source.get_pixel(x1, y0),
+            source.get_pixel(x0, y1),
+            source.get_pixel(x1, y1),
+        ];
+        let weights = [
+            (1.0 - fx) * (1.0 - fy),
+            fx * (1.0 - fy),
+            (1.0 - fx) * fy,
+            fx * fy,
+        ];
+        let channel = |c: usize| {
+            let sum: f32 = p.iter().zip(weights).map(|(p, w)| p[c] as f32 * w).sum();
+            sum.round() as u8
+        };
+        Rgba([channel(0), channel(1), channel(2), channel(3)])
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    use crate::{Decoder, EncodingError, FrameRect, PanZoom, WebPAnimator};
+
+    #[test]
+    fn test_from_pan_zoom() {
+        // The left half is red and the right half is blue.
+        let image = RgbaImage::from_fn(32, 16, |x, _| match x {
+            0..16 => Rgba([255, 0, 0, 255]),
+            _ => Rgba([0, 0, 255, 255]),
+        });
+        let image = DynamicImage::from(image);
+        let mut camera = PanZoom {
+            start: FrameRect {
+                x: 0,
+                y: 0,
+                width: 16,
+                height: 16,
+            },
+            end: FrameRect {
+                x: 16,
+                y: 0,
+                width: 16,
+                height: 16,
+            },
+            width: 8,
+            height: 8,
+            duration: 1000,
+            frames_per_second: 10,
+        };
+        let out = WebPAnimator::from_pan_zoom(&image, &camera)
+            .unwrap()
+            .into_bytes()
+            .unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (8, 8));
+        let frames: Vec<_> = decoder.canvas_frames().map(Result::unwrap).collect();
+        assert_eq!(frames.len(), 10);
+        assert!(frames.iter().all(|f| f.duration == 100));
+        assert_eq!(frames[0].image.get_pixel(0, 4), &Rgba([255, 0, 0, 255]));
+        assert_eq!(frames[9].image.get_pixel(7, 4), &Rgba([0, 0, 255, 255]));
+        // Halfway along, the view straddles the edge between the halves.
+        let middle = &frames[5].image;
+        assert_eq!(middle.get_pixel(0, 4), &Rgba([255, 0, 0, 255]));
+        assert_eq!(middle.get_pixel(7, 4), &Rgba([0, 0, 255, 255]));
+
+        camera.end.x = 17;
+        assert!(matches!(
+            WebPAnimator::from_pan_zoom(&image, &camera),
+            Err(EncodingError::InvalidDimensions)
+        ));
+    }
+}
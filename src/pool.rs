@@ -0,0 +1,63 @@
+use std::sync::{Mutex, PoisonError};
+
+use crate::{EncodingError, WebPAnimator};
+
+/// A pool of output buffers that can be shared between threads, so that
+/// services encoding many animations can reuse the memory of earlier ones
+/// instead of allocating a new buffer each time.
+#[derive(Debug, Default)]
+pub struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    max_buffers: usize,
+}
+
+impl BufferPool {
+    /// A pool that keeps up to `max_buffers` returned buffers.
+    pub fn new(max_buffers: usize) -> Self {
+        Self {
+            buffers: Mutex::new(Vec::new()),
+            max_buffers,
+        }
+    }
+
+    /// Returns an empty buffer, reusing a returned one if there is one.
+    pub fn take(&self) -> Vec<u8> {
+        self.lock().pop().unwrap_or_default()
+    }
+
+    /// Returns a buffer to the pool, or drops it if the pool is full.
+    pub fn give(&self, mut buf: Vec<u8>) {
+        buf.clear();
+        let mut buffers = self.lock();
+        if buffers.len() < self.max_buffers && buf.capacity() > 0 {
+            buffers.push(buf);
+        }
+    }
+
+    /// The number of buffers waiting to be reused.
+    pub fn available(&self) -> usize {
+        self.lock().len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Vec<u8>>> {
+        self.buffers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl WebPAnimator {
+    /// Like [`into_bytes`](Self::into_bytes), but writes into a buffer from
+    /// `pool` and leaves the animator in place, so that it can be
+    /// [`clear`](Self::clear)ed and reused.  Give the buffer back with
+    /// [`BufferPool::give`] when done with it.
+    pub fn to_pooled_bytes(&mut self, pool: &BufferPool) -> Result<Vec<u8>, EncodingError> {
+        let mut buf = pool.take();
+        buf.reserve(self.estimated_size());
+        match self.write(&mut buf) {
+            Ok(()) => Ok(buf),
+            Err(e) => {
+                pool.give(buf);
+                Err(e)
+            }
+        }
+    }
+}
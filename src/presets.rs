@@ -0,0 +1,370 @@
+//! The limits that messaging apps place on animated stickers and emoji.
+//!
+//! The limits are the ones documented at the time of writing, and may
+//! change.  A [`Preset`] can be built by hand for other targets.
+
+use crate::{Decoder, EncodingError, LoopCount, Params, Retime, Rounding, WebPAnimator};
+
+/// The allowed canvas sizes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum CanvasLimit {
+    Exact {
+        width: u32,
+        height: u32,
+    },
+    /// One side must be exactly this long, and the other at most this long.
+    LongSide(u32),
+    AtMost {
+        width: u32,
+        height: u32,
+    },
+}
+
+impl CanvasLimit {
+    fn allows(self, width: u32, height: u32) -> bool {
+        match self {
+            Self::Exact {
+                width: w,
+                height: h,
+            } => (width, height) == (w, h),
+            Self::LongSide(n) => width.max(height) == n,
+            Self::AtMost {
+                width: w,
+                height: h,
+            } => width <= w && height <= h,
+        }
+    }
+
+    /// The largest allowed canvas.
+    fn size(self) -> (u32, u32) {
+        match self {
+            Self::Exact { width, height } | Self::AtMost { width, height } => (width, height),
+            Self::LongSide(n) => (n, n),
+        }
+    }
+}
+
+/// A set of constraints on an animation.
+///
+/// With the `serde` feature, presets can be serialized and deserialized.
+/// Since `name` is a `&'static str`, deserializing a preset whose name isn't
+/// that of a built-in preset leaks the name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Preset {
+    pub name: &'static str,
+    pub canvas: CanvasLimit,
+    /// The largest allowed file size in bytes.
+    pub max_bytes: usize,
+    /// The longest allowed total duration in milliseconds.
+    pub max_duration: Option<u64>,
+    /// The shortest allowed frame duration in milliseconds, which limits
+    /// the frame rate.
+    pub min_frame_duration: u32,
+    /// The number of times the animation must play.
+    pub loop_count: Option<LoopCount>,
+}
+
+/// Telegram video stickers: 512 pixels on the long side, at most 256 KiB,
+/// 3 seconds and 30 frames per second, looping forever.
+pub const TELEGRAM_STICKER: Preset = Preset {
+    name: "Telegram sticker",
+    canvas: CanvasLimit::LongSide(512),
+    max_bytes: 256 * 1024,
+    max_duration: Some(3000),
+    min_frame_duration: 33,
+    loop_count: Some(LoopCount::Infinite),
+};
+
+/// WhatsApp animated stickers: exactly 512×512, at most 500 KiB and 10
+/// seconds, with frames of at least 8 ms, looping forever.
+pub const WHATSAPP_STICKER: Preset = Preset {
+    name: "WhatsApp sticker",
+    canvas: CanvasLimit::Exact {
+        width: 512,
+        height: 512,
+    },
+    max_bytes: 500 * 1024,
+    max_duration: Some(10000),
+    min_frame_duration: 8,
+    loop_count: Some(LoopCount::Infinite),
+};
+
+/// Discord emoji: at most 128×128 and 256 KiB.
+pub const DISCORD_EMOJI: Preset = Preset {
+    name: "Discord emoji",
+    canvas: CanvasLimit::AtMost {
+        width: 128,
+        height: 128,
+    },
+    max_bytes: 256 * 1024,
+    max_duration: None,
+    min_frame_duration: 0,
+    loop_count: None,
+};
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Preset {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct Fields {
+            name: String,
+            canvas: CanvasLimit,
+            max_bytes: usize,
+            max_duration: Option<u64>,
+            min_frame_duration: u32,
+            loop_count: Option<LoopCount>,
+        }
+
+        let fields = Fields::deserialize(deserializer)?;
+        let name = [TELEGRAM_STICKER, WHATSAPP_STICKER, DISCORD_EMOJI]
+            .into_iter()
+            .map(|preset| preset.name)
+            .find(|&name| name == fields.name)
+            .unwrap_or_else(|| fields.name.leak());
+        Ok(Self {
+            name,
+            canvas: fields.canvas,
+            max_bytes: fields.max_bytes,
+            max_duration: fields.max_duration,
+            min_frame_duration: fields.min_frame_duration,
+            loop_count: fields.loop_count,
+        })
+    }
+}
+
+/// A constraint of a [`Preset`] that an animation doesn't meet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Violation {
+    Canvas {
+        width: u32,
+        height: u32,
+    },
+    Size {
+        bytes: usize,
+        max: usize,
+    },
+    Duration {
+        ms: u64,
+        max: u64,
+    },
+    /// `count` frames are shorter than `min` milliseconds.
+    FrameDuration {
+        count: usize,
+        min: u32,
+    },
+    LoopCount {
+        found: LoopCount,
+        required: LoopCount,
+    },
+}
+
+impl core::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Canvas { width, height } => write!(f, "canvas size {width}x{height} not allowed"),
+            Self::Size { bytes, max } => write!(f, "{bytes} bytes exceeds the limit of {max}"),
+            Self::Duration { ms, max } => write!(f, "{ms} ms exceeds the limit of {max} ms"),
+            Self::FrameDuration { count, min } => {
+                write!(f, "{count} frames are shorter than {min} ms")
+            }
+            Self::LoopCount { found, required } => {
+                write!(f, "loop count {found:?} should be {required:?}")
+            }
+        }
+    }
+}
+
+impl Preset {
+    /// Parameters for an animation with the largest allowed canvas and the
+    /// required loop count.  The background is transparent.
+    pub fn params(&self) -> Params {
+        let (width, height) = self.canvas.size();
+        Params {
+            width,
+            height,
+            background_bgra: [0; 4],
+            loop_count: self.loop_count.unwrap_or_default(),
+            has_alpha: true,
+        }
+    }
+
+    /// Checks an encoded animation, failing with
+    /// [`EncodingError::Constraints`] listing every unmet constraint.
+    pub fn check(&self, data: &[u8]) -> Result<(), EncodingError> {
+        let decoder = Decoder::new(data)?;
+        let durations: Vec<u32> = decoder.frames().iter().map(|f| f.duration).collect();
+        self.report(
+            (decoder.width(), decoder.height()),
+            data.len(),
+            &durations,
+            decoder.loop_count(),
+        )
+    }
+
+    /// Sets the loop count, lengthens frames that are too short, and speeds
+    /// the animation up if it is too long, then checks the result as in
+    /// [`check`](Self::check).  The canvas size and file size are not
+    /// adjusted; with the `image` feature, `Decoder::resize` and `Optimizer`
+    /// can help with those.
+    pub fn adjust(&self, animator: &mut WebPAnimator) -> Result<(), EncodingError> {
+        if let Some(loop_count) = self.loop_count {
+            animator.loop_count = loop_count.into();
+        }
+        let mut retime = Retime {
+            factor: 1.0,
+            rounding: Rounding::Down,
+            min_duration: self.min_frame_duration,
+        };
+        animator.retime(retime)?;
+        if let Some(max) = self.max_duration {
+            // Frames raised to the minimum duration can keep the total above
+            // the limit, so repeat until it stops shrinking.
+            let mut total = animator.total_duration().as_millis() as u64;
+            while total > max {
+                retime.factor = max as f64 / total as f64;
+                animator.retime(retime)?;
+                let shorter = animator.total_duration().as_millis() as u64;
+                if shorter == total {
+                    break;
+                }
+                total = shorter;
+            }
+        }
+        let durations: Vec<u32> = animator.frames.iter().map(|f| f.duration).collect();
+        self.report(
+            (animator.width, animator.height),
+            animator.estimated_size(),
+            &durations,
+            animator.loop_count.into(),
+        )
+    }
+
+    fn report(
+        &self,
+        (width, height): (u32, u32),
+        bytes: usize,
+        durations: &[u32],
+        loop_count: LoopCount,
+    ) -> Result<(), EncodingError> {
+        let mut violations = Vec::new();
+        if !self.canvas.allows(width, height) {
+            violations.push(Violation::Canvas { width, height });
+        }
+        if bytes > self.max_bytes {
+            violations.push(Violation::Size {
+                bytes,
+                max: self.max_bytes,
+            });
+        }
+        let ms = durations.iter().map(|&d| d as u64).sum();
+        if let Some(max) = self.max_duration.filter(|&max| ms > max) {
+            violations.push(Violation::Duration { ms, max });
+        }
+        let min = self.min_frame_duration;
+        let count = durations.iter().filter(|&&d| d < min).count();
+        if count > 0 {
+            violations.push(Violation::FrameDuration { count, min });
+        }
+        if let Some(required) = self.loop_count.filter(|&l| l != loop_count) {
+            violations.push(Violation::LoopCount {
+                found: loop_count,
+                required,
+            });
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(EncodingError::Constraints(violations))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgb, RgbImage};
+
+    use super::{DISCORD_EMOJI, TELEGRAM_STICKER, Violation};
+    use crate::{EncodingError, LoopCount, Params, WebPAnimator, test_util::lossless_webp};
+
+    #[test]
+    fn test_presets() {
+        let params = TELEGRAM_STICKER.params();
+        assert_eq!((params.width, params.height), (512, 512));
+        let mut animator = WebPAnimator::new(Params {
+            loop_count: LoopCount::from(2),
+            ..params
+        })
+        .unwrap();
+        let frame = lossless_webp(&RgbImage::from_pixel(512, 512, Rgb([255, 0, 0])));
+        animator.add_webp_image(&frame, None, 10).unwrap();
+        animator.add_webp_image(&frame, None, 4000).unwrap();
+        let data = animator.into_bytes().unwrap();
+        let Err(EncodingError::Constraints(violations)) = TELEGRAM_STICKER.check(&data) else {
+            panic!("expected violations");
+        };
+        assert_eq!(
+            violations,
+            [
+                Violation::Duration {
+                    ms: 4010,
+                    max: 3000
+                },
+                Violation::FrameDuration { count: 1, min: 33 },
+                Violation::LoopCount {
+                    found: LoopCount::from(2),
+                    required: LoopCount::Infinite
+                },
+            ]
+        );
+
+        let mut animator = WebPAnimator::new(params).unwrap();
+        animator.append_animation(&data).unwrap();
+        TELEGRAM_STICKER.adjust(&mut animator).unwrap();
+        assert!(animator.total_duration().as_millis() <= 3000);
+        assert!(animator.frames().iter().all(|f| f.duration() >= 33));
+        let Err(EncodingError::Constraints(violations)) = DISCORD_EMOJI.adjust(&mut animator)
+        else {
+            panic!("expected violations");
+        };
+        assert_eq!(
+            violations,
+            [Violation::Canvas {
+                width: 512,
+                height: 512
+            }]
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        use super::{CanvasLimit, Preset};
+
+        let json = serde_json::to_string(&TELEGRAM_STICKER).unwrap();
+        assert_eq!(
+            json,
+            r#"{"name":"Telegram sticker","canvas":{"long_side":512},"max_bytes":262144,"max_duration":3000,"min_frame_duration":33,"loop_count":0}"#
+        );
+        let preset: Preset = serde_json::from_str(&json).unwrap();
+        assert_eq!(preset, TELEGRAM_STICKER);
+        // The built-in name is reused rather than leaked.
+        assert!(std::ptr::eq(preset.name, TELEGRAM_STICKER.name));
+
+        let json = r#"{"name":"Banner","canvas":{"at_most":{"width":640,"height":128}},"max_bytes":1000,"max_duration":null,"min_frame_duration":20,"loop_count":3}"#;
+        let preset: Preset = serde_json::from_str(json).unwrap();
+        assert_eq!(preset.name, "Banner");
+        assert_eq!(
+            preset.canvas,
+            CanvasLimit::AtMost {
+                width: 640,
+                height: 128
+            }
+        );
+        assert_eq!(preset.loop_count, Some(LoopCount::from(3)));
+    }
+}
@@ -0,0 +1,115 @@
+/// What a writer is doing when it reports [`Progress`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// A frame has been encoded and added.  A
+    /// [`WebPStreamWriter`](crate::WebPStreamWriter) writes each frame out as
+    /// it is added.
+    Adding,
+    /// A [`WebPAnimator`](crate::WebPAnimator) or
+    /// [`WebPSpoolWriter`](crate::WebPSpoolWriter) is writing out the
+    /// frames.
+    Writing,
+    /// The output is complete.
+    Finished,
+}
+
+/// A snapshot of the work done by a writer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    pub stage: Stage,
+    /// The number of frames added so far, or written so far while
+    /// [`Stage::Writing`].
+    pub frames: usize,
+    /// The number of frames that will be written, once it is known.
+    pub total_frames: Option<usize>,
+    /// The number of bytes passed to the underlying writer so far.
+    pub bytes_written: u64,
+    /// The final size of the output, once it is known.
+    pub total_bytes: Option<u64>,
+}
+
+/// Receives [`Progress`] reports, for example to update a progress bar.
+/// Implemented for closures taking a `&Progress`.
+pub trait ProgressSink: Send + Sync {
+    fn progress(&mut self, progress: &Progress);
+}
+
+impl<F: FnMut(&Progress) + Send + Sync> ProgressSink for F {
+    fn progress(&mut self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+/// Sends `progress` to `sink`, if there is one.
+pub(crate) fn report(sink: &mut Option<Box<dyn ProgressSink>>, progress: Progress) {
+    if let Some(sink) = sink {
+        sink.progress(&progress);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::Cursor,
+        sync::{Arc, Mutex},
+    };
+
+    use image::{Rgb, RgbImage};
+
+    use crate::{
+        Progress, Stage, WebPAnimator, WebPStreamWriter,
+        test_util::{lossless_webp, params},
+    };
+
+    #[test]
+    fn test_progress() {
+        let params = params(16, 16);
+        let mut frames = Vec::new();
+        for color in [[255, 0, 0], [0, 0, 255]] {
+            let buf = lossless_webp(&RgbImage::from_pixel(16, 16, Rgb(color)));
+            frames.push(buf);
+        }
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = {
+            let reports = reports.clone();
+            move |p: &Progress| reports.lock().unwrap().push(*p)
+        };
+        let summary = |reports: &Mutex<Vec<Progress>>| -> Vec<_> {
+            let reports = std::mem::take(&mut *reports.lock().unwrap());
+            reports
+                .iter()
+                .map(|p| (p.stage, p.frames, p.bytes_written, p.total_bytes))
+                .collect()
+        };
+
+        let mut writer = WebPAnimator::new(params).unwrap();
+        writer.set_progress_sink(Some(Box::new(sink.clone())));
+        for frame in &frames {
+            writer.add_webp_image(frame, None, 100).unwrap();
+        }
+        let mut out = Vec::new();
+        writer.write(&mut out).unwrap();
+        let len = out.len() as u64;
+        assert_eq!(
+            summary(&reports),
+            [
+                (Stage::Adding, 1, 0, None),
+                (Stage::Adding, 2, 0, None),
+                (Stage::Writing, 0, 0, Some(len)),
+                (Stage::Finished, 2, len, Some(len)),
+            ]
+        );
+
+        let mut stream = WebPStreamWriter::new(Cursor::new(Vec::new()), params).unwrap();
+        stream.set_progress_sink(Some(Box::new(sink)));
+        for frame in &frames {
+            stream.add_webp_image(frame, None, 100).unwrap();
+        }
+        assert_eq!(stream.finish().unwrap().into_inner(), out);
+        let reports = summary(&reports);
+        assert_eq!(reports.len(), 3);
+        assert_eq!(reports[0].0, Stage::Adding);
+        assert!(reports[0].2 < reports[1].2 && reports[1].2 == len);
+        assert_eq!(reports[2], (Stage::Finished, 2, len, Some(len)));
+    }
+}
@@ -0,0 +1,297 @@
+//! Python bindings, for asset tooling written in Python.  Build a wheel
+//! with `maturin build --release`, which enables this module and the GIF
+//! and APNG converters as set in `pyproject.toml`.
+//!
+//! ```python
+//! import webp_animator
+//!
+//! animator = webp_animator.Animator(64, 64)
+//! animator.add_rgba_frame(pixels, 64, 64, 100)
+//! open("out.webp", "wb").write(animator.to_bytes())
+//!
+//! decoder = webp_animator.Decoder(open("in.webp", "rb").read())
+//! for rgba, duration in decoder.frames():
+//!     ...
+//! ```
+//!
+//! Errors are raised as `webp_animator.WebPAnimatorError`.
+
+use pyo3::{exceptions::PyException, prelude::*, types::PyBytes};
+
+use crate::{EncodingError, FrameOptions, WebPAnimator};
+
+pyo3::create_exception!(webp_animator, WebPAnimatorError, PyException);
+
+fn py_error(error: EncodingError) -> PyErr {
+    WebPAnimatorError::new_err(error.to_string())
+}
+
+/// An animation being assembled.
+#[pyclass(name = "Animator", module = "webp_animator")]
+pub struct PyAnimator {
+    animator: WebPAnimator,
+}
+
+#[pymethods]
+impl PyAnimator {
+    /// Creates an animation with the given canvas size, played `loop_count`
+    /// times, where 0 means forever.  Whether it has alpha is detected from
+    /// the frames unless `has_alpha` is true.
+    #[new]
+    #[pyo3(signature = (width, height, loop_count = 0, background_bgra = [0; 4], has_alpha = false))]
+    fn new(
+        width: u32,
+        height: u32,
+        loop_count: u16,
+        background_bgra: [u8; 4],
+        has_alpha: bool,
+    ) -> PyResult<Self> {
+        let animator = WebPAnimator::builder()
+            .width(width)
+            .height(height)
+            .loop_count(loop_count)
+            .background(background_bgra)
+            .has_alpha(has_alpha)
+            .detect_alpha(!has_alpha)
+            .build()
+            .map_err(py_error)?;
+        Ok(Self { animator })
+    }
+
+    /// Adds an encoded WebP image as a frame shown for `duration`
+    /// milliseconds.
+    fn add_frame(&mut self, data: &[u8], duration: u32) -> PyResult<()> {
+        self.animator
+            .add_frame(data, FrameOptions::with_duration(duration))
+            .map_err(py_error)
+    }
+
+    /// Encodes RGBA pixels, row by row, losslessly and adds them as a frame
+    /// shown for `duration` milliseconds.
+    fn add_rgba_frame(
+        &mut self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        duration: u32,
+    ) -> PyResult<()> {
+        let options = FrameOptions::with_duration(duration);
+        self.animator
+            .add_rgba_frame(pixels, width, height, options)
+            .map_err(py_error)
+    }
+
+    fn set_icc_profile(&mut self, icc_profile: &[u8]) -> PyResult<()> {
+        self.animator
+            .set_icc_profile(icc_profile.to_vec())
+            .map_err(py_error)
+    }
+
+    fn set_exif_metadata(&mut self, exif_metadata: &[u8]) -> PyResult<()> {
+        self.animator
+            .set_exif_metadata(exif_metadata.to_vec())
+            .map_err(py_error)
+    }
+
+    fn set_xmp_metadata(&mut self, xmp_metadata: &[u8]) -> PyResult<()> {
+        self.animator
+            .set_xmp_metadata(xmp_metadata.to_vec())
+            .map_err(py_error)
+    }
+
+    #[getter]
+    fn frame_count(&self) -> usize {
+        self.animator.frame_count()
+    }
+
+    /// Returns the animated WebP file.
+    #[pyo3(name = "to_bytes")]
+    fn bytes<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let mut data = Vec::new();
+        self.animator.write(&mut data).map_err(py_error)?;
+        Ok(PyBytes::new(py, &data))
+    }
+
+    /// Writes the animated WebP file to `path`.
+    fn write(&mut self, path: std::path::PathBuf) -> PyResult<()> {
+        self.animator.write_to_file(path).map_err(py_error)
+    }
+}
+
+/// A decoded animation.
+#[pyclass(name = "Decoder", module = "webp_animator")]
+pub struct PyDecoder {
+    data: Vec<u8>,
+}
+
+impl PyDecoder {
+    fn decoder(&self) -> crate::Decoder<'_> {
+        // The data was checked by `new`.
+        crate::Decoder::new(&self.data).unwrap()
+    }
+}
+
+#[pymethods]
+impl PyDecoder {
+    /// Parses an animated WebP file.  Still images are rejected with
+    /// `WebPAnimatorError`.
+    #[new]
+    fn new(data: &[u8]) -> PyResult<Self> {
+        crate::Decoder::new(data).map_err(|e| py_error(e.into()))?;
+        Ok(Self {
+            data: data.to_vec(),
+        })
+    }
+
+    #[getter]
+    fn width(&self) -> u32 {
+        self.decoder().width()
+    }
+
+    #[getter]
+    fn height(&self) -> u32 {
+        self.decoder().height()
+    }
+
+    /// The number of times to play the animation, where 0 means forever.
+    #[getter]
+    fn loop_count(&self) -> u16 {
+        self.decoder().loop_count().into()
+    }
+
+    #[getter]
+    fn background_bgra(&self) -> [u8; 4] {
+        self.decoder().background_bgra()
+    }
+
+    /// The duration of each frame in milliseconds.
+    #[getter]
+    fn durations(&self) -> Vec<u32> {
+        let decoder = self.decoder();
+        decoder.frames().iter().map(|f| f.duration).collect()
+    }
+
+    #[getter]
+    fn icc_profile<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyBytes>> {
+        self.decoder()
+            .icc_profile()
+            .map(|data| PyBytes::new(py, data))
+    }
+
+    #[getter]
+    fn exif_metadata<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyBytes>> {
+        let decoder = self.decoder();
+        decoder.exif_metadata().map(|data| PyBytes::new(py, data))
+    }
+
+    #[getter]
+    fn xmp_metadata<'py>(&self, py: Python<'py>) -> Option<Bound<'py, PyBytes>> {
+        let decoder = self.decoder();
+        decoder.xmp_metadata().map(|data| PyBytes::new(py, data))
+    }
+
+    /// Renders the frames, returning the RGBA pixels of the canvas while
+    /// each is shown and its duration.
+    fn frames<'py>(&self, py: Python<'py>) -> PyResult<Vec<(Bound<'py, PyBytes>, u32)>> {
+        self.decoder()
+            .canvas_frames()
+            .map(|frame| {
+                let frame = frame.map_err(|e| py_error(e.into()))?;
+                Ok((PyBytes::new(py, frame.image.as_raw()), frame.duration))
+            })
+            .collect()
+    }
+
+    /// Renders the RGBA pixels of the canvas `ms` milliseconds in.
+    fn frame_at<'py>(&self, py: Python<'py>, ms: u64) -> PyResult<Bound<'py, PyBytes>> {
+        let image = self.decoder().frame_at(ms).map_err(py_error)?;
+        Ok(PyBytes::new(py, image.as_raw()))
+    }
+
+    /// Renders the animation as a GIF.
+    #[cfg(feature = "gif")]
+    fn to_gif<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let data = self.decoder().to_gif(Vec::new()).map_err(py_error)?;
+        Ok(PyBytes::new(py, &data))
+    }
+
+    /// Writes the animation as an APNG.
+    #[cfg(feature = "apng")]
+    fn to_apng<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let data = self.decoder().to_apng(Vec::new()).map_err(py_error)?;
+        Ok(PyBytes::new(py, &data))
+    }
+}
+
+/// Converts a GIF animation.
+#[cfg(feature = "gif")]
+#[pyfunction]
+fn from_gif(data: &[u8]) -> PyResult<PyAnimator> {
+    let animator = WebPAnimator::from_gif(data).map_err(py_error)?;
+    Ok(PyAnimator { animator })
+}
+
+/// Converts an APNG animation.
+#[cfg(feature = "apng")]
+#[pyfunction]
+fn from_apng(data: &[u8]) -> PyResult<PyAnimator> {
+    let animator = WebPAnimator::from_apng(std::io::Cursor::new(data)).map_err(py_error)?;
+    Ok(PyAnimator { animator })
+}
+
+#[pymodule]
+fn webp_animator(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyAnimator>()?;
+    m.add_class::<PyDecoder>()?;
+    m.add("WebPAnimatorError", m.py().get_type::<WebPAnimatorError>())?;
+    #[cfg(feature = "gif")]
+    m.add_function(wrap_pyfunction!(from_gif, m)?)?;
+    #[cfg(feature = "apng")]
+    m.add_function(wrap_pyfunction!(from_apng, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgba, RgbaImage};
+    use pyo3::{ffi::c_str, prelude::*, types::PyBytes};
+
+    use crate::test_util::lossless_webp;
+
+    #[test]
+    fn test_python() {
+        Python::initialize();
+        Python::attach(|py| {
+            let module = PyModule::new(py, "webp_animator").unwrap();
+            super::webp_animator(&module).unwrap();
+            let globals = pyo3::types::PyDict::new(py);
+            globals.set_item("webp_animator", module).unwrap();
+            let still = lossless_webp(&RgbaImage::from_pixel(2, 1, Rgba([255, 0, 0, 255])));
+            globals.set_item("still", PyBytes::new(py, &still)).unwrap();
+            let code = c_str!(
+                r#"
+animator = webp_animator.Animator(2, 1, loop_count=3)
+animator.add_rgba_frame(bytes([255, 0, 0, 255] * 2), 2, 1, 100)
+animator.set_xmp_metadata(b"<x:xmpmeta/>")
+assert animator.frame_count == 1
+try:
+    animator.add_frame(b"RIFF", 100)
+    raise AssertionError
+except webp_animator.WebPAnimatorError as e:
+    assert str(e) == "not a WebP image", e
+decoder = webp_animator.Decoder(animator.to_bytes())
+assert (decoder.width, decoder.height, decoder.loop_count) == (2, 1, 3)
+assert decoder.durations == [100]
+assert decoder.xmp_metadata == b"<x:xmpmeta/>"
+assert decoder.frames() == [(bytes([255, 0, 0, 255] * 2), 100)]
+try:
+    webp_animator.Decoder(still)
+    raise AssertionError
+except webp_animator.WebPAnimatorError as e:
+    assert str(e) == "not an animated WebP image", e
+"#
+            );
+            py.run(code, Some(&globals), None).unwrap();
+        });
+    }
+}
@@ -0,0 +1,212 @@
+use image::RgbaImage;
+
+use crate::{CanvasFrame, Decoder, EncodingError, montage::Track};
+
+/// How closely a frame matches its original.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameQuality {
+    /// The peak signal-to-noise ratio in decibels, or infinity if the frames
+    /// are identical.  Above 40 is usually hard to tell apart.
+    pub psnr: f64,
+    /// The structural similarity of the luma, which is 1 if the frames are
+    /// identical and lower the more they differ.
+    pub ssim: f64,
+}
+
+/// How closely an animation matches the frames it was made from.  See
+/// [`Decoder::quality`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quality {
+    pub frames: Vec<FrameQuality>,
+    /// The PSNR of the mean squared error over all frames.
+    pub psnr: f64,
+    /// The mean SSIM of the frames.
+    pub ssim: f64,
+}
+
+/// Compares `encoded` with `original`.  Colors are premultiplied by alpha,
+/// so that the colors of transparent pixels, which lossy encoding doesn't
+/// keep, don't count.  The PSNR covers all four channels, and the SSIM is
+/// computed over 8×8 windows.  Fails with
+/// [`EncodingError::InvalidDimensions`] if the images differ in size.
+pub fn frame_quality(
+    original: &RgbaImage,
+    encoded: &RgbaImage,
+) -> Result<FrameQuality, EncodingError> {
+    let (squared_error, ssim) = compare(original, encoded)?;
+    Ok(FrameQuality {
+        psnr: psnr(squared_error, original.as_raw().len()),
+        ssim,
+    })
+}
+
+impl Decoder<'_> {
+    /// Compares the rendered animation with the frames it was made from,
+    /// for example to pick the lowest lossy quality that looks good enough.
+    ///
+    /// Each original is compared with the canvas shown at its start time, so
+    /// frames that were merged by an [`Optimizer`](crate::Optimizer) or
+    /// split by an overlay don't throw off the comparison.  Fails with
+    /// [`EncodingError::NoFrames`] if either has no frames, and with
+    /// [`EncodingError::InvalidDimensions`] if an original isn't the size of
+    /// the canvas.
+    pub fn quality(&self, originals: &[CanvasFrame]) -> Result<Quality, EncodingError> {
+        let rendered = self.canvas_frames().collect::<Result<Vec<_>, _>>()?;
+        if originals.is_empty() || rendered.is_empty() {
+            return Err(EncodingError::NoFrames);
+        }
+        let track = Track::new(&rendered, RgbaImage::clone);
+        let mut frames = Vec::with_capacity(originals.len());
+        let (mut squared_error, mut samples, mut ssim) = (0.0, 0, 0.0);
+        let mut start = 0;
+        for original in originals {
+            let encoded = track.frame_at(start, false);
+            let (error, frame_ssim) = compare(&original.image, encoded)?;
+            let len = original.image.as_raw().len();
+            frames.push(FrameQuality {
+                psnr: psnr(error, len),
+                ssim: frame_ssim,
+            });
+            (squared_error, samples, ssim) =
+                (squared_error + error, samples + len, ssim + frame_ssim);
+            start += original.duration as u64;
+        }
+        Ok(Quality {
+            psnr: psnr(squared_error, samples),
+            ssim: ssim / originals.len() as f64,
+            frames,
+        })
+    }
+}
+
+/// The PSNR of 8-bit samples with the given total squared error.
+fn psnr(squared_error: f64, samples: usize) -> f64 {
+    if squared_error == 0.0 {
+        return f64::INFINITY;
+    }
+    10.0 * (255.0 * 255.0 * samples as f64 / squared_error).log10()
+}
+
+/// The total squared error of the premultiplied samples, and the SSIM of the
+/// luma.
+fn compare(a: &RgbaImage, b: &RgbaImage) -> Result<(f64, f64), EncodingError> {
+    if a.dimensions() != b.dimensions() {
+        return Err(EncodingError::InvalidDimensions);
+    }
+    let premultiplied = |image: &RgbaImage| -> Vec<[f64; 4]> {
+        image
+            .pixels()
+            .map(|p| {
+                let [r, g, b, a] = p.0.map(f64::from);
+                let alpha = a / 255.0;
+                [r * alpha, g * alpha, b * alpha, a]
+            })
+            .collect()
+    };
+    let (pa, pb) = (premultiplied(a), premultiplied(b));
+    let squared_error = pa
+        .iter()
+        .zip(&pb)
+        .flat_map(|(x, y)| x.iter().zip(y).map(|(s, t)| (s - t) * (s - t)))
+        .sum();
+    let luma = |pixels: &[[f64; 4]]| -> Vec<f64> {
+        pixels
+            .iter()
+            .map(|[r, g, b, _]| 0.299 * r + 0.587 * g + 0.114 * b)
+            .collect()
+    };
+    let (width, height) = a.dimensions();
+    let ssim = ssim(&luma(&pa), &luma(&pb), width as usize, height as usize);
+    Ok((squared_error, ssim))
+}
+
+/// The mean SSIM of 8×8 windows, or smaller ones for small images, every 4
+/// pixels.
+fn ssim(a: &[f64], b: &[f64], width: usize, height: usize) -> f64 {
+    const C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+    const C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+    let (w, h) = (width.min(8), height.min(8));
+    if w == 0 || h == 0 {
+        return 1.0;
+    }
+    let (mut total, mut windows) = (0.0, 0);
+    for y0 in (0..=height - h).step_by(4) {
+        for x0 in (0..=width - w).step_by(4) {
+            let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for y in y0..y0 + h {
+                for i in y * width + x0..y * width + x0 + w {
+                    let (p, q) = (a[i], b[i]);
+                    (sa, sb) = (sa + p, sb + q);
+                    (saa, sbb, sab) = (saa + p * p, sbb + q * q, sab + p * q);
+                }
+            }
+            let n = (w * h) as f64;
+            let (ma, mb) = (sa / n, sb / n);
+            let (va, vb) = (saa / n - ma * ma, sbb / n - mb * mb);
+            let cov = sab / n - ma * mb;
+            total += (2.0 * ma * mb + C1) * (2.0 * cov + C2)
+                / ((ma * ma + mb * mb + C1) * (va + vb + C2));
+            windows += 1;
+        }
+    }
+    total / windows as f64
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgba, RgbaImage};
+
+    use crate::{CanvasFrame, Decoder, EncodingError, Optimizer, WebPAnimator, test_util::params};
+
+    use super::frame_quality;
+
+    #[test]
+    fn test_quality() {
+        let gray = RgbaImage::from_pixel(16, 16, Rgba([100, 100, 100, 255]));
+        let lighter = RgbaImage::from_pixel(16, 16, Rgba([110, 110, 110, 255]));
+        let same = frame_quality(&gray, &gray).unwrap();
+        assert_eq!(same.psnr, f64::INFINITY);
+        assert!((same.ssim - 1.0).abs() < 1e-9);
+        // A squared error of 100 in three of the four channels.
+        let off = frame_quality(&gray, &lighter).unwrap();
+        assert!((off.psnr - 10.0 * (255.0f64 * 255.0 / 75.0).log10()).abs() < 1e-9);
+        assert!(off.ssim < 1.0);
+        // Transparent pixels match whatever their color.
+        let clear = RgbaImage::from_pixel(16, 16, Rgba([0, 0, 0, 0]));
+        let clear_red = RgbaImage::from_pixel(16, 16, Rgba([255, 0, 0, 0]));
+        assert_eq!(
+            frame_quality(&clear, &clear_red).unwrap().psnr,
+            f64::INFINITY
+        );
+        assert!(matches!(
+            frame_quality(&gray, &RgbaImage::new(8, 8)),
+            Err(EncodingError::InvalidDimensions)
+        ));
+
+        let params = params(16, 16);
+        let originals = [&gray, &gray, &lighter].map(|image| CanvasFrame {
+            image: image.clone(),
+            duration: 100,
+        });
+        let mut optimizer = Optimizer::new(WebPAnimator::new(params).unwrap());
+        for frame in &originals {
+            optimizer.add_frame(&frame.image, frame.duration).unwrap();
+        }
+        let data = optimizer.into_inner().into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+        // The first two frames were merged.
+        assert_eq!(decoder.frames().len(), 2);
+        let quality = decoder.quality(&originals).unwrap();
+        assert_eq!(quality.frames.len(), 3);
+        assert_eq!(quality.psnr, f64::INFINITY);
+
+        let shifted = [&lighter, &lighter, &lighter].map(|image| CanvasFrame {
+            image: image.clone(),
+            duration: 100,
+        });
+        let quality = decoder.quality(&shifted).unwrap();
+        assert!(quality.frames[0].psnr.is_finite());
+        assert_eq!(quality.frames[2].psnr, f64::INFINITY);
+        assert!((quality.psnr - 10.0 * (255.0f64 * 255.0 * 3.0 / 150.0).log10()).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,182 @@
+//! Editing the settings of an existing animation in place, without touching
+//! the frame data.
+//!
+//! The functions accept anything that can be read, written and seeked, such
+//! as a [`File`](std::fs::File) opened for reading and writing, or a
+//! [`Cursor`](std::io::Cursor) over a buffer in memory, whose contents start
+//! with the WebP image.  Only the `ANIM` chunk and the headers of the `ANMF`
+//! chunks are modified.
+
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+
+use crate::{DecodingError, EncodingError, LoopCount, Retime, riff::u24_from_le, u24_bytes};
+
+/// Set the number of times the animation plays.
+pub fn set_loop_count<F: Read + Write + Seek>(
+    file: &mut F,
+    loop_count: LoopCount,
+) -> Result<(), EncodingError> {
+    let offset = anim_offset(file)?;
+    file.seek(SeekFrom::Start(offset + 4))?;
+    file.write_all(&u16::from(loop_count).to_le_bytes())?;
+    Ok(())
+}
+
+/// Set the background color, in BGRA order.
+pub fn set_background<F: Read + Write + Seek>(
+    file: &mut F,
+    background_bgra: [u8; 4],
+) -> Result<(), EncodingError> {
+    let offset = anim_offset(file)?;
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(&background_bgra)?;
+    Ok(())
+}
+
+/// Scale the duration of every frame.  See
+/// [`WebPAnimator::retime`](crate::WebPAnimator::retime).  The file is not
+/// modified if any new duration is invalid.
+pub fn retime<F: Read + Write + Seek>(
+    file: &mut F,
+    retime: impl Into<Retime>,
+) -> Result<(), EncodingError> {
+    let retime = retime.into();
+    let mut durations = Vec::new();
+    for chunk in chunk_headers(file)? {
+        if &chunk.fourcc != b"ANMF" {
+            continue;
+        }
+        if chunk.len < 16 {
+            return Err(DecodingError::InvalidChunk.into());
+        }
+        let mut duration = [0; 3];
+        file.seek(SeekFrom::Start(chunk.offset + 12))?;
+        file.read_exact(&mut duration)?;
+        durations.push((chunk.offset, retime.apply(u24_from_le(&duration))?));
+    }
+    for (offset, duration) in durations {
+        file.seek(SeekFrom::Start(offset + 12))?;
+        file.write_all(&u24_bytes(duration))?;
+    }
+    Ok(())
+}
+
+/// Returns the stream position of the payload of the `ANIM` chunk.
+fn anim_offset<F: Read + Seek>(file: &mut F) -> Result<u64, EncodingError> {
+    for chunk in chunk_headers(file)? {
+        match &chunk.fourcc {
+            b"ANIM" if chunk.len >= 6 => return Ok(chunk.offset),
+            b"ANIM" | b"ANMF" => return Err(DecodingError::InvalidChunk.into()),
+            _ => {}
+        }
+    }
+    Err(DecodingError::NotAnimated.into())
+}
+
+struct ChunkHeader {
+    fourcc: [u8; 4],
+    /// The stream position of the payload.
+    offset: u64,
+    len: u64,
+}
+
+/// Reads the headers of the chunks in an animated WebP file, skipping over
+/// their payloads.
+fn chunk_headers<F: Read + Seek>(file: &mut F) -> Result<Vec<ChunkHeader>, EncodingError> {
+    let truncated = |e: std::io::Error| match e.kind() {
+        ErrorKind::UnexpectedEof => EncodingError::Decoding(DecodingError::Truncated),
+        _ => EncodingError::Io(e),
+    };
+    file.rewind()?;
+    let mut header = [0; 12];
+    file.read_exact(&mut header).map_err(truncated)?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WEBP" {
+        return Err(DecodingError::NotWebP.into());
+    }
+    let end = 8 + u32::from_le_bytes(header[4..8].try_into().unwrap()) as u64;
+    let mut chunks = Vec::new();
+    let mut position = 12;
+    while position + 8 <= end {
+        let mut chunk = [0; 8];
+        file.read_exact(&mut chunk).map_err(truncated)?;
+        let fourcc = chunk[..4].try_into().unwrap();
+        let len = u32::from_le_bytes(chunk[4..].try_into().unwrap()) as u64;
+        let offset = position + 8;
+        if offset + len > end {
+            return Err(DecodingError::Truncated.into());
+        }
+        if chunks.is_empty() {
+            let mut flags = [0];
+            file.read_exact(&mut flags).map_err(truncated)?;
+            if &fourcc != b"VP8X" || flags[0] & 0x2 == 0 {
+                return Err(DecodingError::NotAnimated.into());
+            }
+        }
+        chunks.push(ChunkHeader {
+            fourcc,
+            offset,
+            len,
+        });
+        position = offset + len + (len & 1);
+        file.seek(SeekFrom::Start(position))?;
+    }
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use image::{Rgb, RgbImage};
+
+    use crate::{
+        Decoder, DecodingError, EncodingError, LoopCount, WebPAnimator, remux,
+        test_util::{lossless_webp, params},
+    };
+
+    #[test]
+    fn test_remux() {
+        let params = params(4, 4);
+        let mut animator = WebPAnimator::new(params).unwrap();
+        animator.set_icc_profile(vec![1; 5]).unwrap();
+        let frame = lossless_webp(&RgbImage::from_pixel(4, 4, Rgb([255, 0, 0])));
+        animator.add_webp_image(&frame, None, 100).unwrap();
+        let original = animator.into_bytes().unwrap();
+
+        let mut data = original.clone();
+        let mut cursor = Cursor::new(&mut data);
+        remux::set_loop_count(&mut cursor, LoopCount::from(4)).unwrap();
+        remux::set_background(&mut cursor, [1, 2, 3, 4]).unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+        assert_eq!(decoder.loop_count(), LoopCount::from(4));
+        assert_eq!(decoder.background_bgra(), [1, 2, 3, 4]);
+        assert_eq!(
+            decoder.frames()[0].data,
+            Decoder::new(&original).unwrap().frames()[0].data
+        );
+        let mut retimed = data.clone();
+        remux::retime(&mut Cursor::new(&mut retimed), 2.5).unwrap();
+        assert_eq!(Decoder::new(&retimed).unwrap().frames()[0].duration, 250);
+
+        let path =
+            std::env::temp_dir().join(format!("webp-animator-remux-{}.webp", std::process::id()));
+        std::fs::write(&path, &original).unwrap();
+        let mut file = std::fs::File::options()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        remux::set_loop_count(&mut file, LoopCount::from(4)).unwrap();
+        remux::set_background(&mut file, [1, 2, 3, 4]).unwrap();
+        drop(file);
+        let from_file = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(from_file, data);
+
+        let mut still = Cursor::new(frame);
+        assert!(matches!(
+            remux::set_loop_count(&mut still, LoopCount::Infinite),
+            Err(EncodingError::Decoding(DecodingError::NotAnimated))
+        ));
+    }
+}
@@ -0,0 +1,261 @@
+use crate::{EncodingError, MAX_DURATION_MS, WebPAnimator};
+
+/// How scaled durations are rounded to whole milliseconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round to the nearest millisecond, with halves rounded up.
+    #[default]
+    Nearest,
+    Down,
+    Up,
+}
+
+/// A change of playback speed.  See [`WebPAnimator::retime`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Retime {
+    /// The factor by which durations are multiplied.  Values above 1 slow
+    /// the animation down.
+    pub factor: f64,
+    pub rounding: Rounding,
+    /// The shortest duration in milliseconds.  Shorter durations are
+    /// raised to this value.
+    pub min_duration: u32,
+}
+
+impl From<f64> for Retime {
+    /// Multiplies durations by `factor`, rounding to the nearest millisecond.
+    fn from(factor: f64) -> Self {
+        Self {
+            factor,
+            rounding: Rounding::Nearest,
+            min_duration: 0,
+        }
+    }
+}
+
+impl Retime {
+    /// Returns the new duration of a frame.  Fails with
+    /// [`EncodingError::InvalidDuration`] if the factor is negative or not
+    /// finite, or if the result exceeds [`MAX_DURATION_MS`].
+    pub fn apply(&self, duration: u32) -> Result<u32, EncodingError> {
+        if !self.factor.is_finite() || self.factor < 0.0 {
+            return Err(EncodingError::InvalidDuration);
+        }
+        let scaled = duration as f64 * self.factor;
+        let rounded = match self.rounding {
+            Rounding::Nearest => (scaled + 0.5).floor(),
+            Rounding::Down => scaled.floor(),
+            Rounding::Up => scaled.ceil(),
+        };
+        let duration = (rounded as u64).max(self.min_duration.into());
+        if duration > MAX_DURATION_MS as u64 {
+            return Err(EncodingError::InvalidDuration);
+        }
+        Ok(duration as u32)
+    }
+}
+
+/// Settings for [`WebPAnimator::make_browser_safe`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BrowserSafe {
+    /// The shortest duration in milliseconds.  Browsers play frames of 10 ms
+    /// or less at 100 ms instead.
+    pub min_duration: u32,
+    /// Durations are rounded to the nearest multiple of this many
+    /// milliseconds, since browsers may round durations to their timer
+    /// resolution.  1 leaves them as they are.
+    pub granularity: u32,
+}
+
+impl Default for BrowserSafe {
+    fn default() -> Self {
+        Self {
+            min_duration: 20,
+            granularity: 10,
+        }
+    }
+}
+
+/// A duration changed by [`WebPAnimator::make_browser_safe`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DurationChange {
+    /// The position of the frame.
+    pub index: usize,
+    pub from: u32,
+    pub to: u32,
+}
+
+impl WebPAnimator {
+    /// Adjusts frame durations so that browsers play the animation at the
+    /// intended speed, and returns the durations that were changed.  Short
+    /// frames, including frames with no duration, are lengthened to
+    /// `settings.min_duration` and then every duration is rounded to a
+    /// multiple of `settings.granularity`.  A duration rounded down to zero
+    /// follows the [zero duration
+    /// policy](WebPAnimator::set_zero_duration_policy).  Nothing is changed
+    /// if any new duration is invalid.
+    pub fn make_browser_safe(
+        &mut self,
+        settings: BrowserSafe,
+    ) -> Result<Vec<DurationChange>, EncodingError> {
+        let step = settings.granularity.max(1) as u64;
+        let min = settings.min_duration as u64;
+        let zero_duration = self.zero_duration;
+        let changes = self
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(index, frame)| {
+                let duration = (frame.duration as u64).max(min);
+                let rounded = (duration + step / 2) / step * step;
+                // Rounding down mustn't undo the minimum.
+                let to = if rounded < min {
+                    rounded + step
+                } else {
+                    rounded
+                };
+                if to > MAX_DURATION_MS as u64 {
+                    return Err(EncodingError::InvalidDuration);
+                }
+                Ok(DurationChange {
+                    index,
+                    from: frame.duration,
+                    to: zero_duration.apply(to as u32)?,
+                })
+            })
+            .filter(|change| !matches!(change, Ok(c) if c.from == c.to))
+            .collect::<Result<Vec<_>, _>>()?;
+        for change in &changes {
+            self.frames[change.index].duration = change.to;
+        }
+        Ok(changes)
+    }
+
+    /// Changes the playback speed by scaling the duration of every frame.
+    /// A duration scaled down to zero follows the [zero duration
+    /// policy](WebPAnimator::set_zero_duration_policy).  Nothing is changed
+    /// if any new duration is invalid.
+    ///
+    /// ```
+    /// # use webp_animator::{Retime, Rounding, WebPAnimator};
+    /// # let mut animator = WebPAnimator::builder().width(1).height(1).build().unwrap();
+    /// // Twice as fast.
+    /// animator.retime(0.5).unwrap();
+    /// // Three times as slow, but no frame shorter than 20 ms.
+    /// animator
+    ///     .retime(Retime {
+    ///         factor: 3.0,
+    ///         rounding: Rounding::Down,
+    ///         min_duration: 20,
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn retime(&mut self, retime: impl Into<Retime>) -> Result<(), EncodingError> {
+        let retime = retime.into();
+        let durations = self
+            .frames
+            .iter()
+            .map(|frame| {
+                retime
+                    .apply(frame.duration)
+                    .and_then(|duration| self.zero_duration.apply(duration))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        for (frame, duration) in self.frames.iter_mut().zip(durations) {
+            frame.duration = duration;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::RgbImage;
+
+    use crate::{
+        BrowserSafe, DurationChange, EncodingError, MAX_DURATION_MS, Retime, Rounding,
+        WebPAnimator, ZeroDurationPolicy, test_util::lossless_webp,
+    };
+
+    #[test]
+    fn test_retime() {
+        let mut retime = Retime::from(1.0 / 3.0);
+        assert_eq!(retime.apply(100).unwrap(), 33);
+        assert_eq!(retime.apply(50).unwrap(), 17);
+        retime.rounding = Rounding::Down;
+        assert_eq!(retime.apply(50).unwrap(), 16);
+        retime.rounding = Rounding::Up;
+        assert_eq!(retime.apply(100).unwrap(), 34);
+        retime.min_duration = 20;
+        assert_eq!(retime.apply(0).unwrap(), 20);
+        assert!(matches!(
+            Retime::from(2.0).apply(MAX_DURATION_MS),
+            Err(EncodingError::InvalidDuration)
+        ));
+        assert!(matches!(
+            Retime::from(-1.0).apply(10),
+            Err(EncodingError::InvalidDuration)
+        ));
+    }
+
+    #[test]
+    fn test_make_browser_safe() {
+        let mut animator = WebPAnimator::builder().width(1).height(1).build().unwrap();
+        let pixel = lossless_webp(&RgbImage::new(1, 1));
+        for duration in [0, 16, 100, 33, 25] {
+            animator.add_webp_image(&pixel, None, duration).unwrap();
+        }
+        let changes = animator.make_browser_safe(BrowserSafe::default()).unwrap();
+        let change = |index, from, to| DurationChange { index, from, to };
+        assert_eq!(
+            changes,
+            [
+                change(0, 0, 20),
+                change(1, 16, 20),
+                change(3, 33, 30),
+                change(4, 25, 30)
+            ]
+        );
+        let durations: Vec<u32> = animator.frames().iter().map(|f| f.duration()).collect();
+        assert_eq!(durations, [20, 20, 100, 30, 30]);
+        let odd = BrowserSafe {
+            min_duration: 24,
+            granularity: 10,
+        };
+        let changes = animator.make_browser_safe(odd).unwrap();
+        assert_eq!(changes, [change(0, 20, 30), change(1, 20, 30)]);
+    }
+
+    #[test]
+    fn test_zero_duration() {
+        let mut animator = WebPAnimator::builder().width(1).height(1).build().unwrap();
+        let pixel = lossless_webp(&RgbImage::new(1, 1));
+        for duration in [4, 100] {
+            animator.add_webp_image(&pixel, None, duration).unwrap();
+        }
+        animator.set_zero_duration_policy(ZeroDurationPolicy::Reject);
+        let no_minimum = BrowserSafe {
+            min_duration: 0,
+            granularity: 10,
+        };
+        assert!(matches!(
+            animator.make_browser_safe(no_minimum),
+            Err(EncodingError::InvalidDuration)
+        ));
+        let faster = Retime {
+            factor: 0.1,
+            rounding: Rounding::Down,
+            min_duration: 0,
+        };
+        assert!(matches!(
+            animator.retime(faster),
+            Err(EncodingError::InvalidDuration)
+        ));
+        let durations: Vec<u32> = animator.frames().iter().map(|f| f.duration()).collect();
+        assert_eq!(durations, [4, 100]);
+        animator.set_zero_duration_policy(ZeroDurationPolicy::Coerce(1));
+        animator.retime(faster).unwrap();
+        let durations: Vec<u32> = animator.frames().iter().map(|f| f.duration()).collect();
+        assert_eq!(durations, [1, 10]);
+    }
+}
@@ -0,0 +1,214 @@
+use std::{
+    borrow::Cow,
+    io::{Read, Seek, SeekFrom},
+    ops::Range,
+};
+
+use crate::{DecodingError, EncodingError, padded_len};
+
+pub(crate) struct Chunk<'a> {
+    pub fourcc: [u8; 4],
+    pub data: &'a [u8],
+}
+
+/// Iterates over a sequence of RIFF chunks.  Chunks with odd sizes are
+/// followed by a padding byte, which may be omitted for the final chunk.
+pub(crate) struct Chunks<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Chunks<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Result<Chunk<'a>, DecodingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let Some((header, rest)) = self.data.split_first_chunk::<8>() else {
+            self.data = &[];
+            return Some(Err(DecodingError::Truncated));
+        };
+        let fourcc = header[..4].try_into().unwrap();
+        let len = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+        if len > rest.len() {
+            self.data = &[];
+            return Some(Err(DecodingError::Truncated));
+        }
+        let (data, rest) = rest.split_at(len);
+        self.data = if len & 1 != 0 && !rest.is_empty() {
+            &rest[1..]
+        } else {
+            rest
+        };
+        Some(Ok(Chunk { fourcc, data }))
+    }
+}
+
+/// Splits a RIFF WebP file into the payload following the `WEBP` tag.
+pub(crate) fn webp_payload(data: &[u8]) -> Result<&[u8], DecodingError> {
+    let Some((header, rest)) = data.split_first_chunk::<12>() else {
+        return Err(DecodingError::NotWebP);
+    };
+    if &header[..4] != b"RIFF" || &header[8..] != b"WEBP" {
+        return Err(DecodingError::NotWebP);
+    }
+    let len = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+    if len < 4 || len - 4 > rest.len() {
+        return Err(DecodingError::Truncated);
+    }
+    Ok(&rest[..len - 4])
+}
+
+/// Returns the payload of a metadata chunk.  `data` is either the payload
+/// itself or a complete chunk with the given FourCC, in which case the
+/// declared size must match the length of `data`.
+pub(crate) fn metadata_payload(fourcc: &[u8; 4], data: Vec<u8>) -> Result<Vec<u8>, EncodingError> {
+    if !data.starts_with(fourcc) {
+        return Ok(data);
+    }
+    let mut chunks = Chunks::new(&data);
+    match (chunks.next(), chunks.next()) {
+        (Some(Ok(chunk)), None) => Ok(chunk.data.to_vec()),
+        _ => Err(EncodingError::InvalidMetadata),
+    }
+}
+
+/// The header of a chunk with a payload of `len` bytes.
+pub(crate) fn chunk_header(fourcc: &[u8; 4], len: usize) -> [u8; 8] {
+    let mut header = [0; 8];
+    header[..4].copy_from_slice(fourcc);
+    header[4..].copy_from_slice(&(len as u32).to_le_bytes());
+    header
+}
+
+/// The size of a metadata chunk with a payload of `len` bytes, including
+/// its header and padding.  Empty metadata is omitted from the file.
+pub(crate) fn metadata_chunk_len(len: usize) -> usize {
+    if len == 0 { 0 } else { 8 + padded_len(len) }
+}
+
+pub(crate) fn u24_from_le(b: &[u8]) -> u32 {
+    u32::from_le_bytes([b[0], b[1], b[2], 0])
+}
+
+/// Extracts the image data from a still WebP image, in either the simple or
+/// the extended format.  The result consists of a `VP8 ` or `VP8L` chunk,
+/// possibly preceded by an `ALPH` chunk.
+pub(crate) fn still_image_chunks(data: &[u8]) -> Result<Cow<'_, [u8]>, EncodingError> {
+    let payload = webp_payload(data)?;
+    let mut alpha = None;
+    let mut vp8x = false;
+    let mut offset = 0;
+    for chunk in Chunks::new(payload) {
+        let chunk = chunk?;
+        let start = offset;
+        offset += 8 + chunk.data.len() + (chunk.data.len() & 1);
+        match &chunk.fourcc {
+            b"VP8X" if start == 0 => vp8x = true,
+            b"VP8 " | b"VP8L" if start == 0 => {
+                return Ok(Cow::Borrowed(&payload[..offset.min(payload.len())]));
+            }
+            b"ALPH" if vp8x => alpha = Some(start..offset),
+            b"VP8 " if vp8x => {
+                let image = &payload[start..offset.min(payload.len())];
+                return Ok(match alpha {
+                    Some(alpha) if alpha.end == start => {
+                        Cow::Borrowed(&payload[alpha.start..offset.min(payload.len())])
+                    }
+                    Some(alpha) => Cow::Owned([&payload[alpha], image].concat()),
+                    None => Cow::Borrowed(image),
+                });
+            }
+            // VP8L has its own alpha channel, so any ALPH chunk is ignored.
+            b"VP8L" if vp8x => {
+                return Ok(Cow::Borrowed(&payload[start..offset.min(payload.len())]));
+            }
+            b"ANIM" | b"ANMF" => return Err(EncodingError::UnrecognizedImage),
+            _ if vp8x => {}
+            _ => return Err(EncodingError::UnrecognizedImage),
+        }
+    }
+    Err(EncodingError::UnrecognizedImage)
+}
+
+/// Like [`still_image_chunks`], but only reads the chunk headers from
+/// `reader`, and returns the position of the image data so that nothing else
+/// needs to be read.  Returns `None` if the image data isn't contiguous,
+/// when the `ALPH` chunk isn't directly before the `VP8 ` chunk.
+pub(crate) fn still_image_span<R: Read + Seek>(
+    reader: &mut R,
+) -> Result<Option<Range<u64>>, EncodingError> {
+    let mut header = [0; 12];
+    reader
+        .read_exact(&mut header)
+        .map_err(|_| DecodingError::NotWebP)?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WEBP" {
+        return Err(DecodingError::NotWebP.into());
+    }
+    let len = u32::from_le_bytes(header[4..8].try_into().unwrap()) as u64;
+    let end = 8 + len;
+    if len < 4 || end > reader.seek(SeekFrom::End(0))? {
+        return Err(DecodingError::Truncated.into());
+    }
+    let mut alpha = None;
+    let mut vp8x = false;
+    let mut start = 12;
+    while start < end {
+        if end - start < 8 {
+            return Err(DecodingError::Truncated.into());
+        }
+        let mut header = [0; 8];
+        reader.seek(SeekFrom::Start(start))?;
+        reader.read_exact(&mut header)?;
+        let len = u32::from_le_bytes(header[4..].try_into().unwrap()) as u64;
+        if start + 8 + len > end {
+            return Err(DecodingError::Truncated.into());
+        }
+        let next = (start + 8 + len + (len & 1)).min(end);
+        match &header[..4] {
+            b"VP8X" if start == 12 => vp8x = true,
+            b"VP8 " | b"VP8L" if start == 12 => return Ok(Some(start..next)),
+            b"ALPH" if vp8x => alpha = Some(start..next),
+            b"VP8 " if vp8x => {
+                return Ok(match alpha {
+                    Some(alpha) if alpha.end == start => Some(alpha.start..next),
+                    Some(_) => None,
+                    None => Some(start..next),
+                });
+            }
+            b"VP8L" if vp8x => return Ok(Some(start..next)),
+            b"ANIM" | b"ANMF" => return Err(EncodingError::UnrecognizedImage),
+            _ if vp8x => {}
+            _ => return Err(EncodingError::UnrecognizedImage),
+        }
+        start = next;
+    }
+    Err(EncodingError::UnrecognizedImage)
+}
+
+/// Checks that `data` consists of a `VP8 ` or `VP8L` chunk, possibly preceded
+/// by an `ALPH` chunk in the case of `VP8 `, and nothing else.  Returns the
+/// `VP8 ` or `VP8L` chunk.
+pub(crate) fn check_image_chunks(data: &[u8]) -> Result<Chunk<'_>, EncodingError> {
+    let mut chunks = Chunks::new(data);
+    let first = chunks.next().ok_or(EncodingError::UnrecognizedImage)??;
+    let image = if &first.fourcc == b"ALPH" {
+        let image = chunks.next().ok_or(DecodingError::Truncated)??;
+        if &image.fourcc != b"VP8 " {
+            return Err(EncodingError::UnrecognizedImage);
+        }
+        image
+    } else {
+        first
+    };
+    if !matches!(&image.fourcc, b"VP8 " | b"VP8L") || chunks.next().is_some() {
+        return Err(EncodingError::UnrecognizedImage);
+    }
+    Ok(image)
+}
@@ -0,0 +1,111 @@
+use std::io::{Seek, Write};
+
+use crate::{EncodingError, FrameSource, InputFrame, WebPAnimator, WebPStreamWriter};
+
+/// Something that frames can be written to.
+///
+/// [`WebPAnimator`] is the usual sink.  Others are the
+/// [`Optimizer`](crate::Optimizer), the [`WebPStreamWriter`], a
+/// [`ManifestWriter`](crate::manifest::ManifestWriter), which writes an
+/// image sequence, and `Vec<InputFrame>`, which records the frames, for
+/// tests.  Code that produces frames can be written once against this trait
+/// and used with any of them.
+pub trait FrameSink {
+    /// Adds a frame.
+    fn add_input_frame(&mut self, frame: InputFrame) -> Result<(), EncodingError>;
+
+    /// Adds all frames of `source`, and returns the number of frames added.
+    /// Stops at the first error.
+    fn add_frames_from<S: FrameSource>(&mut self, mut source: S) -> Result<usize, EncodingError>
+    where
+        Self: Sized,
+    {
+        let mut count = 0;
+        while let Some(frame) = source.next_frame() {
+            self.add_input_frame(frame?)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// Images are encoded losslessly with
+/// [`add_image`](WebPAnimator::add_image).
+impl FrameSink for WebPAnimator {
+    fn add_input_frame(&mut self, frame: InputFrame) -> Result<(), EncodingError> {
+        match frame {
+            InputFrame::Encoded { data, options } => self.add_frame(&data, options),
+            #[cfg(feature = "image")]
+            InputFrame::Image { image, duration } => {
+                self.add_image(&image, crate::FrameOptions::with_duration(duration))
+            }
+        }
+    }
+}
+
+/// Images are encoded losslessly.  Call
+/// [`finish`](WebPStreamWriter::finish) once all frames have been added.
+impl<W: Write + Seek> FrameSink for WebPStreamWriter<W> {
+    fn add_input_frame(&mut self, frame: InputFrame) -> Result<(), EncodingError> {
+        match frame {
+            InputFrame::Encoded { data, options } => self.add_frame(&data, options),
+            #[cfg(feature = "image")]
+            InputFrame::Image { image, duration } => {
+                let data = crate::encode::encode_lossless(&image)?;
+                self.add_frame(&data, crate::FrameOptions::with_duration(duration))
+            }
+        }
+    }
+}
+
+impl FrameSink for Vec<InputFrame> {
+    fn add_input_frame(&mut self, frame: InputFrame) -> Result<(), EncodingError> {
+        self.push(frame);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use image::{Rgb, RgbImage};
+
+    use crate::{
+        Decoder, EncodingError, FrameOptions, FrameSink, InputFrame, WebPAnimator,
+        WebPStreamWriter,
+        test_util::{lossless_webp, params},
+    };
+
+    /// Adds a red frame and a blue frame to any sink.
+    fn add_frames(sink: &mut impl FrameSink) -> Result<usize, EncodingError> {
+        let frames = [[255, 0, 0], [0, 0, 255]].map(|color| {
+            let data = lossless_webp(&RgbImage::from_pixel(8, 8, Rgb(color)));
+            InputFrame::Encoded {
+                data,
+                options: FrameOptions::with_duration(100),
+            }
+        });
+        sink.add_frames_from(frames.into_iter())
+    }
+
+    #[test]
+    fn test_frame_sinks() {
+        let params = params(8, 8);
+        let mut recorded = Vec::new();
+        assert_eq!(add_frames(&mut recorded).unwrap(), 2);
+        assert!(matches!(
+            &recorded[1],
+            InputFrame::Encoded { options, .. } if options.duration == 100
+        ));
+
+        let mut animator = WebPAnimator::new(params).unwrap();
+        add_frames(&mut animator).unwrap();
+        let buffered = animator.into_bytes().unwrap();
+        let mut stream = WebPStreamWriter::new(Cursor::new(Vec::new()), params).unwrap();
+        add_frames(&mut stream).unwrap();
+        let streamed = stream.finish().unwrap().into_inner();
+        assert_eq!(Decoder::new(&streamed).unwrap().frames().len(), 2);
+        assert_eq!(buffered, streamed);
+    }
+}
@@ -0,0 +1,298 @@
+use std::{
+    cmp::Ordering,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{EncodingError, FrameOptions};
+
+/// A frame yielded by a [`FrameSource`].
+#[derive(Clone, Debug)]
+pub enum InputFrame {
+    /// A WebP image, added without re-encoding, as with
+    /// [`WebPAnimator::add_frame`](crate::WebPAnimator::add_frame).
+    Encoded {
+        data: Vec<u8>,
+        options: FrameOptions,
+    },
+    /// An image covering the canvas, shown for `duration` milliseconds.
+    #[cfg(feature = "image")]
+    Image {
+        image: image::DynamicImage,
+        duration: u32,
+    },
+}
+
+/// Something that frames can be read from, such as a directory of images
+/// ([`DirSource`]), a channel, or an iterator.
+///
+/// Every iterator over items implementing [`IntoInputFrame`] is a frame
+/// source.  This includes the receiving end of a channel, through
+/// [`Receiver::iter`](std::sync::mpsc::Receiver::iter), and, with the
+/// `image` feature, the [`Frames`](image::Frames) of the `image` crate's
+/// GIF and APNG decoders:
+///
+/// ```no_run
+/// # #[cfg(feature = "image")]
+/// # fn main() -> Result<(), webp_animator::EncodingError> {
+/// use std::{fs::File, io::BufReader};
+/// use image::{AnimationDecoder, codecs::gif::GifDecoder};
+/// use webp_animator::{FrameSink, WebPAnimator};
+/// let reader = BufReader::new(File::open("in.gif")?);
+/// let frames = GifDecoder::new(reader)?.into_frames();
+/// let mut writer = WebPAnimator::builder().width(64).height(64).build()?;
+/// writer.add_frames_from(frames)?;
+/// # Ok(())
+/// # }
+/// # #[cfg(not(feature = "image"))]
+/// # fn main() {}
+/// ```
+pub trait FrameSource {
+    /// Returns the next frame, or `None` once the source is exhausted.
+    fn next_frame(&mut self) -> Option<Result<InputFrame, EncodingError>>;
+}
+
+impl<I> FrameSource for I
+where
+    I: Iterator,
+    I::Item: IntoInputFrame,
+{
+    fn next_frame(&mut self) -> Option<Result<InputFrame, EncodingError>> {
+        self.next().map(IntoInputFrame::into_input_frame)
+    }
+}
+
+/// Conversion into an [`InputFrame`], for the items of iterators used as
+/// [`FrameSource`]s.
+pub trait IntoInputFrame {
+    fn into_input_frame(self) -> Result<InputFrame, EncodingError>;
+}
+
+impl IntoInputFrame for InputFrame {
+    fn into_input_frame(self) -> Result<InputFrame, EncodingError> {
+        Ok(self)
+    }
+}
+
+impl<T: IntoInputFrame, E: Into<EncodingError>> IntoInputFrame for Result<T, E> {
+    fn into_input_frame(self) -> Result<InputFrame, EncodingError> {
+        self.map_err(Into::into)?.into_input_frame()
+    }
+}
+
+/// The frame is assumed to cover the canvas, as the frames of the `image`
+/// crate's animation decoders do.  The delay is rounded to the nearest
+/// millisecond.
+#[cfg(feature = "image")]
+impl IntoInputFrame for image::Frame {
+    fn into_input_frame(self) -> Result<InputFrame, EncodingError> {
+        let duration = crate::encode::delay_ms(self.delay());
+        Ok(InputFrame::Image {
+            image: self.into_buffer().into(),
+            duration,
+        })
+    }
+}
+
+#[cfg(feature = "image")]
+impl IntoInputFrame for crate::CanvasFrame {
+    fn into_input_frame(self) -> Result<InputFrame, EncodingError> {
+        Ok(InputFrame::Image {
+            image: self.image.into(),
+            duration: self.duration,
+        })
+    }
+}
+
+/// The files of a directory, or a list of files, as frames at a fixed frame
+/// rate.
+///
+/// WebP files are yielded as [`InputFrame::Encoded`].  With the `image`
+/// feature, other formats are decoded and yielded as [`InputFrame::Image`].
+/// Frame start times are rounded to the nearest millisecond individually, so
+/// that the rounding errors don't accumulate.  An error reading or decoding
+/// a file is returned as [`EncodingError::InFile`], naming the file.
+#[derive(Clone, Debug)]
+pub struct DirSource {
+    paths: Vec<PathBuf>,
+    fps: f64,
+    next: usize,
+}
+
+impl DirSource {
+    /// The files in `dir`, sorted naturally, so that `frame2` comes before
+    /// `frame10`.  Subdirectories are skipped.
+    pub fn new(dir: impl AsRef<Path>, fps: f64) -> Result<Self, EncodingError> {
+        let dir = dir.as_ref();
+        let in_dir = |e: std::io::Error| EncodingError::InFile(dir.into(), Box::new(e.into()));
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir).map_err(in_dir)? {
+            let path = entry.map_err(in_dir)?.path();
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+        sort_naturally(&mut paths);
+        Self::from_paths(paths, fps)
+    }
+
+    /// The given files, in order.  Fails with
+    /// [`EncodingError::InvalidDuration`] unless `fps` is positive.
+    pub fn from_paths(paths: Vec<PathBuf>, fps: f64) -> Result<Self, EncodingError> {
+        if !(fps > 0.0 && fps.is_finite()) {
+            return Err(EncodingError::InvalidDuration);
+        }
+        Ok(Self {
+            paths,
+            fps,
+            next: 0,
+        })
+    }
+
+    /// The paths of the files, including those already yielded.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// The duration of frame `index`.
+    pub(crate) fn duration(&self, index: usize) -> Result<u32, EncodingError> {
+        let start = |i: usize| (i as f64 * 1000.0 / self.fps).round() as u64;
+        u32::try_from(start(index + 1) - start(index)).map_err(|_| EncodingError::InvalidDuration)
+    }
+}
+
+impl Iterator for DirSource {
+    type Item = Result<InputFrame, EncodingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.next;
+        let path = self.paths.get(index)?;
+        self.next += 1;
+        let frame = self
+            .duration(index)
+            .and_then(|duration| read_frame(path, duration))
+            .map_err(|e| EncodingError::InFile(path.clone(), Box::new(e)));
+        Some(frame)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = self.paths.len() - self.next;
+        (left, Some(left))
+    }
+}
+
+/// Reads the file at `path` as a frame.
+pub(crate) fn read_frame(path: &Path, duration: u32) -> Result<InputFrame, EncodingError> {
+    let data = fs::read(path)?;
+    #[cfg(feature = "image")]
+    if !(data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP")) {
+        let image = image::ImageReader::new(std::io::Cursor::new(&data))
+            .with_guessed_format()?
+            .decode()?;
+        return Ok(InputFrame::Image { image, duration });
+    }
+    Ok(InputFrame::Encoded {
+        data,
+        options: FrameOptions::with_duration(duration),
+    })
+}
+
+/// Sorts paths naturally, comparing runs of digits by their numeric value.
+pub(crate) fn sort_naturally(paths: &mut [PathBuf]) {
+    paths.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+}
+
+/// Compares strings with runs of digits ordered by their numeric value.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        let order = if x.is_ascii_digit() && y.is_ascii_digit() {
+            let ((m, rest_a), (n, rest_b)) = (split_digits(a), split_digits(b));
+            (a, b) = (rest_a, rest_b);
+            let (m_trimmed, n_trimmed) = (m.trim_start_matches('0'), n.trim_start_matches('0'));
+            // Numbers are compared by length and then digit by digit, and
+            // equal numbers with more leading zeros go first.
+            m_trimmed
+                .len()
+                .cmp(&n_trimmed.len())
+                .then_with(|| m_trimmed.cmp(n_trimmed))
+                .then_with(|| n.len().cmp(&m.len()))
+        } else {
+            (a, b) = (&a[x.len_utf8()..], &b[y.len_utf8()..]);
+            x.cmp(&y)
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+}
+
+/// Splits the leading digits off `s`.
+fn split_digits(s: &str) -> (&str, &str) {
+    s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cmp::Ordering, fs, sync::mpsc};
+
+    use image::{Rgb, RgbImage};
+
+    use super::natural_cmp;
+    use crate::{
+        Decoder, DirSource, EncodingError, FrameOptions, FrameSink, InputFrame, WebPAnimator,
+        test_util::lossless_webp,
+    };
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = ["frame10", "frame2", "frame1", "frame02", "frame", "frame1a"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            ["frame", "frame1", "frame1a", "frame02", "frame2", "frame10"]
+        );
+        assert_eq!(natural_cmp("a9b", "a10a"), Ordering::Less);
+    }
+
+    fn encode(color: [u8; 3]) -> Vec<u8> {
+        lossless_webp(&RgbImage::from_pixel(8, 8, Rgb(color)))
+    }
+
+    #[test]
+    fn test_frame_sources() {
+        let dir = std::env::temp_dir().join(format!("webp-animator-source-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("b10.webp"), encode([0, 0, 255])).unwrap();
+        fs::write(dir.join("b9.webp"), encode([255, 0, 0])).unwrap();
+        let mut writer = WebPAnimator::builder().width(8).height(8).build().unwrap();
+        let added = writer.add_frames_from(DirSource::new(&dir, 25.0).unwrap());
+        fs::write(dir.join("bad.webp"), "not a frame").unwrap();
+        let bad = DirSource::new(&dir, 25.0).unwrap().last().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(added.unwrap(), 2);
+        assert!(writer.add_frames_from(std::iter::once(bad)).is_err());
+
+        let (sender, receiver) = mpsc::channel();
+        let frame = InputFrame::Encoded {
+            data: encode([0, 255, 0]),
+            options: FrameOptions::with_duration(100),
+        };
+        sender.send(frame).unwrap();
+        drop(sender);
+        assert_eq!(writer.add_frames_from(receiver.iter()).unwrap(), 1);
+        let failing = [Err::<InputFrame, _>(EncodingError::InvalidDuration)];
+        assert!(matches!(
+            writer.add_frames_from(failing.into_iter()),
+            Err(EncodingError::InvalidDuration)
+        ));
+
+        let data = writer.into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+        let durations: Vec<u32> = decoder.frames().iter().map(|f| f.duration).collect();
+        assert_eq!(durations, [40, 40, 100]);
+    }
+}
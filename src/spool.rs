@@ -0,0 +1,434 @@
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    time::Duration,
+};
+
+use crate::{
+    CancellationToken, EncodingError, FrameOptions, FrameRect, Header, MAX_RIFF_SIZE, Metadata,
+    Params, Progress, ProgressSink, Stage, Validation, Warning, ZeroDurationPolicy, bitstream,
+    cancel, check_dimensions, check_frame, check_riff_size, padded_len, progress::report,
+    riff::still_image_chunks, write_anmf_chunk,
+};
+
+/// Where a frame's data was put in the store.
+struct SpooledFrame {
+    rect: FrameRect,
+    options: FrameOptions,
+    offset: u64,
+    len: usize,
+    has_alpha: bool,
+}
+
+/// Assembles an animated WebP image like
+/// [`WebPAnimator`](crate::WebPAnimator), but keeps the frame data in a
+/// separate store, such as a temporary file, instead of in memory.  Only the
+/// settings of each frame are kept in memory, so very long animations can
+/// be assembled on devices with little RAM.
+///
+/// Unlike [`WebPStreamWriter`](crate::WebPStreamWriter), the output doesn't
+/// need to be seekable, and the metadata and frame durations can be changed
+/// until the animation is written.  Frames are validated, and progress and
+/// cancellation are handled, as by `WebPAnimator`, but the editing and
+/// optimization features of `WebPAnimator` aren't available.
+pub struct WebPSpoolWriter<S: Read + Write + Seek> {
+    store: S,
+    params: Params,
+    detect_alpha: bool,
+    metadata: Metadata,
+    frames: Vec<SpooledFrame>,
+    frame_len: usize,
+    zero_duration: ZeroDurationPolicy,
+    validation: Validation,
+    warnings: Vec<Warning>,
+    progress: Option<Box<dyn ProgressSink>>,
+    cancel: Option<CancellationToken>,
+}
+
+#[cfg(not(all(target_family = "wasm", target_os = "unknown")))]
+impl WebPSpoolWriter<crate::TempFile> {
+    /// Creates a writer that keeps the frame data in a new file in the
+    /// system's temporary directory.  The file is deleted when the writer is
+    /// dropped.
+    pub fn with_temp_file(params: Params) -> Result<Self, EncodingError> {
+        Self::new(crate::TempFile::new()?, params)
+    }
+}
+
+impl<S: Read + Write + Seek> WebPSpoolWriter<S> {
+    /// Creates a writer that appends the frame data to `store`.  Anything
+    /// already in `store` is left alone.
+    pub fn new(store: S, params: Params) -> Result<Self, EncodingError> {
+        check_dimensions(params.width, params.height)?;
+        Ok(Self {
+            store,
+            params,
+            detect_alpha: false,
+            metadata: Metadata::default(),
+            frames: Vec::new(),
+            frame_len: 0,
+            zero_duration: ZeroDurationPolicy::default(),
+            validation: Validation::default(),
+            warnings: Vec::new(),
+            progress: None,
+            cancel: None,
+        })
+    }
+
+    /// See [`WebPAnimator::set_icc_profile`](crate::WebPAnimator::set_icc_profile).
+    pub fn set_icc_profile(&mut self, icc_profile: Vec<u8>) -> Result<(), EncodingError> {
+        self.metadata.set_icc_profile(icc_profile, self.frame_len)
+    }
+
+    /// See [`WebPAnimator::set_exif_metadata`](crate::WebPAnimator::set_exif_metadata).
+    pub fn set_exif_metadata(&mut self, exif_metadata: Vec<u8>) -> Result<(), EncodingError> {
+        self.metadata
+            .set_exif_metadata(exif_metadata, self.frame_len)
+    }
+
+    /// See [`WebPAnimator::set_xmp_metadata`](crate::WebPAnimator::set_xmp_metadata).
+    pub fn set_xmp_metadata(&mut self, xmp_metadata: Vec<u8>) -> Result<(), EncodingError> {
+        self.metadata.set_xmp_metadata(xmp_metadata, self.frame_len)
+    }
+
+    /// See [`WebPAnimator::set_detect_alpha`](crate::WebPAnimator::set_detect_alpha).
+    pub fn set_detect_alpha(&mut self, detect: bool) {
+        self.detect_alpha = detect;
+    }
+
+    /// See
+    /// [`WebPAnimator::set_zero_duration_policy`](crate::WebPAnimator::set_zero_duration_policy).
+    pub fn set_zero_duration_policy(&mut self, policy: ZeroDurationPolicy) {
+        self.zero_duration = policy;
+    }
+
+    /// See [`WebPAnimator::set_validation`](crate::WebPAnimator::set_validation).
+    pub fn set_validation(&mut self, validation: Validation) {
+        self.validation = validation;
+    }
+
+    /// The corrections made so far in [`Validation::Lenient`] mode.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Report progress to `sink` as frames are added and written, or stop
+    /// reporting if `None`.  Defaults to `None`.
+    pub fn set_progress_sink(&mut self, sink: Option<Box<dyn ProgressSink>>) {
+        self.progress = sink;
+    }
+
+    /// Stop adding frames, failing with [`EncodingError::Cancelled`], once
+    /// `token` is cancelled.  Since the frames are read back from the store
+    /// one at a time, this also stops [`write`](Self::write) before the next
+    /// frame, leaving the output incomplete.  Defaults to `None`.
+    pub fn set_cancellation_token(&mut self, token: Option<CancellationToken>) {
+        self.cancel = token;
+    }
+
+    /// The value of the RIFF size field, which counts everything after it.
+    fn riff_size(&self) -> usize {
+        self.metadata.riff_size(self.frame_len)
+    }
+
+    /// See [`WebPAnimator::estimated_size`](crate::WebPAnimator::estimated_size).
+    pub fn estimated_size(&self) -> usize {
+        self.riff_size() + 8
+    }
+
+    /// See [`WebPAnimator::headroom`](crate::WebPAnimator::headroom).
+    pub fn headroom(&self) -> usize {
+        MAX_RIFF_SIZE.saturating_sub(self.riff_size())
+    }
+
+    /// Add an image to the animation.  See
+    /// [`WebPAnimator::add_webp_chunk`](crate::WebPAnimator::add_webp_chunk).
+    pub fn add_webp_chunk(
+        &mut self,
+        data: &[u8],
+        frame: Option<FrameRect>,
+        duration: u32,
+    ) -> Result<(), EncodingError> {
+        let options = FrameOptions {
+            rect: frame,
+            ..FrameOptions::with_duration(duration)
+        };
+        self.add_chunk(data, options)
+    }
+
+    /// Add an image to the animation.  See
+    /// [`WebPAnimator::add_chunk`](crate::WebPAnimator::add_chunk).
+    pub fn add_chunk(&mut self, data: &[u8], options: FrameOptions) -> Result<(), EncodingError> {
+        trace_span!(DEBUG, "add_frame", index = self.frames.len());
+        cancel::check(&self.cancel)?;
+        let mut warnings = Vec::new();
+        let lenient = (self.validation == Validation::Lenient).then_some(&mut warnings);
+        let mut options = options;
+        options.duration = self.zero_duration.apply(options.duration)?;
+        let (rect, options) = check_frame(
+            self.params.width,
+            self.params.height,
+            data,
+            &options,
+            self.frames.len(),
+            lenient,
+        )?;
+        let chunk_len = padded_len(data.len()) + 24;
+        check_riff_size(self.riff_size() + chunk_len)?;
+        let offset = self.store.seek(SeekFrom::End(0))?;
+        self.store.write_all(data)?;
+        self.warnings.extend(warnings);
+        self.frames.push(SpooledFrame {
+            rect,
+            options,
+            offset,
+            len: data.len(),
+            has_alpha: bitstream::has_alpha(data),
+        });
+        self.frame_len += chunk_len;
+        trace_event!(
+            debug,
+            "spooled frame {}: {} bytes, {:?}, {} ms",
+            self.frames.len() - 1,
+            data.len(),
+            rect,
+            options.duration
+        );
+        let progress = Progress {
+            stage: Stage::Adding,
+            frames: self.frames.len(),
+            total_frames: None,
+            bytes_written: 0,
+            total_bytes: None,
+        };
+        report(&mut self.progress, progress);
+        Ok(())
+    }
+
+    /// Add an image to the animation.  See
+    /// [`WebPAnimator::add_webp_image`](crate::WebPAnimator::add_webp_image).
+    pub fn add_webp_image(
+        &mut self,
+        data: &[u8],
+        frame: Option<FrameRect>,
+        duration: u32,
+    ) -> Result<(), EncodingError> {
+        let options = FrameOptions {
+            rect: frame,
+            ..FrameOptions::with_duration(duration)
+        };
+        self.add_frame(data, options)
+    }
+
+    /// Add an image to the animation.  See
+    /// [`WebPAnimator::add_frame`](crate::WebPAnimator::add_frame).
+    pub fn add_frame(&mut self, data: &[u8], options: FrameOptions) -> Result<(), EncodingError> {
+        let data = still_image_chunks(data)?;
+        self.add_chunk(&data, options)
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Sets the duration in milliseconds of the frame at position `index`.
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn set_duration(&mut self, index: usize, duration: u32) -> Result<(), EncodingError> {
+        let duration = self.zero_duration.apply(duration)?;
+        if duration > crate::MAX_DURATION_MS {
+            return Err(EncodingError::InvalidDuration);
+        }
+        self.frames[index].options.duration = duration;
+        Ok(())
+    }
+
+    /// The sum of the durations of all frames.
+    pub fn total_duration(&self) -> Duration {
+        let ms: u64 = self.frames.iter().map(|f| f.options.duration as u64).sum();
+        Duration::from_millis(ms)
+    }
+
+    /// Writes the animation, reading the frames back from the store one at a
+    /// time.
+    pub fn write<W: Write + ?Sized>(&mut self, writer: &mut W) -> Result<(), EncodingError> {
+        trace_span!(DEBUG, "write", frames = self.frames.len());
+        self.validation
+            .check_frame_count(self.frames.len(), &mut self.warnings)?;
+        let has_alpha =
+            self.params.has_alpha || (self.detect_alpha && self.frames.iter().any(|f| f.has_alpha));
+        let header = Header {
+            width: self.params.width,
+            height: self.params.height,
+            background_bgra: self.params.background_bgra,
+            loop_count: self.params.loop_count.into(),
+            flags: self.metadata.flags(has_alpha),
+        };
+        let size = self.riff_size();
+        let mut progress = Progress {
+            stage: Stage::Writing,
+            frames: 0,
+            total_frames: Some(self.frames.len()),
+            bytes_written: 0,
+            total_bytes: Some(size as u64 + 8),
+        };
+        report(&mut self.progress, progress);
+        trace_event!(
+            debug,
+            "writing {} frames, {} bytes",
+            self.frames.len(),
+            size as u64 + 8
+        );
+        header.write(writer, size as u32, &self.metadata.icc_profile)?;
+        progress.bytes_written += self.metadata.header_len() as u64;
+        let mut buf = Vec::new();
+        for frame in &self.frames {
+            cancel::check(&self.cancel)?;
+            buf.resize(frame.len, 0);
+            self.store.seek(SeekFrom::Start(frame.offset))?;
+            self.store.read_exact(&mut buf)?;
+            write_anmf_chunk(writer, &buf, &frame.rect, &frame.options)?;
+            progress.frames += 1;
+            progress.bytes_written += (padded_len(buf.len()) + 24) as u64;
+            report(&mut self.progress, progress);
+        }
+        self.metadata.write_exif_xmp(writer)?;
+        progress.bytes_written = size as u64 + 8;
+        progress.stage = Stage::Finished;
+        report(&mut self.progress, progress);
+        trace_event!(debug, "finished writing {} bytes", progress.bytes_written);
+        Ok(())
+    }
+
+    /// Returns the store.
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::Cursor,
+        sync::{Arc, Mutex},
+    };
+
+    use image::{Rgba, RgbaImage};
+
+    use crate::{
+        CancellationToken, EncodingError, FrameRect, Progress, Stage, Validation, Warning,
+        WebPAnimator, WebPSpoolWriter, ZeroDurationPolicy,
+        test_util::{lossless_webp, params},
+    };
+
+    #[test]
+    fn test_matches_animator() {
+        let params = params(16, 16);
+        let mut animator = WebPAnimator::new(params).unwrap();
+        animator.set_detect_alpha(true);
+        let mut spool = WebPSpoolWriter::new(Cursor::new(b"existing".to_vec()), params).unwrap();
+        spool.set_detect_alpha(true);
+        let mut file = WebPSpoolWriter::with_temp_file(params).unwrap();
+        for color in [[255, 0, 0, 255], [0, 0, 255, 128], [0, 255, 0, 255]] {
+            let buf = lossless_webp(&RgbaImage::from_pixel(16, 16, Rgba(color)));
+            animator.add_webp_image(&buf, None, 100).unwrap();
+            spool.add_webp_image(&buf, None, 100).unwrap();
+            file.add_webp_image(&buf, None, 100).unwrap();
+        }
+        let xmp = b"<x:xmpmeta/>".to_vec();
+        animator.set_xmp_metadata(xmp.clone()).unwrap();
+        spool.set_xmp_metadata(xmp).unwrap();
+        animator.set_duration(1, 40).unwrap();
+        spool.set_duration(1, 40).unwrap();
+        file.set_duration(1, 40).unwrap();
+        assert_eq!(spool.estimated_size(), animator.estimated_size());
+
+        let mut expected = Vec::new();
+        animator.write(&mut expected).unwrap();
+        let mut actual = Vec::new();
+        spool.write(&mut actual).unwrap();
+        assert_eq!(actual, expected);
+        assert!(spool.into_inner().into_inner().starts_with(b"existing"));
+
+        animator.set_xmp_metadata(Vec::new()).unwrap();
+        animator.set_detect_alpha(false);
+        let mut expected = Vec::new();
+        animator.write(&mut expected).unwrap();
+        let mut actual = Vec::new();
+        file.write(&mut actual).unwrap();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_policies() {
+        let params = params(16, 16);
+        let buf = lossless_webp(&RgbaImage::from_pixel(8, 8, Rgba([255, 0, 0, 255])));
+        let mut spool = WebPSpoolWriter::new(Cursor::new(Vec::new()), params).unwrap();
+        let mut empty = Vec::new();
+        spool.write(&mut empty).unwrap();
+        assert_eq!(spool.warnings(), [Warning::EmptyAnimation]);
+        spool.set_validation(Validation::Strict);
+        assert!(matches!(
+            spool.write(&mut Vec::new()),
+            Err(EncodingError::NoFrames)
+        ));
+        let rect = FrameRect {
+            x: 11,
+            y: 0,
+            width: 8,
+            height: 8,
+        };
+        assert!(spool.add_webp_image(&buf, Some(rect), 100).is_err());
+        spool.set_validation(Validation::Lenient);
+        spool.add_webp_image(&buf, Some(rect), 100).unwrap();
+        assert_eq!(
+            spool.warnings(),
+            [
+                Warning::EmptyAnimation,
+                Warning::OffsetRounded(0),
+                Warning::FrameMoved(0)
+            ]
+        );
+
+        spool.set_zero_duration_policy(ZeroDurationPolicy::Reject);
+        assert!(matches!(
+            spool.add_webp_image(&buf, None, 0),
+            Err(EncodingError::InvalidDuration)
+        ));
+        assert!(spool.set_duration(0, 0).is_err());
+        spool.set_zero_duration_policy(ZeroDurationPolicy::Coerce(20));
+        spool.add_webp_image(&buf, None, 0).unwrap();
+        assert_eq!(spool.total_duration().as_millis(), 120);
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let sink = {
+            let reports = reports.clone();
+            move |p: &Progress| reports.lock().unwrap().push(*p)
+        };
+        spool.set_progress_sink(Some(Box::new(sink)));
+        let mut out = Vec::new();
+        spool.write(&mut out).unwrap();
+        let reports = std::mem::take(&mut *reports.lock().unwrap());
+        let summary: Vec<_> = reports.iter().map(|p| (p.stage, p.frames)).collect();
+        assert_eq!(
+            summary,
+            [
+                (Stage::Writing, 0),
+                (Stage::Writing, 1),
+                (Stage::Writing, 2),
+                (Stage::Finished, 2)
+            ]
+        );
+        assert_eq!(reports[3].bytes_written, out.len() as u64);
+        assert_eq!(out.len(), spool.estimated_size());
+
+        spool.set_cancellation_token(Some(CancellationToken::from_fn(|| true)));
+        assert!(matches!(
+            spool.add_webp_image(&buf, None, 100),
+            Err(EncodingError::Cancelled)
+        ));
+        assert!(matches!(
+            spool.write(&mut Vec::new()),
+            Err(EncodingError::Cancelled)
+        ));
+    }
+}
@@ -0,0 +1,225 @@
+use image::{
+    DynamicImage, GenericImageView, RgbaImage,
+    imageops::{self, FilterType},
+};
+
+use crate::{Compositor, Decoder, EncodingError, FrameOptions, LoopCount, Params, WebPAnimator};
+
+/// The order in which the tiles of a sprite sheet are played.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TileOrder {
+    /// Left to right, then top to bottom.
+    #[default]
+    RowMajor,
+    /// Top to bottom, then left to right.
+    ColumnMajor,
+}
+
+/// The layout of the frames in a sprite sheet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteSheet {
+    pub tile_width: u32,
+    pub tile_height: u32,
+    /// The number of frames, or `None` to use every tile.  Partial tiles at
+    /// the right and bottom edges are ignored.
+    pub count: Option<u32>,
+    pub order: TileOrder,
+}
+
+impl SpriteSheet {
+    /// The number of columns and rows of whole tiles in an image of the
+    /// given size.
+    fn grid(&self, width: u32, height: u32) -> Result<(u32, u32), EncodingError> {
+        if self.tile_width == 0 || self.tile_height == 0 {
+            return Err(EncodingError::InvalidDimensions);
+        }
+        Ok((width / self.tile_width, height / self.tile_height))
+    }
+}
+
+impl WebPAnimator {
+    /// Slices a sprite sheet into tiles and encodes each one losslessly as a
+    /// frame.  The canvas is the size of a tile and the animation loops
+    /// forever.  Fails with [`EncodingError::InvalidDimensions`] if the
+    /// sheet has fewer tiles than `sheet.count`.
+    pub fn from_sprite_sheet(
+        image: &DynamicImage,
+        sheet: &SpriteSheet,
+        duration: u32,
+    ) -> Result<Self, EncodingError> {
+        let (columns, rows) = sheet.grid(image.width(), image.height())?;
+        let count = sheet.count.unwrap_or(columns * rows);
+        if count > columns * rows {
+            return Err(EncodingError::InvalidDimensions);
+        }
+        let params = Params {
+            width: sheet.tile_width,
+            height: sheet.tile_height,
+            background_bgra: [0; 4],
+            loop_count: LoopCount::Infinite,
+            has_alpha: false,
+        };
+        let mut animator = WebPAnimator::new(params)?;
+        animator.set_detect_alpha(true);
+        for i in 0..count {
+            let (column, row) = match sheet.order {
+                TileOrder::RowMajor => (i % columns, i / columns),
+                TileOrder::ColumnMajor => (i / rows, i % rows),
+            };
+            let tile = image.view(
+                column * sheet.tile_width,
+                row * sheet.tile_height,
+                sheet.tile_width,
+                sheet.tile_height,
+            );
+            let tile = DynamicImage::from(tile.to_image());
+            animator.add_image(&tile, FrameOptions::with_duration(duration))?;
+        }
+        Ok(animator)
+    }
+}
+
+/// The layout of a contact sheet created by [`Decoder::contact_sheet`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ContactSheet {
+    /// Include every `step`th frame, starting with the first.
+    pub step: usize,
+    /// The maximum number of tiles in each row.
+    pub columns: u32,
+    /// The size of a tile relative to the canvas.
+    pub scale: f32,
+}
+
+impl Default for ContactSheet {
+    fn default() -> Self {
+        Self {
+            step: 1,
+            columns: 8,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Decoder<'_> {
+    /// Renders the animation and arranges the selected frames in a grid,
+    /// row by row.  Tiles are scaled with a triangle filter, and any unused
+    /// tiles in the last row are transparent.
+    pub fn contact_sheet(&self, layout: &ContactSheet) -> Result<RgbaImage, EncodingError> {
+        if layout.step == 0 || layout.columns == 0 || layout.scale.is_nan() || layout.scale <= 0.0 {
+            return Err(EncodingError::InvalidDimensions);
+        }
+        let count = self.frames().len().div_ceil(layout.step) as u32;
+        if count == 0 {
+            return Err(EncodingError::NoFrames);
+        }
+        let scaled = |len: u32| ((len as f32 * layout.scale).round() as u32).max(1);
+        let (tile_width, tile_height) = (scaled(self.width()), scaled(self.height()));
+        let columns = layout.columns.min(count);
+        let rows = count.div_ceil(columns);
+        let (width, height) = (
+            tile_width.checked_mul(columns),
+            tile_height.checked_mul(rows),
+        );
+        let (Some(width), Some(height)) = (width, height) else {
+            return Err(EncodingError::InvalidDimensions);
+        };
+        let mut sheet = RgbaImage::new(width, height);
+        let mut compositor = Compositor::new(self.width(), self.height());
+        for (i, frame) in self.frames().iter().enumerate() {
+            let canvas = compositor.add_frame(frame)?;
+            if i % layout.step != 0 {
+                continue;
+            }
+            let tile = (i / layout.step) as u32;
+            let x = (tile % columns * tile_width) as i64;
+            let y = (tile / columns * tile_height) as i64;
+            if (tile_width, tile_height) == canvas.dimensions() {
+                imageops::replace(&mut sheet, canvas, x, y);
+            } else {
+                let tile = imageops::resize(canvas, tile_width, tile_height, FilterType::Triangle);
+                imageops::replace(&mut sheet, &tile, x, y);
+            }
+        }
+        Ok(sheet)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    use crate::{
+        Compositor, ContactSheet, Decoder, EncodingError, FrameOptions, SpriteSheet, TileOrder,
+        WebPAnimator, test_util::params,
+    };
+
+    #[test]
+    fn test_from_sprite_sheet() {
+        // A 3x2 grid with a partial column, where the tile at column x and
+        // row y has red value x and green value y.
+        let image = RgbaImage::from_fn(3 * 4 + 1, 2 * 6, |x, y| {
+            Rgba([(x / 4) as u8, (y / 6) as u8, 0, 255])
+        });
+        let image = DynamicImage::from(image);
+        let mut sheet = SpriteSheet {
+            tile_width: 4,
+            tile_height: 6,
+            count: Some(5),
+            order: TileOrder::ColumnMajor,
+        };
+        let out = WebPAnimator::from_sprite_sheet(&image, &sheet, 80)
+            .unwrap()
+            .into_bytes()
+            .unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (4, 6));
+        let mut compositor = Compositor::new(4, 6);
+        let tiles: Vec<_> = decoder
+            .frames()
+            .iter()
+            .map(|f| {
+                assert_eq!(f.duration, 80);
+                let p = compositor.add_frame(f).unwrap().get_pixel(0, 0);
+                (p[0], p[1])
+            })
+            .collect();
+        assert_eq!(tiles, [(0, 0), (0, 1), (1, 0), (1, 1), (2, 0)]);
+
+        sheet.order = TileOrder::RowMajor;
+        sheet.count = None;
+        let animator = WebPAnimator::from_sprite_sheet(&image, &sheet, 80).unwrap();
+        assert_eq!(animator.frame_count(), 6);
+        sheet.count = Some(7);
+        assert!(matches!(
+            WebPAnimator::from_sprite_sheet(&image, &sheet, 80),
+            Err(EncodingError::InvalidDimensions)
+        ));
+    }
+
+    #[test]
+    fn test_contact_sheet() {
+        let params = params(8, 4);
+        let mut animator = WebPAnimator::new(params).unwrap();
+        for i in 0..5 {
+            let image = RgbaImage::from_pixel(8, 4, Rgba([i * 50, 0, 0, 255]));
+            animator
+                .add_image(&DynamicImage::from(image), FrameOptions::with_duration(100))
+                .unwrap();
+        }
+        let out = animator.into_bytes().unwrap();
+        let decoder = Decoder::new(&out).unwrap();
+        let layout = ContactSheet {
+            step: 2,
+            columns: 2,
+            scale: 0.5,
+        };
+        let sheet = decoder.contact_sheet(&layout).unwrap();
+        assert_eq!(sheet.dimensions(), (8, 4));
+        assert_eq!(*sheet.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+        assert_eq!(*sheet.get_pixel(4, 0), Rgba([100, 0, 0, 255]));
+        assert_eq!(*sheet.get_pixel(0, 2), Rgba([200, 0, 0, 255]));
+        assert_eq!(*sheet.get_pixel(4, 2), Rgba([0; 4]));
+        let sheet = decoder.contact_sheet(&ContactSheet::default()).unwrap();
+        assert_eq!(sheet.dimensions(), (40, 4));
+    }
+}
@@ -0,0 +1,143 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::{FrameRect, TOTAL_HEADER_LEN, WebPAnimator, riff::Chunks};
+
+/// The kind of image data in a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameFormat {
+    /// A `VP8 ` chunk, without transparency.
+    Lossy,
+    /// An `ALPH` chunk followed by a `VP8 ` chunk.
+    LossyWithAlpha,
+    /// A `VP8L` chunk.
+    Lossless,
+}
+
+/// Statistics about one frame of an animation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameStats {
+    /// The size of the frame data.  The frame takes up 24 more bytes in the
+    /// file, plus a padding byte if the size is odd.
+    pub size: usize,
+    pub format: FrameFormat,
+    pub rect: FrameRect,
+    /// The duration in milliseconds.
+    pub duration: u32,
+    /// Whether the frame covers only part of the canvas, as is usual for
+    /// frames that only encode the changes from the previous frame.
+    pub partial: bool,
+    /// The first earlier frame with the same data, if any.  Such frames are
+    /// stored once in memory, but written in full each time.
+    pub duplicate_of: Option<usize>,
+}
+
+/// Statistics about the file that [`write`](WebPAnimator::write) produces,
+/// for working out where the bytes go.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stats {
+    pub frames: Vec<FrameStats>,
+    /// The size of the whole file.
+    pub file_size: usize,
+    /// The size of the RIFF, `VP8X` and `ANIM` headers.
+    pub header_size: usize,
+    /// The size of the `ANMF` chunks, including their headers.
+    pub frame_size: usize,
+    /// The size of the `ICCP`, `EXIF` and `XMP ` chunks, including their
+    /// headers.
+    pub metadata_size: usize,
+    pub total_duration: Duration,
+}
+
+impl WebPAnimator {
+    /// Describes the frames and the size of each part of the file.
+    pub fn stats(&self) -> Stats {
+        let canvas = (self.width, self.height);
+        let mut first_use: HashMap<&[u8], usize> = HashMap::new();
+        let frames = self
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                let first = *first_use.entry(&frame.data).or_insert(i);
+                FrameStats {
+                    size: frame.data.len(),
+                    format: format(&frame.data),
+                    rect: frame.rect,
+                    duration: frame.duration,
+                    partial: (frame.rect.x, frame.rect.y) != (0, 0)
+                        || (frame.rect.width, frame.rect.height) != canvas,
+                    duplicate_of: (first != i).then_some(first),
+                }
+            })
+            .collect();
+        Stats {
+            frames,
+            file_size: self.estimated_size(),
+            header_size: TOTAL_HEADER_LEN + 8,
+            frame_size: self.frame_len(),
+            metadata_size: self.metadata.len(),
+            total_duration: self.total_duration(),
+        }
+    }
+}
+
+/// The format of validated frame data.
+fn format(data: &[u8]) -> FrameFormat {
+    let mut chunks = Chunks::new(data).flatten().map(|chunk| chunk.fourcc);
+    match chunks.next() {
+        Some(fourcc) if &fourcc == b"VP8L" => FrameFormat::Lossless,
+        Some(fourcc) if &fourcc == b"ALPH" => FrameFormat::LossyWithAlpha,
+        _ => FrameFormat::Lossy,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgb, RgbImage};
+
+    use crate::{FrameFormat, FrameRect, WebPAnimator, test_util::lossless_webp};
+
+    #[test]
+    fn test_stats() {
+        let mut writer = WebPAnimator::builder()
+            .width(16)
+            .height(16)
+            .exif_metadata(b"Exif\0\0MM".to_vec())
+            .build()
+            .unwrap();
+        let encode = |w, h, color| lossless_webp(&RgbImage::from_pixel(w, h, Rgb(color)));
+        let red = encode(16, 16, [255, 0, 0]);
+        writer.add_webp_image(&red, None, 100).unwrap();
+        let rect = FrameRect {
+            x: 2,
+            y: 4,
+            width: 8,
+            height: 8,
+        };
+        writer
+            .add_webp_image(&encode(8, 8, [0, 0, 255]), Some(rect), 50)
+            .unwrap();
+        writer.add_webp_image(&red, None, 100).unwrap();
+
+        let stats = writer.stats();
+        assert_eq!(stats.frames.len(), 3);
+        assert!(
+            stats
+                .frames
+                .iter()
+                .all(|f| f.format == FrameFormat::Lossless)
+        );
+        assert_eq!(stats.frames[1].rect, rect);
+        assert_eq!(stats.frames[1].duration, 50);
+        let partial: Vec<bool> = stats.frames.iter().map(|f| f.partial).collect();
+        assert_eq!(partial, [false, true, false]);
+        let duplicates: Vec<_> = stats.frames.iter().map(|f| f.duplicate_of).collect();
+        assert_eq!(duplicates, [None, None, Some(0)]);
+        assert_eq!(stats.metadata_size, 16);
+        assert_eq!(
+            stats.header_size + stats.frame_size + stats.metadata_size,
+            stats.file_size
+        );
+        assert_eq!(stats.file_size, writer.into_bytes().unwrap().len());
+    }
+}
@@ -0,0 +1,247 @@
+use std::io::{Seek, SeekFrom, Write};
+
+use crate::{
+    CancellationToken, EncodingError, FrameOptions, FrameRect, Header, MAX_RIFF_SIZE, Metadata,
+    Params, Progress, ProgressSink, Stage, VP8X_FLAGS_OFFSET, check_dimensions, check_frame,
+    check_riff_size, padded_len, riff::still_image_chunks, write_anmf_chunk,
+};
+
+/// Writes an animated WebP image directly to `W` as frames are added.
+///
+/// Unlike [`WebPAnimator`](crate::WebPAnimator), frames are not buffered in
+/// memory.  The RIFF size and VP8X flags are patched by seeking back once
+/// [`finish`](Self::finish) is called.  If the writer is dropped without
+/// calling `finish`, the output will not be a valid WebP image.  Frames are
+/// checked as in [`Validation::Strict`](crate::Validation::Strict) mode, and
+/// zero durations are kept as they are.
+pub struct WebPStreamWriter<W: Write + Seek> {
+    writer: W,
+    pub(crate) header: Header,
+    metadata: Metadata,
+    /// Stream position of the start of the file, once the header has been
+    /// written.
+    start: Option<u64>,
+    frame_len: usize,
+    frame_count: usize,
+    progress: Option<Box<dyn ProgressSink>>,
+    cancel: Option<CancellationToken>,
+}
+
+impl<W: Write + Seek> WebPStreamWriter<W> {
+    pub fn new(writer: W, params: Params) -> Result<Self, EncodingError> {
+        check_dimensions(params.width, params.height)?;
+        Ok(Self {
+            writer,
+            header: Header {
+                width: params.width,
+                height: params.height,
+                background_bgra: params.background_bgra,
+                loop_count: params.loop_count.into(),
+                flags: Metadata::default().flags(params.has_alpha),
+            },
+            metadata: Metadata::default(),
+            start: None,
+            frame_len: 0,
+            frame_count: 0,
+            progress: None,
+            cancel: None,
+        })
+    }
+
+    /// Set the ICC profile.  The ICC profile precedes the frames in the
+    /// file, so this fails with [`EncodingError::HeaderAlreadyWritten`] once
+    /// a frame has been added.
+    pub fn set_icc_profile(&mut self, icc_profile: Vec<u8>) -> Result<(), EncodingError> {
+        if self.start.is_some() {
+            return Err(EncodingError::HeaderAlreadyWritten);
+        }
+        self.metadata.set_icc_profile(icc_profile, self.frame_len)
+    }
+
+    /// See [`WebPAnimator::set_exif_metadata`](crate::WebPAnimator::set_exif_metadata).
+    pub fn set_exif_metadata(&mut self, exif_metadata: Vec<u8>) -> Result<(), EncodingError> {
+        self.metadata
+            .set_exif_metadata(exif_metadata, self.frame_len)
+    }
+
+    /// See [`WebPAnimator::set_xmp_metadata`](crate::WebPAnimator::set_xmp_metadata).
+    pub fn set_xmp_metadata(&mut self, xmp_metadata: Vec<u8>) -> Result<(), EncodingError> {
+        self.metadata.set_xmp_metadata(xmp_metadata, self.frame_len)
+    }
+
+    /// The value of the RIFF size field, which counts everything after it.
+    fn riff_size(&self) -> usize {
+        self.metadata.riff_size(self.frame_len)
+    }
+
+    /// See [`WebPAnimator::headroom`](crate::WebPAnimator::headroom).
+    pub fn headroom(&self) -> usize {
+        MAX_RIFF_SIZE.saturating_sub(self.riff_size())
+    }
+
+    fn ensure_header(&mut self) -> Result<(), EncodingError> {
+        if self.start.is_none() {
+            self.start = Some(self.writer.stream_position()?);
+            // The size and flags are placeholders until `finish`.
+            self.header
+                .write(&mut self.writer, 0, &self.metadata.icc_profile)?;
+        }
+        Ok(())
+    }
+
+    /// Report progress to `sink` as frames are written, or stop reporting if
+    /// `None`.  Defaults to `None`.
+    pub fn set_progress_sink(&mut self, sink: Option<Box<dyn ProgressSink>>) {
+        self.progress = sink;
+    }
+
+    /// See
+    /// [`WebPAnimator::set_cancellation_token`](crate::WebPAnimator::set_cancellation_token).
+    pub fn set_cancellation_token(&mut self, token: Option<CancellationToken>) {
+        self.cancel = token;
+    }
+
+    /// Reports the bytes written so far.
+    fn report(&mut self, stage: Stage) {
+        let finished = stage == Stage::Finished;
+        let bytes_written = match finished {
+            true => self.riff_size() + 8,
+            false => self.metadata.header_len() + self.frame_len,
+        } as u64;
+        let progress = Progress {
+            stage,
+            frames: self.frame_count,
+            total_frames: finished.then_some(self.frame_count),
+            bytes_written,
+            total_bytes: finished.then_some(bytes_written),
+        };
+        crate::progress::report(&mut self.progress, progress);
+    }
+
+    /// Add an image to the animation.  See
+    /// [`WebPAnimator::add_webp_chunk`](crate::WebPAnimator::add_webp_chunk).
+    pub fn add_webp_chunk(
+        &mut self,
+        data: &[u8],
+        frame: Option<FrameRect>,
+        duration: u32,
+    ) -> Result<(), EncodingError> {
+        let options = FrameOptions {
+            rect: frame,
+            ..FrameOptions::with_duration(duration)
+        };
+        self.add_chunk(data, options)
+    }
+
+    /// Add an image to the animation.  See
+    /// [`WebPAnimator::add_chunk`](crate::WebPAnimator::add_chunk).
+    pub fn add_chunk(&mut self, data: &[u8], options: FrameOptions) -> Result<(), EncodingError> {
+        trace_span!(DEBUG, "add_frame", index = self.frame_count);
+        crate::cancel::check(&self.cancel)?;
+        let (frame, options) = check_frame(
+            self.header.width,
+            self.header.height,
+            data,
+            &options,
+            self.frame_count,
+            None,
+        )?;
+        let chunk_len = padded_len(data.len()) + 24;
+        check_riff_size(self.riff_size() + chunk_len)?;
+        self.ensure_header()?;
+        write_anmf_chunk(&mut self.writer, data, &frame, &options)?;
+        trace_event!(
+            debug,
+            "wrote frame {}: {} bytes, {:?}, {} ms",
+            self.frame_count,
+            data.len(),
+            frame,
+            options.duration
+        );
+        self.frame_len += chunk_len;
+        self.frame_count += 1;
+        self.report(Stage::Adding);
+        Ok(())
+    }
+
+    /// Add an image to the animation.  See
+    /// [`WebPAnimator::add_webp_image`](crate::WebPAnimator::add_webp_image).
+    pub fn add_webp_image(
+        &mut self,
+        data: &[u8],
+        frame: Option<FrameRect>,
+        duration: u32,
+    ) -> Result<(), EncodingError> {
+        let options = FrameOptions {
+            rect: frame,
+            ..FrameOptions::with_duration(duration)
+        };
+        self.add_frame(data, options)
+    }
+
+    /// Add an image to the animation.  See
+    /// [`WebPAnimator::add_frame`](crate::WebPAnimator::add_frame).
+    pub fn add_frame(&mut self, data: &[u8], options: FrameOptions) -> Result<(), EncodingError> {
+        let data = still_image_chunks(data)?;
+        self.add_chunk(&data, options)
+    }
+
+    /// Write the trailing metadata, fix up the header, and return the
+    /// underlying writer.
+    pub fn finish(mut self) -> Result<W, EncodingError> {
+        trace_span!(DEBUG, "finish", frames = self.frame_count);
+        self.ensure_header()?;
+        let start = self.start.unwrap();
+        self.metadata.write_exif_xmp(&mut self.writer)?;
+        let end = self.writer.stream_position()?;
+        let size = self.riff_size();
+        let flags = self.metadata.flags(self.header.flags & 0x10 != 0);
+        self.writer.seek(SeekFrom::Start(start + 4))?;
+        self.writer.write_all(&(size as u32).to_le_bytes())?;
+        self.writer
+            .seek(SeekFrom::Start(start + VP8X_FLAGS_OFFSET))?;
+        self.writer.write_all(&[flags])?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        trace_event!(debug, "finished stream of {} frames", self.frame_count);
+        self.report(Stage::Finished);
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use image::{Rgb, RgbImage};
+
+    use crate::{
+        Params, WebPAnimator, WebPStreamWriter,
+        test_util::{lossless_webp, params},
+    };
+
+    #[test]
+    fn test_matches_animator() {
+        let params = Params {
+            background_bgra: [255; 4],
+            ..params(64, 64)
+        };
+        let mut animator = WebPAnimator::new(params).unwrap();
+        let mut stream = WebPStreamWriter::new(Cursor::new(Vec::new()), params).unwrap();
+        let icc = vec![7; 21];
+        let xmp = b"<x:xmpmeta/>".to_vec();
+        animator.set_icc_profile(icc.clone()).unwrap();
+        animator.set_xmp_metadata(xmp.clone()).unwrap();
+        stream.set_icc_profile(icc).unwrap();
+        stream.set_xmp_metadata(xmp).unwrap();
+        for color in [[255, 0, 0], [0, 0, 255]] {
+            let buf = lossless_webp(&RgbImage::from_pixel(64, 64, Rgb(color)));
+            animator.add_webp_image(&buf, None, 500).unwrap();
+            stream.add_webp_image(&buf, None, 500).unwrap();
+        }
+        let mut expected = Vec::new();
+        animator.write(&mut expected).unwrap();
+        let actual = stream.finish().unwrap().into_inner();
+        assert_eq!(actual, expected);
+        webp_animation::Decoder::new(&actual).unwrap();
+    }
+}
@@ -0,0 +1,30 @@
+//! Helpers shared by the tests.
+
+use image::{EncodableLayout, ImageBuffer, PixelWithColorType, codecs::webp::WebPEncoder};
+
+use crate::{LoopCount, Params};
+
+/// The settings of an opaque canvas with a transparent background that
+/// loops forever.
+pub(crate) fn params(width: u32, height: u32) -> Params {
+    Params {
+        width,
+        height,
+        background_bgra: [0; 4],
+        loop_count: LoopCount::Infinite,
+        has_alpha: false,
+    }
+}
+
+/// Encodes `image` as a lossless still WebP image.
+pub(crate) fn lossless_webp<P>(image: &ImageBuffer<P, Vec<P::Subpixel>>) -> Vec<u8>
+where
+    P: PixelWithColorType,
+    [P::Subpixel]: EncodableLayout,
+{
+    let mut data = Vec::new();
+    image
+        .write_with_encoder(WebPEncoder::new_lossless(&mut data))
+        .unwrap();
+    data
+}
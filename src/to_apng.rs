@@ -0,0 +1,178 @@
+use std::io::Write;
+
+use image::{RgbaImage, imageops};
+use png::{BitDepth, BlendOp, ColorType, DisposeOp, Encoder, Info};
+
+use crate::{Blend, Decoder, Disposal, EncodingError, WebPAnimator};
+
+impl Decoder<'_> {
+    /// Writes the animation to `writer` as an APNG.
+    ///
+    /// Each frame keeps its rectangle, duration, disposal and blending
+    /// method, except that the first frame is expanded to the whole canvas
+    /// as APNG requires.  The ICC profile, EXIF and XMP metadata are copied
+    /// to `iCCP`, `eXIf` and `iTXt` chunks.  Alpha blending in APNG viewers
+    /// may round colors slightly differently.
+    pub fn to_apng<W: Write>(&self, mut writer: W) -> Result<W, EncodingError> {
+        let frames = self.frames();
+        if frames.is_empty() {
+            return Err(EncodingError::NoFrames);
+        }
+        let mut info = Info::with_size(self.width(), self.height());
+        info.color_type = ColorType::Rgba;
+        info.bit_depth = BitDepth::Eight;
+        info.icc_profile = self.icc_profile().map(Into::into);
+        info.exif_metadata = self.exif_metadata().map(Into::into);
+        let mut encoder = Encoder::with_info(&mut writer, info)?;
+        encoder.set_animated(frames.len() as u32, u16::from(self.loop_count()).into())?;
+        if let Some(xmp) = self.xmp_metadata().and_then(|x| str::from_utf8(x).ok()) {
+            encoder.add_itxt_chunk("XML:com.adobe.xmp".into(), xmp.into())?;
+        }
+        let mut png = encoder.write_header()?;
+        for (i, frame) in frames.iter().enumerate() {
+            self.check_cancelled()?;
+            let mut image = frame.decode()?;
+            let mut rect = frame.rect;
+            let mut blend = frame.blend;
+            if i == 0 {
+                // Drawing onto the initial transparent canvas is the same as
+                // drawing onto nothing.
+                let mut canvas = RgbaImage::new(self.width(), self.height());
+                imageops::replace(&mut canvas, &image, rect.x.into(), rect.y.into());
+                image = canvas;
+                (rect.x, rect.y) = (0, 0);
+                (rect.width, rect.height) = image.dimensions();
+                blend = Blend::Overwrite;
+            }
+            png.reset_frame_position()?;
+            png.set_frame_dimension(rect.width, rect.height)?;
+            png.set_frame_position(rect.x, rect.y)?;
+            let (num, den) = apng_delay(frame.duration);
+            png.set_frame_delay(num, den)?;
+            png.set_dispose_op(match frame.dispose {
+                Disposal::None => DisposeOp::None,
+                Disposal::Background => DisposeOp::Background,
+            })?;
+            png.set_blend_op(match blend {
+                Blend::Alpha => BlendOp::Over,
+                Blend::Overwrite => BlendOp::Source,
+            })?;
+            png.write_image_data(image.as_raw())?;
+        }
+        png.finish()?;
+        Ok(writer)
+    }
+}
+
+impl WebPAnimator {
+    /// Writes the animation as an APNG.  See [`Decoder::to_apng`].
+    pub fn to_apng<W: Write>(&mut self, writer: W) -> Result<W, EncodingError> {
+        let mut data = Vec::with_capacity(self.estimated_size());
+        self.write(&mut data)?;
+        Decoder::new(&data)?.to_apng(writer)
+    }
+}
+
+/// Converts a duration in milliseconds to an APNG delay fraction in seconds,
+/// rounding long durations that don't fit.
+fn apng_delay(duration: u32) -> (u16, u16) {
+    [1000, 100, 10, 1]
+        .into_iter()
+        .find_map(|den| {
+            let num = (duration as u64 * den as u64 + 500) / 1000;
+            u16::try_from(num).ok().map(|num| (num, den))
+        })
+        .unwrap_or((u16::MAX, 1))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use image::{AnimationDecoder, DynamicImage, Rgba, RgbaImage, codecs::png::PngDecoder};
+
+    use crate::{
+        Blend, Compositor, Decoder, Disposal, FrameOptions, FrameRect, LoopCount, Params,
+        WebPAnimator,
+    };
+
+    use super::apng_delay;
+
+    #[test]
+    fn test_to_apng() {
+        let params = Params {
+            width: 8,
+            height: 6,
+            background_bgra: [0; 4],
+            loop_count: LoopCount::from(2),
+            has_alpha: true,
+        };
+        let mut animator = WebPAnimator::new(params).unwrap();
+        animator.set_xmp_metadata(b"<x:xmpmeta/>".to_vec()).unwrap();
+        let frames = [
+            (2, 0, 4, 4, [255, 0, 0, 255], Disposal::None, Blend::Alpha),
+            (
+                4,
+                2,
+                4,
+                4,
+                [0, 255, 0, 255],
+                Disposal::Background,
+                Blend::Alpha,
+            ),
+            (0, 2, 4, 2, [0, 0, 255, 0], Disposal::None, Blend::Overwrite),
+            (2, 4, 6, 2, [9, 9, 9, 255], Disposal::None, Blend::Alpha),
+        ];
+        for (x, y, width, height, color, dispose, blend) in frames {
+            let image = RgbaImage::from_pixel(width, height, Rgba(color));
+            let options = FrameOptions {
+                rect: Some(FrameRect {
+                    x,
+                    y,
+                    width,
+                    height,
+                }),
+                dispose,
+                blend,
+                ..FrameOptions::with_duration(40)
+            };
+            animator
+                .add_image(&DynamicImage::from(image), options)
+                .unwrap();
+        }
+        let mut webp = Vec::new();
+        animator.write(&mut webp).unwrap();
+        let apng = animator.to_apng(Vec::new()).unwrap();
+
+        let png = png::Decoder::new(Cursor::new(&apng)).read_info().unwrap();
+        assert_eq!(png.info().animation_control.unwrap().num_plays, 2);
+        assert!(
+            png.info()
+                .utf8_text
+                .iter()
+                .any(|t| t.keyword == "XML:com.adobe.xmp")
+        );
+        let actual: Vec<_> = PngDecoder::new(Cursor::new(&apng))
+            .unwrap()
+            .apng()
+            .unwrap()
+            .into_frames()
+            .map(|f| f.unwrap())
+            .collect();
+        assert_eq!(actual.len(), frames.len());
+        let decoder = Decoder::new(&webp).unwrap();
+        let mut compositor = Compositor::new(8, 6);
+        for (frame, actual) in decoder.frames().iter().zip(&actual) {
+            assert_eq!(actual.delay().numer_denom_ms(), (40, 1));
+            assert_eq!(compositor.add_frame(frame).unwrap(), actual.buffer());
+        }
+    }
+
+    #[test]
+    fn test_apng_delay() {
+        assert_eq!(apng_delay(40), (40, 1000));
+        assert_eq!(apng_delay(100_000), (10000, 100));
+        assert_eq!(apng_delay(1_000_000), (10000, 10));
+        assert_eq!(apng_delay(16_777_215), (16777, 1));
+    }
+}
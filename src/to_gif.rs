@@ -0,0 +1,151 @@
+use std::io::Write;
+
+use gif::{DisposalMethod, Encoder, Repeat};
+
+use crate::{Compositor, Decoder, EncodingError, LoopCount, WebPAnimator};
+
+/// The NeuQuant sampling factor, trading quality (1) for speed (30).
+const QUANTIZATION_SPEED: i32 = 10;
+
+impl Decoder<'_> {
+    /// Renders the animation and writes it to `writer` as a GIF.
+    ///
+    /// Each frame covers the whole canvas and is reduced to a palette of at
+    /// most 256 colors.  GIF transparency is all or nothing, so partially
+    /// transparent pixels become opaque.  Durations are rounded to the
+    /// nearest hundredth of a second, without accumulating error over the
+    /// course of the animation.
+    pub fn to_gif<W: Write>(&self, writer: W) -> Result<W, EncodingError> {
+        let width = u16::try_from(self.width()).map_err(|_| EncodingError::InvalidDimensions)?;
+        let height = u16::try_from(self.height()).map_err(|_| EncodingError::InvalidDimensions)?;
+        let mut encoder = Encoder::new(writer, width, height, &[])?;
+        match self.loop_count() {
+            LoopCount::Infinite => encoder.set_repeat(Repeat::Infinite)?,
+            // Without a repeat count, the GIF plays once.
+            LoopCount::Finite(n) if n.get() == 1 => {}
+            LoopCount::Finite(n) => encoder.set_repeat(Repeat::Finite(n.get() - 1))?,
+        }
+        let mut compositor = Compositor::new(self.width(), self.height());
+        let (mut time, mut gif_time) = (0u64, 0u64);
+        for frame in self.frames() {
+            self.check_cancelled()?;
+            let mut pixels = compositor.add_frame(frame)?.as_raw().clone();
+            let mut gif_frame =
+                gif::Frame::from_rgba_speed(width, height, &mut pixels, QUANTIZATION_SPEED);
+            time += frame.duration as u64;
+            let delay = (time + 5) / 10 - gif_time;
+            gif_time += delay;
+            gif_frame.delay = u16::try_from(delay).unwrap_or(u16::MAX);
+            // Transparent pixels must not reveal the previous frame.
+            gif_frame.dispose = DisposalMethod::Background;
+            encoder.write_frame(&gif_frame)?;
+        }
+        Ok(encoder.into_inner()?)
+    }
+}
+
+impl WebPAnimator {
+    /// Writes the animation as a GIF.  See [`Decoder::to_gif`].
+    pub fn to_gif<W: Write>(&mut self, writer: W) -> Result<W, EncodingError> {
+        let mut data = Vec::with_capacity(self.estimated_size());
+        self.write(&mut data)?;
+        Decoder::new(&data)?.to_gif(writer)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use image::{AnimationDecoder, DynamicImage, Rgba, RgbaImage, codecs::gif::GifDecoder};
+
+    use crate::{
+        Blend, CancellationToken, Compositor, Decoder, EncodingError, FrameOptions, FrameRect,
+        LoopCount, Params, WebPAnimator,
+    };
+
+    #[test]
+    fn test_to_gif() {
+        let params = Params {
+            width: 8,
+            height: 6,
+            background_bgra: [0; 4],
+            loop_count: LoopCount::from(3),
+            has_alpha: true,
+        };
+        let mut animator = WebPAnimator::new(params).unwrap();
+        let mut image = RgbaImage::from_pixel(8, 6, Rgba([255, 0, 0, 255]));
+        image.put_pixel(7, 5, Rgba([0; 4]));
+        animator
+            .add_image(&DynamicImage::from(image), FrameOptions::with_duration(33))
+            .unwrap();
+        let rect = FrameRect {
+            x: 2,
+            y: 2,
+            width: 4,
+            height: 2,
+        };
+        let options = FrameOptions {
+            rect: Some(rect),
+            ..FrameOptions::with_duration(33)
+        };
+        let image = RgbaImage::from_pixel(4, 2, Rgba([0, 0, 255, 255]));
+        animator
+            .add_image(&DynamicImage::from(image), options)
+            .unwrap();
+        animator
+            .add_image(
+                &DynamicImage::from(RgbaImage::new(4, 2)),
+                FrameOptions {
+                    blend: Blend::Overwrite,
+                    ..options
+                },
+            )
+            .unwrap();
+        let mut webp = Vec::new();
+        animator.write(&mut webp).unwrap();
+        let gif = animator.to_gif(Vec::new()).unwrap();
+        let decoder = GifDecoder::new(Cursor::new(&gif)).unwrap();
+        let frames: Vec<_> = decoder.into_frames().map(|f| f.unwrap()).collect();
+        let delays: Vec<_> = frames
+            .iter()
+            .map(|f| f.delay().numer_denom_ms().0 / f.delay().numer_denom_ms().1)
+            .collect();
+        assert_eq!(delays, [30, 40, 30]);
+        let decoder = Decoder::new(&webp).unwrap();
+        let mut compositor = Compositor::new(8, 6);
+        for (frame, gif_frame) in decoder.frames().iter().zip(&frames) {
+            assert_eq!(compositor.add_frame(frame).unwrap(), gif_frame.buffer());
+        }
+        let mut gif_decoder = gif::DecodeOptions::new()
+            .read_info(Cursor::new(&gif))
+            .unwrap();
+        while gif_decoder.read_next_frame().unwrap().is_some() {}
+        assert_eq!(gif_decoder.repeat(), gif::Repeat::Finite(2));
+    }
+
+    #[test]
+    fn test_to_gif_cancelled() {
+        let mut animator = WebPAnimator::builder().width(4).height(4).build().unwrap();
+        animator
+            .add_image(
+                &DynamicImage::from(RgbaImage::new(4, 4)),
+                FrameOptions::with_duration(100),
+            )
+            .unwrap();
+        let data = animator.into_bytes().unwrap();
+        let mut decoder = Decoder::new(&data).unwrap();
+        decoder.set_cancellation_token(Some(CancellationToken::from_fn(|| true)));
+        assert!(matches!(
+            decoder.to_gif(Vec::new()),
+            Err(EncodingError::Cancelled)
+        ));
+        let rect = FrameRect {
+            x: 0,
+            y: 0,
+            width: 2,
+            height: 2,
+        };
+        assert!(matches!(decoder.crop(rect), Err(EncodingError::Cancelled)));
+    }
+}
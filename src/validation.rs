@@ -0,0 +1,68 @@
+use crate::EncodingError;
+
+/// How [`WebPAnimator`](crate::WebPAnimator) and
+/// [`WebPSpoolWriter`](crate::WebPSpoolWriter) handle input that violates
+/// the WebP specification.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Validation {
+    /// Reject anything that violates the specification, including writing
+    /// an animation without any frames.
+    Strict,
+    /// Correct problems where it is safe to do so, and record a [`Warning`]
+    /// for each correction.  Problems that can't be corrected, such as
+    /// malformed image data, are still errors.  This is the default.
+    #[default]
+    Lenient,
+}
+
+impl Validation {
+    /// Checks that an animation with `frame_count` frames can be written,
+    /// recording a warning (once) if it is empty in lenient mode.
+    pub(crate) fn check_frame_count(
+        self,
+        frame_count: usize,
+        warnings: &mut Vec<Warning>,
+    ) -> Result<(), EncodingError> {
+        if frame_count == 0 {
+            match self {
+                Self::Strict => return Err(EncodingError::NoFrames),
+                Self::Lenient if !warnings.contains(&Warning::EmptyAnimation) => {
+                    warnings.push(Warning::EmptyAnimation);
+                }
+                Self::Lenient => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A correction made in [`Validation::Lenient`] mode.  Frames are identified
+/// by their index at the time they were added.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Warning {
+    /// The frame rectangle had a different size from the image, so the size
+    /// of the image was used instead.
+    FrameSizeCorrected(usize),
+    /// The frame had an odd offset, which was rounded down.
+    OffsetRounded(usize),
+    /// The frame extended past the edge of the canvas, so it was moved
+    /// inside.
+    FrameMoved(usize),
+    /// The duration was longer than
+    /// [`MAX_DURATION_MS`](crate::MAX_DURATION_MS), so it was shortened.
+    DurationClamped(usize),
+    /// The animation was written without any frames.
+    EmptyAnimation,
+}
+
+impl core::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FrameSizeCorrected(i) => write!(f, "frame {i}: used the size of the image"),
+            Self::OffsetRounded(i) => write!(f, "frame {i}: rounded odd offset down"),
+            Self::FrameMoved(i) => write!(f, "frame {i}: moved inside the canvas"),
+            Self::DurationClamped(i) => write!(f, "frame {i}: shortened duration"),
+            Self::EmptyAnimation => write!(f, "animation has no frames"),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{Decoder, EncodingError, LoopCount, WebPAnimator, riff::Chunks};
+
+impl WebPAnimator {
+    /// Writes the animation to memory and parses it again, checking that
+    /// the RIFF and chunk sizes are consistent, and that the header, the
+    /// metadata and every frame match what was added, with each frame
+    /// inside the canvas.  Returns the file if so, so that it doesn't have
+    /// to be written again.  Fails with
+    /// [`EncodingError::VerificationFailed`] naming the first mismatch
+    /// otherwise.
+    pub fn verify(&mut self) -> Result<Vec<u8>, EncodingError> {
+        let mut data = Vec::with_capacity(self.estimated_size());
+        self.write(&mut data)?;
+        self.check_output(&data)?;
+        Ok(data)
+    }
+
+    fn check_output(&self, data: &[u8]) -> Result<(), EncodingError> {
+        let fail = |what| Err(EncodingError::VerificationFailed(what));
+        let decoder = Decoder::new(data)?;
+        let riff_size = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        if riff_size + 8 != data.len() || data.len() != self.estimated_size() {
+            return fail("file size");
+        }
+        // Every chunk, including the last, must end within the file.
+        if Chunks::new(&data[12..]).any(|chunk| chunk.is_err()) {
+            return fail("chunk size");
+        }
+        if (decoder.width(), decoder.height()) != (self.width, self.height) {
+            return fail("canvas size");
+        }
+        if decoder.background_bgra() != self.background_bgra
+            || decoder.loop_count() != LoopCount::from(self.loop_count)
+        {
+            return fail("animation parameters");
+        }
+        if decoder.has_alpha() != self.has_alpha() {
+            return fail("alpha flag");
+        }
+        if decoder.icc_profile() != metadata(&self.metadata.icc_profile)
+            || decoder.exif_metadata() != metadata(&self.metadata.exif)
+            || decoder.xmp_metadata() != metadata(&self.metadata.xmp)
+        {
+            return fail("metadata");
+        }
+        if decoder.frames().len() != self.frames.len() {
+            return fail("frame count");
+        }
+        for (decoded, frame) in decoder.frames().iter().zip(&self.frames) {
+            let rect = decoded.rect;
+            if rect.x as u64 + rect.width as u64 > self.width as u64
+                || rect.y as u64 + rect.height as u64 > self.height as u64
+            {
+                return fail("frame outside canvas");
+            }
+            if decoded.options() != frame.options() || decoded.data != &*frame.data {
+                return fail("frame contents");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `None` for empty metadata, which isn't written.
+fn metadata(m: &[u8]) -> Option<&[u8]> {
+    Some(m).filter(|m| !m.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use image::{Rgb, RgbImage};
+
+    use crate::{EncodingError, FrameRect, WebPAnimator, test_util::lossless_webp};
+
+    #[test]
+    fn test_verify() {
+        let mut writer = WebPAnimator::builder()
+            .width(16)
+            .height(16)
+            .loop_count(3)
+            .xmp_metadata(b"<x/>".to_vec())
+            .build()
+            .unwrap();
+        let buf = lossless_webp(&RgbImage::from_pixel(8, 8, Rgb([255, 0, 0])));
+        let rect = FrameRect {
+            x: 8,
+            y: 8,
+            width: 8,
+            height: 8,
+        };
+        writer.add_webp_image(&buf, Some(rect), 100).unwrap();
+        let data = writer.verify().unwrap();
+        assert_eq!(data, writer.into_bytes().unwrap());
+
+        let mut other = WebPAnimator::builder()
+            .width(16)
+            .height(16)
+            .build()
+            .unwrap();
+        other.add_webp_image(&buf, Some(rect), 100).unwrap();
+        assert!(matches!(
+            other.check_output(&data),
+            Err(EncodingError::VerificationFailed("file size"))
+        ));
+        other.set_xmp_metadata(b"<y/>".to_vec()).unwrap();
+        other.loop_count = 3;
+        assert!(matches!(
+            other.check_output(&data),
+            Err(EncodingError::VerificationFailed("metadata"))
+        ));
+        let truncated = &data[..data.len() - 2];
+        assert!(matches!(
+            other.check_output(truncated),
+            Err(EncodingError::Decoding(_))
+        ));
+    }
+}
@@ -0,0 +1,167 @@
+//! JavaScript bindings, for assembling and decoding animations client-side
+//! in web apps.  Build with `wasm-pack build --features wasm`.
+//!
+//! ```js
+//! import { Animation, decode } from "webp-animator";
+//!
+//! const animation = new Animation(canvas.width, canvas.height);
+//! const pixels = context.getImageData(0, 0, canvas.width, canvas.height).data;
+//! animation.addRgbaFrame(pixels, canvas.width, canvas.height, 100);
+//! const webp = animation.finish();
+//!
+//! const decoded = decode(webp);
+//! const first = new ImageData(new Uint8ClampedArray(decoded.frame(0)), decoded.width);
+//! ```
+
+use wasm_bindgen::prelude::*;
+
+use crate::{Decoder, EncodingError, FrameOptions, LoopCount, WebPAnimator};
+
+fn js_error(error: EncodingError) -> JsError {
+    JsError::new(&error.to_string())
+}
+
+/// An animation being assembled.  Whether it has alpha is detected from the
+/// frames.
+#[wasm_bindgen]
+pub struct Animation {
+    animator: WebPAnimator,
+}
+
+#[wasm_bindgen]
+impl Animation {
+    /// Creates an animation with the given canvas size, which is played
+    /// `loop_count` times, where 0 or `undefined` means forever.
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: u32, height: u32, loop_count: Option<u16>) -> Result<Animation, JsError> {
+        let animator = WebPAnimator::builder()
+            .width(width)
+            .height(height)
+            .loop_count(LoopCount::from(loop_count.unwrap_or(0)))
+            .detect_alpha(true)
+            .build()
+            .map_err(js_error)?;
+        Ok(Self { animator })
+    }
+
+    /// Adds a still WebP image, such as one from `canvas.toBlob`, shown for
+    /// `duration` milliseconds.
+    #[wasm_bindgen(js_name = addFrame)]
+    pub fn add_frame(&mut self, data: &[u8], duration: u32) -> Result<(), JsError> {
+        self.animator
+            .add_frame(data, FrameOptions::with_duration(duration))
+            .map_err(js_error)
+    }
+
+    /// Encodes RGBA pixels, such as the data of an `ImageData`, losslessly
+    /// and adds them, shown for `duration` milliseconds.
+    #[wasm_bindgen(js_name = addRgbaFrame)]
+    pub fn add_rgba_frame(
+        &mut self,
+        pixels: &[u8],
+        width: u32,
+        height: u32,
+        duration: u32,
+    ) -> Result<(), JsError> {
+        self.animator
+            .add_rgba_frame(pixels, width, height, FrameOptions::with_duration(duration))
+            .map_err(js_error)
+    }
+
+    /// Returns the animated WebP file.
+    pub fn finish(self) -> Result<Vec<u8>, JsError> {
+        self.animator.into_bytes().map_err(js_error)
+    }
+}
+
+/// A decoded animation, with each frame rendered on the canvas.
+#[wasm_bindgen]
+pub struct DecodedAnimation {
+    width: u32,
+    height: u32,
+    loop_count: u16,
+    durations: Vec<u32>,
+    frames: Vec<Vec<u8>>,
+}
+
+#[wasm_bindgen]
+impl DecodedAnimation {
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The number of times to play the animation, where 0 means forever.
+    #[wasm_bindgen(getter, js_name = loopCount)]
+    pub fn loop_count(&self) -> u16 {
+        self.loop_count
+    }
+
+    #[wasm_bindgen(getter, js_name = frameCount)]
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The duration of each frame in milliseconds.
+    #[wasm_bindgen(getter)]
+    pub fn durations(&self) -> Vec<u32> {
+        self.durations.clone()
+    }
+
+    /// The RGBA pixels of the canvas while frame `index` is shown, or
+    /// `undefined` if there is no such frame.
+    pub fn frame(&self, index: usize) -> Option<Vec<u8>> {
+        self.frames.get(index).cloned()
+    }
+}
+
+/// Decodes an animated WebP file and renders its frames.
+#[wasm_bindgen]
+pub fn decode(data: &[u8]) -> Result<DecodedAnimation, JsError> {
+    render(data).map_err(js_error)
+}
+
+fn render(data: &[u8]) -> Result<DecodedAnimation, EncodingError> {
+    let decoder = Decoder::new(data)?;
+    let mut durations = Vec::new();
+    let mut frames = Vec::new();
+    for frame in decoder.canvas_frames() {
+        let frame = frame?;
+        durations.push(frame.duration);
+        frames.push(frame.image.into_raw());
+    }
+    Ok(DecodedAnimation {
+        width: decoder.width(),
+        height: decoder.height(),
+        loop_count: decoder.loop_count().into(),
+        durations,
+        frames,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Animation, render};
+
+    #[test]
+    fn test_round_trip() {
+        let mut animation = Animation::new(2, 1, Some(3)).unwrap();
+        let red = [255, 0, 0, 255, 255, 0, 0, 255];
+        let half_clear = [0, 0, 255, 0, 0, 255, 0, 255];
+        animation.add_rgba_frame(&red, 2, 1, 100).unwrap();
+        animation.add_rgba_frame(&half_clear, 2, 1, 50).unwrap();
+        let data = animation.finish().unwrap();
+        let decoded = render(&data).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (2, 1));
+        assert_eq!(decoded.loop_count(), 3);
+        assert_eq!(decoded.durations(), [100, 50]);
+        assert_eq!(decoded.frame(0).unwrap(), red);
+        assert_eq!(decoded.frame(1).unwrap()[4..], half_clear[4..]);
+        assert_eq!(decoded.frame(2), None);
+    }
+}
@@ -0,0 +1,223 @@
+use libwebp_sys::{WEBP_YUV420, WebPPictureAlloc};
+
+use crate::{EncodingError, FrameOptions, WebPAnimator, lossy};
+
+/// The range of the samples of a [`Yuv420`] image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum YuvRange {
+    /// Luma from 16 to 235 and chroma from 16 to 240, as in most video.
+    #[default]
+    Limited,
+    /// All samples from 0 to 255, as in JPEG.
+    Full,
+}
+
+/// An image in planar YUV 4:2:0 format, as produced by video decoders.
+///
+/// The colors are taken to use the BT.601 coefficients, as lossy WebP does.
+/// The chroma planes are half the width and height of the luma plane,
+/// rounded up.
+#[derive(Clone, Copy, Debug)]
+pub struct Yuv420<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub y: &'a [u8],
+    pub u: &'a [u8],
+    pub v: &'a [u8],
+    /// The distance between the starts of consecutive rows of `y`.
+    pub y_stride: usize,
+    /// The distance between the starts of consecutive rows of `u` and `v`.
+    pub uv_stride: usize,
+    pub range: YuvRange,
+}
+
+impl Yuv420<'_> {
+    /// Checks that the planes are large enough.
+    fn check(&self) -> Result<(), EncodingError> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (chroma_width, chroma_height) = (width.div_ceil(2), height.div_ceil(2));
+        let fits = |plane: &[u8], stride: usize, w: usize, h: usize| {
+            stride >= w
+                && stride
+                    .checked_mul(h - 1)
+                    .and_then(|n| n.checked_add(w))
+                    .is_some_and(|len| plane.len() >= len)
+        };
+        let valid = (1..=16383).contains(&self.width)
+            && (1..=16383).contains(&self.height)
+            && fits(self.y, self.y_stride, width, height)
+            && fits(self.u, self.uv_stride, chroma_width, chroma_height)
+            && fits(self.v, self.uv_stride, chroma_width, chroma_height);
+        if valid {
+            Ok(())
+        } else {
+            Err(EncodingError::InvalidDimensions)
+        }
+    }
+}
+
+/// Copies `h` rows of `w` samples, mapping each through `lut`.
+fn copy_plane(
+    src: &[u8],
+    src_stride: usize,
+    dst: *mut u8,
+    dst_stride: usize,
+    (w, h): (usize, usize),
+    lut: Option<&[u8; 256]>,
+) {
+    for row in 0..h {
+        let src = &src[row * src_stride..][..w];
+        // SAFETY: libwebp allocated `h` rows of `dst_stride >= w` bytes.
+        let dst = unsafe { std::slice::from_raw_parts_mut(dst.add(row * dst_stride), w) };
+        match lut {
+            Some(lut) => {
+                for (d, &s) in dst.iter_mut().zip(src) {
+                    *d = lut[s as usize];
+                }
+            }
+            None => dst.copy_from_slice(src),
+        }
+    }
+}
+
+/// Maps full range samples to the limited range, for luma if `luma` and for
+/// chroma otherwise.
+fn full_to_limited(luma: bool) -> [u8; 256] {
+    let (scale, center) = if luma {
+        (219.0 / 255.0, 16.0)
+    } else {
+        (224.0 / 255.0, 128.0)
+    };
+    std::array::from_fn(|i| {
+        let x = if luma { i as f32 } else { i as f32 - 128.0 };
+        (x * scale + center).round() as u8
+    })
+}
+
+impl WebPAnimator {
+    /// Encodes a YUV 4:2:0 image lossily with libwebp, without converting
+    /// it to RGB, and adds it to the animation.  Full range samples are
+    /// mapped to the limited range that lossy WebP uses.
+    ///
+    /// * `quality` - Between 0 (smallest output) and 100 (highest quality).
+    ///   Overridden by [`FrameOptions::quality`] if it is set.
+    /// * `options` - The frame settings, as for
+    ///   [`add_frame`](Self::add_frame).
+    ///
+    /// Fails with [`EncodingError::InvalidDimensions`] if a plane is too
+    /// small for the image, or `options.rect` has a different size.
+    pub fn add_yuv420_frame(
+        &mut self,
+        image: &Yuv420,
+        quality: f32,
+        options: FrameOptions,
+    ) -> Result<(), EncodingError> {
+        trace_span!(
+            TRACE,
+            "encode_yuv420",
+            width = image.width,
+            height = image.height
+        );
+        image.check()?;
+        if options
+            .rect
+            .is_some_and(|rect| (rect.width, rect.height) != (image.width, image.height))
+        {
+            return Err(EncodingError::InvalidDimensions);
+        }
+        let quality = options.quality.map_or(quality, f32::from);
+        let method = options.method.unwrap_or(lossy::DEFAULT_METHOD);
+        let (luma_lut, chroma_lut) = match image.range {
+            YuvRange::Limited => (None, None),
+            YuvRange::Full => (Some(full_to_limited(true)), Some(full_to_limited(false))),
+        };
+        let (width, height) = (image.width as usize, image.height as usize);
+        let chroma = (width.div_ceil(2), height.div_ceil(2));
+        let data = lossy::encode_picture(image.width, image.height, quality, method, |picture| {
+            picture.use_argb = 0;
+            picture.colorspace = WEBP_YUV420;
+            // SAFETY: the picture has been initialized, and its size set.
+            if unsafe { WebPPictureAlloc(picture) } == 0 {
+                return false;
+            }
+            let uv_stride = picture.uv_stride as usize;
+            let planes = [
+                (
+                    image.y,
+                    image.y_stride,
+                    picture.y,
+                    picture.y_stride as usize,
+                ),
+                (image.u, image.uv_stride, picture.u, uv_stride),
+                (image.v, image.uv_stride, picture.v, uv_stride),
+            ];
+            for (i, (src, src_stride, dst, dst_stride)) in planes.into_iter().enumerate() {
+                let (size, lut) = if i == 0 {
+                    ((width, height), luma_lut.as_ref())
+                } else {
+                    (chroma, chroma_lut.as_ref())
+                };
+                copy_plane(src, src_stride, dst, dst_stride, size, lut);
+            }
+            true
+        })?;
+        trace_event!(
+            trace,
+            "encoded {}x{} YUV 4:2:0 image lossily at quality {quality} in {} bytes",
+            image.width,
+            image.height,
+            data.len()
+        );
+        self.add_frame(&data, options)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Decoder, EncodingError, FrameOptions, WebPAnimator, Yuv420, YuvRange};
+
+    #[test]
+    fn test_add_yuv420_frame() {
+        // A 5x3 image has 3x2 chroma planes.
+        let y = [126; 6 * 3];
+        let (u, v) = ([128; 3 * 2], [240; 3 * 2]);
+        let limited = Yuv420 {
+            width: 5,
+            height: 3,
+            y: &y,
+            u: &u,
+            v: &v,
+            y_stride: 6,
+            uv_stride: 3,
+            range: YuvRange::Limited,
+        };
+        let full_y = [128; 5 * 3];
+        let full_v = [255; 3 * 2];
+        let full = Yuv420 {
+            y: &full_y,
+            v: &full_v,
+            y_stride: 5,
+            range: YuvRange::Full,
+            ..limited
+        };
+        let mut writer = WebPAnimator::builder().width(5).height(3).build().unwrap();
+        let options = FrameOptions::with_duration(100);
+        writer.add_yuv420_frame(&limited, 90.0, options).unwrap();
+        writer.add_yuv420_frame(&full, 90.0, options).unwrap();
+        let short = Yuv420 {
+            y: &y[..15],
+            ..limited
+        };
+        assert!(matches!(
+            writer.add_yuv420_frame(&short, 90.0, options),
+            Err(EncodingError::InvalidDimensions)
+        ));
+        let data = writer.into_bytes().unwrap();
+        let decoder = Decoder::new(&data).unwrap();
+        // The full range samples are the same color.
+        assert_eq!(decoder.frames()[0].data, decoder.frames()[1].data);
+        let frame = decoder.canvas_frames().next().unwrap().unwrap().image;
+        let [r, g, b, a] = frame.get_pixel(2, 1).0;
+        assert!(r > 230 && g < 100 && b < 160 && a == 255, "{r} {g} {b}");
+    }
+}